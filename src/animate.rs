@@ -1,23 +1,36 @@
-use crate::utils::Vector;
+use crate::utils::{Color, Matrix4, Quaternion, Transform, Vector};
+use serde::{Deserialize, Serialize};
 
 /// # Animation
 ///
 /// An animation is a timed translation, scaling and/or rotation of a component: Object, Light or Camera.
-#[derive(Clone)]
+/// Lights and emissive objects can also have their emission animated (see EmissionRamp), and the Camera its lens (see LensRamp).
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Animation {
     pub start_time: f64,
     pub end_time: f64,
     pub translation: Vector,
     pub scale: f64,
+    pub scale_center: Vector,
     pub rotation_x: f64,
     pub rotation_center_x: Vector,
     pub rotation_y: f64,
     pub rotation_center_y: Vector,
     pub rotation_z: f64,
     pub rotation_center_z: Vector,
+    pub emission: Option<EmissionRamp>,
+    pub lens: Option<LensRamp>,
+    /// A rotation around an arbitrary axis, interpolated from the identity (see Quaternion::slerp) and applied in a single step after the rotations around x, y and z
+    pub rotation: Option<Quaternion>,
+    pub rotation_center: Vector,
+    /// A motion following keyframes, applied after all the other transformations (see Keyframes)
+    /// Unlike them, it does not depend on start_time and end_time: before its first key and after its last one, it holds the pose of that key.
+    #[serde(default)]
+    pub keyframes: Option<Keyframes>,
 }
 
 impl Animation {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         start_time: f64,
         end_time: f64,
@@ -35,12 +48,18 @@ impl Animation {
             end_time,
             translation,
             scale,
+            scale_center: Vector::new_eq(0.),
             rotation_x,
             rotation_center_x,
             rotation_y,
             rotation_center_y,
             rotation_z,
             rotation_center_z,
+            emission: None,
+            lens: None,
+            rotation: None,
+            rotation_center: Vector::new_eq(0.),
+            keyframes: None,
         }
     }
 
@@ -50,27 +69,45 @@ impl Animation {
             end_time,
             translation,
             scale: 1.,
+            scale_center: Vector::new_eq(0.),
             rotation_x: 0.,
             rotation_center_x: Vector::new_eq(0.),
             rotation_y: 0.,
             rotation_center_y: Vector::new_eq(0.),
             rotation_z: 0.,
             rotation_center_z: Vector::new_eq(0.),
+            emission: None,
+            lens: None,
+            rotation: None,
+            rotation_center: Vector::new_eq(0.),
+            keyframes: None,
         }
     }
 
+    /// Scales the component around the origin, up to a factor scale at end_time (see scale_around)
     pub fn scale(start_time: f64, end_time: f64, scale: f64) -> Self {
+        Animation::scale_around(start_time, end_time, scale, Vector::new_eq(0.))
+    }
+
+    /// Scales the component around scale_center (usually the center of the object), up to a factor scale at end_time
+    pub fn scale_around(start_time: f64, end_time: f64, scale: f64, scale_center: Vector) -> Self {
         Animation {
             start_time,
             end_time,
             translation: Vector::new_eq(0.),
             scale,
+            scale_center,
             rotation_x: 0.,
             rotation_center_x: Vector::new_eq(0.),
             rotation_y: 0.,
             rotation_center_y: Vector::new_eq(0.),
             rotation_z: 0.,
             rotation_center_z: Vector::new_eq(0.),
+            emission: None,
+            lens: None,
+            rotation: None,
+            rotation_center: Vector::new_eq(0.),
+            keyframes: None,
         }
     }
 
@@ -85,12 +122,18 @@ impl Animation {
             end_time,
             translation: Vector::new_eq(0.),
             scale: 1.,
+            scale_center: Vector::new_eq(0.),
             rotation_x,
             rotation_center_x,
             rotation_y: 0.,
             rotation_center_y: Vector::new_eq(0.),
             rotation_z: 0.,
             rotation_center_z: Vector::new_eq(0.),
+            emission: None,
+            lens: None,
+            rotation: None,
+            rotation_center: Vector::new_eq(0.),
+            keyframes: None,
         }
     }
     pub fn rotation_y(
@@ -104,12 +147,18 @@ impl Animation {
             end_time,
             translation: Vector::new_eq(0.),
             scale: 1.,
+            scale_center: Vector::new_eq(0.),
             rotation_x: 0.,
             rotation_center_x: Vector::new_eq(0.),
             rotation_y,
             rotation_center_y,
             rotation_z: 0.,
             rotation_center_z: Vector::new_eq(0.),
+            emission: None,
+            lens: None,
+            rotation: None,
+            rotation_center: Vector::new_eq(0.),
+            keyframes: None,
         }
     }
     pub fn rotation_z(
@@ -123,14 +172,366 @@ impl Animation {
             end_time,
             translation: Vector::new_eq(0.),
             scale: 1.,
+            scale_center: Vector::new_eq(0.),
             rotation_x: 0.,
             rotation_center_x: Vector::new_eq(0.),
             rotation_y: 0.,
             rotation_center_y: Vector::new_eq(0.),
             rotation_z,
             rotation_center_z,
+            emission: None,
+            lens: None,
+            rotation: None,
+            rotation_center: Vector::new_eq(0.),
+            keyframes: None,
+        }
+    }
+
+    /// Animates the emission of a light or an emissive object
+    pub fn emission(start_time: f64, end_time: f64, emission: EmissionRamp) -> Self {
+        Animation {
+            emission: Some(emission),
+            ..Animation::translation(start_time, end_time, Vector::new_eq(0.))
+        }
+    }
+
+    /// Rotates the component by rotation_deg degrees around an axis going through rotation_center
+    /// Unlike combining rotation_x, rotation_y and rotation_z, the component turns around a fixed axis during the whole animation.
+    pub fn rotation_axis(
+        start_time: f64,
+        end_time: f64,
+        axis: Vector,
+        rotation_deg: f64,
+        rotation_center: Vector,
+    ) -> Self {
+        Animation {
+            rotation: Some(Quaternion::from_axis_angle(axis, rotation_deg)),
+            rotation_center,
+            ..Animation::translation(start_time, end_time, Vector::new_eq(0.))
+        }
+    }
+
+    /// Computes the rotation around an arbitrary axis at a given progress
+    pub fn rotation_at(&self, progress: f64) -> Quaternion {
+        match self.rotation {
+            Some(rotation) => Quaternion::identity().slerp(rotation, progress),
+            None => Quaternion::identity(),
+        }
+    }
+
+    /// Moves the component through the poses of keyframes
+    /// start_time and end_time are the times of its first and last keys.
+    pub fn keyframes(keyframes: Keyframes) -> Self {
+        let (start_time, end_time) = match (keyframes.keys.first(), keyframes.keys.last()) {
+            (Some(first), Some(last)) => (first.time, last.time),
+            _ => (0., 0.),
+        };
+        Animation {
+            keyframes: Some(keyframes),
+            ..Animation::translation(start_time, end_time, Vector::new_eq(0.))
+        }
+    }
+
+    /// Animates the field of view or the focal distance of a Camera
+    pub fn lens(start_time: f64, end_time: f64, lens: LensRamp) -> Self {
+        Animation {
+            lens: Some(lens),
+            ..Animation::translation(start_time, end_time, Vector::new_eq(0.))
+        }
+    }
+
+    /// Computes how far the animation has progressed at a given time, from 0. to 1.
+    /// Returns None if the animation has not started yet.
+    pub fn progress(&self, time: f64) -> Option<f64> {
+        if self.start_time >= time || self.start_time > self.end_time {
+            None
+        } else if self.end_time > time {
+            Some((time - self.start_time) / (self.end_time - self.start_time))
+        } else {
+            Some(1.)
+        }
+    }
+
+    /// Computes the scaling factor at a given progress
+    /// The factor is interpolated geometrically, so that the reverse animation (of factor 1 / scale) is its exact inverse at all times.
+    pub fn scale_at(&self, progress: f64) -> f64 {
+        self.scale.powf(progress)
+    }
+}
+
+/// # Keyframe
+///
+/// The pose of a component at a given time: scaled by scale and rotated by rotation around the pivot of its Keyframes, then translated by translation
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vector,
+    pub rotation: Quaternion,
+    pub scale: f64,
+}
+
+/// # Interpolation
+///
+/// How the translation of Keyframes goes from one key to the next
+#[derive(Copy, Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// In a straight line at constant speed: the speed jumps at each key
+    #[default]
+    Linear,
+    /// Along a Catmull-Rom spline through the translations of the keys, whose speed at each key is the average speed from the previous key to the next one
+    /// The path is smooth, and the speed changes continuously (C1), which suits camera paths.
+    CatmullRom,
+}
+
+/// # Keyframes
+///
+/// A motion going through a list of Keyframes, sorted by time, for multi-segment animations (see Animation::keyframes)
+/// Between two keys, the translation follows the Interpolation of the Keyframes, the rotation the shortest path (see Quaternion::slerp),
+/// and the scale changes geometrically: two consecutive keys should be less than half a turn apart.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Keyframes {
+    /// The point the rotations and scalings are around, usually the center of the object
+    pub pivot: Vector,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    keys: Vec<Keyframe>,
+}
+
+impl Keyframes {
+    pub fn new(pivot: Vector) -> Self {
+        Keyframes {
+            pivot,
+            interpolation: Interpolation::Linear,
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    /// Adds a key, keeping the keys sorted by time
+    pub fn add_key(&mut self, time: f64, translation: Vector, rotation: Quaternion, scale: f64) {
+        let index = self.keys.partition_point(|key| key.time <= time);
+        self.keys.insert(
+            index,
+            Keyframe {
+                time,
+                translation,
+                rotation,
+                scale,
+            },
+        );
+    }
+
+    pub fn keys(&self) -> &[Keyframe] {
+        &self.keys
+    }
+
+    /// Computes the translation, rotation and scale at a given time, from the keys before and after it
+    /// Without any key, the component does not move.
+    pub fn pose_at(&self, time: f64) -> (Vector, Quaternion, f64) {
+        let after = self.keys.partition_point(|key| key.time <= time);
+        let (from, to) = match (after.checked_sub(1), after < self.keys.len()) {
+            (None, false) => return (Vector::new_eq(0.), Quaternion::identity(), 1.),
+            (Some(before), false) => (before, before),
+            (None, true) => (after, after),
+            (Some(before), true) => (before, after),
+        };
+        let (from_key, to_key) = (self.keys[from], self.keys[to]);
+
+        let duration = to_key.time - from_key.time;
+        let t = match duration > 0. {
+            true => (time - from_key.time) / duration,
+            false => 0.,
+        };
+        let translation = match self.interpolation {
+            Interpolation::Linear => from_key.translation * (1. - t) + to_key.translation * t,
+            // Cubic Hermite basis, the tangents being speeds scaled to the duration of the segment
+            Interpolation::CatmullRom => {
+                let (t2, t3) = (t * t, t * t * t);
+                from_key.translation * (2. * t3 - 3. * t2 + 1.)
+                    + self.speed(from) * (duration * (t3 - 2. * t2 + t))
+                    + to_key.translation * (-2. * t3 + 3. * t2)
+                    + self.speed(to) * (duration * (t3 - t2))
+            }
+        };
+        (
+            translation,
+            from_key.rotation.slerp(to_key.rotation, t),
+            from_key.scale.powf(1. - t) * to_key.scale.powf(t),
+        )
+    }
+
+    /// Computes the speed of the translation at a key of the Catmull-Rom spline: the average speed between its neighbours
+    /// The first and the last keys take the speed towards their only neighbour.
+    fn speed(&self, index: usize) -> Vector {
+        let previous = self.keys[index.saturating_sub(1)];
+        let next = self.keys[(index + 1).min(self.keys.len() - 1)];
+        match next.time > previous.time {
+            true => (next.translation - previous.translation) / (next.time - previous.time),
+            false => Vector::new_eq(0.),
+        }
+    }
+}
+
+/// # EmissionRamp
+///
+/// Describes how the emission of a light or an emissive object evolves during an Animation
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum EmissionRamp {
+    /// The emitted color goes from the first to the second Color
+    Color(Color, Color),
+    /// The emitted color follows a black body, from the first to the second temperature (in Kelvin)
+    Temperature(f64, f64),
+    /// The emitted intensity is multiplied by a factor going from the first to the second value
+    Intensity(f64, f64),
+}
+
+impl EmissionRamp {
+    /// Applies the ramp at a given progress to an emitted color and intensity
+    pub fn apply(self, color: Color, intensity: f64, progress: f64) -> (Color, f64) {
+        let lerp = |from: f64, to: f64| from + (to - from) * progress;
+
+        match self {
+            EmissionRamp::Color(from, to) => (from * (1. - progress) + to * progress, intensity),
+            EmissionRamp::Temperature(from, to) => {
+                (Color::from_temperature(lerp(from, to)), intensity)
+            }
+            EmissionRamp::Intensity(from, to) => (color, intensity * lerp(from, to)),
+        }
+    }
+}
+
+/// Computes the emitted color and intensity at a given time, applying the EmissionRamps of the animations
+/// Before its start, a ramp holds its first value.
+pub fn animate_emission(
+    color: Color,
+    intensity: f64,
+    animations: &[Animation],
+    time: f64,
+) -> (Color, f64) {
+    let mut cur = (color, intensity);
+
+    for a in animations {
+        if let Some(emission) = a.emission {
+            let progress = a.progress(time).unwrap_or(0.);
+            cur = emission.apply(cur.0, cur.1, progress);
+        }
+    }
+    cur
+}
+
+/// Computes the Transform moving a component from its resting place to where its animations put it at a given time
+/// The steps of Ray::apply_animations are folded into a single Matrix4, and their inverses, in the reverse order, into its inverse.
+pub fn animate_transform(animations: &[Animation], time: f64) -> Transform {
+    // A step around a center: the center is moved to the origin, the step applied, and the center moved back
+    let around = |center: Vector, step: Matrix4| {
+        Matrix4::translation(center) * step * Matrix4::translation(center * (-1.))
+    };
+    let rotation = |center: Vector, rotation: Quaternion| {
+        (
+            around(center, Matrix4::rotation(rotation)),
+            around(center, Matrix4::rotation(rotation.conjugate())),
+        )
+    };
+    let scaling = |center: Vector, scale: f64| {
+        (
+            around(center, Matrix4::scaling(Vector::new_eq(scale))),
+            around(center, Matrix4::scaling(Vector::new_eq(1. / scale))),
+        )
+    };
+    let translation = |translation: Vector| {
+        (
+            Matrix4::translation(translation),
+            Matrix4::translation(translation * (-1.)),
+        )
+    };
+
+    let mut steps = Vec::new();
+    for a in animations {
+        if let Some(progress) = a.progress(time) {
+            let axis = |x, y, z, theta| Quaternion::from_axis_angle(Vector::new(x, y, z), theta);
+            steps.extend([
+                scaling(a.scale_center, a.scale_at(progress)),
+                translation(a.translation * progress),
+                rotation(
+                    a.rotation_center_x,
+                    axis(1., 0., 0., a.rotation_x * progress),
+                ),
+                rotation(
+                    a.rotation_center_y,
+                    axis(0., 1., 0., a.rotation_y * progress),
+                ),
+                rotation(
+                    a.rotation_center_z,
+                    axis(0., 0., 1., a.rotation_z * progress),
+                ),
+                rotation(a.rotation_center, a.rotation_at(progress)),
+            ]);
+        }
+        if let Some(keyframes) = &a.keyframes {
+            let (position, orientation, scale) = keyframes.pose_at(time);
+            steps.extend([
+                scaling(keyframes.pivot, scale),
+                rotation(keyframes.pivot, orientation),
+                translation(position),
+            ]);
+        }
+    }
+
+    steps.into_iter().fold(
+        Transform {
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        },
+        |transform, (step, step_inverse)| Transform {
+            matrix: step * transform.matrix,
+            inverse: transform.inverse * step_inverse,
+        },
+    )
+}
+
+/// # LensRamp
+///
+/// Describes how the lens of a Camera evolves during an Animation, for zooms (or dolly zooms, combined with a translation) and rack focus effects
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum LensRamp {
+    /// The vertical field of view goes from the first to the second value (in degrees)
+    Fov(f64, f64),
+    /// The focal distance goes from the first to the second value
+    Focal(f64, f64),
+}
+
+impl LensRamp {
+    /// Applies the ramp at a given progress to a field of view and a focal distance
+    pub fn apply(self, fov_degrees: f64, focal: f64, progress: f64) -> (f64, f64) {
+        let lerp = |from: f64, to: f64| from + (to - from) * progress;
+
+        match self {
+            LensRamp::Fov(from, to) => (lerp(from, to), focal),
+            LensRamp::Focal(from, to) => (fov_degrees, lerp(from, to)),
+        }
+    }
+}
+
+/// Computes the field of view and the focal distance at a given time, applying the LensRamps of the animations
+/// Before its start, a ramp holds its first value.
+pub fn animate_lens(
+    fov_degrees: f64,
+    focal: f64,
+    animations: &[Animation],
+    time: f64,
+) -> (f64, f64) {
+    let mut cur = (fov_degrees, focal);
+
+    for a in animations {
+        if let Some(lens) = a.lens {
+            let progress = a.progress(time).unwrap_or(0.);
+            cur = lens.apply(cur.0, cur.1, progress);
         }
     }
+    cur
 }
 
 /// # Animatable
@@ -140,3 +541,210 @@ pub trait Animatable {
     fn add_animation(&mut self, animation: Animation);
     fn get_animations(&self) -> Vec<Animation>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+    use crate::ray::Ray;
+
+    #[test]
+    fn temperature_ramp_gets_warmer() {
+        let mut light = Light::new(Vector::new(0., 10., 0.), Vector::new_eq(1000.));
+        light.add_animation(Animation::emission(
+            0.,
+            10.,
+            EmissionRamp::Temperature(6500., 2000.),
+        ));
+
+        let warmth = |time: f64| {
+            let intensity = light.get_intensity_local(
+                Vector::new_eq(0.),
+                Vector::new(0., 1., 0.),
+                Color::white(),
+                time,
+            );
+            intensity.x / intensity.z
+        };
+
+        assert!(warmth(0.) < warmth(5.));
+        assert!(warmth(5.) < warmth(10.));
+    }
+
+    #[test]
+    fn keyframes_interpolate_between_bracketing_keys() {
+        let pivot = Vector::new(1., 0., 0.);
+        let mut keyframes = Keyframes::new(pivot);
+        // Added out of order
+        keyframes.add_key(
+            3.,
+            Vector::new(10., 0., 10.),
+            Quaternion::from_axis_angle(Vector::new(0., 1., 0.), 90.),
+            4.,
+        );
+        keyframes.add_key(0., Vector::new_eq(0.), Quaternion::identity(), 1.);
+        keyframes.add_key(1., Vector::new(10., 0., 0.), Quaternion::identity(), 1.);
+        assert_eq!(
+            keyframes
+                .keys()
+                .iter()
+                .map(|key| key.time)
+                .collect::<Vec<f64>>(),
+            vec![0., 1., 3.]
+        );
+
+        let animations = vec![Animation::keyframes(keyframes)];
+        let point = |time: f64| {
+            Ray::new(Vector::new(2., 0., 0.), Vector::new(1., 0., 0.))
+                .apply_animations(animations.clone(), time)
+        };
+        let close = |a: Vector, b: Vector| (a - b).norm() < 1e-9;
+
+        // The endpoints are held outside of the keys
+        assert!(close(point(-5.).origin, Vector::new(2., 0., 0.)));
+        assert!(close(point(0.5).origin, Vector::new(7., 0., 0.)));
+        assert!(close(point(1.).origin, Vector::new(12., 0., 0.)));
+
+        // Halfway between the last two keys: an eighth of a turn, twice as large
+        let halfway = point(2.);
+        let eighth = Vector::new(1., 0., -1.).normalize();
+        assert!(close(
+            halfway.origin,
+            pivot + eighth * 2. + Vector::new(10., 0., 5.)
+        ));
+        assert!(close(halfway.direction, eighth));
+        assert!(close(point(10.).origin, point(3.).origin));
+        assert!(close(point(3.).origin, Vector::new(11., 0., 6.)));
+
+        // The motion is undone exactly
+        for time in [-1., 0.5, 2., 2.7, 8.] {
+            let back = point(time).reverse_animations(animations.clone(), time);
+            assert!(close(back.origin, Vector::new(2., 0., 0.)));
+            assert!(close(back.direction, Vector::new(1., 0., 0.)));
+        }
+    }
+
+    #[test]
+    fn catmull_rom_path_is_smooth_through_its_keys() {
+        let mut keyframes = Keyframes::new(Vector::new_eq(0.));
+        let keys = [
+            (0., Vector::new(0., 0., 0.)),
+            (1., Vector::new(10., 0., 0.)),
+            (3., Vector::new(10., 5., 10.)),
+            (4., Vector::new(0., 5., 10.)),
+        ];
+        for (time, translation) in keys {
+            keyframes.add_key(time, translation, Quaternion::identity(), 1.);
+        }
+        let position = |keyframes: &Keyframes, time: f64| keyframes.pose_at(time).0;
+        // Speeds just before and just after a time
+        let speeds = |keyframes: &Keyframes, time: f64| {
+            let h = 1e-6;
+            (
+                (position(keyframes, time) - position(keyframes, time - h)) / h,
+                (position(keyframes, time + h) - position(keyframes, time)) / h,
+            )
+        };
+
+        // With a linear interpolation, the speed jumps at the joints
+        let (before, after) = speeds(&keyframes, 1.);
+        assert!((before - after).norm() > 1.);
+
+        keyframes.set_interpolation(Interpolation::CatmullRom);
+        for (time, translation) in keys {
+            assert!((position(&keyframes, time) - translation).norm() < 1e-9);
+        }
+        for time in [1., 3.] {
+            let (before, after) = speeds(&keyframes, time);
+            assert!((before - after).norm() < 1e-3);
+        }
+        // The speed at a key is the average speed between its neighbours, and the ends hold still
+        let (_, at_second_key) = speeds(&keyframes, 1.);
+        assert!((at_second_key - Vector::new(10., 5., 10.) / 3.).norm() < 1e-3);
+        assert!((position(&keyframes, 6.) - Vector::new(0., 5., 10.)).norm() < 1e-9);
+    }
+
+    #[test]
+    fn axis_rotation_turns_around_the_diagonal() {
+        let axis = Vector::new(1., 1., 0.);
+        let ray = Ray::new(Vector::new(1., 0., 0.), Vector::new(1., 0., 0.));
+        let animation = Animation::rotation_axis(0., 10., axis, 90., Vector::new_eq(0.));
+
+        // A quarter turn around (1, 1, 0) / sqrt(2) sends x to (1 / 2, 1 / 2, -1 / sqrt(2))
+        let expected = Vector::new(0.5, 0.5, -(0.5_f64.sqrt()));
+        let rotated = ray.apply_animations(vec![animation.clone()], 10.);
+        assert_approx_eq::assert_approx_eq!((rotated.origin - expected).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!((rotated.direction - expected).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!(
+            (Vector::new(1., 0., 0.).rotate_axis(axis, 90.) - expected).norm(),
+            0.
+        );
+
+        // Halfway, the rotation stays around the same axis
+        let halfway = ray.apply_animations(vec![animation.clone()], 5.);
+        assert_approx_eq::assert_approx_eq!(
+            (halfway.origin - Vector::new(1., 0., 0.).rotate_axis(axis, 45.)).norm(),
+            0.
+        );
+        let back = halfway.reverse_animations(vec![animation], 5.);
+        assert_approx_eq::assert_approx_eq!((back.origin - ray.origin).norm(), 0.);
+
+        // Composing rotations around x and y does not give the same result
+        let euler = Animation {
+            rotation_x: 90.,
+            rotation_y: 90.,
+            ..Animation::translation(0., 10., Vector::new_eq(0.))
+        };
+        let rotated_euler = ray.apply_animations(vec![euler], 10.);
+        assert!((rotated_euler.origin - expected).norm() > 0.1);
+    }
+
+    #[test]
+    fn animate_transform_moves_like_the_animations() {
+        let mut keyframes = Keyframes::new(Vector::new(1., 2., 0.));
+        keyframes.add_key(0., Vector::new_eq(0.), Quaternion::identity(), 1.);
+        keyframes.add_key(
+            4.,
+            Vector::new(3., -1., 2.),
+            Quaternion::from_axis_angle(Vector::new(0., 0., 1.), 60.),
+            2.,
+        );
+        let animations = vec![
+            Animation {
+                translation: Vector::new(5., 0., -3.),
+                rotation_x: 30.,
+                rotation_center_x: Vector::new(0., 1., 0.),
+                rotation_y: -45.,
+                rotation_center_y: Vector::new(2., 0., 0.),
+                rotation_z: 80.,
+                rotation_center_z: Vector::new(0., 0., 3.),
+                ..Animation::scale_around(0., 4., 3., Vector::new(1., 1., 1.))
+            },
+            Animation::rotation_axis(
+                1.,
+                3.,
+                Vector::new(1., 1., 0.),
+                90.,
+                Vector::new(0., 2., 1.),
+            ),
+            Animation::keyframes(keyframes),
+        ];
+        let ray = Ray::new(Vector::new(2., -1., 4.), Vector::new(0.5, 1., -2.));
+
+        for time in [0., 1.5, 3., 10.] {
+            let transform = animate_transform(&animations, time);
+            let moved = ray.apply_animations(animations.clone(), time);
+            assert!((transform.to_world_point(ray.origin) - moved.origin).norm() < 1e-9);
+            // The animations scale the positions but not the directions
+            assert!(
+                (transform.to_world_vector(ray.direction).normalize()
+                    - moved.direction.normalize())
+                .norm()
+                    < 1e-9
+            );
+
+            let back = ray.reverse_animations(animations.clone(), time);
+            assert!((transform.to_local_point(ray.origin) - back.origin).norm() < 1e-9);
+        }
+    }
+}
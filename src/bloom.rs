@@ -0,0 +1,113 @@
+//! # Bloom
+//!
+//! This module adds a glow around the highlights of a frame, like the light scattered in the lens of a real camera.
+//! The radiance of the pixels brighter than a threshold is blurred with a Gaussian, and added back to the frame before the gamma correction.
+
+use crate::utils::Vector;
+
+/// Relative brightness of a radiance, as perceived by the eye (Rec. 709 weights)
+fn luminance(radiance: Vector) -> f64 {
+    0.2126 * radiance.x + 0.7152 * radiance.y + 0.0722 * radiance.z
+}
+
+/// Computes the weights of a Gaussian blur fading out at radius pixels from the center, from the center outwards
+/// The standard deviation is a third of the radius, and the weights of the whole kernel (both sides) sum to 1.
+fn gaussian_kernel(radius: f64) -> Vec<f64> {
+    let sigma = (radius / 3.).max(1e-6);
+    let weights: Vec<f64> = (0..=radius.ceil() as usize)
+        .map(|offset| (-(offset as f64).powi(2) / (2. * sigma * sigma)).exp())
+        .collect();
+    let sum = weights[0] + 2. * weights[1..].iter().sum::<f64>();
+
+    weights.iter().map(|weight| weight / sum).collect()
+}
+
+/// Blurs a buffer along one direction: step is the distance in the buffer between two neighbours (1 for the rows, width for the columns)
+/// The pixels beyond the edges of the image count as black, so that the halo of a highlight fades out of the frame.
+fn blur_along(
+    buffer: &[Vector],
+    kernel: &[f64],
+    length: usize,
+    lines: usize,
+    step: usize,
+    line_step: usize,
+) -> Vec<Vector> {
+    let mut result = vec![Vector::new_eq(0.); buffer.len()];
+    for line in 0..lines {
+        for position in 0..length {
+            let mut sum = buffer[line * line_step + position * step] * kernel[0];
+            for (offset, weight) in kernel.iter().enumerate().skip(1) {
+                if position >= offset {
+                    sum += buffer[line * line_step + (position - offset) * step] * *weight;
+                }
+                if position + offset < length {
+                    sum += buffer[line * line_step + (position + offset) * step] * *weight;
+                }
+            }
+            result[line * line_step + position * step] = sum;
+        }
+    }
+    result
+}
+
+/// Adds the bloom of the pixels whose luminance is above threshold to a width x height buffer of radiance
+/// Each pixel contributes the part of its radiance above the threshold, blurred over radius pixels (separably, along the rows then the columns)
+/// and scaled by intensity: an intensity of 1. spreads all of it around the pixel.
+pub fn add_bloom(
+    radiance: &[Vector],
+    width: usize,
+    height: usize,
+    threshold: f64,
+    radius: f64,
+    intensity: f64,
+) -> Vec<Vector> {
+    let bright: Vec<Vector> = radiance
+        .iter()
+        .map(|pixel| {
+            let luminance = luminance(*pixel);
+            match luminance > threshold {
+                true => *pixel * ((luminance - threshold) / luminance),
+                false => Vector::new_eq(0.),
+            }
+        })
+        .collect();
+
+    let kernel = gaussian_kernel(radius);
+    let blurred = blur_along(&bright, &kernel, width, height, 1, width);
+    let blurred = blur_along(&blurred, &kernel, height, width, width, 1);
+
+    radiance
+        .iter()
+        .zip(blurred)
+        .map(|(pixel, glow)| *pixel + glow * intensity)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bright_pixel_gets_a_soft_halo() {
+        let (width, height) = (21, 15);
+        let mut radiance = vec![Vector::new_eq(1.); width * height];
+        radiance[7 * width + 10] = Vector::new_eq(101.);
+
+        let bloomed = add_bloom(&radiance, width, height, 10., 4., 1.);
+        let glow = |i: usize, j: usize| bloomed[i * width + j].x - radiance[i * width + j].x;
+
+        // The halo fades smoothly away from the highlight, and stops at the radius
+        let profile: Vec<f64> = (10..16).map(|j| glow(7, j)).collect();
+        assert!(profile.windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(profile[4] > 0.);
+        assert_eq!(profile[5], 0.);
+        assert_approx_eq::assert_approx_eq!(glow(7, 12), glow(9, 10));
+
+        // The radiance above the threshold is spread around, and the dim pixels do not glow by themselves
+        let total: f64 = (0..width * height)
+            .map(|index| bloomed[index].x - radiance[index].x)
+            .sum();
+        assert_approx_eq::assert_approx_eq!(total, 91.);
+        assert_eq!(glow(0, 0), 0.);
+    }
+}
@@ -1,18 +1,54 @@
-use crate::animate::{Animatable, Animation};
-use crate::utils::Vector;
+use crate::animate::{animate_lens, Animatable, Animation};
+use crate::scene::Scene;
+use crate::utils::{Matrix4, Quaternion, Vector};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 
+/// # Projection
+///
+/// How the Camera maps the pixels of the image to directions
+#[derive(Copy, Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Projection {
+    /// A pinhole camera with a vertical field of view of fov_degrees
+    #[default]
+    Perspective,
+    /// A 360° panorama: the longitude goes from -180° to 180° across the width, and the latitude from 90° to -90° down the height
+    /// The field of view is ignored, and an image twice as wide as high has square pixels and wraps around seamlessly.
+    Equirectangular,
+    /// An equidistant fisheye lens: the angle from the direction grows linearly with the distance from the center of the image,
+    /// up to fov_degrees / 2 (at most 90°) on the image circle inscribed in the image. The pixels outside the image circle only see the background.
+    Fisheye,
+}
+
 /// # Camera
 ///
 /// A Camera has a given position and direction, as well as more information regarding how the image will be rendered.
+///
+/// When depth of field is enabled (see Config::dof), the rays start from a random point of a square lens of side aperture,
+/// and converge on the focal plane, at distance focal from the center of the camera.
+/// Objects on the focal plane stay sharp, and the blur of the other objects grows with the aperture and their distance to the focal plane.
+/// An aperture of 0. gives a pinhole camera, where everything is sharp.
+///
+/// Like its position, the field of view and the focal distance of the Camera can be animated (see LensRamp and at_time).
+///
+/// With depth of field, the lens can also bend the color channels differently (see chromatic_aberration), which fringes the edges of the blurred objects.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub center: Vector,
     pub direction: Vector,
     pub up: Vector,
     pub fov_degrees: f64,
     pub focal: f64,
+    pub aperture: f64,
+    /// Relative difference of focal distance between the color channels: red converges at focal * (1 - chromatic_aberration) and blue at focal * (1 + chromatic_aberration)
+    /// 0. (the default) disables it. Otherwise each sample traces one Ray per color channel through the same point of the lens.
+    #[serde(default)]
+    pub chromatic_aberration: f64,
+    #[serde(default)]
+    pub projection: Projection,
     pub height: usize,
     pub width: usize,
+    inverse_view_projection: Option<Matrix4>,
     animations: Vec<Animation>,
 }
 
@@ -32,11 +68,119 @@ impl Camera {
             up,
             fov_degrees,
             focal,
+            aperture: 5.,
+            chromatic_aberration: 0.,
+            projection: Projection::Perspective,
+            height,
+            width,
+            inverse_view_projection: None,
+            animations: Vec::new(),
+        }
+    }
+
+    /// Builds a Camera placed at eye and aiming at target.
+    /// The up Vector is computed from world_up so that it is orthogonal to the direction (Gram-Schmidt).
+    ///
+    /// Panics if eye and target are the same point, or if world_up is parallel to the viewing direction.
+    pub fn look_at(
+        eye: Vector,
+        target: Vector,
+        world_up: Vector,
+        fov_degrees: f64,
+        focal: f64,
+        height: usize,
+        width: usize,
+    ) -> Self {
+        assert!(
+            (target - eye).norm_sq() > 0.,
+            "Camera::look_at: eye and target must be different points"
+        );
+        let direction = (target - eye).normalize();
+
+        let up = world_up - direction * world_up.dot(direction);
+        assert!(
+            up.norm_sq() > 0.,
+            "Camera::look_at: world_up must not be parallel to the viewing direction"
+        );
+        let up = up.normalize();
+
+        Camera::new(eye, direction, up, fov_degrees, focal, height, width)
+    }
+
+    /// Builds a Camera from the view (world to camera) and perspective projection matrices of an external engine
+    /// Rays are built by unprojecting the pixel coordinates through the inverse matrices, so they match the engine exactly.
+    /// The center, direction, up and fov_degrees fields are extracted from the matrices for the other features (animations, depth of field).
+    ///
+    /// Panics if one of the matrices is not invertible.
+    pub fn from_matrices(view: Matrix4, projection: Matrix4, height: usize, width: usize) -> Self {
+        let inverse_view = view
+            .inverse()
+            .expect("Camera::from_matrices: singular view matrix");
+        let inverse_view_projection = (projection * view)
+            .inverse()
+            .expect("Camera::from_matrices: singular projection matrix");
+
+        let center = inverse_view.transform_point(Vector::new_eq(0.));
+        let direction = inverse_view
+            .transform_vector(Vector::new(0., 0., -1.))
+            .normalize();
+        let up = inverse_view
+            .transform_vector(Vector::new(0., 1., 0.))
+            .normalize();
+        let fov_degrees = 2. * (1. / projection.m[1][1]).atan() * 180. / PI;
+
+        Camera {
+            center,
+            direction,
+            up,
+            fov_degrees,
+            focal: 35.,
+            aperture: 5.,
+            chromatic_aberration: 0.,
+            projection: Projection::Perspective,
             height,
             width,
+            inverse_view_projection: Some(inverse_view_projection),
             animations: Vec::new(),
         }
     }
+
+    /// Builds a Camera looking along direction at the center of the Scene, just far enough for the Scene to fit the image
+    /// The Scene is enclosed in the sphere around its bounding box (see Scene::bounding_box), which must fit the narrowest of the vertical
+    /// and horizontal fields of view: at a distance radius / sin(half angle) from its center, the sphere touches the edges of that field of view.
+    /// The focal distance is set to that distance, so that depth of field keeps the center of the Scene sharp.
+    ///
+    /// Returns None if the Scene has no object with finite bounds.
+    /// Panics if direction is zero, or if up is parallel to it (see look_at).
+    pub fn frame_scene(
+        scene: &Scene,
+        direction: Vector,
+        up: Vector,
+        fov_degrees: f64,
+        height: usize,
+        width: usize,
+    ) -> Option<Self> {
+        let (min, max) = scene.bounding_box()?;
+        let target = (min + max) / 2.;
+        let radius = (max - min).norm() / 2.;
+
+        let vertical = (fov_degrees * PI / 180. / 2.).tan();
+        let horizontal = vertical * width as f64 / height as f64;
+        let half_angle = vertical.min(horizontal).atan();
+        let distance = radius / half_angle.sin();
+
+        let eye = target - direction.normalize() * distance;
+        Some(Camera::look_at(
+            eye,
+            target,
+            up,
+            fov_degrees,
+            distance,
+            height,
+            width,
+        ))
+    }
+
     pub fn new_default() -> Camera {
         Camera {
             center: Vector::new(0., 0., 0.),
@@ -44,15 +188,157 @@ impl Camera {
             up: Vector::new(0., 0., 0.),
             fov_degrees: 10.,
             focal: 10.,
+            aperture: 5.,
+            chromatic_aberration: 0.,
+            projection: Projection::Perspective,
             height: 500,
             width: 500,
+            inverse_view_projection: None,
             animations: Vec::new(),
         }
     }
 
+    /// Places the Camera on a circle of radius around center, in the plane orthogonal to axis, and animates it around the circle
+    /// from start_angle to end_angle (in degrees, counterclockwise around axis) between start_time and end_time, always looking at center (a turntable).
+    /// The angles are measured from the side of center where the Camera stands, and its up Vector becomes the axis.
+    /// The rotation is split into animations of at most 90° each (see Animation::rotation_axis), so that it may go around several times.
+    ///
+    /// Panics if axis is zero.
+    #[allow(clippy::too_many_arguments)]
+    pub fn orbit(
+        &mut self,
+        center: Vector,
+        radius: f64,
+        axis: Vector,
+        start_angle: f64,
+        end_angle: f64,
+        start_time: f64,
+        end_time: f64,
+    ) {
+        assert!(axis.norm_sq() > 0., "Camera::orbit: axis must not be zero");
+        let axis = axis.normalize();
+
+        // A Camera standing on the axis starts from an arbitrary side
+        let side = self.center - center;
+        let side = side - axis * side.dot(axis);
+        let side = match side.norm_sq() > 1e-12 {
+            true => side.normalize(),
+            false => axis.orthonormal_basis().0,
+        };
+
+        let start = Quaternion::from_axis_angle(axis, start_angle).rotate(side);
+        self.center = center + start * radius;
+        self.direction = start * (-1.);
+        self.up = axis;
+
+        let sweep = end_angle - start_angle;
+        let nb_steps = ((sweep.abs() / 90.).ceil() as usize).max(1);
+        for step in 0..nb_steps {
+            let time =
+                |step: usize| start_time + (end_time - start_time) * step as f64 / nb_steps as f64;
+            self.add_animation(Animation::rotation_axis(
+                time(step),
+                time(step + 1),
+                axis,
+                sweep / nb_steps as f64,
+                center,
+            ));
+        }
+    }
+
+    /// Returns the Camera with its field of view and focal distance animated to a given time (see LensRamp)
+    /// The position and orientation are not changed: they are animated on each Ray (see Ray::apply_animations).
+    /// A Camera built from matrices keeps the field of view of its projection matrix.
+    pub fn at_time(&self, time: f64) -> Camera {
+        let (fov_degrees, focal) =
+            animate_lens(self.fov_degrees, self.focal, &self.animations, time);
+
+        Camera {
+            fov_degrees,
+            focal,
+            ..self.clone()
+        }
+    }
+
+    /// Distance (in pixels) between the camera center and the image plane
+    /// fov_degrees is the vertical field of view: the horizontal one follows from the aspect ratio, with square pixels.
     pub fn depth(&self) -> f64 {
         self.height as f64 / (2. * (self.fov_degrees * PI / 180.0 / 2.).tan())
     }
+
+    /// Computes the focal distance of a color channel (0 for red, 1 for green and 2 for blue), see chromatic_aberration
+    pub fn channel_focal(&self, channel: usize) -> f64 {
+        self.focal * (1. + self.chromatic_aberration * (channel as f64 - 1.))
+    }
+
+    /// Computes the right and up Vectors of the image plane, as an orthonormal basis with the direction
+    pub fn basis(&self) -> (Vector, Vector) {
+        let direction = self.direction.normalize();
+        let right = direction.cross(self.up).normalize();
+        let up = right.cross(direction);
+
+        (right, up)
+    }
+
+    /// Computes the direction going through the point (i, j) of the image plane, in pixel units
+    /// i goes down the rows and j goes right along the columns: pixel (i, j) covers [i, i+1] x [j, j+1].
+    /// The mapping follows the Projection of the Camera, except for a Camera built from matrices which always follows its matrices.
+    pub fn pixel_direction(&self, i: f64, j: f64) -> Vector {
+        if let Some(inverse_view_projection) = self.inverse_view_projection {
+            let x = 2. * j / self.width as f64 - 1.;
+            let y = 1. - 2. * i / self.height as f64;
+
+            let near = inverse_view_projection.transform_point(Vector::new(x, y, -1.));
+            let far = inverse_view_projection.transform_point(Vector::new(x, y, 1.));
+
+            return far - near;
+        }
+
+        let (right, up) = self.basis();
+        let direction = self.direction.normalize();
+
+        match self.projection {
+            Projection::Perspective => {
+                right * (j - self.width as f64 / 2.)
+                    + up * (self.height as f64 / 2. - i)
+                    + direction * self.depth()
+            }
+            Projection::Equirectangular => {
+                let longitude = (j / self.width as f64 - 0.5) * 2. * PI;
+                let latitude = (0.5 - i / self.height as f64) * PI;
+
+                (direction * longitude.cos() + right * longitude.sin()) * latitude.cos()
+                    + up * latitude.sin()
+            }
+            Projection::Fisheye => {
+                let x = j - self.width as f64 / 2.;
+                let y = self.height as f64 / 2. - i;
+                let angle = (x * x + y * y).sqrt() / self.image_circle_radius()
+                    * self.fov_degrees.min(180.).to_radians()
+                    / 2.;
+                let around = y.atan2(x);
+
+                direction * angle.cos() + (right * around.cos() + up * around.sin()) * angle.sin()
+            }
+        }
+    }
+
+    /// Radius (in pixels) of the circle inscribed in the image, where a fisheye lens forms its image
+    fn image_circle_radius(&self) -> f64 {
+        self.width.min(self.height) as f64 / 2.
+    }
+
+    /// Tells if the point (i, j) of the image plane, in pixel units, is lit by the lens: always true, except outside the image circle of a fisheye lens
+    pub fn in_image_circle(&self, i: f64, j: f64) -> bool {
+        match self.projection {
+            Projection::Fisheye => {
+                let x = j - self.width as f64 / 2.;
+                let y = self.height as f64 / 2. - i;
+                x * x + y * y <= self.image_circle_radius().powi(2)
+            }
+            _ => true,
+        }
+    }
 }
 
 impl Animatable for Camera {
@@ -64,3 +350,369 @@ impl Animatable for Camera {
         self.animations.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animate::LensRamp;
+    use crate::object::sphere::Sphere;
+    use crate::ray::Ray;
+    use crate::testing::example_camera;
+    use crate::utils::{Color, Config, Material, RenderMode};
+
+    #[test]
+    fn look_at_builds_orthonormal_basis() {
+        let camera = Camera::look_at(
+            Vector::new(10., 5., 10.),
+            Vector::new(0., 0., 0.),
+            Vector::new(0., 1., 0.),
+            60.,
+            35.,
+            100,
+            100,
+        );
+
+        assert_approx_eq::assert_approx_eq!(camera.direction.norm(), 1.);
+        assert_approx_eq::assert_approx_eq!(camera.up.norm(), 1.);
+        assert_approx_eq::assert_approx_eq!(camera.direction.dot(camera.up), 0.);
+        assert!(camera.up.y > 0.);
+    }
+
+    #[test]
+    fn framed_scene_projects_inside_the_image() {
+        let mut scene = Scene::new();
+        assert!(Camera::frame_scene(
+            &scene,
+            Vector::new(0., 0., -1.),
+            Vector::new(0., 1., 0.),
+            60.,
+            100,
+            100
+        )
+        .is_none());
+
+        for (center, radius) in [
+            (Vector::new(-4., 0., 2.), 1.),
+            (Vector::new(3., 2., -5.), 2.),
+        ] {
+            scene.add_object(Box::new(Sphere::new(
+                center,
+                radius,
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        let (min, max) = scene
+            .bounding_box()
+            .expect("the spheres have finite bounds");
+
+        for (height, width) in [(100, 200), (200, 100)] {
+            let camera = Camera::frame_scene(
+                &scene,
+                Vector::new(1., -1., -2.),
+                Vector::new(0., 1., 0.),
+                40.,
+                height,
+                width,
+            )
+            .expect("the Scene has finite objects");
+            let (right, up) = camera.basis();
+
+            let mut widest: f64 = 0.;
+            for corner in 0..8 {
+                let point = Vector::new(
+                    [min.x, max.x][corner & 1],
+                    [min.y, max.y][(corner >> 1) & 1],
+                    [min.z, max.z][(corner >> 2) & 1],
+                );
+                let offset = point - camera.center;
+                let forward = offset.dot(camera.direction);
+                assert!(forward > 0.);
+
+                let i = height as f64 / 2. - offset.dot(up) * camera.depth() / forward;
+                let j = width as f64 / 2. + offset.dot(right) * camera.depth() / forward;
+                assert!((0. ..=height as f64).contains(&i));
+                assert!((0. ..=width as f64).contains(&j));
+                widest = widest.max(
+                    ((i / height as f64 - 0.5).abs() * 2.).max((j / width as f64 - 0.5).abs() * 2.),
+                );
+            }
+
+            // The Scene is not lost in the middle of the image
+            assert!(widest > 0.5);
+        }
+    }
+
+    #[test]
+    fn orbiting_camera_keeps_its_distance() {
+        let center = Vector::new(1., 2., 3.);
+        let mut camera = Camera::new(
+            Vector::new(1., 10., 30.),
+            Vector::new(0., 0., -1.),
+            Vector::new(0., 1., 0.),
+            60.,
+            35.,
+            100,
+            100,
+        );
+        camera.orbit(center, 20., Vector::new(0., 1., 0.), 90., 450., 0., 10.);
+
+        // The Camera starts a quarter turn away from its side (+z), counterclockwise around y
+        assert_approx_eq::assert_approx_eq!((camera.center - Vector::new(21., 2., 3.)).norm(), 0.);
+
+        let positions: Vec<Vector> = (0..=20)
+            .map(|k| {
+                let ray = Ray::new(camera.center, camera.direction)
+                    .apply_animations(camera.get_animations(), k as f64 / 2.);
+                assert_approx_eq::assert_approx_eq!((ray.origin - center).norm(), 20.);
+                assert_approx_eq::assert_approx_eq!(ray.origin.y, 2.);
+                // The camera still looks at the center
+                assert_approx_eq::assert_approx_eq!(
+                    (ray.origin + ray.direction * 20. - center).norm(),
+                    0.
+                );
+                ray.origin
+            })
+            .collect();
+
+        // A full turn in even steps, back to the start
+        assert_approx_eq::assert_approx_eq!((positions[0] - positions[20]).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!((positions[5] - Vector::new(1., 2., -17.)).norm(), 0.);
+        let steps: Vec<f64> = positions
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).norm())
+            .collect();
+        assert!(steps.iter().all(|step| (step - steps[0]).abs() < 1e-6));
+    }
+
+    #[test]
+    fn pixels_are_square() {
+        let camera = Camera::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 0., -1.),
+            Vector::new(0., 1., 0.),
+            60.,
+            35.,
+            400,
+            800,
+        );
+
+        let angle = |a: Vector, b: Vector| a.normalize().dot(b.normalize()).acos();
+        let center = camera.pixel_direction(200., 400.);
+        let horizontal = angle(center, camera.pixel_direction(200., 401.));
+        let vertical = angle(center, camera.pixel_direction(201., 400.));
+
+        assert_approx_eq::assert_approx_eq!(horizontal, vertical);
+    }
+
+    #[test]
+    fn matrices_match_fov_camera() {
+        let eye = Vector::new(5., 3., 40.);
+        let target = Vector::new(0., 1., 0.);
+        let up = Vector::new(0., 1., 0.);
+        let (height, width) = (60, 90);
+
+        let camera = Camera::look_at(eye, target, up, 50., 35., height, width);
+        let camera_matrices = Camera::from_matrices(
+            Matrix4::look_at(eye, target, up),
+            Matrix4::perspective(50., width as f64 / height as f64, 0.1, 1000.),
+            height,
+            width,
+        );
+
+        for (i, j) in [(0, 0), (12, 70), (30, 45), (59, 89)] {
+            let ray = Ray::new_basic_ray(i, j, &camera);
+            let ray_matrices = Ray::new_basic_ray(i, j, &camera_matrices);
+
+            assert_approx_eq::assert_approx_eq!((ray.origin - ray_matrices.origin).norm(), 0.);
+            assert_approx_eq::assert_approx_eq!(
+                (ray.direction - ray_matrices.direction).norm(),
+                0.
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn look_at_same_point_panics() {
+        Camera::look_at(
+            Vector::new_eq(1.),
+            Vector::new_eq(1.),
+            Vector::new(0., 1., 0.),
+            60.,
+            35.,
+            100,
+            100,
+        );
+    }
+
+    #[test]
+    fn fov_animation_zooms_in() {
+        // The camera and the sphere move together, so the sphere stays at the center of the image while the camera zooms in
+        let mut sphere = Sphere::new(
+            Vector::new_eq(0.),
+            6.,
+            Material::create_diffuse(Color::white()),
+        );
+        sphere.add_animation(Animation::translation(0., 1., Vector::new(20., 0., 0.)));
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(sphere));
+
+        let mut config = Config::new(21, 21, 1., false, 0, 1, false, false, 0., 1., 2);
+        config.render_mode = RenderMode::Normals;
+        let mut camera = example_camera(&config);
+        camera.add_animation(Animation::translation(0., 1., Vector::new(20., 0., 0.)));
+        camera.add_animation(Animation::lens(0., 1., LensRamp::Fov(60., 20.)));
+
+        let coverage = |k: usize| {
+            let radiance = crate::render_radiance(&camera, &scene, config, k);
+            let hit = |j: usize| {
+                (0..config.height)
+                    .filter(|i| radiance[i * 21 + j].x > 0.)
+                    .count()
+            };
+            (
+                (0..10).map(hit).sum::<usize>(),
+                (11..21).map(hit).sum::<usize>(),
+            )
+        };
+
+        let (left_0, right_0) = coverage(0);
+        let (left_1, right_1) = coverage(1);
+        assert_eq!((left_0, left_1), (right_0, right_1));
+        assert!(left_1 > left_0);
+    }
+
+    #[test]
+    fn equirectangular_edges_wrap_around() {
+        let mut camera = Camera::new(
+            Vector::new_eq(0.),
+            Vector::new(1., 0., 0.),
+            Vector::new(0., 0., 1.),
+            60.,
+            35.,
+            50,
+            100,
+        );
+        camera.projection = Projection::Equirectangular;
+
+        // The left and right edges of every row look towards the same longitude, behind the camera
+        for i in [0.5, 10., 25., 49.5] {
+            let left = camera.pixel_direction(i, 0.);
+            let right = camera.pixel_direction(i, 100.);
+            assert_approx_eq::assert_approx_eq!((left - right).norm(), 0.);
+            assert!(left.x < 0.);
+        }
+
+        // The center of the image looks forward, the top straight up, and a quarter of the width is a quarter turn
+        let (right, _) = camera.basis();
+        assert_approx_eq::assert_approx_eq!(camera.pixel_direction(25., 50.).x, 1.);
+        assert_approx_eq::assert_approx_eq!(camera.pixel_direction(0., 50.).z, 1.);
+        assert_approx_eq::assert_approx_eq!(camera.pixel_direction(25., 75.).dot(right), 1.);
+        assert_approx_eq::assert_approx_eq!(camera.pixel_direction(37.5, 10.).norm(), 1.);
+    }
+
+    #[test]
+    fn fisheye_center_looks_along_direction() {
+        let mut camera = Camera::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 0., -2.),
+            Vector::new(0., 1., 0.),
+            180.,
+            35.,
+            21,
+            31,
+        );
+        camera.projection = Projection::Fisheye;
+
+        let center = Ray::new_basic_ray(10, 15, &camera);
+        assert_approx_eq::assert_approx_eq!(center.direction.z, -1.);
+
+        // The rim of the image circle looks sideways, at 90° from the direction
+        assert_approx_eq::assert_approx_eq!(camera.pixel_direction(10.5, 26.).x, 1.);
+        assert_approx_eq::assert_approx_eq!(camera.pixel_direction(0., 15.5).y, 1.);
+        assert!(camera.in_image_circle(10.5, 25.5));
+        assert!(!camera.in_image_circle(0.5, 0.5));
+    }
+
+    #[test]
+    fn fisheye_corners_see_the_background() {
+        // The camera is inside a sphere, which fills the whole image circle
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            100.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.set_background(crate::scene::background::Background::Solid(Vector::new(
+            1., 2., 3.,
+        )));
+
+        let mut config = Config::new(11, 11, 1., false, 1, 1, false, false, 0., 0., 1);
+        config.render_mode = RenderMode::PathTracing;
+        let mut camera = Camera::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 0., -1.),
+            Vector::new(0., 1., 0.),
+            120.,
+            35.,
+            config.height,
+            config.width,
+        );
+        camera.projection = Projection::Fisheye;
+
+        for packet_size in [1, 4] {
+            config.packet_size = packet_size;
+            let radiance = crate::render_radiance(&camera, &scene, config, 0);
+            assert_eq!(radiance[0].z, 3.);
+            assert_eq!(radiance[10 * 11 + 10].z, 3.);
+            assert_ne!(radiance[5 * 11 + 5].z, 3.);
+        }
+
+        // The auxiliary passes agree with the image
+        let gbuffer = crate::gbuffer::render_gbuffer(&camera, &scene, config);
+        assert_eq!(gbuffer.color[0].z, 3.);
+        assert_eq!(gbuffer.albedo[0].x, 0.);
+        assert_eq!(gbuffer.normal[0].norm_sq(), 0.);
+        assert!(gbuffer.normal[5 * 11 + 5].norm_sq() > 0.);
+        let ids = crate::gbuffer::object_id_pass(&camera, &scene, config, 0.);
+        assert_eq!((ids[0], ids[5 * 11 + 5]), (0, 1));
+        let guides = crate::upscale::render_guides(&camera, &scene, config, 0.);
+        assert!(!guides[0].depth.is_finite());
+        assert!(guides[5 * 11 + 5].depth.is_finite());
+    }
+
+    #[test]
+    fn chromatic_aberration_fringes_blurred_edges() {
+        // A white sphere on a black background, in front of the focal plane
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            6.,
+            Material::create_diffuse(Color::white()),
+        )));
+
+        let mut config = Config::new(21, 21, 1., false, 1, 200, true, true, 0., 0., 1);
+        config.render_mode = RenderMode::Albedo;
+        let mut camera = Camera {
+            fov_degrees: 30.,
+            ..example_camera(&config)
+        };
+
+        let fringe = |camera: &Camera| {
+            crate::render_radiance(camera, &scene, config, 0)
+                .iter()
+                .map(|pixel| (pixel.x - pixel.z).abs())
+                .fold(0., f64::max)
+        };
+
+        // Without aberration, the three channels follow the same Rays
+        assert_eq!(fringe(&camera), 0.);
+
+        // The red channel focuses closer to the camera than the blue one: the edges of the sphere are blurred differently
+        camera.chromatic_aberration = 0.3;
+        assert!(fringe(&camera) > 0.2 * 255.);
+        let radiance = crate::render_radiance(&camera, &scene, config, 0);
+        let center = radiance[10 * 21 + 10];
+        assert!(center.x > 250. && center.z > 250.);
+    }
+}
@@ -0,0 +1,153 @@
+//! # Error
+//!
+//! This module defines the errors which can stop a render, or the loading and saving of a Scene, and the invalid values of a Config.
+
+use std::fmt;
+
+/// # RenderError
+///
+/// An error preventing a frame from being written to the disk
+#[derive(Debug)]
+pub enum RenderError {
+    /// The file could not be written (e.g. missing directory, permissions, disk full)
+    Io(std::io::Error),
+    /// The image could not be encoded
+    Image(image::ImageError),
+    /// A frame of an animation does not hold 3 bytes per pixel of the image (see export_gif)
+    FrameSize {
+        index: usize,
+        expected: usize,
+        len: usize,
+    },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Io(error) => write!(f, "could not write the image: {}", error),
+            RenderError::Image(error) => write!(f, "could not encode the image: {}", error),
+            RenderError::FrameSize {
+                index,
+                expected,
+                len,
+            } => write!(
+                f,
+                "frame {} holds {} bytes instead of {}",
+                index, len, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Io(error) => Some(error),
+            RenderError::Image(error) => Some(error),
+            RenderError::FrameSize { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for RenderError {
+    fn from(error: std::io::Error) -> Self {
+        RenderError::Io(error)
+    }
+}
+
+impl From<image::ImageError> for RenderError {
+    fn from(error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(error) => RenderError::Io(error),
+            error => RenderError::Image(error),
+        }
+    }
+}
+
+/// # SceneError
+///
+/// An error preventing a Scene from being saved to or loaded from a scene file (see Scene::to_json and Scene::from_json)
+#[derive(Debug)]
+pub enum SceneError {
+    /// The file could not be read or written
+    Io(std::io::Error),
+    /// The file is not a valid scene description
+    Json(serde_json::Error),
+    /// The Scene holds an object which can not be described as a Primitive (see Object::to_primitive)
+    UnsupportedObject,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(error) => write!(f, "could not access the scene file: {}", error),
+            SceneError::Json(error) => write!(f, "invalid scene file: {}", error),
+            SceneError::UnsupportedObject => {
+                write!(f, "the scene holds an object which can not be saved")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(error) => Some(error),
+            SceneError::Json(error) => Some(error),
+            SceneError::UnsupportedObject => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(error: std::io::Error) -> Self {
+        SceneError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(error: serde_json::Error) -> Self {
+        SceneError::Json(error)
+    }
+}
+
+/// # ConfigError
+///
+/// A value of a Config with which no image can be rendered (see Config::try_new)
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    /// The image has no pixel: its height or its width is 0
+    EmptyImage { height: usize, width: usize },
+    /// No Ray is shot through the pixels, which stay black
+    NoRays,
+    /// The gamma is not a positive number, and the gamma correction gives NaNs
+    InvalidGamma(f64),
+    /// The animation ends before it starts
+    BackwardsTime { start_time: f64, end_time: f64 },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::EmptyImage { height, width } => write!(
+                f,
+                "the image must have at least one pixel, got {}x{}",
+                width, height
+            ),
+            ConfigError::NoRays => write!(f, "nb_rays must be at least 1"),
+            ConfigError::InvalidGamma(gamma) => {
+                write!(f, "the gamma must be a positive number, got {}", gamma)
+            }
+            ConfigError::BackwardsTime {
+                start_time,
+                end_time,
+            } => write!(
+                f,
+                "the animation must not end (end_time {}) before it starts (start_time {})",
+                end_time, start_time
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
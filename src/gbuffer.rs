@@ -0,0 +1,244 @@
+//! # GBuffer
+//!
+//! This module renders the auxiliary buffers expected by external denoisers (such as Open Image Denoise) alongside the noisy image.
+
+use crate::animate::Animatable;
+use crate::camera::Camera;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::upscale::Guide;
+use crate::utils::{Config, Vector};
+use crate::{frame_time, primary_ray, shade_primary};
+
+use rayon::prelude::*;
+
+/// # GBuffer
+///
+/// Aligned float buffers of the first frame, row by row, each pixel being averaged over config.nb_aa_samples() primary Rays:
+/// - color: the linear radiance, as computed by render_radiance
+/// - albedo: the albedo of the surface at the primary hit, from 0. to 1.
+/// - normal: the normal of the surface at the primary hit, with components from -1. to 1.
+///
+/// Pixels whose Rays miss every object, or fall outside the image circle of a fisheye lens, have a null albedo and normal.
+pub struct GBuffer {
+    pub color: Vec<Vector>,
+    pub albedo: Vec<Vector>,
+    pub normal: Vec<Vector>,
+}
+
+/// Renders the color, albedo and normal buffers in one pass: the albedo and the normal are read at the primary hit of each sample
+pub fn render_gbuffer(camera: &Camera, scene: &Scene, config: Config) -> GBuffer {
+    let time = frame_time(config, 0);
+    let camera = &camera.at_time(time);
+    let size = config.height * config.width;
+    let mut gbuffer = GBuffer {
+        color: Vec::with_capacity(size),
+        albedo: Vec::with_capacity(size),
+        normal: Vec::with_capacity(size),
+    };
+
+    for i in 0..(config.height as isize) {
+        for j in 0..(config.width as isize) {
+            let (color, albedo, normal) = (0..config.nb_aa_samples())
+                .into_par_iter()
+                .map(|sample| {
+                    let mut rng = config.sample_rng(0, i as usize, j as usize, sample);
+                    let ray = primary_ray(camera, config, i, j, sample, time, &mut rng);
+                    // Outside the image circle of a fisheye lens, the Rays only see the background
+                    let intersection = scene
+                        .compute_primary_intersection(
+                            ray,
+                            time,
+                            config.stochastic_transparency,
+                            &mut rng,
+                        )
+                        .filter(|_| camera.in_image_circle(i as f64 + 0.5, j as f64 + 0.5));
+                    let (albedo, normal) = match intersection {
+                        Some(inter) => (Vector::from(scene.surface_albedo(inter)), inter.normal),
+                        None => (Vector::new_eq(0.), Vector::new_eq(0.)),
+                    };
+
+                    (
+                        shade_primary(scene, config, ray, intersection, sample, time, &mut rng),
+                        albedo,
+                        normal,
+                    )
+                })
+                .reduce(
+                    || (Vector::new_eq(0.), Vector::new_eq(0.), Vector::new_eq(0.)),
+                    |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2),
+                );
+
+            let nb_samples = config.nb_aa_samples() as f64;
+            gbuffer.color.push(color / nb_samples);
+            gbuffer.albedo.push(albedo / nb_samples);
+            gbuffer.normal.push(normal / nb_samples);
+        }
+    }
+    gbuffer
+}
+
+/// Encodes the depth of the Guides (see upscale::render_guides) as a grey RGB buffer, row by row
+/// The depth is mapped linearly from black at near to white at far, and clamped outside (see Config::depth_range);
+/// pixels whose Ray misses every object, and every pixel when far is not beyond near, are black.
+pub fn depth_pass(guides: &[Guide], (near, far): (f64, f64)) -> Vec<u8> {
+    guides
+        .iter()
+        .flat_map(|guide| {
+            let value = match guide.depth.is_finite() && far > near {
+                true => ((guide.depth - near) / (far - near)).clamp(0., 1.) * 255.,
+                false => 0.,
+            };
+            [value.round() as u8; 3]
+        })
+        .collect()
+}
+
+/// Encodes the world-space normals of the Guides as an RGB buffer, row by row, each component being mapped from -1. .. 1. to 0 .. 255
+/// Pixels whose Ray misses every object have a null normal, which is encoded as a flat grey.
+pub fn normal_pass(guides: &[Guide]) -> Vec<u8> {
+    guides
+        .iter()
+        .flat_map(|guide| {
+            let color = (guide.normal + Vector::new_eq(1.)) * 127.5;
+            [color.x as u8, color.y as u8, color.z as u8]
+        })
+        .collect()
+}
+
+/// Encodes the number of primary Rays drawn for each pixel (see Config::adaptive_error) as a grayscale RGB buffer, row by row
+/// The pixels which drew the most Rays of the frame are white.
+pub fn sample_count_pass(sample_counts: &[usize]) -> Vec<u8> {
+    let max_count = sample_counts.iter().copied().max().unwrap_or(0).max(1);
+
+    sample_counts
+        .iter()
+        .flat_map(|count| [(*count as f64 / max_count as f64 * 255.).round() as u8; 3])
+        .collect()
+}
+
+/// Computes the object ID of every pixel, row by row, with one Ray per pixel
+/// The ID is the index of the object hit (in the order of add_object) plus one, and 0 for the pixels whose Ray misses every object
+/// or falls outside the image circle of a fisheye lens.
+pub fn object_id_pass(camera: &Camera, scene: &Scene, config: Config, time: f64) -> Vec<u16> {
+    let camera = &camera.at_time(time);
+    (0..config.height as isize)
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            (0..config.width as isize).map(move |j| {
+                let ray = Ray::new_basic_ray(i, j, camera)
+                    .apply_animations(camera.get_animations(), time);
+                let mut rng = config.sample_rng(0, i as usize, j as usize, 0);
+
+                scene
+                    .compute_primary_intersection(
+                        ray,
+                        time,
+                        config.stochastic_transparency,
+                        &mut rng,
+                    )
+                    .filter(|_| camera.in_image_circle(i as f64 + 0.5, j as f64 + 0.5))
+                    .and_then(|inter| inter.object_index)
+                    .map_or(0, |index| (index + 1).min(u16::MAX as usize) as u16)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::sphere::Sphere;
+    use crate::render_radiance;
+    use crate::testing::example_camera;
+    use crate::utils::{Color, Material, RenderMode};
+
+    #[test]
+    fn gbuffer_matches_render_modes() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(-8., 0., 0.),
+            6.,
+            Material::create_diffuse(Color::new(0.8, 0.1, 1.0)),
+        )));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(8., 0., 0.),
+            6.,
+            Material::create_checker(Color::red(), Color::blue(), 8.),
+        )));
+
+        let mut config = Config::new(16, 24, 1., false, 1, 1, false, false, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        let gbuffer = render_gbuffer(&camera, &scene, config);
+        config.render_mode = RenderMode::Normals;
+        let normals = render_radiance(&camera, &scene, config, 0);
+        config.render_mode = RenderMode::Albedo;
+        let albedos = render_radiance(&camera, &scene, config, 0);
+
+        for (k, normal) in gbuffer.normal.iter().enumerate() {
+            let expected = match normal.norm_sq() > 0. {
+                true => (*normal + Vector::new_eq(1.)) * 127.5,
+                false => Vector::new_eq(0.),
+            };
+            assert!((expected - normals[k]).norm() < 1e-9);
+            assert!((gbuffer.albedo[k] * 255. - albedos[k]).norm() < 1e-9);
+        }
+        assert!(gbuffer.normal.iter().any(|normal| normal.norm_sq() > 0.));
+    }
+
+    #[test]
+    fn depth_and_normal_passes_encode_primary_hits() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            6.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let config = Config::new(9, 9, 1., false, 1, 1, false, false, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        let guides = crate::upscale::render_guides(&camera, &scene, config, 0.);
+        let depth = depth_pass(&guides, config.depth_range);
+        let normal = normal_pass(&guides);
+
+        // The center of the sphere faces the camera, the corners miss it
+        let center = 3 * (4 * config.width + 4);
+        assert!(depth[center] > 0);
+        assert_eq!(&normal[center..center + 3], &[127, 127, 255]);
+        assert_eq!(&depth[0..3], &[0, 0, 0]);
+        assert_eq!(&normal[0..3], &[127, 127, 127]);
+
+        // The front of the sphere is 49. away: its grey does not depend on the other objects of the frame
+        assert_eq!(depth[center], 125);
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., 0., -1000.),
+            940.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let guides = crate::upscale::render_guides(&camera, &scene, config, 0.);
+        let depth = depth_pass(&guides, config.depth_range);
+        assert_eq!(depth[center], 125);
+        assert_eq!(&depth[0..3], &[255, 255, 255]);
+    }
+
+    #[test]
+    fn object_ids_are_distinct() {
+        let mut scene = Scene::new();
+        for x in [-15., 0., 15.] {
+            scene.add_object(Box::new(Sphere::new(
+                Vector::new(x, 0., 0.),
+                6.,
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        let config = Config::new(12, 30, 1., false, 1, 1, false, false, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        let mut ids = object_id_pass(&camera, &scene, config, 0.);
+        ids.sort_unstable();
+        ids.dedup();
+
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+    }
+}
@@ -1,23 +1,55 @@
 use crate::light::Light;
-use crate::utils::{Material, Vector};
+use crate::ray::Ray;
+use crate::utils::{Material, Transform, Vector};
 
 /// # Intersection
 ///
 /// Contains information relative to a collision between a Ray and an arbitrary object.
 /// More precisely, it contains the position of the collision, as well as the Vector normal to the surface of the object, and the Material information at that point.
+/// The uv coordinates (both from 0. to 1.) locate the point on the surface of the object, for texture mapping.
 #[derive(Copy, Clone)]
 pub struct Intersection {
     pub point: Vector,
     pub normal: Vector,
     pub material: Material,
+    pub uv: (f64, f64),
+    /// A unit Vector tangent to the surface, along which u increases when the object defines it (used by anisotropic materials)
+    pub tangent: Vector,
+    /// Index of the hit object in the Scene, set by Scene::compute_intersection (used for light linking and the object ID pass)
+    pub object_index: Option<usize>,
+    /// False when the Ray hits the surface from inside the object: the normal is then flipped to face the Ray
+    pub front_face: bool,
+    /// Set by Scene::compute_intersection when the hit object is also a light object of the Scene, whose light compute_direct samples
+    pub light_object: bool,
 }
 
 impl Intersection {
-    pub fn new(point: Vector, normal: Vector, material: Material) -> Self {
+    /// The tangent is set to an arbitrary Vector perpendicular to the normal
+    pub fn new(point: Vector, normal: Vector, material: Material, uv: (f64, f64)) -> Self {
+        let helper = match normal.x.abs() > 0.9 {
+            true => Vector::new(0., 1., 0.),
+            false => Vector::new(1., 0., 0.),
+        };
+
         Intersection {
             point,
             normal,
             material,
+            uv,
+            tangent: normal.cross(helper).normalize(),
+            object_index: None,
+            front_face: true,
+            light_object: false,
+        }
+    }
+
+    /// Moves an Intersection computed in the space of an object to the world space, given the Transform of the object
+    pub fn to_world(self, transform: &Transform) -> Self {
+        Intersection {
+            point: transform.to_world_point(self.point),
+            normal: transform.to_world_normal(self.normal),
+            tangent: transform.to_world_vector(self.tangent).normalize(),
+            ..self
         }
     }
 
@@ -26,6 +58,30 @@ impl Intersection {
         light.get_intensity_local(self.point, self.normal, self.material.color, time)
     }
 
+    /// Normalizes the normal and flips it to face the Ray, for the objects (e.g. once transformed or animated) whose normal may face away from it
+    /// front_face then tells which side of the surface the Ray hits, seen from the outward normal of the object.
+    pub fn face_ray(self, ray: Ray) -> Self {
+        let outward = self.outward_normal().normalize();
+        let front_face = outward.dot(ray.direction) <= 0.;
+        let normal = match front_face {
+            true => outward,
+            false => outward * (-1.),
+        };
+        Intersection {
+            normal,
+            front_face,
+            ..self
+        }
+    }
+
+    /// Returns the normal pointing out of the object, whichever side the Ray came from
+    pub fn outward_normal(self) -> Vector {
+        match self.front_face {
+            true => self.normal,
+            false => self.normal * (-1.),
+        }
+    }
+
     /// Used to make sure the ray starts from outside the object, to avoid getting shadowed by itself in case of float compute errors
     pub fn get_point_nudged(self) -> Vector {
         self.point + self.normal * 0.0001
@@ -40,8 +96,7 @@ impl Intersection {
     pub fn get_inter_nudged(self) -> Self {
         Intersection {
             point: self.point + self.normal * 0.0001,
-            normal: self.normal,
-            material: self.material,
+            ..self
         }
     }
 
@@ -49,8 +104,7 @@ impl Intersection {
     pub fn get_inter_nudged_neg(self) -> Self {
         Intersection {
             point: self.point - self.normal * 0.0001,
-            normal: self.normal,
-            material: self.material,
+            ..self
         }
     }
 }
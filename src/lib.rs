@@ -8,7 +8,16 @@
 //! use std::f64::consts::PI;
 //! use raytracer::{object::sphere::Sphere, camera::Camera, scene::Scene, utils::{Vector, Color, Material, Config}};
 //!
-//! const CONFIG: Config = Config::new(200,200, 2.2, true, 5, 100, false, true, 0., 100., 1);
+//! const CONFIG: Config = Config::builder()
+//!     .height(200)
+//!     .width(200)
+//!     .gamma(2.2)
+//!     .debug_info(true)
+//!     .nb_iter_max(5)
+//!     .nb_rays(100)
+//!     .aa(true)
+//!     .end_time(100.)
+//!     .build();
 //!
 //! fn create_camera() -> Camera {
 //!     Camera::new(Vector::new(0.,0.,55.), Vector::new(0.,0.,-1.),Vector::new(0.,1.,0.),60.0,35.0, CONFIG.height,CONFIG.width)
@@ -21,45 +30,97 @@
 //!     let sphere_wall1 = Box::new(Sphere::new(Vector::new(0.,1000.,0.), 940., Material::create_diffuse(Color::red())));
 //!     let sphere_wall2 = Box::new(Sphere::new(Vector::new(0.,0.,-1000.), 940., Material::create_diffuse(Color::green())));
 //!     let sphere_wall3 = Box::new(Sphere::new(Vector::new(0.,0.,1000.), 940., Material::create_diffuse(Color::yellow())));
-//!     let sphere_wall4 = Box::new(Sphere::new(Vector::new(0.,-1000.,0.), 990., Material::create_diffuse(Color::blue())));
 //!     let light_emissive = Box::new(Sphere::new(Vector::new(-30.,5.,45.), 10.0, Material::create_emissive(Color::white(), 2000000000. / (4. * PI * 10. * 10.))));
 //!                                                                                                                                                             
 //!     scene.add_object(sphere);
 //!     scene.add_object(sphere_wall1);
 //!     scene.add_object(sphere_wall2);
 //!     scene.add_object(sphere_wall3);
-//!     scene.add_object(sphere_wall4);
+//!     scene.add_checker_floor(-10., 10., Color::blue(), Color::white());
 //!     scene.add_light_object(light_emissive);
 //!     scene
 //! }
 //!
-//! fn main() {
+//! fn main() -> Result<(), raytracer::error::RenderError> {
 //!     let camera = create_camera();
 //!     let scene = create_scene();
+//! #   // Keep the doc test from writing image_0.bmp in the repository
+//! #   std::env::set_current_dir(std::env::temp_dir())?;
 //!     
-//!     raytracer::render_all_frames(&camera, &scene, CONFIG);
+//!     raytracer::render_all_frames(&camera, &scene, CONFIG)
 //! }
 //! ```
 //!
 pub mod animate;
+pub mod bloom;
 pub mod camera;
+pub mod error;
+pub mod gbuffer;
 pub mod intersection;
 pub mod light;
 pub mod object;
+pub mod progressive;
 pub mod ray;
 pub mod scene;
+pub mod stats;
+pub mod texture;
+pub mod upscale;
 pub mod utils;
 
+#[cfg(test)]
+mod testing;
+
 use crate::animate::*;
 use crate::camera::Camera;
+use crate::error::RenderError;
+use crate::intersection::Intersection;
 use crate::ray::Ray;
 use crate::scene::Scene;
-use crate::utils::{Config, Vector};
+use crate::stats::RenderStats;
+use crate::utils::{BitDepth, Config, Parallelism, RenderMode, Vector};
 
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore};
 use rayon::prelude::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// A callback receiving the frame number, the number of rows of that frame completed so far, and its total number of rows
+/// See render_with_progress.
+pub type Progress<'a> = &'a (dyn Fn(usize, usize, usize) + Send + Sync);
+
+/// Does not report any progress
+fn no_progress(_frame: usize, _row: usize, _total_rows: usize) {}
+
+/// Never set: the renders which can not be cancelled check it (see render_one_frame_cancellable)
+static NEVER_CANCELLED: AtomicBool = AtomicBool::new(false);
 
 /// Start the computations of all frames (this will loop render_one_frame over 0..nb_frames)
-pub fn render_all_frames(camera: &Camera, scene: &Scene, config: Config) {
+/// Stops at the first frame which can not be saved.
+/// When config.gif_delay_ms is set, the frames are also assembled into animation.gif (see export_gif).
+/// The frames are rendered on config.threads threads (see Config::threads), one at a time or in parallel (see Config::parallelism).
+pub fn render_all_frames(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+) -> Result<(), RenderError> {
+    render_with_progress(camera, scene, config, no_progress)
+}
+
+/// Same as render_all_frames, calling progress(frame, row, total_rows) each time a row of a frame is completed
+/// so that the caller can display a progress bar or an ETA.
+pub fn render_with_progress<F>(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    progress: F,
+) -> Result<(), RenderError>
+where
+    F: Fn(usize, usize, usize) + Send + Sync,
+{
     if config.debug_info {
         match config.nb_frames {
             1 => {
@@ -71,81 +132,944 @@ pub fn render_all_frames(camera: &Camera, scene: &Scene, config: Config) {
         }
     }
 
-    for k in 0..config.nb_frames {
-        render_one_frame(camera, scene, config, k);
+    in_thread_pool(config, || {
+        let frames = Mutex::new(Vec::new());
+        for_each_frame(config, |k| {
+            let (image_1d, _) = render_frame(camera, scene, config, k, &progress)?;
+            if config.gif_delay_ms.is_some() {
+                frames.lock().unwrap().push((k, image_1d));
+            }
+            Ok(())
+        })?;
+
+        if let Some(delay_ms) = config.gif_delay_ms {
+            let mut frames = frames.into_inner().unwrap();
+            frames.sort_by_key(|(k, _)| *k);
+            let frames: Vec<Vec<u8>> = frames.into_iter().map(|(_, frame)| frame).collect();
+            export_gif(
+                &frames,
+                "animation.gif",
+                config.width as u32,
+                config.height as u32,
+                delay_ms,
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/// Assembles RGB buffers (3 bytes per pixel, row by row, as returned by render_one_frame_to_buffer) into an animated GIF,
+/// showing each frame for delay_ms milliseconds and looping forever
+/// Each frame gets its own palette of 256 colors, picked by the NeuQuant quantizer of the gif crate.
+/// Fails without writing anything if a frame does not hold width * height * 3 bytes.
+pub fn export_gif<P: AsRef<Path>>(
+    frames: &[Vec<u8>],
+    path: P,
+    width: u32,
+    height: u32,
+    delay_ms: u32,
+) -> Result<(), RenderError> {
+    let expected = width as usize * height as usize * 3;
+    if let Some((index, frame)) = frames
+        .iter()
+        .enumerate()
+        .find(|(_, frame)| frame.len() != expected)
+    {
+        return Err(RenderError::FrameSize {
+            index,
+            expected,
+            len: frame.len(),
+        });
+    }
+
+    let gif_frames = frames.iter().map(|frame| {
+        let rgba: Vec<u8> = frame
+            .chunks(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect();
+        let image = image::RgbaImage::from_raw(width, height, rgba)
+            .expect("the size of each frame was checked");
+        image::Frame::from_parts(image, 0, 0, image::Delay::from_numer_denom_ms(delay_ms, 1))
+    });
+
+    // A speed of 10 is the default trade-off of the quantizer between the quality of the palette and the encoding time
+    let mut encoder =
+        image::codecs::gif::GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+    encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+    encoder.encode_frames(gif_frames)?;
+    Ok(())
+}
+
+/// Runs render for every frame number, in order or in parallel depending on config.parallelism
+/// Stops at the first error (in parallel, the frames already started are completed).
+/// The frames rendered in parallel do not collide on the disk: each one writes its own files, named after its number.
+fn for_each_frame<F>(config: Config, render: F) -> Result<(), RenderError>
+where
+    F: Fn(usize) -> Result<(), RenderError> + Send + Sync,
+{
+    match config.parallelism {
+        Parallelism::Pixels => (0..config.nb_frames).try_for_each(render),
+        Parallelism::Frames => (0..config.nb_frames).into_par_iter().try_for_each(render),
+    }
+}
+
+/// Runs a render on a thread pool of config.threads threads, or on the global thread pool of rayon when config.threads is 0
+/// The parallel iterators of the render run in the pool, which only lives for this render.
+pub(crate) fn in_thread_pool<T, F>(config: Config, render: F) -> T
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    match thread_pool(config) {
+        Some(pool) => pool.install(render),
+        None => render(),
+    }
+}
+
+/// Builds a thread pool of config.threads threads, or returns None when the renders should run on the global thread pool of rayon
+/// This is the case when config.threads is 0, and when the pool can not be built: the render still runs, on the global pool.
+pub(crate) fn thread_pool(config: Config) -> Option<rayon::ThreadPool> {
+    match config.threads {
+        0 => None,
+        threads => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .ok(),
     }
 }
 
 /// Start the computation of one frame
 /// k: the frame number, used to compute the time for animations.
-pub fn render_one_frame(camera: &Camera, scene: &Scene, config: Config, k: usize) {
+/// When config.save_aovs is set, the depth and normal passes of the frame are saved alongside it (see gbuffer::depth_pass and gbuffer::normal_pass).
+/// When config.save_object_ids is set, the object ID pass is saved as a 16-bit grayscale image (see gbuffer::object_id_pass).
+/// When config.save_sample_counts is set, the number of primary Rays drawn for each pixel is saved as a heatmap (see gbuffer::sample_count_pass).
+pub fn render_one_frame(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+) -> Result<(), RenderError> {
+    in_thread_pool(config, || {
+        render_frame(camera, scene, config, k, &no_progress).map(|_| ())
+    })
+}
+
+/// Same as render_one_frame, returning the work done to compute the frame when config.stats is set (see the stats module)
+pub fn render_one_frame_with_stats(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+) -> Result<Option<RenderStats>, RenderError> {
+    in_thread_pool(config, || {
+        render_frame(camera, scene, config, k, &no_progress).map(|(_, stats)| stats)
+    })
+}
+
+/// Same as render_one_frame, reporting the completed rows to progress and returning the saved image as an RGB buffer,
+/// with the work done to compute it when config.stats is set (printed with config.debug_info)
+fn render_frame(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    progress: Progress,
+) -> Result<(Vec<u8>, Option<RenderStats>), RenderError> {
     if config.debug_info {
         println!("   Start render frame n°{} / {}", k + 1, config.nb_frames);
     }
-    let mut image: Vec<Vec<u8>> = Vec::with_capacity(config.height);
-    for i in 0..(config.height as isize) {
-        let mut row: Vec<u8> = Vec::with_capacity(config.width * 3);
-        for j in 0..(config.width as isize) {
-            // Create the Ray
-            let intensity: Vector = (0..config.nb_rays)
+    let ((radiance, sample_counts), stats) =
+        measured_frame_radiance(camera, scene, config, k, progress);
+    if let (Some(stats), true) = (stats, config.debug_info) {
+        println!("   Frame n°{}: {}", k + 1, stats);
+    }
+    let image_1d = tone_map(&radiance, config);
+    match config.bit_depth {
+        BitDepth::Sixteen => save_image_16(
+            &tone_map_16(&radiance, config),
+            &format!("image_{}.png", k),
+            config.width as u32,
+            config.height as u32,
+        )?,
+        BitDepth::Eight => save_image(
+            &image_1d,
+            &format!("image_{}.bmp", k),
+            config.width as u32,
+            config.height as u32,
+        )?,
+    }
+
+    if config.save_hdr {
+        save_hdr(
+            &radiance,
+            format!("image_{}.hdr", k),
+            config.width as u32,
+            config.height as u32,
+        )?;
+    }
+
+    if config.save_sample_counts {
+        // The counts are the ones of the rendered resolution, before any upscaling
+        let (width, height) = rendered_size(config);
+        save_image(
+            &gbuffer::sample_count_pass(&sample_counts),
+            &format!("samples_{}.png", k),
+            width as u32,
+            height as u32,
+        )?;
+    }
+
+    if config.save_aovs {
+        let guides = upscale::render_guides(camera, scene, config, frame_time(config, k));
+        save_image(
+            &gbuffer::depth_pass(&guides, config.depth_range),
+            &format!("depth_{}.png", k),
+            config.width as u32,
+            config.height as u32,
+        )?;
+        save_image(
+            &gbuffer::normal_pass(&guides),
+            &format!("normal_{}.png", k),
+            config.width as u32,
+            config.height as u32,
+        )?;
+    }
+
+    if config.save_object_ids {
+        let ids = gbuffer::object_id_pass(camera, scene, config, frame_time(config, k));
+        let ids = image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_raw(
+            config.width as u32,
+            config.height as u32,
+            ids,
+        )
+        .expect("the object ID pass has one value per pixel");
+        ids.save(format!("object_id_{}.png", k))?;
+    }
+    Ok((image_1d, stats))
+}
+
+/// Same as frame_radiance, with the work done to compute the frame when config.stats is set (see stats::measure)
+fn measured_frame_radiance(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    progress: Progress,
+) -> ((Vec<Vector>, Vec<usize>), Option<RenderStats>) {
+    let compute = || frame_radiance(camera, scene, config, k, progress, &NEVER_CANCELLED);
+    match config.stats {
+        true => {
+            let (frame, stats) = stats::measure(config.threads, compute);
+            (frame, Some(stats))
+        }
+        false => (compute(), None),
+    }
+}
+
+/// Computes one frame and returns it as an RGB buffer (3 bytes per pixel, row by row), without saving it
+/// When config.render_scale is below 1., the frame is rendered at a lower resolution then upscaled (see the upscale module).
+pub fn render_one_frame_to_buffer(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+) -> Vec<u8> {
+    in_thread_pool(config, || {
+        frame_to_buffer(camera, scene, config, k, &no_progress, &NEVER_CANCELLED).0
+    })
+}
+
+/// Same as render_one_frame_to_buffer, stopping early once cancel is set (e.g. by a GUI whose user changed a parameter, from another thread)
+/// The flag is checked before each tile (or each packet, see Config::packet_size): the tiles already started are completed,
+/// and the returned frame keeps the pixels of the tiles not rendered yet black. An upscaled frame (see Config::render_scale) blurs their edges.
+pub fn render_one_frame_cancellable(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    cancel: &AtomicBool,
+) -> Vec<u8> {
+    in_thread_pool(config, || {
+        frame_to_buffer(camera, scene, config, k, &no_progress, cancel).0
+    })
+}
+
+/// Renders a rough preview of the first frame, at 1/scale_factor of the resolution and with a single Ray per pixel, and returns it as an RGB buffer
+/// The preview is (width / scale_factor) x (height / scale_factor) pixels, rounded down (at least 1 x 1).
+/// It traces about scale_factor² times fewer pixels, each nb_rays times cheaper: a GUI can show it at once, before the full quality render.
+pub fn render_preview(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    scale_factor: usize,
+) -> Vec<u8> {
+    let scale_factor = scale_factor.max(1);
+    let (width, height) = (
+        (config.width / scale_factor).max(1),
+        (config.height / scale_factor).max(1),
+    );
+
+    let preview_config = Config {
+        width,
+        height,
+        nb_rays: 1,
+        aa_samples: None,
+        gi_samples: None,
+        adaptive_error: None,
+        render_scale: 1.,
+        crop: config.crop.map(|(x0, y0, x1, y1)| {
+            (
+                x0 / scale_factor,
+                y0 / scale_factor,
+                x1.div_ceil(scale_factor),
+                y1.div_ceil(scale_factor),
+            )
+        }),
+        bloom_radius: config.bloom_radius / scale_factor as f64,
+        ..config
+    };
+    let mut preview_camera = camera.clone();
+    preview_camera.width = width;
+    preview_camera.height = height;
+
+    render_one_frame_to_buffer(&preview_camera, scene, preview_config, 0)
+}
+
+/// Computes the resolution at which frames are actually rendered, before being upscaled (see Config::render_scale)
+fn rendered_size(config: Config) -> (usize, usize) {
+    match config.render_scale < 1. {
+        true => (
+            ((config.width as f64 * config.render_scale).round() as usize).max(1),
+            ((config.height as f64 * config.render_scale).round() as usize).max(1),
+        ),
+        false => (config.width, config.height),
+    }
+}
+
+/// Same as render_one_frame_cancellable, reporting the completed rows to progress
+/// The number of primary Rays drawn for each pixel is returned along the buffer.
+/// When the frame is upscaled, the rows and the pixels are the ones of the lower resolution.
+fn frame_to_buffer(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    progress: Progress,
+    cancel: &AtomicBool,
+) -> (Vec<u8>, Vec<usize>) {
+    let (radiance, sample_counts) = frame_radiance(camera, scene, config, k, progress, cancel);
+    (tone_map(&radiance, config), sample_counts)
+}
+
+/// Same as frame_to_buffer, returning the linear radiance of the pixels before the gamma correction
+/// The bloom of the Config is added to the full resolution frame.
+fn frame_radiance(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    progress: Progress,
+    cancel: &AtomicBool,
+) -> (Vec<Vector>, Vec<usize>) {
+    let (radiance, sample_counts) = unbloomed_radiance(camera, scene, config, k, progress, cancel);
+    (with_bloom(radiance, config), sample_counts)
+}
+
+/// Adds the bloom of the Config to the radiance of a frame, when config.bloom_intensity is set (see bloom::add_bloom)
+pub(crate) fn with_bloom(radiance: Vec<Vector>, config: Config) -> Vec<Vector> {
+    match config.bloom_intensity > 0. {
+        true => bloom::add_bloom(
+            &radiance,
+            config.width,
+            config.height,
+            config.bloom_threshold * 255_f64.powf(config.gamma),
+            config.bloom_radius,
+            config.bloom_intensity,
+        ),
+        false => radiance,
+    }
+}
+
+/// Same as frame_radiance, without the bloom
+fn unbloomed_radiance(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    progress: Progress,
+    cancel: &AtomicBool,
+) -> (Vec<Vector>, Vec<usize>) {
+    if config.render_scale < 1. {
+        let (low_width, low_height) = rendered_size(config);
+
+        let mut low_config = config;
+        low_config.width = low_width;
+        low_config.height = low_height;
+        // The crop region is given in the pixels of the full resolution
+        low_config.crop = config.crop.map(|(x0, y0, x1, y1)| {
+            let scale = |x: usize| x as f64 * config.render_scale;
+            (
+                scale(x0).floor() as usize,
+                scale(y0).floor() as usize,
+                scale(x1).ceil() as usize,
+                scale(y1).ceil() as usize,
+            )
+        });
+        let mut low_camera = camera.clone();
+        low_camera.width = low_width;
+        low_camera.height = low_height;
+
+        let (low_radiance, sample_counts) =
+            radiance_with_progress(&low_camera, scene, low_config, k, progress, cancel);
+
+        let radiance = match config.upscale_guided {
+            true => {
+                let guides = upscale::render_guides(camera, scene, config, frame_time(config, k));
+                upscale::upscale_guided(
+                    &low_radiance,
+                    low_width,
+                    low_height,
+                    &guides,
+                    config.width,
+                    config.height,
+                )
+            }
+            false => upscale::upscale_bilinear(
+                &low_radiance,
+                low_width,
+                low_height,
+                config.width,
+                config.height,
+            ),
+        };
+        (radiance, sample_counts)
+    } else {
+        radiance_with_progress(camera, scene, config, k, progress, cancel)
+    }
+}
+
+/// Computes the time of the k-th frame, for animations
+pub fn frame_time(config: Config, k: usize) -> f64 {
+    match config.nb_frames {
+        1 => config.start_time,
+        _ => {
+            config.start_time
+                + k as f64 * (config.end_time - config.start_time) / (config.nb_frames - 1) as f64
+        }
+    }
+}
+
+/// Draws the time seen by a primary Ray of the k-th frame: the shutter opens at the time of the frame, and stays open
+/// for config.shutter times the time between two frames (or the whole animation, for a single frame).
+pub(crate) fn sample_time(config: Config, k: usize, rng: &mut dyn RngCore) -> f64 {
+    let time = frame_time(config, k);
+    let frame_duration = match config.nb_frames {
+        1 => config.end_time - config.start_time,
+        _ => (config.end_time - config.start_time) / (config.nb_frames - 1) as f64,
+    };
+
+    match config.shutter > 0. {
+        true => time + rng.gen_range(0.0..1.0) * config.shutter * frame_duration,
+        false => time,
+    }
+}
+
+/// Computes the linear radiance of every pixel of the k-th frame, row by row
+/// Each pixel averages config.nb_aa_samples() primary Rays, sharing config.nb_gi_samples() light paths,
+/// or more primary Rays where the image is noisy with adaptive sampling (see Config::adaptive_error).
+pub fn render_radiance(camera: &Camera, scene: &Scene, config: Config, k: usize) -> Vec<Vector> {
+    in_thread_pool(config, || {
+        radiance_with_progress(camera, scene, config, k, &no_progress, &NEVER_CANCELLED).0
+    })
+}
+
+/// Same as render_radiance, reporting the completed rows to progress and returning the number of primary Rays drawn for each pixel
+/// The image is split into tiles of config.tile_size x config.tile_size pixels, rendered in parallel one band of tiles at a time:
+/// each tile works on nearby pixels, which keeps the caches warm, and the rows of a band are completed together.
+/// Once cancel is set, the tiles not started yet are left black, without any sample.
+fn radiance_with_progress(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    progress: Progress,
+    cancel: &AtomicBool,
+) -> (Vec<Vector>, Vec<usize>) {
+    let time = frame_time(config, k);
+    let camera = &camera.at_time(time);
+    // With chromatic aberration, the three Rays of each sample are traced one by one, and with motion blur each Ray sees its own time
+    if config.packet_size > 1
+        && config.render_mode == RenderMode::PathTracing
+        && camera.chromatic_aberration == 0.
+        && config.shutter == 0.
+    {
+        return render_radiance_packets(camera, scene, config, k, progress, cancel);
+    }
+
+    let size = config.tile_size.max(1);
+    let mut radiance: Vec<Vector> = vec![Vector::new_eq(0.); config.height * config.width];
+    let mut sample_counts: Vec<usize> = vec![0; config.height * config.width];
+
+    for band_i in (0..config.height).step_by(size) {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let band_end = (band_i + size).min(config.height);
+
+        let tiles: Vec<(usize, Vec<(Vector, usize)>)> = (0..config.width)
+            .into_par_iter()
+            .step_by(size)
+            .map(|tile_j| {
+                let tile_end = (tile_j + size).min(config.width);
+                let cancelled = cancel.load(Ordering::Relaxed);
+                let tile = (band_i..band_end)
+                    .flat_map(|i| (tile_j..tile_end).map(move |j| (i, j)))
+                    .map(|(i, j)| match !cancelled && config.in_crop(i, j) {
+                        true => render_pixel(camera, scene, config, i, j, k),
+                        false => (Vector::new_eq(0.), 0),
+                    })
+                    .collect();
+                (tile_j, tile)
+            })
+            .collect();
+
+        for (tile_j, tile) in tiles {
+            let tile_width = (tile_j + size).min(config.width) - tile_j;
+            for (row, pixels) in tile.chunks(tile_width).enumerate() {
+                let start = (band_i + row) * config.width + tile_j;
+                for (offset, (pixel, count)) in pixels.iter().enumerate() {
+                    radiance[start + offset] = *pixel;
+                    sample_counts[start + offset] = *count;
+                }
+            }
+        }
+        progress(k, band_end, config.height);
+    }
+    (radiance, sample_counts)
+}
+
+/// Computes the radiance of the pixel (i, j), averaged over its primary Rays, and the number of primary Rays drawn
+/// The Rays are drawn by batches of config.nb_aa_samples(). Without adaptive sampling, a single batch is drawn.
+/// With adaptive sampling, batches are drawn until the standard error of the mean (of the three channels) falls below
+/// config.adaptive_error times the mean, or config.adaptive_max_samples Rays have been drawn.
+/// Each Ray of the pixel draws its random numbers from its own stream (see Config::sample_rng), and its time (see sample_time).
+fn render_pixel(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    i: usize,
+    j: usize,
+    k: usize,
+) -> (Vector, usize) {
+    let batch_size = config.nb_aa_samples();
+    let max_samples = match config.adaptive_error {
+        Some(_) => config.adaptive_max_samples.max(batch_size),
+        None => batch_size,
+    };
+
+    let mut sum = Vector::new_eq(0.);
+    let mut sum_sq = 0.;
+    let mut nb_samples = 0;
+    while nb_samples < max_samples {
+        let batch = batch_size.min(max_samples - nb_samples);
+        // Each batch is a full set of samples, e.g. a whole stratified grid
+        let (batch_sum, batch_sum_sq) = (0..batch)
+            .into_par_iter()
+            .map(|sample| {
+                let mut rng = config.sample_rng(k, i, j, nb_samples + sample);
+                let time = sample_time(config, k, &mut rng);
+                let (i, j) = (i as isize, j as isize);
+                let intensity = render_sample(camera, scene, config, i, j, sample, time, &mut rng);
+                let value = (intensity.x + intensity.y + intensity.z) / 3.;
+                (intensity, value * value)
+            })
+            .reduce(
+                || (Vector::new_eq(0.), 0.),
+                |(a, a_sq), (b, b_sq)| (a + b, a_sq + b_sq),
+            );
+        sum += batch_sum;
+        sum_sq += batch_sum_sq;
+        nb_samples += batch;
+
+        if let Some(error) = config.adaptive_error {
+            let n = nb_samples as f64;
+            let mean = (sum.x + sum.y + sum.z) / 3. / n;
+            let variance = (sum_sq / n - mean * mean).max(0.);
+            if nb_samples > 1 && (variance / n).sqrt() <= error * mean.abs() {
+                break;
+            }
+        }
+    }
+
+    (sum / nb_samples as f64, nb_samples)
+}
+
+/// Same as render_radiance, with the primary Rays of each block of config.packet_size x config.packet_size pixels traced as a packet
+/// The secondary Rays are still traced one by one, as they are not coherent.
+/// Like radiance_with_progress, it also returns the number of primary Rays drawn for each pixel, and stops once cancel is set.
+fn render_radiance_packets(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    k: usize,
+    progress: Progress,
+    cancel: &AtomicBool,
+) -> (Vec<Vector>, Vec<usize>) {
+    let time = frame_time(config, k);
+    let size = config.packet_size;
+    let mut radiance: Vec<Vector> = vec![Vector::new_eq(0.); config.height * config.width];
+    let mut sample_counts: Vec<usize> = vec![0; config.height * config.width];
+
+    // Without any pixel to render, no packet is traced at all
+    let empty_crop = config
+        .crop
+        .is_some_and(|(x0, y0, x1, y1)| x0 >= x1.min(config.width) || y0 >= y1.min(config.height));
+    if config.height == 0 || config.width == 0 || empty_crop {
+        return (radiance, sample_counts);
+    }
+
+    for block_i in (0..config.height).step_by(size) {
+        for block_j in (0..config.width).step_by(size) {
+            if cancel.load(Ordering::Relaxed) {
+                return (radiance, sample_counts);
+            }
+            let pixels: Vec<(usize, usize)> = (block_i..(block_i + size).min(config.height))
+                .flat_map(|i| (block_j..(block_j + size).min(config.width)).map(move |j| (i, j)))
+                .filter(|(i, j)| config.in_crop(*i, *j))
+                .collect();
+            if pixels.is_empty() {
+                continue;
+            }
+
+            let intensities: Vec<Vector> = (0..config.nb_aa_samples())
                 .into_par_iter()
-                .map(|_| {
-                    let ray: Ray;
-                    if config.nb_rays > 1 && config.dof {
-                        ray = Ray::new_aa_and_dof_ray(i, j, camera);
-                    } else if config.nb_rays > 1 && config.aa {
-                        ray = Ray::new_aa_ray(i, j, camera);
-                    } else {
-                        ray = Ray::new_basic_ray(i, j, camera);
-                    }
-                    let time = match config.nb_frames {
-                        1 => config.start_time,
-                        _ => {
-                            config.start_time
-                                + k as f64 * (config.end_time - config.start_time)
-                                    / (config.nb_frames - 1) as f64
-                        }
-                    };
-                    let ray = ray.apply_animations(camera.get_animations(), time);
-                    // Compute collisions between the Ray and the objects from the Scene, keep the closest intersection found
-
-                    let intersection = scene.compute_intersection(ray, time);
-                    if let Some(inter) = intersection {
-                        scene.compute_intensity(ray, inter, config.nb_iter_max, time)
-                    } else {
-                        Vector::new_eq(0.)
-                    }
+                .map(|sample| {
+                    let mut rngs: Vec<StdRng> = pixels
+                        .iter()
+                        .map(|(i, j)| config.sample_rng(k, *i, *j, sample))
+                        .collect();
+                    let rays: Vec<Ray> = pixels
+                        .iter()
+                        .zip(rngs.iter_mut())
+                        .map(|((i, j), rng)| {
+                            primary_ray(camera, config, *i as isize, *j as isize, sample, time, rng)
+                        })
+                        .collect();
+                    let intersections = scene.compute_intersection_packet(
+                        &rays,
+                        time,
+                        config.stochastic_transparency,
+                        &mut rngs,
+                    );
+
+                    rays.iter()
+                        .zip(intersections)
+                        .zip(pixels.iter().zip(rngs.iter_mut()))
+                        .map(|((ray, intersection), ((i, j), rng))| {
+                            let intersection = intersection.filter(|_| {
+                                camera.in_image_circle(*i as f64 + 0.5, *j as f64 + 0.5)
+                            });
+                            shade_primary(scene, config, *ray, intersection, sample, time, rng)
+                        })
+                        .collect::<Vec<Vector>>()
                 })
-                .sum();
-            let intensity = intensity / config.nb_rays as f64;
-            let value_r = intensity.x.powf(1. / config.gamma).min(255.);
-            let value_g = intensity.y.powf(1. / config.gamma).min(255.);
-            let value_b = intensity.z.powf(1. / config.gamma).min(255.);
-            row.push(value_r as u8);
-            row.push(value_g as u8);
-            row.push(value_b as u8);
-        }
-        image.push(row);
-    }
-    let image_1d: Vec<u8> = image.into_iter().flatten().collect();
-    save_image(
-        image_1d,
-        &format!("image_{}.bmp", k),
-        config.width as u32,
-        config.height as u32,
-    );
+                .reduce(
+                    || vec![Vector::new_eq(0.); pixels.len()],
+                    |a, b| a.into_iter().zip(b).map(|(a, b)| a + b).collect(),
+                );
+
+            for ((i, j), intensity) in pixels.iter().zip(intensities) {
+                radiance[i * config.width + j] = intensity / config.nb_aa_samples() as f64;
+                sample_counts[i * config.width + j] = config.nb_aa_samples();
+            }
+        }
+        progress(k, (block_i + size).min(config.height), config.height);
+    }
+    (radiance, sample_counts)
+}
+
+/// Creates the sample-th Ray shot through the pixel (i, j), as seen by the camera at that time
+pub(crate) fn primary_ray(
+    camera: &Camera,
+    config: Config,
+    i: isize,
+    j: isize,
+    sample: usize,
+    time: f64,
+    rng: &mut dyn RngCore,
+) -> Ray {
+    let ray = pinhole_ray(camera, config, i, j, sample, rng);
+    let ray = match config.nb_aa_samples() > 1 && config.dof {
+        true => ray.with_depth_of_field(camera, rng),
+        false => ray,
+    };
+    ray.apply_animations(camera.get_animations(), time)
+}
+
+/// Creates the sample-th Ray shot through the pixel (i, j), anti-aliased but before depth of field and the animations of the camera
+fn pinhole_ray(
+    camera: &Camera,
+    config: Config,
+    i: isize,
+    j: isize,
+    sample: usize,
+    rng: &mut dyn RngCore,
+) -> Ray {
+    match (
+        config.nb_aa_samples() > 1 && (config.aa || config.dof),
+        config.stratified_aa,
+    ) {
+        (false, _) => Ray::new_basic_ray(i, j, camera),
+        (true, false) => Ray::new_aa_ray(i, j, camera, rng),
+        (true, true) => {
+            Ray::new_stratified_aa_ray(i, j, sample, config.nb_aa_samples(), camera, rng)
+        }
+    }
+}
+
+/// Creates the sample-th Rays shot through the pixel (i, j) for the red, green and blue channels, when the camera has chromatic aberration
+/// Returns None without chromatic aberration or depth of field, where the three Rays are the same as primary_ray.
+fn chromatic_primary_rays(
+    camera: &Camera,
+    config: Config,
+    i: isize,
+    j: isize,
+    sample: usize,
+    time: f64,
+    rng: &mut dyn RngCore,
+) -> Option<[Ray; 3]> {
+    match config.nb_aa_samples() > 1 && config.dof && camera.chromatic_aberration != 0. {
+        false => None,
+        true => Some(
+            pinhole_ray(camera, config, i, j, sample, rng)
+                .with_chromatic_depth_of_field(camera, rng)
+                .map(|ray| ray.apply_animations(camera.get_animations(), time)),
+        ),
+    }
+}
+
+/// Computes the radiance carried by one Ray shot through the pixel (i, j)
+/// With chromatic aberration, each color channel is carried by its own Ray.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn render_sample(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    i: isize,
+    j: isize,
+    sample: usize,
+    time: f64,
+    rng: &mut dyn RngCore,
+) -> Vector {
+    // Outside the image circle of a fisheye lens, the Rays only see the background
+    if !camera.in_image_circle(i as f64 + 0.5, j as f64 + 0.5) {
+        return match config.render_mode {
+            RenderMode::PathTracing => {
+                scene.background(primary_ray(camera, config, i, j, sample, time, rng))
+            }
+            _ => Vector::new_eq(0.),
+        };
+    }
+
+    if let Some(rays) = chromatic_primary_rays(camera, config, i, j, sample, time, rng) {
+        let [red, green, blue] = rays.map(|ray| render_ray(scene, config, ray, sample, time, rng));
+        return Vector::new(red.x, green.y, blue.z);
+    }
+
+    let ray = primary_ray(camera, config, i, j, sample, time, rng);
+    render_ray(scene, config, ray, sample, time, rng)
+}
+
+/// Computes the radiance carried by the sample-th primary Ray, depending on the render mode
+fn render_ray(
+    scene: &Scene,
+    config: Config,
+    ray: Ray,
+    sample: usize,
+    time: f64,
+    rng: &mut dyn RngCore,
+) -> Vector {
+    match config.render_mode {
+        RenderMode::PathTracing => {
+            // Compute collisions between the Ray and the objects from the Scene, keep the closest intersection found
+            let intersection =
+                scene.compute_primary_intersection(ray, time, config.stochastic_transparency, rng);
+            shade_primary(scene, config, ray, intersection, sample, time, rng)
+        }
+        RenderMode::XRay => scene.compute_xray(ray, config.xray_density, time),
+        RenderMode::Normals => {
+            match scene.compute_primary_intersection(ray, time, config.stochastic_transparency, rng)
+            {
+                Some(inter) => (inter.normal + Vector::new_eq(1.)) * 127.5,
+                None => Vector::new_eq(0.),
+            }
+        }
+        RenderMode::ShadowCatcher => {
+            Vector::new_eq(255.)
+                * scene.compute_shadow_catcher(ray, time, config.stochastic_transparency, rng)
+        }
+        RenderMode::AmbientOcclusion => {
+            Vector::new_eq(255.)
+                * scene.compute_ambient_occlusion(
+                    ray,
+                    config.ao_samples,
+                    config.ao_distance,
+                    time,
+                    config.stochastic_transparency,
+                    rng,
+                )
+        }
+        RenderMode::Albedo => {
+            match scene.compute_primary_intersection(ray, time, config.stochastic_transparency, rng)
+            {
+                Some(inter) => Vector::from(scene.surface_albedo(inter)) * 255.,
+                None => Vector::new_eq(0.),
+            }
+        }
+    }
+}
+
+/// Computes the radiance carried by the sample-th primary Ray, given its closest intersection
+/// The radiance of a hit is averaged over the config.nb_paths(sample) paths started there, each one clamped to config.max_sample_radiance.
+/// It then fades into the fog of the Scene with the distance of the hit (see Scene::set_fog).
+pub(crate) fn shade_primary(
+    scene: &Scene,
+    config: Config,
+    ray: Ray,
+    intersection: Option<Intersection>,
+    sample: usize,
+    time: f64,
+    rng: &mut dyn RngCore,
+) -> Vector {
+    if let Some(inter) = intersection {
+        let nb_paths = config.nb_paths(sample);
+        let intensity: Vector = (0..nb_paths)
+            .map(|_| match config.nee {
+                true => scene.compute_intensity(
+                    ray,
+                    inter,
+                    config.depth(),
+                    time,
+                    config.stochastic_transparency,
+                    rng,
+                ),
+                false => scene.compute_intensity_without_nee(
+                    ray,
+                    inter,
+                    config.depth(),
+                    time,
+                    config.stochastic_transparency,
+                    rng,
+                ),
+            })
+            // A degenerate path (NaN or infinite radiance) would corrupt the whole pixel: it is counted as black instead
+            .filter(|intensity| intensity.is_finite())
+            .map(|intensity| intensity.clamp_max_component(config.max_sample_radiance))
+            .sum();
+        scene.apply_fog(
+            intensity / nb_paths as f64,
+            (inter.point - ray.origin).norm(),
+        )
+    } else {
+        let radiance = scene
+            .scatter_along(
+                ray,
+                f64::INFINITY,
+                config.depth(),
+                config.nee,
+                time,
+                config.stochastic_transparency,
+                rng,
+            )
+            .unwrap_or_else(|| scene.background(ray));
+        scene.apply_fog(radiance, f64::INFINITY)
+    }
+}
+
+/// Converts linear radiance values to displayable RGB bytes, with the gamma correction of the Config (see GammaMode)
+pub fn tone_map(radiance: &[Vector], config: Config) -> Vec<u8> {
+    let encode = |value: f64| config.gamma_mode.encode(value, config.gamma).min(255.);
+    let mut image_1d: Vec<u8> = Vec::with_capacity(radiance.len() * 3);
+    for intensity in radiance {
+        let value_r = encode(intensity.x);
+        let value_g = encode(intensity.y);
+        let value_b = encode(intensity.z);
+        image_1d.push(value_r as u8);
+        image_1d.push(value_g as u8);
+        image_1d.push(value_b as u8);
+    }
+    image_1d
+}
+
+/// Same as tone_map, with 16 bits per color: the values of tone_map are scaled from 0..255 to 0..65535 before being rounded
+/// Smooth gradients keep 256 times as many levels, which avoids banding when the image is graded later.
+pub fn tone_map_16(radiance: &[Vector], config: Config) -> Vec<u16> {
+    let encode = |value: f64| {
+        (config
+            .gamma_mode
+            .encode(value, config.gamma)
+            .clamp(0., 255.)
+            * 257.)
+            .round() as u16
+    };
+    radiance
+        .iter()
+        .flat_map(|intensity| {
+            [
+                encode(intensity.x),
+                encode(intensity.y),
+                encode(intensity.z),
+            ]
+        })
+        .collect()
 }
 
 /// Uses the image crate to save the rendered image on disk.
-fn save_image(image_buf: Vec<u8>, str_path: &str, width: u32, height: u32) {
-    image::save_buffer(
-        str_path,
-        image_buf.as_slice(),
-        width,
-        height,
-        image::ColorType::Rgb8,
-    )
-    .unwrap();
+fn save_image(
+    image_buf: &[u8],
+    str_path: &str,
+    width: u32,
+    height: u32,
+) -> Result<(), RenderError> {
+    image::save_buffer(str_path, image_buf, width, height, image::ColorType::Rgb8)?;
+    Ok(())
+}
+
+/// Saves linear radiance values (see render_radiance) as a Radiance HDR (RGBE) image, before any gamma correction or clamping
+/// The values keep about 1% of relative precision, and can be tone-mapped later by another tool.
+pub fn save_hdr<P: AsRef<Path>>(
+    radiance: &[Vector],
+    path: P,
+    width: u32,
+    height: u32,
+) -> Result<(), RenderError> {
+    let pixels: Vec<image::Rgb<f32>> = radiance
+        .iter()
+        .map(|intensity| image::Rgb([intensity.x as f32, intensity.y as f32, intensity.z as f32]))
+        .collect();
+
+    image::codecs::hdr::HdrEncoder::new(BufWriter::new(File::create(path)?)).encode(
+        &pixels,
+        width as usize,
+        height as usize,
+    )?;
+    Ok(())
+}
+
+/// Same as save_image, for an RGB buffer with 16 bits per color (see tone_map_16)
+/// The format must support them, e.g. PNG.
+fn save_image_16(
+    image_buf: &[u16],
+    str_path: &str,
+    width: u32,
+    height: u32,
+) -> Result<(), RenderError> {
+    let bytes: Vec<u8> = image_buf
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .collect();
+    image::save_buffer(str_path, &bytes, width, height, image::ColorType::Rgb16)?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -153,7 +1077,9 @@ mod tests {
     use super::*;
     use crate::object::sphere::Sphere;
     use crate::object::Object;
-    use crate::utils::{Color, Material};
+    use crate::scene::background::Background;
+    use crate::testing::{example_camera, example_room, render_variance};
+    use crate::utils::{Color, GammaMode, Material};
 
     #[test]
     fn intersection_is_on_object() {
@@ -181,11 +1107,804 @@ mod tests {
             radius,
             Material::create_diffuse(Color::white()),
         ));
-        let ray = Ray::new(Vector::new_eq(0.), Vector::new(1., 0., 0.));
+        let ray = Ray::new(Vector::new(-20., 0., 0.), Vector::new(1., 0., 0.));
 
         let inter = sphere.intersection(ray);
 
         assert!((inter.expect("").get_point_nudged() - sphere.get_center()).norm() > radius);
         assert!((inter.expect("").get_point_nudged_neg() - sphere.get_center()).norm() < radius);
     }
+
+    #[test]
+    fn intersection_from_inside_faces_the_ray() {
+        let radius = 10.;
+        let sphere = Box::new(Sphere::new(
+            Vector::new(0., 0., 0.),
+            radius,
+            Material::create_diffuse(Color::white()),
+        ));
+        let ray = Ray::new(Vector::new_eq(0.), Vector::new(1., 0., 0.));
+
+        let inter = sphere.intersection(ray).expect("the ray leaves the sphere");
+
+        // The nudge on the side of the Ray stays inside, where a bounce continues
+        assert!(!inter.front_face);
+        assert!(inter.normal.dot(ray.direction) < 0.);
+        assert!(inter.outward_normal().dot(ray.direction) > 0.);
+        assert!((inter.get_point_nudged() - sphere.get_center()).norm() < radius);
+        assert!((inter.get_point_nudged_neg() - sphere.get_center()).norm() > radius);
+    }
+
+    #[test]
+    fn intersects_before_stops_at_max_distance() {
+        let sphere = Sphere::new(
+            Vector::new(10., 0., 0.),
+            2.,
+            Material::create_diffuse(Color::white()),
+        );
+        let ray = Ray::new(Vector::new_eq(0.), Vector::new(1., 0., 0.));
+
+        assert!(!sphere.intersects_before(ray, 7. * 7.));
+        assert!(sphere.intersects_before(ray, 9. * 9.));
+        assert!(
+            !sphere.intersects_before(Ray::new(Vector::new_eq(0.), Vector::new(-1., 0., 0.)), 1e4)
+        );
+    }
+
+    #[test]
+    fn intersect_all_returns_sorted_hits() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(10., 0., 0.),
+            2.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(30., 0., 0.),
+            3.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let ray = Ray::new(Vector::new_eq(0.), Vector::new(1., 0., 0.));
+
+        let intersections = scene.intersect_all(ray, 0.);
+
+        let distances: Vec<f64> = intersections.iter().map(|i| i.point.x).collect();
+        assert_eq!(distances.len(), 4);
+        assert_approx_eq::assert_approx_eq!(distances[0], 8.);
+        assert_approx_eq::assert_approx_eq!(distances[1], 12.);
+        assert_approx_eq::assert_approx_eq!(distances[2], 27.);
+        assert_approx_eq::assert_approx_eq!(distances[3], 33.);
+    }
+
+    #[test]
+    fn xray_overlap_is_brighter() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(-3., 0., 0.),
+            5.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(3., 0., 0.),
+            5.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let ray_single = Ray::new(Vector::new(-6., 0., 20.), Vector::new(0., 0., -1.));
+        let ray_overlap = Ray::new(Vector::new(0., 0., 20.), Vector::new(0., 0., -1.));
+
+        let single = scene.compute_xray(ray_single, 0.05, 0.);
+        let overlap = scene.compute_xray(ray_overlap, 0.05, 0.);
+
+        assert!(single.x > 0.);
+        assert!(overlap.x > single.x);
+    }
+
+    #[test]
+    fn scale_animation_grows_sphere() {
+        let center = Vector::new(10., 0., 0.);
+        let mut sphere = Box::new(Sphere::new(
+            center,
+            5.,
+            Material::create_diffuse(Color::white()),
+        ));
+        sphere.add_animation(Animation::scale_around(0., 10., 2., center));
+        let mut scene = Scene::new();
+        scene.add_object(sphere);
+        let ray = Ray::new(Vector::new(10., 7., 50.), Vector::new(0., 0., -1.));
+
+        assert!(scene.compute_intersection(ray, 0.).is_none());
+
+        let inter = scene
+            .compute_intersection(ray, 10.)
+            .expect("the scaled sphere should be hit");
+        assert_approx_eq::assert_approx_eq!((inter.point - center).norm(), 10.);
+        assert_approx_eq::assert_approx_eq!(
+            ray.reverse_animations(vec![Animation::scale_around(0., 10., 2., center)], 5.)
+                .apply_animations(vec![Animation::scale_around(0., 10., 2., center)], 5.)
+                .origin
+                .z,
+            50.
+        );
+    }
+
+    #[test]
+    fn packet_rendering_matches_single_rays() {
+        let mut scene = Scene::new();
+        for x in [-15., 0., 15.] {
+            scene.add_object(Box::new(Sphere::new(
+                Vector::new(x, 0., 0.),
+                6.,
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        scene.set_background(Background::Solid(Vector::new(10., 20., 30.)));
+
+        // Without any bounce, hits are black and misses see the environment: the render is deterministic
+        let mut config = Config::new(18, 30, 1., false, 0, 1, false, false, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        let single = render_radiance(&camera, &scene, config, 0);
+        config.packet_size = 4;
+        let packets = render_radiance(&camera, &scene, config, 0);
+
+        assert!(single
+            .iter()
+            .zip(packets.iter())
+            .all(|(a, b)| a.x == b.x && a.y == b.y && a.z == b.z));
+        assert!(single.iter().any(|v| v.x == 0.) && single.iter().any(|v| v.x == 10.));
+    }
+
+    #[test]
+    fn packets_render_empty_images() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            6.,
+            Material::create_diffuse(Color::white()),
+        )));
+
+        // An empty crop leaves every pixel black, without any sample
+        let mut config = Config::new(8, 8, 1., false, 1, 1, false, false, 0., 0., 1);
+        config.packet_size = 4;
+        for crop in [(2, 2, 2, 6), (2, 2, 6, 2), (6, 6, 2, 2), (20, 20, 30, 30)] {
+            config.crop = Some(crop);
+            let (radiance, sample_counts) = render_radiance_packets(
+                &example_camera(&config),
+                &scene,
+                config,
+                0,
+                &|_, _, _| {},
+                &NEVER_CANCELLED,
+            );
+            assert_eq!(radiance.len(), 64);
+            assert!(radiance.iter().all(|v| v.norm_sq() == 0.));
+            assert!(sample_counts.iter().all(|count| *count == 0));
+        }
+
+        // An image without pixels renders nothing
+        config.crop = None;
+        for (height, width) in [(0, 8), (8, 0), (0, 0)] {
+            config.height = height;
+            config.width = width;
+            assert!(render_radiance(&example_camera(&config), &scene, config, 0).is_empty());
+        }
+
+        // Nor does an empty packet
+        assert!(scene
+            .compute_intersection_packet(&[], 0., false, &mut [] as &mut [StdRng])
+            .is_empty());
+    }
+
+    #[test]
+    fn tiles_match_row_major_rendering() {
+        let mut scene = Scene::new();
+        for x in [-15., 0., 15.] {
+            scene.add_object(Box::new(Sphere::new(
+                Vector::new(x, 0., 0.),
+                6.,
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        scene.set_background(Background::Gradient(
+            Vector::new(10., 20., 30.),
+            Vector::new(200., 100., 50.),
+        ));
+
+        // A tile as wide as the image renders row by row, as before tiles were introduced
+        let mut config = Config::new(37, 45, 2.2, false, 0, 1, false, false, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        config.tile_size = config.width;
+        let row_major = render_one_frame_to_buffer(&camera, &scene, config, 0);
+        for tile_size in [0, 1, 8, 32] {
+            config.tile_size = tile_size;
+            assert_eq!(
+                render_one_frame_to_buffer(&camera, &scene, config, 0),
+                row_major
+            );
+        }
+    }
+
+    #[test]
+    fn preview_renders_a_smaller_image_with_one_ray() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.set_background(Background::Solid(Vector::new(50., 100., 150.)));
+
+        let config = Config::new(40, 62, 2.2, false, 2, 16, false, true, 0., 0., 1);
+        let preview = render_preview(&example_camera(&config), &scene, config, 4);
+        assert_eq!(preview.len(), 10 * 15 * 3);
+
+        let small_config = Config {
+            height: 10,
+            width: 15,
+            nb_rays: 1,
+            ..config
+        };
+        assert_eq!(
+            preview,
+            render_one_frame_to_buffer(&example_camera(&small_config), &scene, small_config, 0)
+        );
+    }
+
+    #[test]
+    fn same_seed_renders_the_same_image() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., -1000., 0.),
+            990.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            8.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.set_background(Background::Solid(Vector::new_eq(100.)));
+
+        // Anti-aliasing and bounces draw random numbers in every pixel, rendered in parallel
+        let mut config = Config::new(12, 16, 2.2, false, 3, 4, false, true, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        let first = render_radiance(&camera, &scene, config, 0);
+        config.tile_size = 4;
+        assert_eq!(render_radiance(&camera, &scene, config, 0), first);
+        config.threads = 2;
+        assert_eq!(render_radiance(&camera, &scene, config, 0), first);
+
+        config.seed = 1;
+        assert_ne!(render_radiance(&camera, &scene, config, 0), first);
+
+        config.packet_size = 4;
+        let packets = render_radiance(&camera, &scene, config, 0);
+        assert_eq!(render_radiance(&camera, &scene, config, 0), packets);
+    }
+
+    #[test]
+    fn open_shutter_streaks_moving_spheres() {
+        let mut scene = Scene::new();
+        let mut sphere = Sphere::new(
+            Vector::new(-10., 0., 0.),
+            5.,
+            Material::create_diffuse(Color::white()),
+        );
+        sphere.add_animation(Animation::translation(0., 1., Vector::new(20., 0., 0.)));
+        scene.add_object(Box::new(sphere));
+        scene.set_background(Background::Solid(Vector::new_eq(100.)));
+
+        // Without any bounce, hits are black and misses see the background
+        let mut config = Config::new(9, 31, 1., false, 0, 64, false, false, 0., 1., 1);
+        let camera = example_camera(&config);
+        let middle_row = |radiance: Vec<Vector>| -> Vec<f64> {
+            radiance[4 * config.width..5 * config.width]
+                .iter()
+                .map(|pixel| pixel.x)
+                .collect()
+        };
+
+        // The closed shutter sees the sphere on the left, sharp
+        let sharp = middle_row(render_radiance(&camera, &scene, config, 0));
+        assert!(sharp.iter().all(|value| *value == 0. || *value == 100.));
+        assert_eq!(sharp[15], 100.);
+
+        // The open shutter sees it sweep to the right: the streak covers the center partially
+        config.shutter = 1.;
+        let blurred = middle_row(render_radiance(&camera, &scene, config, 0));
+        assert!(blurred[15] > 0. && blurred[15] < 100.);
+        assert!(
+            blurred.iter().filter(|value| **value < 100.).count()
+                > 2 * sharp.iter().filter(|value| **value < 100.).count()
+        );
+    }
+
+    #[test]
+    fn stratified_aa_reduces_edge_variance() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., 0., 0.),
+            20.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.set_background(Background::Solid(Vector::new_eq(1.)));
+
+        // Without any bounce, each pixel averages the coverage of the background: pixels on the silhouette are noisy
+        let mut config = Config::new(12, 12, 1., false, 0, 16, false, true, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        let random = render_variance(&camera, &scene, config, 50);
+        config.stratified_aa = true;
+        let stratified = render_variance(&camera, &scene, config, 50);
+
+        assert!(random > 0.);
+        assert!(stratified < random * 0.5);
+    }
+
+    #[test]
+    fn radiance_clamp_removes_fireflies() {
+        // The scene of the crate example: a small bright light in a closed room
+        let mut scene = example_room();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., 0., 0.),
+            10.,
+            Material::create_diffuse(Color::new(0.8, 0.1, 1.0)),
+        )));
+
+        let mut config = Config::new(8, 8, 2.2, false, 3, 4, false, false, 0., 0., 1);
+        let camera = example_camera(&config);
+
+        let unclamped = render_variance(&camera, &scene, config, 20);
+        config.max_sample_radiance = 5e4;
+        let clamped = render_variance(&camera, &scene, config, 20);
+
+        assert!(clamped * 10. < unclamped);
+    }
+
+    #[test]
+    fn far_spheres_are_foggier() {
+        // Lit white spheres about 10 and 100 away from the origin, seen through a red fog
+        let (near_center, far_center) = (Vector::new(-2., 0., -11.), Vector::new(20., 0., -101.));
+        let mut scene = Scene::new();
+        for center in [near_center, far_center] {
+            scene.add_object(Box::new(Sphere::new(
+                center,
+                1.,
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        scene.add_light(crate::light::Light::new(
+            Vector::new_eq(0.),
+            Vector::new_eq(1e6),
+        ));
+        let config = Config::new(1, 1, 1., false, 1, 1, false, false, 0., 0., 1);
+        let shade = |scene: &Scene, direction: Vector| {
+            let ray = Ray::new(Vector::new_eq(0.), direction);
+            let inter = scene.compute_primary_intersection(ray, 0., false, &mut rand::thread_rng());
+            shade_primary(scene, config, ray, inter, 0, 0., &mut rand::thread_rng())
+        };
+        let (near_ray, far_ray) = (near_center.normalize(), far_center.normalize());
+        let (near_clear, far_clear) = (shade(&scene, near_ray), shade(&scene, far_ray));
+
+        scene.set_fog(Vector::new(100., 0., 0.), 0.01);
+        let (near, far) = (shade(&scene, near_ray), shade(&scene, far_ray));
+
+        // The part of the fog in the radiance of a sphere: 0. when clear, 1. when fully fogged
+        let fogginess = |fogged: Vector, clear: Vector| 1. - fogged.y / clear.y;
+        assert!(near_clear.y > 0. && far_clear.y > 0.);
+        let near_distance = near_center.norm() - 1.;
+        assert_approx_eq::assert_approx_eq!(
+            fogginess(near, near_clear),
+            1. - (-0.01 * near_distance).exp()
+        );
+        assert!(fogginess(far, far_clear) > 0.6);
+        assert!(far.x - far.y > near.x - near.y);
+
+        // The Rays escaping the Scene only see the fog
+        assert_eq!(
+            shade(&scene, Vector::new(0., 1., 0.)),
+            Vector::new(100., 0., 0.)
+        );
+    }
+
+    #[test]
+    fn saving_to_missing_directory_fails() {
+        let result = save_image(&[0; 12], "missing_directory/image.bmp", 2, 2);
+
+        assert!(matches!(result, Err(RenderError::Io(_))));
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_on_noise() {
+        use std::f64::consts::PI;
+
+        // A sphere lit by a small light object on the left of the image, the black background on the right
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(-25., 0., 0.),
+            20.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_light_object(Box::new(Sphere::new(
+            Vector::new(-10., 30., 30.),
+            3.,
+            Material::create_emissive(Color::white(), 1e7 / (4. * PI * 3. * 3.)),
+        )));
+
+        let config = Config::builder()
+            .height(6)
+            .width(8)
+            .nb_iter_max(2)
+            .nb_rays(4)
+            .adaptive_error(Some(0.02))
+            .adaptive_max_samples(64)
+            .build();
+        let camera = example_camera(&config);
+
+        let (radiance, sample_counts) =
+            radiance_with_progress(&camera, &scene, config, 0, &no_progress, &NEVER_CANCELLED);
+
+        assert!(sample_counts.iter().all(|count| (4..=64).contains(count)));
+        for (pixel, count) in radiance.iter().zip(&sample_counts) {
+            match pixel.x > 0. {
+                // The noisy lit pixels draw more Rays
+                true => assert!(*count > 4),
+                // The background is flat: one batch is enough
+                false => assert_eq!(*count, 4),
+            }
+        }
+        assert!(radiance.iter().any(|pixel| pixel.x == 0.));
+        assert!(radiance.iter().any(|pixel| pixel.x > 0.));
+        assert!(gbuffer::sample_count_pass(&sample_counts).contains(&255));
+    }
+
+    #[test]
+    fn renders_run_on_their_own_thread_pool() {
+        let mut config = Config::new(6, 8, 1., false, 1, 2, false, false, 0., 0., 1);
+        config.render_mode = RenderMode::Normals;
+        assert_eq!(
+            in_thread_pool(config, rayon::current_num_threads),
+            rayon::current_num_threads()
+        );
+
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let camera = example_camera(&config);
+        let all_cores = render_radiance(&camera, &scene, config, 0);
+
+        config.threads = 3;
+        assert_eq!(in_thread_pool(config, rayon::current_num_threads), 3);
+        assert_eq!(render_radiance(&camera, &scene, config, 0), all_cores);
+    }
+
+    #[test]
+    fn crop_only_renders_the_region() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            20.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_light(crate::light::Light::new(
+            Vector::new(0., 40., 40.),
+            Vector::new_eq(1e6),
+        ));
+        // A single bounce lit by a point light is deterministic, with or without packets
+        let mut config = Config::new(8, 10, 1., false, 1, 1, false, false, 0., 0., 1);
+        let camera = example_camera(&config);
+        let full = render_radiance(&camera, &scene, config, 0);
+
+        config.crop = Some((2, 1, 7, 5));
+        for packet_size in [1, 4] {
+            config.packet_size = packet_size;
+            let cropped = render_radiance(&camera, &scene, config, 0);
+
+            for (p, pixel) in cropped.iter().enumerate() {
+                let (i, j) = (p / config.width, p % config.width);
+                match (1..5).contains(&i) && (2..7).contains(&j) {
+                    true => assert_eq!(*pixel, full[p]),
+                    false => assert_eq!(*pixel, Vector::new_eq(0.)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frames_can_render_in_parallel() {
+        let mut config = Config::new(1, 1, 1., false, 1, 1, false, false, 0., 0., 12);
+
+        for parallelism in [Parallelism::Pixels, Parallelism::Frames] {
+            config.parallelism = parallelism;
+            let rendered = Mutex::new(Vec::new());
+            for_each_frame(config, |k| {
+                rendered.lock().unwrap().push(k);
+                Ok(())
+            })
+            .expect("every frame renders");
+
+            let mut rendered = rendered.into_inner().unwrap();
+            if parallelism == Parallelism::Pixels {
+                assert!(rendered.windows(2).all(|pair| pair[0] < pair[1]));
+            }
+            rendered.sort();
+            assert_eq!(rendered, (0..12).collect::<Vec<usize>>());
+
+            // A frame which can not be saved fails the render
+            let failing = for_each_frame(config, |k| match k {
+                5 => save_image(&[0; 3], "missing_directory/image.bmp", 1, 1),
+                _ => Ok(()),
+            });
+            assert!(matches!(failing, Err(RenderError::Io(_))));
+        }
+    }
+
+    #[test]
+    fn srgb_has_a_linear_segment_near_black() {
+        let mut config = Config::builder().gamma(2.2).build();
+        let white = 255_f64.powf(2.2);
+        let radiance = [
+            Vector::new(0.001 * white, 0.0001 * white, 0.),
+            Vector::new(0.5 * white, 1.01 * white, 2. * white),
+        ];
+
+        let power = tone_map(&radiance, config);
+        config.gamma_mode = GammaMode::Srgb;
+        let srgb = tone_map(&radiance, config);
+
+        // Near black, the power curve is much steeper than the linear segment of sRGB
+        assert_eq!(&power[..3], &[11, 3, 0]);
+        assert_eq!(&srgb[..3], &[3, 0, 0]);
+
+        // Both saturate past the same radiance, and stay close in the mid-tones
+        assert_eq!(&power[4..], &[255, 255]);
+        assert_eq!(&srgb[4..], &[255, 255]);
+        assert!((power[3] as i32 - srgb[3] as i32).abs() <= 3);
+    }
+
+    #[test]
+    fn sixteen_bit_png_keeps_smooth_gradients() {
+        let config = Config::builder()
+            .gamma(2.2)
+            .bit_depth(BitDepth::Sixteen)
+            .build();
+
+        // A dark gradient, spanning only a few levels of an 8 bits image
+        let radiance: Vec<Vector> = (0..64)
+            .map(|x| Vector::new_eq((1. + x as f64 / 64.).powf(2.2) * 20_f64.powf(2.2)))
+            .collect();
+        let levels_8: std::collections::HashSet<u8> =
+            tone_map(&radiance, config).into_iter().collect();
+        let image_16 = tone_map_16(&radiance, config);
+        let levels_16: std::collections::HashSet<u16> = image_16.iter().copied().collect();
+        assert!(levels_8.len() <= 21);
+        assert_eq!(levels_16.len(), 64);
+
+        // The 16 bits values match the 8 bits ones, scaled
+        assert_eq!(tone_map_16(&[Vector::new_eq(1e12)], config), vec![65535; 3]);
+        assert_eq!(
+            tone_map_16(&[Vector::new_eq(100_f64.powf(2.2))], config)[0],
+            100 * 257
+        );
+
+        let path = std::env::temp_dir().join("raytracer_sixteen_bit_png.png");
+        save_image_16(&image_16, path.to_str().unwrap(), 64, 1).expect("the PNG is written");
+        let read = image::open(&path).expect("the PNG is valid");
+        std::fs::remove_file(&path).ok();
+        match read {
+            image::DynamicImage::ImageRgb16(read) => assert_eq!(read.into_raw(), image_16),
+            _ => panic!("the PNG has 16 bits per color"),
+        }
+    }
+
+    #[test]
+    fn hdr_keeps_the_linear_radiance() {
+        let radiance = vec![
+            Vector::new(0., 0.5, 1.),
+            Vector::new(1e-3, 255., 4e6),
+            Vector::new(123.4, 56.78, 9.1),
+            Vector::new_eq(2e9),
+        ];
+
+        let path = std::env::temp_dir().join("raytracer_hdr_keeps_the_linear_radiance.hdr");
+        save_hdr(&radiance, &path, 2, 2).expect("the HDR image is written");
+        let read = image::codecs::hdr::HdrDecoder::new(std::io::BufReader::new(
+            File::open(&path).expect("the HDR image exists"),
+        ))
+        .expect("the HDR image is valid")
+        .read_image_hdr()
+        .expect("the pixels decode");
+        std::fs::remove_file(&path).ok();
+
+        // RGBE shares one exponent between the colors of a pixel: each one is precise relative to the brightest
+        assert_eq!(read.len(), 4);
+        for (expected, pixel) in radiance.iter().zip(read) {
+            let brightest = expected.x.max(expected.y).max(expected.z);
+            for (value, read) in [expected.x, expected.y, expected.z].iter().zip(pixel.0) {
+                assert!((read as f64 - value).abs() <= brightest / 128.);
+            }
+        }
+    }
+
+    #[test]
+    fn animation_is_exported_as_gif() {
+        use image::AnimationDecoder;
+
+        // A checkered sphere turning around the center of the Scene
+        let mut scene = Scene::new();
+        let mut sphere = Sphere::new(
+            Vector::new(8., 0., 0.),
+            4.,
+            Material::create_checker(Color::red(), Color::white(), 2.),
+        );
+        sphere.add_animation(Animation::rotation_y(0., 1., 324., Vector::new_eq(0.)));
+        scene.add_object(Box::new(sphere));
+        scene.add_light(crate::light::Light::new(
+            Vector::new(0., 20., 40.),
+            Vector::new_eq(1e7),
+        ));
+
+        let config = Config::builder()
+            .height(16)
+            .width(24)
+            .nb_rays(1)
+            .nb_iter_max(1)
+            .start_time(0.)
+            .end_time(1.)
+            .nb_frames(10)
+            .build();
+        let camera = example_camera(&config);
+        let frames: Vec<Vec<u8>> = (0..config.nb_frames)
+            .map(|k| render_one_frame_to_buffer(&camera, &scene, config, k))
+            .collect();
+
+        let path = std::env::temp_dir().join("raytracer_animation_is_exported_as_gif.gif");
+        export_gif(&frames, &path, 24, 16, 100).expect("the GIF is written");
+
+        let decoder = image::codecs::gif::GifDecoder::new(std::io::BufReader::new(
+            File::open(&path).expect("the GIF exists"),
+        ))
+        .expect("the GIF is valid");
+        let decoded = decoder
+            .into_frames()
+            .collect_frames()
+            .expect("every frame decodes");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(decoded.len(), 10);
+        assert!(decoded
+            .iter()
+            .all(|frame| frame.buffer().dimensions() == (24, 16)));
+        assert_eq!(decoded[0].delay().numer_denom_ms(), (100, 1));
+        assert!(decoded
+            .windows(2)
+            .all(|pair| pair[0].buffer() != pair[1].buffer()));
+
+        // A frame of the wrong size is reported instead of written
+        let mut frames = frames;
+        frames[3].pop();
+        assert!(matches!(
+            export_gif(&frames, &path, 24, 16, 100),
+            Err(RenderError::FrameSize {
+                index: 3,
+                expected: 1152,
+                len: 1151
+            })
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn progress_reports_every_row() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            6.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let mut config = Config::builder().height(6).width(4).nb_rays(2).build();
+        let camera = example_camera(&config);
+
+        for packet_size in [1, 4] {
+            config.packet_size = packet_size;
+            let calls = std::sync::Mutex::new(Vec::new());
+            let _ = frame_to_buffer(
+                &camera,
+                &scene,
+                config,
+                3,
+                &|frame, row, total_rows| calls.lock().unwrap().push((frame, row, total_rows)),
+                &NEVER_CANCELLED,
+            );
+
+            let calls = calls.into_inner().unwrap();
+            assert_eq!(calls.last(), Some(&(3, 6, 6)));
+            assert!(calls.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        }
+    }
+
+    #[test]
+    fn cancelled_render_stops_early() {
+        let mut scene = Scene::new();
+        scene.set_background(Background::Solid(Vector::new_eq(100.)));
+        let mut config = Config::builder().height(12).width(8).nb_rays(2).build();
+        config.tile_size = 4;
+        let camera = example_camera(&config);
+
+        // Cancelled before it starts, the frame stays black
+        let cancel = std::sync::Arc::new(AtomicBool::new(true));
+        let frame = render_one_frame_cancellable(&camera, &scene, config, 0, &cancel);
+        assert_eq!(frame.len(), 12 * 8 * 3);
+        assert!(frame.iter().all(|value| *value == 0));
+
+        // Cancelled once the first band of tiles is done, the rows below it stay black
+        for packet_size in [1, 4] {
+            config.packet_size = packet_size;
+            cancel.store(false, Ordering::Relaxed);
+            let (frame, sample_counts) = frame_to_buffer(
+                &camera,
+                &scene,
+                config,
+                0,
+                &|_, _, _| cancel.store(true, Ordering::Relaxed),
+                &cancel,
+            );
+
+            let row_is_black = |i: usize| frame[i * 8 * 3..(i + 1) * 8 * 3].iter().all(|v| *v == 0);
+            assert!((0..4).all(|i| !row_is_black(i)));
+            assert!((4..12).all(row_is_black));
+            assert!(sample_counts[4 * 8..].iter().all(|count| *count == 0));
+        }
+    }
+
+    #[test]
+    fn stats_count_the_rays_of_a_measured_render() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_light(crate::light::Light::new(
+            Vector::new(0., 30., 30.),
+            Vector::new_eq(1e5),
+        ));
+        let mut config = Config::builder()
+            .height(12)
+            .width(8)
+            .nb_rays(3)
+            .stats(true)
+            .build();
+        let camera = example_camera(&config);
+
+        for packet_size in [1, 4] {
+            config.packet_size = packet_size;
+            // An unmeasured render of the same Scene running meanwhile is not counted
+            let (measured, _) = rayon::join(
+                || measured_frame_radiance(&camera, &scene, config, 0, &no_progress),
+                || render_radiance(&camera, &scene, config, 0),
+            );
+            let stats = measured.1.expect("the render is measured");
+            assert_eq!(stats.primary_rays, 12 * 8 * 3);
+            // Each primary Ray hitting the sphere traces a shadow Ray, and the Rays tested against the sphere are counted
+            assert!(stats.secondary_rays > 0);
+            assert!(stats.intersection_tests >= stats.secondary_rays);
+        }
+
+        let unmeasured = Config {
+            stats: false,
+            ..config
+        };
+        assert!(
+            measured_frame_radiance(&camera, &scene, unmeasured, 0, &no_progress)
+                .1
+                .is_none()
+        );
+        assert_eq!(RenderStats::default().rays_per_second(), 0.);
+    }
 }
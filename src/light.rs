@@ -1,22 +1,69 @@
-use crate::animate::{Animatable, Animation};
+use crate::animate::{animate_emission, Animatable, Animation};
 use crate::ray::Ray;
 use crate::utils::{Color, Vector};
+use serde::{Deserialize, Serialize};
 
+/// # Falloff
+///
+/// How the intensity of a Light decreases with the distance to the lit point.
+/// Quadratic is the physical inverse-square law, the other modes are meant for stylized renders.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Falloff {
+    None,
+    Linear,
+    #[default]
+    Quadratic,
+}
+
+/// # Light
+///
+/// A point light. Its intensity is the product of its color and of its intensity, which may be given together (see new)
+/// or apart, like the emission of Material::create_emissive (see new_colored).
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Light {
     pub center: Vector,
     intensity: Vector,
+    /// Scene files written without it have white lights
+    #[serde(default = "Color::white")]
+    color: Color,
     animations: Vec<Animation>,
+    #[serde(default)]
+    falloff: Falloff,
 }
 
 impl Light {
+    /// The intensity is given color by color: a white Light of intensity 100. is Vector::new_eq(100.)
     pub fn new(center: Vector, intensity: Vector) -> Self {
         Light {
             center,
             intensity,
+            color: Color::white(),
             animations: Vec::new(),
+            falloff: Falloff::Quadratic,
+        }
+    }
+
+    /// The color and the intensity are kept apart, so that each can be tuned on its own (see set_color and set_intensity)
+    pub fn new_colored(center: Vector, color: Color, intensity: f64) -> Self {
+        Light {
+            color,
+            ..Light::new(center, Vector::new_eq(intensity))
         }
     }
 
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    /// Sets the same intensity for the three colors, leaving the tint of the Light to its color
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = Vector::new_eq(intensity);
+    }
+
+    pub fn set_falloff(&mut self, falloff: Falloff) {
+        self.falloff = falloff;
+    }
+
     pub fn get_intensity_local(
         &self,
         point: Vector,
@@ -31,10 +78,20 @@ impl Light {
         let light_dir = (fake_ray.origin - point).normalize();
         let apparent = light_dir.dot(normal).max(0.);
 
+        let (emission_color, emission_factor) =
+            animate_emission(Color::white(), 1., &self.animations, time);
+        let intensity = self.intensity * self.color * emission_color * emission_factor;
+
+        let attenuation = match self.falloff {
+            Falloff::None => 1.,
+            Falloff::Linear => (point - fake_ray.origin).norm(),
+            Falloff::Quadratic => (point - fake_ray.origin).norm_sq(),
+        };
+
         Vector {
-            x: self.intensity.x / (point - fake_ray.origin).norm_sq() * apparent * color.r,
-            y: self.intensity.y / (point - fake_ray.origin).norm_sq() * apparent * color.g,
-            z: self.intensity.z / (point - fake_ray.origin).norm_sq() * apparent * color.b,
+            x: intensity.x / attenuation * apparent * color.r,
+            y: intensity.y / attenuation * apparent * color.g,
+            z: intensity.z / attenuation * apparent * color.b,
         }
     }
 }
@@ -48,3 +105,51 @@ impl Animatable for Light {
         self.animations.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falloff_modes_at_one_and_two_meters() {
+        let up = Vector::new(0., 1., 0.);
+        let illumination = |falloff: Falloff, distance: f64| {
+            let mut light = Light::new(Vector::new(0., distance, 0.), Vector::new_eq(100.));
+            light.set_falloff(falloff);
+            light
+                .get_intensity_local(Vector::new_eq(0.), up, Color::white(), 0.)
+                .x
+        };
+
+        for (falloff, ratio) in [
+            (Falloff::None, 1.),
+            (Falloff::Linear, 2.),
+            (Falloff::Quadratic, 4.),
+        ] {
+            assert_approx_eq::assert_approx_eq!(illumination(falloff, 1.), 100.);
+            assert_approx_eq::assert_approx_eq!(
+                illumination(falloff, 1.) / illumination(falloff, 2.),
+                ratio
+            );
+        }
+    }
+
+    #[test]
+    fn colored_light_multiplies_its_color_and_intensity() {
+        let up = Vector::new(0., 1., 0.);
+        let orange = Color::new(1., 0.5, 0.);
+        let mut colored = Light::new_colored(Vector::new(0., 2., 0.), orange, 400.);
+        let combined = Light::new(Vector::new(0., 2., 0.), Vector::new(400., 200., 0.));
+
+        let lit =
+            |light: &Light| light.get_intensity_local(Vector::new_eq(0.), up, Color::white(), 0.);
+        assert_approx_eq::assert_approx_eq!((lit(&colored) - lit(&combined)).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!(lit(&colored).x, 100.);
+
+        // Each one is tuned without touching the other
+        colored.set_intensity(800.);
+        assert_approx_eq::assert_approx_eq!(lit(&colored).y, 100.);
+        colored.set_color(Color::white());
+        assert_approx_eq::assert_approx_eq!(lit(&colored).z, 200.);
+    }
+}
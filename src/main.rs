@@ -2,12 +2,20 @@ use std::f64::consts::PI;
 
 use raytracer::{
     camera::Camera,
+    error::RenderError,
     object::sphere::Sphere,
     scene::Scene,
     utils::{Color, Config, Material, Vector},
 };
 
-const CONFIG: Config = Config::new(500, 500, 2.2, true, 10, 200, false, true, 0., 100., 1);
+const CONFIG: Config = Config::builder()
+    .height(500)
+    .width(500)
+    .debug_info(true)
+    .nb_iter_max(10)
+    .nb_rays(200)
+    .end_time(100.)
+    .build();
 
 fn create_camera() -> Camera {
     if CONFIG.debug_info {
@@ -95,9 +103,9 @@ fn create_scene() -> Scene {
     scene
 }
 
-fn main() {
+fn main() -> Result<(), RenderError> {
     let camera = create_camera();
     let scene = create_scene();
 
-    raytracer::render_all_frames(&camera, &scene, CONFIG);
+    raytracer::render_all_frames(&camera, &scene, CONFIG)
 }
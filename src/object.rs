@@ -1,7 +1,14 @@
-use crate::animate::Animatable;
+use crate::animate::{Animatable, Animation};
 use crate::intersection::Intersection;
 use crate::ray::Ray;
-use crate::utils::{Material, Vector};
+use crate::utils::{Color, Material, Transform, Vector};
+use disk::Disk;
+use quad::Quad;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sphere::Sphere;
+use torus::Torus;
+use volume::ConstantMedium;
 
 /// # Object
 ///
@@ -12,6 +19,31 @@ pub trait Object: Animatable {
     fn intersection(&self, _ray: Ray) -> Option<Intersection> {
         None
     }
+
+    /// This function computes every Intersection of your object along a Ray (e.g. both the entry and the exit points of a sphere)
+    /// By default, only the closest one given by intersection is returned.
+    fn intersections(&self, ray: Ray) -> Vec<Intersection> {
+        self.intersection(ray).into_iter().collect()
+    }
+
+    /// This function tells if the Ray hits the object closer than a given (squared) distance from its origin
+    /// It is used for shadows, where only a yes / no answer is needed: override it when it can be computed faster than a full intersection.
+    fn intersects_before(&self, ray: Ray, max_dist_sq: f64) -> bool {
+        self.intersection(ray)
+            .is_some_and(|inter| (inter.point - ray.origin).norm_sq() <= max_dist_sq)
+    }
+
+    /// Same as intersection, for the objects whose Intersection is random (e.g. the scattering point of a ConstantMedium), which draw from rng
+    /// The Scene always goes through it, with the random number generator of the sample. By default, the Intersection does not depend on rng.
+    fn sample_intersection(&self, ray: Ray, _rng: &mut dyn RngCore) -> Option<Intersection> {
+        self.intersection(ray)
+    }
+
+    /// Same as intersects_before, drawing from rng like sample_intersection
+    fn sample_intersects_before(&self, ray: Ray, max_dist_sq: f64, _rng: &mut dyn RngCore) -> bool {
+        self.intersects_before(ray, max_dist_sq)
+    }
+
     fn get_material(&self) -> Material;
 
     fn get_surface_area(&self) -> f64 {
@@ -20,6 +52,131 @@ pub trait Object: Animatable {
     fn get_center(&self) -> Vector {
         Vector::new_eq(0.)
     }
+
+    /// Samples a point uniformly on the surface of the object, and returns it with the normal and the uv coordinates at that point
+    /// This is used to aim at area lights: the default (center, null normal) makes the object receive no direct light from it.
+    fn sample_point(&self, _rng: &mut dyn RngCore) -> (Vector, Vector, (f64, f64)) {
+        (self.get_center(), Vector::new_eq(0.), (0., 0.))
+    }
+
+    /// Samples a point on the surface of the object to light a receiving point, and returns it with the normal and the uv coordinates
+    /// at that point, and the density of the sample per unit of area. By default the point is sampled uniformly (see sample_point):
+    /// objects which can aim at the part of their surface facing the receiver override it, along with sample_pdf.
+    fn sample_point_towards(
+        &self,
+        _receiver: Vector,
+        rng: &mut dyn RngCore,
+    ) -> (Vector, Vector, (f64, f64), f64) {
+        let (point, normal, uv) = self.sample_point(rng);
+        (point, normal, uv, 1. / self.get_surface_area())
+    }
+
+    /// The density per unit of area with which sample_point_towards samples a point of the surface, to light a receiving point
+    fn sample_pdf(&self, _receiver: Vector, _point: Vector) -> f64 {
+        1. / self.get_surface_area()
+    }
+
+    /// This function computes the axis-aligned box (min corner, max corner) enclosing the object, used to build the bounding volume hierarchy of the Scene
+    /// By default the box is infinite, and the object is tested against every Ray (e.g. infinite planes).
+    fn bounding_box(&self) -> (Vector, Vector) {
+        (
+            Vector::new_eq(f64::NEG_INFINITY),
+            Vector::new_eq(f64::INFINITY),
+        )
+    }
+
+    /// The object-to-world Transform placing the object in the Scene, applied before its animations
+    /// By default, the object is defined directly in the world space.
+    fn get_transform(&self) -> Option<Transform> {
+        None
+    }
+
+    /// Whether the object blocks the light on its way to the other objects (see Scene::compute_shadows)
+    fn casts_shadows(&self) -> bool {
+        true
+    }
+
+    /// Whether the object is seen by the Rays of the camera (see Scene::compute_primary_intersection)
+    /// Hidden objects still cast shadows and show in reflections, e.g. to add the shadow of an object missing from a photograph.
+    fn visible_primary(&self) -> bool {
+        true
+    }
+
+    /// Describes the object as a Primitive, so that it can be saved in a scene file (see Scene::to_json)
+    /// Objects defined outside of the raytracer can not be saved, and return None.
+    fn to_primitive(&self) -> Option<Primitive> {
+        None
+    }
+}
+
+/// # Visibility
+///
+/// The visibility flags of the objects provided by the raytracer (see Object::casts_shadows and Object::visible_primary)
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Visibility {
+    pub casts_shadows: bool,
+    pub visible_primary: bool,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility {
+            casts_shadows: true,
+            visible_primary: true,
+        }
+    }
+}
+
+/// # Primitive
+///
+/// The objects provided by the raytracer, as they are written in scene files: each one is tagged with its "type".
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Primitive {
+    Sphere(Sphere),
+    Disk(Disk),
+    Quad(Quad),
+    Torus(Torus),
+    ConstantMedium {
+        boundary: Box<Primitive>,
+        density: f64,
+        scatter_color: Color,
+        animations: Vec<Animation>,
+        transform: Option<Transform>,
+    },
+}
+
+impl Primitive {
+    /// Builds the object described by the Primitive
+    pub fn into_object(self) -> Box<dyn Object + Sync> {
+        match self {
+            Primitive::Sphere(sphere) => Box::new(sphere),
+            Primitive::Disk(disk) => Box::new(disk),
+            Primitive::Quad(quad) => Box::new(quad),
+            Primitive::Torus(torus) => Box::new(torus),
+            Primitive::ConstantMedium {
+                boundary,
+                density,
+                scatter_color,
+                animations,
+                transform,
+            } => {
+                let mut medium =
+                    ConstantMedium::new(boundary.into_object(), density, scatter_color);
+                for animation in animations {
+                    medium.add_animation(animation);
+                }
+                if let Some(transform) = transform {
+                    medium.set_transform(transform);
+                }
+                Box::new(medium)
+            }
+        }
+    }
 }
 
+pub mod disk;
+pub mod quad;
 pub mod sphere;
+pub mod torus;
+pub mod volume;
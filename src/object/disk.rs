@@ -0,0 +1,273 @@
+use crate::animate::{Animatable, Animation};
+use crate::intersection::Intersection;
+use crate::object::{Object, Primitive, Visibility};
+use crate::ray::Ray;
+use crate::utils::{Material, Transform, Vector};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// # Disk
+///
+/// A flat disk, facing its normal. Used as an emissive object (see Scene::add_light_object), it only emits light
+/// on the side of its normal and gives soft shadows.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Disk {
+    center: Vector,
+    normal: Vector,
+    radius: f64,
+    material: Material,
+    animations: Vec<Animation>,
+    transform: Option<Transform>,
+    #[serde(default)]
+    visibility: Visibility,
+}
+
+impl Disk {
+    pub fn new(center: Vector, normal: Vector, radius: f64, material: Material) -> Disk {
+        Disk {
+            center,
+            normal: normal.normalize(),
+            radius,
+            material,
+            animations: Vec::new(),
+            transform: None,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// Places the Disk in the Scene
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = Some(transform);
+    }
+
+    /// Hides the Disk from the shadow Rays, so that it does not cast shadows
+    pub fn set_casts_shadows(&mut self, casts_shadows: bool) {
+        self.visibility.casts_shadows = casts_shadows;
+    }
+
+    /// Hides the Disk from the Rays of the camera, so that it only shows through its shadows and reflections
+    pub fn set_visible_primary(&mut self, visible_primary: bool) {
+        self.visibility.visible_primary = visible_primary;
+    }
+
+    /// Two unit Vectors spanning the plane of the Disk, orthogonal to each other and to its normal
+    fn basis(&self) -> (Vector, Vector) {
+        self.normal.orthonormal_basis()
+    }
+
+    /// Computes the uv coordinates of a point of the Disk: u goes around the center, v from the center (0.) to the rim (1.)
+    fn uv(&self, point: Vector) -> (f64, f64) {
+        let offset = point - self.center;
+        let (axis_u, axis_v) = self.basis();
+        let u = 0.5 + offset.dot(axis_v).atan2(offset.dot(axis_u)) / (2. * PI);
+        let v = offset.norm() / self.radius;
+        (u, v)
+    }
+}
+
+impl Animatable for Disk {
+    fn add_animation(&mut self, animation: Animation) {
+        self.animations.push(animation);
+    }
+
+    fn get_animations(&self) -> Vec<Animation> {
+        self.animations.clone()
+    }
+}
+
+impl Object for Disk {
+    // We intersect the plane of the Disk, then check that the point is close enough to its center
+    fn intersection(&self, ray: Ray) -> Option<Intersection> {
+        let denominator = ray.direction.dot(self.normal);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = (self.center - ray.origin).dot(self.normal) / denominator;
+        if t < 0. {
+            return None;
+        }
+
+        let point = ray.get_point(t);
+        let offset = point - self.center;
+        if offset.norm_sq() > self.radius * self.radius {
+            return None;
+        }
+
+        let inter = Intersection::new(point, self.normal, self.material, self.uv(point));
+
+        // Seen from behind, the normal faces the Ray
+        match denominator > 0. {
+            true => Some(Intersection {
+                normal: self.normal * (-1.),
+                front_face: false,
+                ..inter
+            }),
+            false => Some(inter),
+        }
+    }
+
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
+    fn get_surface_area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    fn get_center(&self) -> Vector {
+        self.center
+    }
+
+    fn sample_point(&self, rng: &mut dyn RngCore) -> (Vector, Vector, (f64, f64)) {
+        // The square root spreads the points uniformly over the area, instead of crowding the center
+        let r = self.radius * rng.gen_range(0.0..1.0_f64).sqrt();
+        let theta: f64 = rng.gen_range(0.0..(2. * PI));
+        let (u, v) = self.basis();
+        let point = self.center + u * (r * theta.cos()) + v * (r * theta.sin());
+
+        (point, self.normal, self.uv(point))
+    }
+
+    fn bounding_box(&self) -> (Vector, Vector) {
+        let n = self.normal;
+        let extent = Vector::new(
+            (1. - n.x * n.x).max(0.).sqrt(),
+            (1. - n.y * n.y).max(0.).sqrt(),
+            (1. - n.z * n.z).max(0.).sqrt(),
+        ) * self.radius;
+
+        (self.center - extent, self.center + extent)
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.visibility.casts_shadows
+    }
+
+    fn visible_primary(&self) -> bool {
+        self.visibility.visible_primary
+    }
+
+    fn to_primitive(&self) -> Option<Primitive> {
+        Some(Primitive::Disk(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+    use crate::object::sphere::Sphere;
+    use crate::scene::Scene;
+    use crate::utils::Color;
+
+    #[test]
+    fn disk_is_hit_inside_its_radius_only() {
+        let disk = Disk::new(
+            Vector::new(0., 2., 0.),
+            Vector::new(0., 1., 0.),
+            1.,
+            Material::create_diffuse(Color::white()),
+        );
+
+        let inter = disk
+            .intersection(Ray::new(Vector::new(0.5, 5., 0.), Vector::new(0., -1., 0.)))
+            .expect("the ray goes through the disk");
+        assert_approx_eq::assert_approx_eq!((inter.point - Vector::new(0.5, 2., 0.)).norm(), 0.);
+        assert!(inter.front_face);
+
+        let below = disk
+            .intersection(Ray::new(Vector::new(0.5, 0., 0.), Vector::new(0., 1., 0.)))
+            .expect("the ray goes through the disk");
+        assert!(!below.front_face);
+        assert_eq!(below.normal.y, -1.);
+
+        assert!(disk
+            .intersection(Ray::new(Vector::new(1.5, 5., 0.), Vector::new(0., -1., 0.)))
+            .is_none());
+        assert_approx_eq::assert_approx_eq!(disk.get_surface_area(), PI);
+    }
+
+    #[test]
+    fn disk_light_casts_soft_shadows() {
+        let floor = |light: &dyn Fn(&mut Scene)| {
+            let mut scene = Scene::new();
+            scene.add_object(Box::new(Disk::new(
+                Vector::new_eq(0.),
+                Vector::new(0., 1., 0.),
+                100.,
+                Material::create_diffuse(Color::white()),
+            )));
+            scene.add_object(Box::new(Sphere::new(
+                Vector::new(0., 5., 0.),
+                1.,
+                Material::create_diffuse(Color::white()),
+            )));
+            light(&mut scene);
+            scene
+        };
+
+        // The direct light received by the floor at a distance x from the foot of the occluder
+        let received = |scene: &Scene, x: f64, nb_samples: usize| {
+            let ray = Ray::new(Vector::new(x, 1., 0.), Vector::new(0., -1., 0.));
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the floor");
+            let sum: f64 = (0..nb_samples)
+                .map(|_| {
+                    let direct =
+                        scene.compute_direct(ray, inter, 1, 0., false, &mut rand::thread_rng())
+                            + scene.compute_point_light(
+                                inter,
+                                1,
+                                0.,
+                                false,
+                                &mut rand::thread_rng(),
+                            );
+                    direct.x
+                })
+                .sum();
+            sum / nb_samples as f64
+        };
+
+        let disk_scene = floor(&|scene| {
+            scene.add_light_object(Box::new(Disk::new(
+                Vector::new(0., 10., 0.),
+                Vector::new(0., -1., 0.),
+                3.,
+                Material::create_emissive(Color::white(), 1000.),
+            )))
+        });
+        let point_scene = floor(&|scene| {
+            scene.add_light(Light::new(Vector::new(0., 10., 0.), Vector::new_eq(1e5)))
+        });
+
+        // The point light gives a hard shadow: fully dark, then fully lit
+        let positions = [0., 0.5, 1., 1.5, 2., 2.5, 3.];
+        let point: Vec<f64> = positions
+            .iter()
+            .map(|x| received(&point_scene, *x, 1))
+            .collect();
+        let lit = point.last().copied().unwrap();
+        assert!(point.iter().all(|value| *value == 0. || *value > lit * 0.9));
+
+        // The disk light gives a penumbra: the light received grows smoothly away from the occluder
+        let disk: Vec<f64> = positions
+            .iter()
+            .map(|x| received(&disk_scene, *x, 4000))
+            .collect();
+        let lit = disk.last().copied().unwrap();
+        assert!(disk.windows(2).all(|pair| pair[0] < pair[1] * 1.05));
+        assert!(
+            disk.iter()
+                .filter(|value| **value > lit * 0.1 && **value < lit * 0.9)
+                .count()
+                >= 3
+        );
+    }
+}
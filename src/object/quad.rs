@@ -0,0 +1,238 @@
+use crate::animate::{Animatable, Animation};
+use crate::intersection::Intersection;
+use crate::object::{Object, Primitive, Visibility};
+use crate::ray::Ray;
+use crate::utils::{Material, Transform, Vector};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// # Quad
+///
+/// A parallelogram spanned by two edges from its origin, facing edge_u x edge_v. Used as an emissive object
+/// (see Scene::add_light_object), it is a panel light that only emits light on the side of its normal.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Quad {
+    origin: Vector,
+    edge_u: Vector,
+    edge_v: Vector,
+    material: Material,
+    animations: Vec<Animation>,
+    transform: Option<Transform>,
+    #[serde(default)]
+    visibility: Visibility,
+}
+
+impl Quad {
+    pub fn new(origin: Vector, edge_u: Vector, edge_v: Vector, material: Material) -> Quad {
+        Quad {
+            origin,
+            edge_u,
+            edge_v,
+            material,
+            animations: Vec::new(),
+            transform: None,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// Places the Quad in the Scene
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = Some(transform);
+    }
+
+    /// Hides the Quad from the shadow Rays, so that it does not cast shadows
+    pub fn set_casts_shadows(&mut self, casts_shadows: bool) {
+        self.visibility.casts_shadows = casts_shadows;
+    }
+
+    /// Hides the Quad from the Rays of the camera, so that it only shows through its shadows and reflections
+    pub fn set_visible_primary(&mut self, visible_primary: bool) {
+        self.visibility.visible_primary = visible_primary;
+    }
+
+    fn normal(&self) -> Vector {
+        self.edge_u.cross(self.edge_v).normalize()
+    }
+}
+
+impl Animatable for Quad {
+    fn add_animation(&mut self, animation: Animation) {
+        self.animations.push(animation);
+    }
+
+    fn get_animations(&self) -> Vec<Animation> {
+        self.animations.clone()
+    }
+}
+
+impl Object for Quad {
+    // We intersect the plane of the Quad, then express the point in the (edge_u, edge_v) coordinates:
+    // it is on the Quad when both coordinates are in [0, 1]
+    fn intersection(&self, ray: Ray) -> Option<Intersection> {
+        let cross = self.edge_u.cross(self.edge_v);
+        let area_sq = cross.norm_sq();
+        if area_sq == 0. {
+            return None;
+        }
+
+        let denominator = ray.direction.dot(cross);
+        if denominator.abs() < 1e-12 * area_sq.sqrt() {
+            return None;
+        }
+
+        let t = (self.origin - ray.origin).dot(cross) / denominator;
+        if t < 0. {
+            return None;
+        }
+
+        let point = ray.get_point(t);
+        let offset = point - self.origin;
+        let u = offset.cross(self.edge_v).dot(cross) / area_sq;
+        let v = self.edge_u.cross(offset).dot(cross) / area_sq;
+        if !(0. ..=1.).contains(&u) || !(0. ..=1.).contains(&v) {
+            return None;
+        }
+
+        let normal = self.normal();
+        let inter = Intersection::new(point, normal, self.material, (u, v));
+
+        // Seen from behind, the normal faces the Ray
+        match denominator > 0. {
+            true => Some(Intersection {
+                normal: normal * (-1.),
+                front_face: false,
+                ..inter
+            }),
+            false => Some(inter),
+        }
+    }
+
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
+    fn get_surface_area(&self) -> f64 {
+        self.edge_u.cross(self.edge_v).norm()
+    }
+
+    fn get_center(&self) -> Vector {
+        self.origin + (self.edge_u + self.edge_v) * 0.5
+    }
+
+    fn sample_point(&self, rng: &mut dyn RngCore) -> (Vector, Vector, (f64, f64)) {
+        let u: f64 = rng.gen_range(0.0..1.0);
+        let v: f64 = rng.gen_range(0.0..1.0);
+
+        (
+            self.origin + self.edge_u * u + self.edge_v * v,
+            self.normal(),
+            (u, v),
+        )
+    }
+
+    fn bounding_box(&self) -> (Vector, Vector) {
+        let corners = [
+            self.origin,
+            self.origin + self.edge_u,
+            self.origin + self.edge_v,
+            self.origin + self.edge_u + self.edge_v,
+        ];
+
+        corners
+            .iter()
+            .skip(1)
+            .fold((corners[0], corners[0]), |(min, max), corner| {
+                (
+                    Vector::new(
+                        min.x.min(corner.x),
+                        min.y.min(corner.y),
+                        min.z.min(corner.z),
+                    ),
+                    Vector::new(
+                        max.x.max(corner.x),
+                        max.y.max(corner.y),
+                        max.z.max(corner.z),
+                    ),
+                )
+            })
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.visibility.casts_shadows
+    }
+
+    fn visible_primary(&self) -> bool {
+        self.visibility.visible_primary
+    }
+
+    fn to_primitive(&self) -> Option<Primitive> {
+        Some(Primitive::Quad(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Color;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn panel() -> Quad {
+        Quad::new(
+            Vector::new(1., 2., 3.),
+            Vector::new(4., 0., 0.),
+            Vector::new(0., 0., 2.),
+            Material::create_diffuse(Color::white()),
+        )
+    }
+
+    #[test]
+    fn quad_is_hit_inside_its_edges_only() {
+        let quad = panel();
+        assert_approx_eq::assert_approx_eq!(quad.get_surface_area(), 8.);
+
+        let inter = quad
+            .intersection(Ray::new(Vector::new(2., 0., 4.), Vector::new(0., 1., 0.)))
+            .expect("the ray goes through the quad");
+        assert_approx_eq::assert_approx_eq!(inter.uv.0, 0.25);
+        assert_approx_eq::assert_approx_eq!(inter.uv.1, 0.5);
+
+        assert!(quad
+            .intersection(Ray::new(Vector::new(6., 0., 4.), Vector::new(0., 1., 0.)))
+            .is_none());
+        assert!(quad
+            .intersection(Ray::new(Vector::new(2., 0., 6.), Vector::new(0., 1., 0.)))
+            .is_none());
+    }
+
+    #[test]
+    fn sampled_points_are_uniform_over_the_quad() {
+        let quad = panel();
+        let mut rng = StdRng::seed_from_u64(0);
+        let nb_samples = 80_000;
+        let mut cells = [[0usize; 4]; 4];
+
+        for _ in 0..nb_samples {
+            let (point, normal, uv) = quad.sample_point(&mut rng);
+            assert_approx_eq::assert_approx_eq!(point.y, 2.);
+            assert_approx_eq::assert_approx_eq!(normal.y.abs(), 1.);
+
+            let u = (point.x - 1.) / 4.;
+            let v = (point.z - 3.) / 2.;
+            assert!((0. ..1.).contains(&u) && (0. ..1.).contains(&v));
+            assert_approx_eq::assert_approx_eq!(uv.0, u);
+            assert_approx_eq::assert_approx_eq!(uv.1, v);
+            cells[(u * 4.) as usize][(v * 4.) as usize] += 1;
+        }
+
+        // Each of the 16 cells of equal area gets its share of the samples
+        let expected = nb_samples as f64 / 16.;
+        for count in cells.iter().flatten() {
+            assert!((*count as f64 - expected).abs() < expected * 0.05);
+        }
+    }
+}
@@ -1,15 +1,21 @@
 use crate::animate::{Animatable, Animation};
 use crate::intersection::Intersection;
-use crate::object::Object;
+use crate::object::{Object, Primitive, Visibility};
 use crate::ray::Ray;
-use crate::utils::{Material, Vector};
+use crate::utils::{Material, Transform, Vector};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Sphere {
     center: Vector,
     radius: f64,
     material: Material,
     animations: Vec<Animation>,
+    transform: Option<Transform>,
+    #[serde(default)]
+    visibility: Visibility,
 }
 
 impl Sphere {
@@ -19,26 +25,29 @@ impl Sphere {
             radius,
             material,
             animations: Vec::new(),
+            transform: None,
+            visibility: Visibility::default(),
         }
     }
-}
 
-impl Animatable for Sphere {
-    fn add_animation(&mut self, animation: Animation) {
-        self.animations.push(animation);
+    /// Places the Sphere in the Scene, e.g. to stretch it into an ellipsoid
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = Some(transform);
     }
 
-    fn get_animations(&self) -> Vec<Animation> {
-        self.animations.clone()
+    /// Hides the Sphere from the shadow Rays, so that it does not cast shadows
+    pub fn set_casts_shadows(&mut self, casts_shadows: bool) {
+        self.visibility.casts_shadows = casts_shadows;
     }
-}
 
-impl Object for Sphere {
-    // We compute the distance from the ray to the center of the sphere.
-    // If it is less than the radius, we collide
-    fn intersection(&self, ray: Ray) -> Option<Intersection> {
-        let t;
+    /// Hides the Sphere from the Rays of the camera, so that it only shows through its shadows and reflections
+    pub fn set_visible_primary(&mut self, visible_primary: bool) {
+        self.visibility.visible_primary = visible_primary;
+    }
 
+    /// Solves the intersection equation between the Ray and the Sphere
+    /// Returns both solutions t1 <= t2 (the ray may enter and exit the sphere), or None if the Ray misses it
+    fn compute_roots(&self, ray: Ray) -> Option<(f64, f64)> {
         let vector_co: Vector = ray.origin - self.center;
         let a = ray.direction.norm_sq();
         let b = 2. * ray.direction.dot(vector_co);
@@ -49,25 +58,106 @@ impl Object for Sphere {
         if delta >= 0. {
             let t1 = (-b - delta.sqrt()) / (2. * a);
             let t2 = (-b + delta.sqrt()) / (2. * a);
-            if t2 >= 0. {
-                if t1 < 0. {
-                    t = t2;
-                } else {
-                    t = t1;
-                }
+            Some((t1, t2))
+        } else {
+            None
+        }
+    }
 
-                let point = ray.get_point(t);
-                let normal = (point - self.center).normalize();
+    /// Computes the cone of directions in which a point outside the Sphere sees it: its axis towards the center, the distance to the center,
+    /// the cosine of its half-angle and its solid angle. Returns None when the point is inside the Sphere.
+    fn visible_cap(&self, receiver: Vector) -> Option<(Vector, f64, f64, f64)> {
+        let to_center = self.center - receiver;
+        let distance = to_center.norm();
+        if distance <= self.radius {
+            return None;
+        }
 
-                Some(Intersection::new(point, normal, self.get_material()))
-            } else {
-                None
+        let sin_max_sq = (self.radius / distance).powi(2);
+        let cos_max = (1. - sin_max_sq).max(0.).sqrt();
+        // 1 - cos_max, without losing the precision of small caps
+        let solid_angle = 2. * PI * sin_max_sq / (1. + cos_max);
+        Some((to_center / distance, distance, cos_max, solid_angle))
+    }
+
+    /// Computes the spherical coordinates of a point given its normal: u goes around the vertical axis, v goes from the north pole (0.) to the south pole (1.)
+    fn uv(normal: Vector) -> (f64, f64) {
+        let u = 0.5 + normal.z.atan2(normal.x) / (2. * PI);
+        let v = normal.y.clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    fn intersection_at(&self, ray: Ray, t: f64) -> Intersection {
+        let point = ray.get_point(t);
+        let normal = (point - self.center).normalize();
+
+        let mut inter = Intersection::new(point, normal, self.get_material(), Self::uv(normal));
+
+        // The derivative of the point with respect to u, undefined at the poles
+        let tangent = Vector::new(-normal.z, 0., normal.x);
+        if tangent.norm_sq() > 1e-12 {
+            inter.tangent = tangent.normalize();
+        }
+        inter
+    }
+}
+
+impl Animatable for Sphere {
+    fn add_animation(&mut self, animation: Animation) {
+        self.animations.push(animation);
+    }
+
+    fn get_animations(&self) -> Vec<Animation> {
+        self.animations.clone()
+    }
+}
+
+impl Object for Sphere {
+    // We compute the distance from the ray to the center of the sphere.
+    // If it is less than the radius, we collide
+    fn intersection(&self, ray: Ray) -> Option<Intersection> {
+        let (t1, t2) = self.compute_roots(ray)?;
+
+        if t2 >= 0. {
+            match t1 < 0. {
+                // The Ray starts inside the Sphere: the normal faces the Ray
+                true => {
+                    let inter = self.intersection_at(ray, t2);
+                    Some(Intersection {
+                        normal: inter.normal * (-1.),
+                        front_face: false,
+                        ..inter
+                    })
+                }
+                false => Some(self.intersection_at(ray, t1)),
             }
         } else {
             None
         }
     }
 
+    fn intersections(&self, ray: Ray) -> Vec<Intersection> {
+        match self.compute_roots(ray) {
+            None => Vec::new(),
+            Some((t1, t2)) => [t1, t2]
+                .into_iter()
+                .filter(|t| *t >= 0.)
+                .map(|t| self.intersection_at(ray, t))
+                .collect(),
+        }
+    }
+
+    // We only need to know if one of the roots lies between the origin of the Ray and the maximum distance
+    fn intersects_before(&self, ray: Ray, max_dist_sq: f64) -> bool {
+        match self.compute_roots(ray) {
+            None => false,
+            Some((t1, t2)) => {
+                let t = if t1 < 0. { t2 } else { t1 };
+                t >= 0. && t * t * ray.direction.norm_sq() <= max_dist_sq
+            }
+        }
+    }
+
     fn get_material(&self) -> Material {
         self.material
     }
@@ -79,4 +169,207 @@ impl Object for Sphere {
     fn get_center(&self) -> Vector {
         self.center
     }
+
+    fn sample_point(&self, rng: &mut dyn RngCore) -> (Vector, Vector, (f64, f64)) {
+        let z: f64 = rng.gen_range(-1.0..1.0);
+        let phi: f64 = rng.gen_range(0.0..(2. * PI));
+        let r = (1. - z * z).sqrt();
+
+        let normal = Vector::new(r * phi.cos(), r * phi.sin(), z);
+
+        (self.center + normal * self.radius, normal, Self::uv(normal))
+    }
+
+    // Seen from outside, the Sphere covers a cone of directions around its center: the directions are sampled uniformly in the cone,
+    // which only aims at the cap facing the receiver. From inside, the whole Sphere is seen and the points are sampled uniformly.
+    fn sample_point_towards(
+        &self,
+        receiver: Vector,
+        rng: &mut dyn RngCore,
+    ) -> (Vector, Vector, (f64, f64), f64) {
+        let cap = match self.visible_cap(receiver) {
+            None => {
+                let (point, normal, uv) = self.sample_point(rng);
+                return (point, normal, uv, 1. / self.get_surface_area());
+            }
+            Some(cap) => cap,
+        };
+        let (axis, distance, cos_max, solid_angle) = cap;
+
+        let (u, v) = axis.orthonormal_basis();
+
+        let cos_theta = 1. - rng.gen_range(0.0..1.0) * (1. - cos_max);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi: f64 = rng.gen_range(0.0..(2. * PI));
+        let direction =
+            u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + axis * cos_theta;
+
+        // The closest crossing of the Sphere along the direction, which grazes it at the edge of the cone
+        let t = distance * cos_theta
+            - (self.radius * self.radius - distance * distance * sin_theta * sin_theta)
+                .max(0.)
+                .sqrt();
+        let point = receiver + direction * t;
+        let normal = (point - self.center).normalize();
+        let cos_light = normal.dot(direction * (-1.)).max(0.);
+
+        (
+            point,
+            normal,
+            Self::uv(normal),
+            cos_light / (t * t * solid_angle),
+        )
+    }
+
+    fn sample_pdf(&self, receiver: Vector, point: Vector) -> f64 {
+        let (_, _, _, solid_angle) = match self.visible_cap(receiver) {
+            None => return 1. / self.get_surface_area(),
+            Some(cap) => cap,
+        };
+
+        let to_receiver = receiver - point;
+        let cos_light = (point - self.center)
+            .normalize()
+            .dot(to_receiver.normalize());
+        match cos_light > 0. {
+            true => cos_light / (to_receiver.norm_sq() * solid_angle),
+            false => 0.,
+        }
+    }
+
+    fn bounding_box(&self) -> (Vector, Vector) {
+        (
+            self.center - Vector::new_eq(self.radius),
+            self.center + Vector::new_eq(self.radius),
+        )
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.visibility.casts_shadows
+    }
+
+    fn visible_primary(&self) -> bool {
+        self.visibility.visible_primary
+    }
+
+    fn to_primitive(&self) -> Option<Primitive> {
+        Some(Primitive::Sphere(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Color;
+
+    fn uv_at(origin: Vector) -> (f64, f64) {
+        let sphere = Sphere::new(
+            Vector::new_eq(0.),
+            1.,
+            Material::create_diffuse(Color::white()),
+        );
+        let ray = Ray::new(origin, origin * (-1.));
+        sphere.intersection(ray).unwrap().uv
+    }
+
+    #[test]
+    fn sphere_uv_at_poles_and_equator() {
+        assert_approx_eq::assert_approx_eq!(uv_at(Vector::new(0., 10., 0.)).1, 0.);
+        assert_approx_eq::assert_approx_eq!(uv_at(Vector::new(0., -10., 0.)).1, 1.);
+
+        let (u, v) = uv_at(Vector::new(10., 0., 0.));
+        assert_approx_eq::assert_approx_eq!(u, 0.5);
+        assert_approx_eq::assert_approx_eq!(v, 0.5);
+
+        let (u, v) = uv_at(Vector::new(0., 0., 10.));
+        assert_approx_eq::assert_approx_eq!(u, 0.75);
+        assert_approx_eq::assert_approx_eq!(v, 0.5);
+    }
+
+    /// The Sphere sampled as before cap sampling: uniformly over its whole surface
+    struct UniformSphere(Sphere);
+
+    impl Animatable for UniformSphere {
+        fn add_animation(&mut self, animation: Animation) {
+            self.0.add_animation(animation);
+        }
+
+        fn get_animations(&self) -> Vec<Animation> {
+            self.0.get_animations()
+        }
+    }
+
+    impl Object for UniformSphere {
+        fn get_material(&self) -> Material {
+            self.0.get_material()
+        }
+
+        fn get_surface_area(&self) -> f64 {
+            self.0.get_surface_area()
+        }
+
+        fn sample_point(&self, rng: &mut dyn RngCore) -> (Vector, Vector, (f64, f64)) {
+            self.0.sample_point(rng)
+        }
+    }
+
+    #[test]
+    fn cap_sampling_converges_with_less_noise() {
+        use crate::object::disk::Disk;
+        use crate::scene::Scene;
+        use crate::testing::mean_and_variance;
+
+        // A large light close to a diffuse floor: most of its surface is hidden from the floor
+        let light = Sphere::new(
+            Vector::new(0., 4., 0.),
+            3.,
+            Material::create_emissive(Color::white(), 100.),
+        );
+        let scene = |light: Box<dyn Object + Sync>| {
+            let mut scene = Scene::new();
+            scene.add_object(Box::new(Disk::new(
+                Vector::new_eq(0.),
+                Vector::new(0., 1., 0.),
+                100.,
+                Material::create_diffuse(Color::white()),
+            )));
+            scene.add_light_object(light);
+            scene
+        };
+
+        let ray = Ray::new(Vector::new(2., 0.5, 0.), Vector::new(0., -1., 0.));
+        let nb_samples = 100_000;
+        let direct = |scene: &Scene| {
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the floor");
+            let mut rng = rand::thread_rng();
+            mean_and_variance(nb_samples, || {
+                scene.compute_direct(ray, inter, 1, 0., false, &mut rng).x
+            })
+        };
+
+        let (uniform, uniform_variance) = direct(&scene(Box::new(UniformSphere(light.clone()))));
+        let (cap, cap_variance) = direct(&scene(Box::new(light.clone())));
+
+        let standard_error = ((uniform_variance + cap_variance) / nb_samples as f64).sqrt();
+        assert!(cap > 0.);
+        assert!((cap - uniform).abs() < 5. * standard_error);
+        assert!(cap_variance * 2. < uniform_variance);
+
+        // The sampled points face the receiver, with the density given by sample_pdf
+        let mut rng = rand::thread_rng();
+        let receiver = Vector::new(2., 0., 0.);
+        for _ in 0..100 {
+            let (point, normal, uv, pdf) = light.sample_point_towards(receiver, &mut rng);
+            assert_approx_eq::assert_approx_eq!((point - light.center).norm(), 3.);
+            assert!(normal.dot(receiver - point) >= 0.);
+            assert_approx_eq::assert_approx_eq!(pdf, light.sample_pdf(receiver, point));
+            assert_eq!(uv, Sphere::uv(normal));
+        }
+    }
 }
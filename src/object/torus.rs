@@ -0,0 +1,366 @@
+use crate::animate::{Animatable, Animation};
+use crate::intersection::Intersection;
+use crate::object::{Object, Primitive, Visibility};
+use crate::ray::Ray;
+use crate::utils::{Material, Transform, Vector};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// # Torus
+///
+/// A ring around an axis going through its center: the points at a distance minor_radius from the circle of radius major_radius.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Torus {
+    center: Vector,
+    axis: Vector,
+    major_radius: f64,
+    minor_radius: f64,
+    material: Material,
+    animations: Vec<Animation>,
+    transform: Option<Transform>,
+    #[serde(default)]
+    visibility: Visibility,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vector,
+        axis: Vector,
+        major_radius: f64,
+        minor_radius: f64,
+        material: Material,
+    ) -> Torus {
+        Torus {
+            center,
+            axis: axis.normalize(),
+            major_radius,
+            minor_radius,
+            material,
+            animations: Vec::new(),
+            transform: None,
+            visibility: Visibility::default(),
+        }
+    }
+
+    /// Places the Torus in the Scene
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = Some(transform);
+    }
+
+    /// Hides the Torus from the shadow Rays, so that it does not cast shadows
+    pub fn set_casts_shadows(&mut self, casts_shadows: bool) {
+        self.visibility.casts_shadows = casts_shadows;
+    }
+
+    /// Hides the Torus from the Rays of the camera, so that it only shows through its shadows and reflections
+    pub fn set_visible_primary(&mut self, visible_primary: bool) {
+        self.visibility.visible_primary = visible_primary;
+    }
+
+    /// Two unit Vectors spanning the plane of the ring, orthogonal to each other and to the axis
+    fn basis(&self) -> (Vector, Vector) {
+        self.axis.orthonormal_basis()
+    }
+
+    /// Expresses a Vector in the frame of the Torus, whose axis is z
+    fn to_local(&self, vector: Vector) -> Vector {
+        let (u, v) = self.basis();
+        Vector::new(vector.dot(u), vector.dot(v), vector.dot(self.axis))
+    }
+
+    /// Computes the uv coordinates of a point of the Torus: u goes around the axis, v around the tube
+    fn uv(&self, point: Vector) -> (f64, f64) {
+        let local = self.to_local(point - self.center);
+        let around_axis = local.y.atan2(local.x);
+        let around_tube = local
+            .z
+            .atan2((local.x * local.x + local.y * local.y).sqrt() - self.major_radius);
+        (0.5 + around_axis / (2. * PI), 0.5 + around_tube / (2. * PI))
+    }
+
+    /// Computes the distances along the Ray at which it crosses the Torus, in increasing order
+    fn compute_roots(&self, ray: Ray) -> Vec<f64> {
+        let o = self.to_local(ray.origin - self.center);
+        let d = self.to_local(ray.direction);
+        let (major, minor) = (self.major_radius, self.minor_radius);
+
+        // The Ray can only hit the Torus inside its bounding sphere, which brackets the roots
+        let (dd, od, oo) = (d.norm_sq(), o.dot(d), o.norm_sq());
+        let outer = major + minor;
+        let delta = od * od - dd * (oo - outer * outer);
+        if delta < 0. || dd == 0. {
+            return Vec::new();
+        }
+        let t_min = ((-od - delta.sqrt()) / dd).max(0.);
+        let t_max = (-od + delta.sqrt()) / dd;
+        if t_max < 0. {
+            return Vec::new();
+        }
+
+        // (|p|² + R² - r²)² - 4R²(px² + py²) = 0, expanded in powers of t
+        let k = oo + major * major - minor * minor;
+        let dxy = d.x * d.x + d.y * d.y;
+        let odxy = o.x * d.x + o.y * d.y;
+        let oxy = o.x * o.x + o.y * o.y;
+        let quartic = [
+            k * k - 4. * major * major * oxy,
+            4. * od * k - 8. * major * major * odxy,
+            4. * od * od + 2. * dd * k - 4. * major * major * dxy,
+            4. * dd * od,
+            dd * dd,
+        ];
+
+        real_roots(&quartic, t_min, t_max)
+    }
+
+    fn intersection_at(&self, ray: Ray, t: f64) -> Intersection {
+        let point = ray.get_point(t);
+        let local = self.to_local(point - self.center);
+
+        // The normal points away from the closest point of the circle of radius major_radius
+        let (u, v) = self.basis();
+        let radial = (u * local.x + v * local.y).normalize();
+        let normal = (point - self.center - radial * self.major_radius).normalize();
+
+        let mut inter = Intersection::new(point, normal, self.material, self.uv(point));
+        inter.tangent = self.axis.cross(radial);
+        inter
+    }
+}
+
+/// Finds the real roots in [min, max] of the polynomial whose coefficients are given from the constant term up, in increasing order
+/// The roots of the derivative split the interval into pieces where the polynomial is monotonic: each holds at most one root, found by bisection.
+fn real_roots(coefficients: &[f64], min: f64, max: f64) -> Vec<f64> {
+    let degree = match coefficients.iter().rposition(|c| *c != 0.) {
+        None | Some(0) => return Vec::new(),
+        Some(degree) => degree,
+    };
+    let coefficients = &coefficients[..=degree];
+
+    if degree == 1 {
+        let root = -coefficients[0] / coefficients[1];
+        return match (min..=max).contains(&root) {
+            true => vec![root],
+            false => Vec::new(),
+        };
+    }
+
+    let evaluate = |t: f64| coefficients.iter().rev().fold(0., |acc, c| acc * t + c);
+    let derivative: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, c)| power as f64 * c)
+        .collect();
+
+    let mut bounds = vec![min];
+    bounds.extend(real_roots(&derivative, min, max));
+    bounds.push(max);
+
+    let mut roots = Vec::new();
+    for pair in bounds.windows(2) {
+        let (mut low, mut high) = (pair[0], pair[1]);
+        let (value_low, value_high) = (evaluate(low), evaluate(high));
+        if value_low == 0. {
+            if roots.last() != Some(&low) {
+                roots.push(low);
+            }
+            continue;
+        }
+        if value_low.signum() == value_high.signum() {
+            continue;
+        }
+
+        while high - low > 1e-12 * high.abs().max(1.) {
+            let middle = 0.5 * (low + high);
+            match evaluate(middle).signum() == value_low.signum() {
+                true => low = middle,
+                false => high = middle,
+            }
+        }
+        roots.push(0.5 * (low + high));
+    }
+    if evaluate(max) == 0. && roots.last() != Some(&max) {
+        roots.push(max);
+    }
+    roots
+}
+
+impl Animatable for Torus {
+    fn add_animation(&mut self, animation: Animation) {
+        self.animations.push(animation);
+    }
+
+    fn get_animations(&self) -> Vec<Animation> {
+        self.animations.clone()
+    }
+}
+
+impl Object for Torus {
+    // The ray crosses the Torus up to four times: the closest crossing is the root of a quartic polynomial
+    fn intersection(&self, ray: Ray) -> Option<Intersection> {
+        let t = *self.compute_roots(ray).first()?;
+        let inter = self.intersection_at(ray, t);
+
+        // The Ray starts inside the tube: the normal faces the Ray
+        match ray.direction.dot(inter.normal) > 0. {
+            true => Some(Intersection {
+                normal: inter.normal * (-1.),
+                front_face: false,
+                ..inter
+            }),
+            false => Some(inter),
+        }
+    }
+
+    fn intersections(&self, ray: Ray) -> Vec<Intersection> {
+        self.compute_roots(ray)
+            .into_iter()
+            .map(|t| self.intersection_at(ray, t))
+            .collect()
+    }
+
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
+    fn get_surface_area(&self) -> f64 {
+        4. * PI * PI * self.major_radius * self.minor_radius
+    }
+
+    fn get_center(&self) -> Vector {
+        self.center
+    }
+
+    fn sample_point(&self, rng: &mut dyn RngCore) -> (Vector, Vector, (f64, f64)) {
+        let (u, v) = self.basis();
+
+        // The outer side of the tube is larger than the inner side: the angle around the tube is sampled by rejection
+        loop {
+            let around_axis: f64 = rng.gen_range(0.0..(2. * PI));
+            let around_tube: f64 = rng.gen_range(0.0..(2. * PI));
+            let acceptance = (self.major_radius + self.minor_radius * around_tube.cos())
+                / (self.major_radius + self.minor_radius);
+            if rng.gen_range(0.0..1.0) < acceptance {
+                let radial = u * around_axis.cos() + v * around_axis.sin();
+                let normal = radial * around_tube.cos() + self.axis * around_tube.sin();
+                let point = self.center + radial * self.major_radius + normal * self.minor_radius;
+                return (point, normal, self.uv(point));
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> (Vector, Vector) {
+        let n = self.axis;
+        let extent = Vector::new(
+            (1. - n.x * n.x).max(0.).sqrt(),
+            (1. - n.y * n.y).max(0.).sqrt(),
+            (1. - n.z * n.z).max(0.).sqrt(),
+        ) * self.major_radius
+            + Vector::new_eq(self.minor_radius);
+
+        (self.center - extent, self.center + extent)
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.visibility.casts_shadows
+    }
+
+    fn visible_primary(&self) -> bool {
+        self.visibility.visible_primary
+    }
+
+    fn to_primitive(&self) -> Option<Primitive> {
+        Some(Primitive::Torus(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Color;
+
+    fn ring() -> Torus {
+        Torus::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 1., 0.),
+            3.,
+            1.,
+            Material::create_diffuse(Color::white()),
+        )
+    }
+
+    #[test]
+    fn ray_through_the_hole_misses() {
+        let torus = ring();
+
+        assert!(torus
+            .intersection(Ray::new(Vector::new(0., 10., 0.), Vector::new(0., -1., 0.)))
+            .is_none());
+        assert!(torus
+            .intersection(Ray::new(
+                Vector::new(1.5, -10., 0.),
+                Vector::new(0., 1., 0.)
+            ))
+            .is_none());
+    }
+
+    #[test]
+    fn ray_hitting_the_ring_gets_its_normal() {
+        let torus = ring();
+
+        // From above, straight onto the top of the tube
+        let inter = torus
+            .intersection(Ray::new(Vector::new(3., 10., 0.), Vector::new(0., -1., 0.)))
+            .expect("the ray hits the top of the tube");
+        assert_approx_eq::assert_approx_eq!((inter.point - Vector::new(3., 1., 0.)).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!(inter.normal.y, 1.);
+
+        // From the side, the ray crosses the ring four times and stops at its outer edge
+        let ray = Ray::new(Vector::new(-10., 0.5, 0.), Vector::new(1., 0., 0.));
+        let inter = torus.intersection(ray).expect("the ray hits the ring");
+        let expected_x = -(3. + (1. - 0.25_f64).sqrt());
+        assert_approx_eq::assert_approx_eq!(inter.point.x, expected_x);
+        assert_approx_eq::assert_approx_eq!(inter.normal.norm(), 1.);
+        assert!(inter.normal.x < 0. && inter.normal.y > 0.);
+        assert_eq!(torus.intersections(ray).len(), 4);
+
+        // A ray going sideways through the tube at the center of the Torus misses
+        assert!(torus
+            .intersection(Ray::new(
+                Vector::new(-10., 1.5, 0.),
+                Vector::new(1., 0., 0.)
+            ))
+            .is_none());
+    }
+
+    #[test]
+    fn sampled_points_lie_on_the_torus() {
+        let torus = Torus::new(
+            Vector::new(1., 2., 3.),
+            Vector::new(1., 1., 0.),
+            3.,
+            1.,
+            Material::create_diffuse(Color::white()),
+        );
+        let (min, max) = torus.bounding_box();
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let (point, normal, uv) = torus.sample_point(&mut rng);
+            let inter = torus
+                .intersection(Ray::new(point + normal * 2., normal * (-1.)))
+                .expect("the point is on the surface");
+            assert!((inter.point - point).norm() < 1e-6);
+            assert_approx_eq::assert_approx_eq!(inter.uv.0, uv.0, 1e-6);
+            assert_approx_eq::assert_approx_eq!(inter.uv.1, uv.1, 1e-6);
+            assert!(point.x >= min.x && point.y >= min.y && point.z >= min.z);
+            assert!(point.x <= max.x && point.y <= max.y && point.z <= max.z);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::animate::{Animatable, Animation};
+use crate::intersection::Intersection;
+use crate::object::{Object, Primitive};
+use crate::ray::Ray;
+use crate::utils::{Color, Material, Transform, Vector};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+/// # ConstantMedium
+///
+/// A volume of constant density (fog, smoke) confined inside a boundary Object.
+/// A Ray crossing the volume scatters at a random distance (following the Beer-Lambert law): the denser the medium, the sooner.
+/// At the scattering point, the returned Intersection has a random normal and a diffuse Material of the scatter color, so that light bounces in every direction.
+/// The Scene draws these random numbers from the generator of the sample (see Object::sample_intersection); without one,
+/// they come from a stream seeded by the Ray, so that a render stays reproducible.
+pub struct ConstantMedium {
+    boundary: Box<dyn Object + Sync>,
+    density: f64,
+    material: Material,
+    animations: Vec<Animation>,
+    transform: Option<Transform>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Object + Sync>, density: f64, scatter_color: Color) -> Self {
+        ConstantMedium {
+            boundary,
+            density,
+            material: Material::create_diffuse(scatter_color),
+            animations: Vec::new(),
+            transform: None,
+        }
+    }
+
+    /// Places the volume in the Scene (the Transform of the boundary itself is ignored)
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = Some(transform);
+    }
+
+    /// Creates the random number generator of the intersections computed without one: the same Ray always scatters at the same point
+    fn ray_rng(ray: Ray) -> StdRng {
+        let coordinates = [
+            ray.origin.x,
+            ray.origin.y,
+            ray.origin.z,
+            ray.direction.x,
+            ray.direction.y,
+            ray.direction.z,
+        ];
+        let seed = coordinates.iter().fold(0_u64, |state, coordinate| {
+            (state ^ coordinate.to_bits())
+                .wrapping_mul(0x9e3779b97f4a7c15)
+                .rotate_left(29)
+        });
+        StdRng::seed_from_u64(seed)
+    }
+}
+
+impl Animatable for ConstantMedium {
+    fn add_animation(&mut self, animation: Animation) {
+        self.animations.push(animation);
+    }
+
+    fn get_animations(&self) -> Vec<Animation> {
+        self.animations.clone()
+    }
+}
+
+impl Object for ConstantMedium {
+    fn intersection(&self, ray: Ray) -> Option<Intersection> {
+        self.sample_intersection(ray, &mut Self::ray_rng(ray))
+    }
+
+    // We find where the Ray enters and exits the boundary, then sample the distance travelled before scattering
+    fn sample_intersection(&self, ray: Ray, rng: &mut dyn RngCore) -> Option<Intersection> {
+        let mut hits: Vec<Vector> = self
+            .boundary
+            .intersections(ray)
+            .iter()
+            .map(|inter| inter.point)
+            .collect();
+        hits.sort_by(|a, b| {
+            (*a - ray.origin)
+                .norm_sq()
+                .total_cmp(&(*b - ray.origin).norm_sq())
+        });
+
+        // With an odd number of hits, the Ray starts inside the volume
+        let (entry, exit) = match hits.len() {
+            0 => return None,
+            n if n % 2 == 1 => (ray.origin, hits[0]),
+            _ => (hits[0], hits[1]),
+        };
+
+        let rand: f64 = rng.gen_range(0.0..1.0);
+        let distance = -(1. - rand).ln() / self.density;
+
+        if distance >= (exit - entry).norm() {
+            return None;
+        }
+
+        let point = entry + ray.direction.normalize() * distance;
+        let normal = Vector::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        )
+        .normalize();
+
+        Some(Intersection::new(point, normal, self.material, (0., 0.)))
+    }
+
+    fn sample_intersects_before(&self, ray: Ray, max_dist_sq: f64, rng: &mut dyn RngCore) -> bool {
+        self.sample_intersection(ray, rng)
+            .is_some_and(|inter| (inter.point - ray.origin).norm_sq() <= max_dist_sq)
+    }
+
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
+    fn get_surface_area(&self) -> f64 {
+        self.boundary.get_surface_area()
+    }
+
+    fn get_center(&self) -> Vector {
+        self.boundary.get_center()
+    }
+
+    fn sample_point(&self, rng: &mut dyn RngCore) -> (Vector, Vector, (f64, f64)) {
+        self.boundary.sample_point(rng)
+    }
+
+    fn bounding_box(&self) -> (Vector, Vector) {
+        self.boundary.bounding_box()
+    }
+
+    fn to_primitive(&self) -> Option<Primitive> {
+        Some(Primitive::ConstantMedium {
+            boundary: Box::new(self.boundary.to_primitive()?),
+            density: self.density,
+            scatter_color: self.material.color,
+            animations: self.animations.clone(),
+            transform: self.transform,
+        })
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        self.transform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::sphere::Sphere;
+
+    /// Averages the distance travelled inside the medium before scattering, for the Rays that do scatter
+    fn average_traversal(density: f64) -> f64 {
+        let boundary = Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_diffuse(Color::white()),
+        ));
+        let medium = ConstantMedium::new(boundary, density, Color::white());
+        let ray = Ray::new(Vector::new(0., 0., 20.), Vector::new(0., 0., -1.));
+
+        let mut rng = rand::thread_rng();
+        let distances: Vec<f64> = (0..5000)
+            .filter_map(|_| medium.sample_intersection(ray, &mut rng))
+            .map(|inter| 10. - inter.point.z)
+            .collect();
+
+        distances.iter().sum::<f64>() / distances.len() as f64
+    }
+
+    #[test]
+    fn denser_medium_scatters_sooner() {
+        assert!(average_traversal(0.5) < average_traversal(0.05));
+    }
+
+    #[test]
+    fn scattering_is_reproducible() {
+        let boundary = Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_diffuse(Color::white()),
+        ));
+        let medium = ConstantMedium::new(boundary, 0.1, Color::white());
+        let ray = Ray::new(Vector::new(0., 0., 20.), Vector::new(0., 0., -1.));
+        let scattered = |rng: &mut dyn RngCore| {
+            (0..20)
+                .map(|_| {
+                    medium
+                        .sample_intersection(ray, rng)
+                        .map(|inter| inter.point.z)
+                })
+                .collect::<Vec<Option<f64>>>()
+        };
+
+        // The same seed scatters the Rays at the same points
+        assert_eq!(
+            scattered(&mut StdRng::seed_from_u64(7)),
+            scattered(&mut StdRng::seed_from_u64(7))
+        );
+        assert_ne!(
+            scattered(&mut StdRng::seed_from_u64(7)),
+            scattered(&mut StdRng::seed_from_u64(8))
+        );
+
+        // Without a generator, the point only depends on the Ray
+        let point = |ray: Ray| medium.intersection(ray).map(|inter| inter.point.z);
+        assert_eq!(point(ray), point(ray));
+    }
+}
@@ -0,0 +1,177 @@
+//! # Progressive
+//!
+//! This module renders the first frame progressively, for interactive viewers: each pass adds one sample to every pixel,
+//! so that the image refines over time instead of appearing all at once.
+
+use crate::camera::Camera;
+use crate::scene::Scene;
+use crate::utils::{Config, Vector};
+use crate::{frame_time, render_sample, sample_time, thread_pool, tone_map, with_bloom};
+
+use rayon::prelude::*;
+
+/// # Renderer
+///
+/// Accumulates the samples of the first frame, one per pixel at a time (see accumulate_one_spp), and shows their running average.
+/// The n-th sample of a pixel is the n-th primary Ray of render_one_frame (with its stratum, its paths and its random numbers):
+/// after config.nb_aa_samples() passes, the image is the one of a full render. The passes go on with new random numbers after that.
+/// The frame is rendered at its full resolution, without adaptive sampling (see Config::render_scale and Config::adaptive_error).
+/// A viewer can run the passes on a thread of its own, and show current_image between them.
+pub struct Renderer<'a> {
+    /// The camera at the time of the frame
+    camera: Camera,
+    scene: &'a Scene,
+    config: Config,
+    /// Sum of the samples of each pixel, row by row
+    sum: Vec<Vector>,
+    nb_samples: usize,
+    /// The thread pool of config.threads threads on which every pass runs, or None for the global thread pool of rayon
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(camera: &Camera, scene: &'a Scene, config: Config) -> Self {
+        Renderer {
+            camera: camera.at_time(frame_time(config, 0)),
+            scene,
+            config,
+            sum: vec![Vector::new_eq(0.); config.height * config.width],
+            nb_samples: 0,
+            pool: thread_pool(config),
+        }
+    }
+
+    /// Number of samples accumulated in each pixel so far
+    pub fn nb_samples(&self) -> usize {
+        self.nb_samples
+    }
+
+    /// Adds one sample to every pixel of the crop region (see Config::crop), on config.threads threads (see Config::threads)
+    pub fn accumulate_one_spp(&mut self) {
+        let (camera, scene, config) = (&self.camera, self.scene, self.config);
+        let sample = self.nb_samples;
+        // The strata of the anti-aliasing (see Config::stratified_aa) are covered again every nb_aa_samples passes
+        let stratum = sample % config.nb_aa_samples();
+
+        let sum = &mut self.sum;
+        let mut pass = || {
+            sum.par_iter_mut().enumerate().for_each(|(index, pixel)| {
+                let (i, j) = (index / config.width, index % config.width);
+                if config.in_crop(i, j) {
+                    let mut rng = config.sample_rng(0, i, j, sample);
+                    let time = sample_time(config, 0, &mut rng);
+                    let (i, j) = (i as isize, j as isize);
+                    *pixel += render_sample(camera, scene, config, i, j, stratum, time, &mut rng);
+                }
+            })
+        };
+        match &self.pool {
+            Some(pool) => pool.install(pass),
+            None => pass(),
+        }
+        self.nb_samples += 1;
+    }
+
+    /// Returns the average of the samples accumulated so far, tone-mapped to an RGB buffer (3 bytes per pixel, row by row)
+    /// The image stays black until the first pass.
+    pub fn current_image(&self) -> Vec<u8> {
+        tone_map(&self.current_radiance(), self.config)
+    }
+
+    /// Linear radiance of each pixel so far, with the bloom of the Config
+    fn current_radiance(&self) -> Vec<Vector> {
+        let nb_samples = self.nb_samples.max(1) as f64;
+        let average = self.sum.iter().map(|sum| *sum / nb_samples).collect();
+        with_bloom(average, self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+    use crate::object::sphere::Sphere;
+    use crate::render_radiance;
+    use crate::testing::example_camera;
+    use crate::utils::{Color, Material};
+
+    #[test]
+    fn passes_converge_to_the_full_render() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., -1000., 0.),
+            990.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_light(Light::new(Vector::new(0., 30., 30.), Vector::new_eq(1e6)));
+        let config = Config::builder()
+            .height(12)
+            .width(8)
+            .nb_rays(4)
+            .nb_iter_max(3)
+            .build();
+        let camera = example_camera(&config);
+
+        let mut renderer = Renderer::new(&camera, &scene, config);
+        assert!(renderer.current_image().iter().all(|value| *value == 0));
+
+        // After nb_aa_samples passes, the pixels average the same samples as a full render
+        (0..4).for_each(|_| renderer.accumulate_one_spp());
+        assert_eq!(renderer.nb_samples(), 4);
+        let full = render_radiance(&camera, &scene, config, 0);
+        for (progressive, full) in renderer.current_radiance().iter().zip(&full) {
+            assert!((*progressive - *full).norm() <= 1e-9 * full.norm().max(1.));
+        }
+
+        // The noise fades with more passes: the image gets closer to a reference with many samples
+        let reference = render_radiance(
+            &camera,
+            &scene,
+            Config {
+                nb_rays: 256,
+                ..config
+            },
+            0,
+        );
+        let error = |renderer: &Renderer| -> f64 {
+            renderer
+                .current_radiance()
+                .iter()
+                .zip(&reference)
+                .map(|(pixel, reference)| (*pixel - *reference).norm_sq())
+                .sum()
+        };
+        let mut renderer = Renderer::new(&camera, &scene, config);
+        renderer.accumulate_one_spp();
+        let one_spp = error(&renderer);
+        (1..32).for_each(|_| renderer.accumulate_one_spp());
+        assert!(error(&renderer) < one_spp / 4.);
+    }
+
+    #[test]
+    fn passes_share_the_thread_pool_of_the_renderer() {
+        let scene = Scene::new();
+        let config = Config::builder().height(4).width(4).threads(3).build();
+        let mut renderer = Renderer::new(&example_camera(&config), &scene, config);
+        let threads = renderer
+            .pool
+            .as_ref()
+            .map(|pool| pool.current_num_threads());
+        assert_eq!(threads, Some(3));
+        (0..3).for_each(|_| renderer.accumulate_one_spp());
+        assert_eq!(renderer.nb_samples(), 3);
+
+        let global = Config {
+            threads: 0,
+            ..config
+        };
+        assert!(Renderer::new(&example_camera(&global), &scene, global)
+            .pool
+            .is_none());
+    }
+}
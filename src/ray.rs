@@ -1,5 +1,10 @@
-use crate::{animate::Animation, camera::Camera, intersection::Intersection, utils::Vector};
-use rand::Rng;
+use crate::{
+    animate::Animation,
+    camera::Camera,
+    intersection::Intersection,
+    utils::{Quaternion, Transform, Vector},
+};
+use rand::{Rng, RngCore};
 use std::f64::consts::E;
 use std::f64::consts::PI;
 
@@ -31,11 +36,8 @@ impl Ray {
 
     /// Creates a Ray that will be used to get the color of a given pixel in the image
     pub fn new_basic_ray(i: isize, j: isize, camera: &Camera) -> Self {
-        let right = camera.direction.cross(camera.up);
-
-        let direction = right * (j - camera.width as isize / 2) as f64
-            + camera.up * (camera.height as isize / 2 - i) as f64
-            + camera.direction * camera.depth();
+        // We aim at the center of the pixel
+        let direction = camera.pixel_direction(i as f64 + 0.5, j as f64 + 0.5);
 
         Ray {
             origin: camera.center,
@@ -45,9 +47,7 @@ impl Ray {
     }
 
     /// Creates a Ray that will be used to get the color of a given pixel in the image, randomized with a gaussian component in order to provide anti-aliasing when averaging the resulting values
-    pub fn new_aa_ray(i: isize, j: isize, camera: &Camera) -> Self {
-        let mut rng = rand::thread_rng();
-
+    pub fn new_aa_ray(i: isize, j: isize, camera: &Camera, rng: &mut dyn RngCore) -> Self {
         let x: f64 = rng.gen_range(0.0..1.0);
         let y: f64 = rng.gen_range(0.0..1.0);
 
@@ -56,11 +56,37 @@ impl Ray {
         let u = r * (2. * PI * y).cos() * 0.5;
         let v = r * (2. * PI * y).sin() * 0.5;
 
-        let right = camera.direction.cross(camera.up);
+        let direction = camera.pixel_direction(i as f64 + 0.5 - v, j as f64 + 0.5 + u);
+
+        Ray {
+            origin: camera.center,
+            direction,
+        }
+        .normalize()
+    }
+
+    /// Creates a Ray that will be used to get the color of a given pixel in the image, for the sample-th of nb_samples anti-aliasing samples
+    /// The pixel is divided into a grid of sqrt(nb_samples) x sqrt(nb_samples) cells, and each sample is jittered inside its own cell:
+    /// the samples cover the pixel evenly, which converges faster than independent random samples.
+    /// The samples left over when nb_samples is not a perfect square are jittered over the whole pixel.
+    pub fn new_stratified_aa_ray(
+        i: isize,
+        j: isize,
+        sample: usize,
+        nb_samples: usize,
+        camera: &Camera,
+        rng: &mut dyn RngCore,
+    ) -> Self {
+        let grid = (nb_samples as f64).sqrt().floor() as usize;
+        let (cell_size, cell_i, cell_j) = match sample < grid * grid {
+            true => (1. / grid as f64, sample / grid, sample % grid),
+            false => (1., 0, 0),
+        };
+
+        let y = (cell_i as f64 + rng.gen_range(0.0..1.0)) * cell_size;
+        let x = (cell_j as f64 + rng.gen_range(0.0..1.0)) * cell_size;
 
-        let direction = right * (j as f64 - camera.width as f64 / 2. + u - 0.5)
-            + camera.up * (camera.height as f64 / 2. - i as f64 + v - 0.5) as f64
-            + camera.direction * camera.depth();
+        let direction = camera.pixel_direction(i as f64 + y, j as f64 + x);
 
         Ray {
             origin: camera.center,
@@ -70,20 +96,41 @@ impl Ray {
     }
 
     /// This anti-aliased ray also handles the Depth of Field of the camera to provide more realistic renders
-    pub fn new_aa_and_dof_ray(i: isize, j: isize, camera: &Camera) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn new_aa_and_dof_ray(i: isize, j: isize, camera: &Camera, rng: &mut dyn RngCore) -> Self {
+        Self::new_aa_ray(i, j, camera, rng).with_depth_of_field(camera, rng)
+    }
 
-        let ray1 = Self::new_aa_ray(i, j, camera);
+    /// Moves the origin of a primary Ray randomly on the aperture of the camera, keeping it aimed at the same point of the focal plane
+    pub fn with_depth_of_field(self, camera: &Camera, rng: &mut dyn RngCore) -> Self {
+        self.through_lens(camera, Self::sample_lens(camera, rng), camera.focal)
+    }
 
-        let right = camera.direction.cross(camera.up);
+    /// Same as with_depth_of_field, with one Ray for each of the red, green and blue channels: they leave the same point of the aperture,
+    /// and converge at the focal distance of their channel (see Camera::chromatic_aberration)
+    pub fn with_chromatic_depth_of_field(
+        self,
+        camera: &Camera,
+        rng: &mut dyn RngCore,
+    ) -> [Self; 3] {
+        let lens = Self::sample_lens(camera, rng);
+        [0, 1, 2].map(|channel| self.through_lens(camera, lens, camera.channel_focal(channel)))
+    }
+
+    /// Picks a random point on the square aperture of the camera, in the (right, up) coordinates of the lens
+    fn sample_lens(camera: &Camera, rng: &mut dyn RngCore) -> (f64, f64) {
+        let px = (rng.gen_range(0.0..1.0) - 0.5) * camera.aperture;
+        let py = (rng.gen_range(0.0..1.0) - 0.5) * camera.aperture;
+        (px, py)
+    }
 
-        let dir = ray1.direction;
-        let px = (rng.gen_range(0.0..1.0) - 0.5) * 5.;
-        let py = (rng.gen_range(0.0..1.0) - 0.5) * 5.;
+    /// Moves the origin of a primary Ray to a point of the lens, keeping it aimed at the same point of the plane at distance focal
+    fn through_lens(self, camera: &Camera, (px, py): (f64, f64), focal: f64) -> Self {
+        let (right, up) = camera.basis();
 
-        let pos2 = camera.center + (right * px) + (camera.up * py);
+        let dir = self.direction;
+        let pos2 = camera.center + (right * px) + (up * py);
 
-        let dir2 = (camera.center + (dir * camera.focal)) - pos2;
+        let dir2 = (camera.center + (dir * focal)) - pos2;
 
         Ray {
             origin: pos2,
@@ -94,8 +141,7 @@ impl Ray {
 
     /// Computes the reflection of a Ray on the object it intersects
     pub fn reflect(self, intersection: Intersection) -> Self {
-        let direction =
-            self.direction - (intersection.normal * 2. * self.direction.dot(intersection.normal));
+        let direction = self.direction.reflect(intersection.normal);
 
         Ray {
             origin: intersection.get_point_nudged(),
@@ -111,69 +157,39 @@ impl Ray {
         n_air: f64,
         n_object: f64,
         fresnel: bool,
+        rng: &mut dyn RngCore,
     ) -> Option<Self> {
-        let mut rng = rand::thread_rng();
+        // The normal of a hit from inside the object faces the Ray: use the one pointing out of the object to know if we enter or leave it
+        let outward = intersection.outward_normal();
 
         let rand: f64 = rng.gen_range(0.0..1.0);
         let threshold = match fresnel {
             false => 1.0,
-            true => self.compute_fresnel(intersection.normal, n_air, n_object),
+            true => self.compute_fresnel(outward, n_air, n_object),
         };
 
         if rand < threshold {
-            // We leave the object
-            if self.direction.dot(intersection.normal) >= 0. {
-                let n_1 = n_object;
-                let n_2 = n_air;
-                let normal = intersection.normal * (-1.);
-
-                let scalar = self.direction.dot(normal);
-                let radical = 1. - n_1 * n_1 / (n_2 * n_2) * (1. - scalar * scalar);
-
-                if radical >= 0.
-                // We refract (too steep)
-                {
-                    let direction = self.direction * (n_1 / n_2)
-                        - normal * (n_1 / n_2 * scalar + radical.sqrt());
-
-                    Some(
-                        Ray {
-                            origin: intersection.get_point_nudged(),
-                            direction,
-                        }
-                        .normalize(),
-                    )
-                } else {
-                    // We reflect FROM THE INSIDE
-                    None
-                }
-            } else {
+            let (n_1, n_2, normal, origin) = match self.direction.dot(outward) >= 0. {
+                // We leave the object
+                true => (
+                    n_object,
+                    n_air,
+                    outward * (-1.),
+                    intersection.point + outward * 0.0001,
+                ),
                 // We enter the object
-                let n_1 = n_air;
-                let n_2 = n_object;
-                let normal = intersection.normal;
-
-                let scalar = self.direction.dot(normal);
-                let radical = 1. - n_1 * n_1 / (n_2 * n_2) * (1. - scalar * scalar);
-
-                if radical >= 0. {
-                    // We refract (too steep)
-
-                    let direction = self.direction * (n_1 / n_2)
-                        - normal * (n_1 / n_2 * scalar + radical.sqrt());
-
-                    Some(
-                        Ray {
-                            origin: intersection.get_point_nudged_neg(),
-                            direction,
-                        }
-                        .normalize(),
-                    )
-                } else {
-                    // We reflect FROM THE OUTSIDE
-                    None
-                }
-            }
+                false => (
+                    n_air,
+                    n_object,
+                    outward,
+                    intersection.point - outward * 0.0001,
+                ),
+            };
+
+            // None if the Ray is reflected (too steep)
+            self.direction
+                .refract(normal, n_1 / n_2)
+                .map(|direction| Ray { origin, direction }.normalize())
         } else {
             None
         }
@@ -201,57 +217,54 @@ impl Ray {
         1. - r
     }
 
-    /// Builds a new random Ray for indirect lightning computations
-    pub fn new_rand_ray(center: Vector, n: Vector) -> Self {
-        let mut rng = rand::thread_rng();
-
+    /// Samples a random direction around axis (of norm 1.), uniform in azimuth, whose cosine to axis is drawn by z_local from a uniform number in [0, 1)
+    fn rand_direction(
+        axis: Vector,
+        z_local: impl FnOnce(f64) -> f64,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
         let rand1: f64 = rng.gen_range(0.0..1.0);
         let rand2: f64 = rng.gen_range(0.0..1.0);
 
-        let sqrt1 = (1. - rand2).sqrt();
+        let z_local = z_local(rand2);
+        let sqrt1 = (1. - z_local * z_local).max(0.).sqrt();
 
         let x_local = (2. * PI * rand1).cos() * sqrt1;
         let y_local = (2. * PI * rand1).sin() * sqrt1;
-        let z_local = rand2.sqrt();
 
-        let randx: f64 = rng.gen_range(-1.0..1.0);
-        let randy: f64 = rng.gen_range(-1.0..1.0);
-        let randz: f64 = rng.gen_range(-1.0..1.0);
+        let (nx, ny) = axis.orthonormal_basis();
 
-        let nx = n.cross(Vector::new(randx, randy, randz).normalize());
-        let ny = n.cross(nx);
+        (nx * x_local + ny * y_local + axis * z_local).normalize()
+    }
 
-        let dir = (nx * x_local + ny * y_local + n * z_local).normalize();
+    /// Builds a new random Ray for indirect lightning computations, uniformly distributed over the hemisphere around n
+    /// Its density is 1 / (2 PI) for every direction, instead of cos(theta) / PI for new_rand_ray.
+    pub fn new_rand_ray_uniform(center: Vector, n: Vector, rng: &mut dyn RngCore) -> Self {
+        // The cosine to the normal is uniform for a uniform density over the hemisphere
+        Ray {
+            origin: center,
+            direction: Self::rand_direction(n, |rand| rand, rng),
+        }
+    }
 
+    /// Builds a new random Ray for indirect lightning computations
+    pub fn new_rand_ray(center: Vector, n: Vector, rng: &mut dyn RngCore) -> Self {
         Ray {
             origin: center,
-            direction: dir,
+            direction: Self::rand_direction(n, f64::sqrt, rng),
         }
     }
 
     /// Builds a new random Ray on the surface of a Spherical light
-    pub fn new_rand_ray_angle_uniform(center: Vector, surface: f64, dir: Vector) -> Self {
+    pub fn new_rand_ray_angle_uniform(
+        center: Vector,
+        surface: f64,
+        dir: Vector,
+        rng: &mut dyn RngCore,
+    ) -> Self {
         let rayon = (surface / (4.0 * PI)).sqrt();
 
-        let mut rng = rand::thread_rng();
-
-        let rand1: f64 = rng.gen_range(0.0..1.0);
-        let rand2: f64 = rng.gen_range(0.0..1.0);
-
-        let sqrt1 = rand2.sqrt();
-
-        let x_local = (2. * PI * rand1).cos() * sqrt1;
-        let y_local = (2. * PI * rand1).sin() * sqrt1;
-        let z_local = (1. - rand2).sqrt();
-
-        let randx: f64 = rng.gen_range(-1.0..1.0);
-        let randy: f64 = rng.gen_range(-1.0..1.0);
-        let randz: f64 = rng.gen_range(-1.0..1.0);
-
-        let nx = dir.cross(Vector::new(randx, randy, randz).normalize());
-        let ny = dir.cross(nx);
-
-        let dir2 = (nx * x_local + ny * y_local + dir * z_local).normalize();
+        let dir2 = Self::rand_direction(dir.normalize(), |rand| (1. - rand).sqrt(), rng);
         let center2 = center + dir2 * rayon;
 
         Ray {
@@ -261,32 +274,17 @@ impl Ray {
     }
 
     /// Builds a new random Ray biased by a Phong BRFD Material
-    pub fn new_rand_ray_phong(center: Vector, phong_exponent: f64, dir: Vector) -> Self {
-        let mut rng = rand::thread_rng();
-
-        let rand1: f64 = rng.gen_range(0.0..1.0);
-        let rand2: f64 = rng.gen_range(0.0..1.0);
-
-        let phong_term = rand2.powf(1. / (phong_exponent + 1.));
-
-        let sqrt1 = (1. - (phong_term * phong_term)).sqrt();
-
-        let x_local = (2. * PI * rand1).cos() * sqrt1;
-        let y_local = (2. * PI * rand1).sin() * sqrt1;
-        let z_local = (1. - phong_term).sqrt();
-
-        let randx: f64 = rng.gen_range(-1.0..1.0);
-        let randy: f64 = rng.gen_range(-1.0..1.0);
-        let randz: f64 = rng.gen_range(-1.0..1.0);
-
-        let nx = dir.cross(Vector::new(randx, randy, randz).normalize());
-        let ny = dir.cross(nx);
-
-        let dir2 = (nx * x_local + ny * y_local + dir * z_local).normalize();
+    pub fn new_rand_ray_phong(
+        center: Vector,
+        phong_exponent: f64,
+        dir: Vector,
+        rng: &mut dyn RngCore,
+    ) -> Self {
+        let phong_term = |rand: f64| rand.powf(1. / (phong_exponent + 1.));
 
         Ray {
             origin: center,
-            direction: dir2,
+            direction: Self::rand_direction(dir.normalize(), phong_term, rng),
         }
     }
 
@@ -317,44 +315,108 @@ impl Ray {
         }
     }
 
+    /// Rotates the Ray around an axis going through rotation_center, with a single rotation given as a Quaternion
+    pub fn rotate_quaternion(self, rotation: Quaternion, rotation_center: Vector) -> Self {
+        Ray {
+            origin: rotation.rotate(self.origin - rotation_center) + rotation_center,
+            direction: rotation.rotate(self.direction),
+        }
+    }
+
+    /// Moves the Ray from the world space to the space of an object placed by a Transform
+    pub fn to_local(self, transform: &Transform) -> Self {
+        Ray {
+            origin: transform.to_local_point(self.origin),
+            direction: transform.to_local_vector(self.direction),
+        }
+    }
+
+    /// Scales the position of the Ray around a center (the direction is unchanged for a uniform scaling)
+    pub fn scale(self, factor: f64, scale_center: Vector) -> Self {
+        Ray {
+            origin: (self.origin - scale_center) * factor + scale_center,
+            direction: self.direction,
+        }
+    }
+
     /// Applies an object's animation to the Ray
     pub fn apply_animations(self, animations: Vec<Animation>, time: f64) -> Self {
         let mut cur_ray = self;
 
         for a in animations {
-            if a.start_time >= time || a.start_time > a.end_time {
-                continue;
-            } else {
-                let progress = if a.end_time > time {
-                    (time - a.start_time) / (a.end_time - a.start_time)
-                } else {
-                    1.
-                };
-
-                cur_ray = self
+            if let Some(progress) = a.progress(time) {
+                cur_ray = cur_ray
+                    .scale(a.scale_at(progress), a.scale_center)
                     .translate(a.translation * progress)
                     .rotate_x(a.rotation_x * progress, a.rotation_center_x)
                     .rotate_y(a.rotation_y * progress, a.rotation_center_y)
-                    .rotate_z(a.rotation_z * progress, a.rotation_center_z);
+                    .rotate_z(a.rotation_z * progress, a.rotation_center_z)
+                    .rotate_quaternion(a.rotation_at(progress), a.rotation_center);
+            }
+            if let Some(keyframes) = &a.keyframes {
+                let (translation, rotation, scale) = keyframes.pose_at(time);
+                cur_ray = cur_ray
+                    .scale(scale, keyframes.pivot)
+                    .rotate_quaternion(rotation, keyframes.pivot)
+                    .translate(translation);
             }
         }
         cur_ray
     }
 
     /// Reverses the application of an object's animation to the Ray
+    /// Each transformation is undone in the reverse order of apply_animations.
     pub fn reverse_animations(self, animations: Vec<Animation>, time: f64) -> Self {
-        let mut reverse_animations: Vec<Animation> = Vec::new();
+        let mut cur_ray = self;
 
-        for a in animations {
-            let mut a_reverse = a;
+        for a in animations.into_iter().rev() {
+            if let Some(keyframes) = &a.keyframes {
+                let (translation, rotation, scale) = keyframes.pose_at(time);
+                cur_ray = cur_ray
+                    .translate(translation * (-1.))
+                    .rotate_quaternion(rotation.conjugate(), keyframes.pivot)
+                    .scale(1. / scale, keyframes.pivot);
+            }
+            if let Some(progress) = a.progress(time) {
+                cur_ray = cur_ray
+                    .rotate_quaternion(a.rotation_at(progress).conjugate(), a.rotation_center)
+                    .rotate_z(-a.rotation_z * progress, a.rotation_center_z)
+                    .rotate_y(-a.rotation_y * progress, a.rotation_center_y)
+                    .rotate_x(-a.rotation_x * progress, a.rotation_center_x)
+                    .translate(a.translation * (-progress))
+                    .scale(1. / a.scale_at(progress), a.scale_center);
+            }
+        }
+        cur_ray
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            a_reverse.rotation_x *= -1.;
-            a_reverse.rotation_y *= -1.;
-            a_reverse.rotation_z *= -1.;
-            a_reverse.translation = a_reverse.translation * (-1.);
+    #[test]
+    fn uniform_hemisphere_directions() {
+        let normal = Vector::new(1., 2., -0.5).normalize();
+        let nb_samples = 100_000;
+        let mut rng = rand::thread_rng();
+        let mut bins = [0usize; 10];
+        let mut mean = Vector::new_eq(0.);
+
+        for _ in 0..nb_samples {
+            let direction =
+                Ray::new_rand_ray_uniform(Vector::new_eq(0.), normal, &mut rng).direction;
+            let cos_theta = direction.dot(normal);
+            assert!(cos_theta >= 0.);
+            bins[((cos_theta * 10.) as usize).min(9)] += 1;
+            mean += direction / nb_samples as f64;
+        }
 
-            reverse_animations.push(a_reverse);
+        // The cosine to the normal is uniform, where the cosine-weighted sampling favours the normal
+        for count in bins {
+            assert!((count as f64 - 10_000.).abs() < 500., "{bins:?}");
         }
-        self.apply_animations(reverse_animations, time)
+        // On average, the directions point along the normal with a cosine of 1/2
+        assert_approx_eq::assert_approx_eq!((mean - normal * 0.5).norm(), 0., 0.01);
     }
 }
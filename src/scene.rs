@@ -1,20 +1,120 @@
-use crate::animate::Animatable;
+use crate::animate::{animate_emission, animate_transform, Animatable};
+use crate::camera::Camera;
+use crate::error::SceneError;
 use crate::intersection::Intersection;
 use crate::light::Light;
-use crate::object::Object;
+use crate::object::quad::Quad;
+use crate::object::sphere::Sphere;
+use crate::object::{Object, Primitive};
 use crate::ray::Ray;
-use crate::utils::Vector;
-use rand::Rng;
+use crate::stats;
+use crate::texture::{ImageTexture, Texture};
+use crate::utils::{Color, Config, Depth, Material, Transform, Vector};
+use background::Background;
+use bvh::Bvh;
+use grid::UniformGrid;
+use integrator::{Bounce, BounceKind};
+use medium::Medium;
+use portal::Portal;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub mod background;
+pub mod bvh;
+pub mod grid;
+mod integrator;
+pub mod medium;
+pub mod portal;
+
+/// # Accelerator
+///
+/// The structure used to find the objects crossed by a Ray without testing all of them
+#[derive(Copy, Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Accelerator {
+    /// A bounding volume hierarchy (see Bvh), which adapts to any distribution of objects
+    #[default]
+    Bvh,
+    /// A uniform grid with this number of cells along its longest side (see UniformGrid)
+    /// It is quicker to build, which pays off for short renders of many objects of similar sizes.
+    /// Packets of Rays (see Config::packet_size) still go through the bounding volume hierarchy.
+    Grid(usize),
+}
+
+/// Albedo of the diffuse sphere of Scene::furnace_test, which an energy-conserving renderer measures back
+pub const FURNACE_ALBEDO: f64 = 0.6;
 
 /// # Scene
 ///
 /// The Scene handles objects and lights for your render.
+/// Rays escaping the Scene see an environment (a Background, black by default, or an equirectangular map), which can be importance-sampled through Portals.
+/// Lights can be unlinked from objects, so that these objects do not receive any light from them.
+/// Partially opaque Materials (see Material::alpha) are cut out either with a threshold or stochastically (see Config::stochastic_transparency).
+/// The space between the objects may be filled with fog (see set_fog) or with participating Media (see add_medium).
+/// A Scene can be saved to and loaded from a JSON file (see to_json and from_json).
 pub struct Scene {
     objects: Vec<Box<dyn Object + Sync>>,
     lights: Vec<Light>,
     light_objects: Vec<Box<dyn Object + Sync>>,
     show_emissive_surfaces: bool,
+    multiple_importance_sampling: bool,
+    background: Background,
+    environment_map: Option<ImageTexture>,
+    environment_map_intensity: f64,
+    portals: Vec<Portal>,
+    /// (light index, object index) pairs of unlinked point lights
+    unlinked_lights: Vec<(usize, usize)>,
+    /// (light object index, object index) pairs of unlinked light objects
+    unlinked_light_objects: Vec<(usize, usize)>,
+    textures: Vec<ImageTexture>,
+    /// A point and the normal of the shadow catcher plane
+    shadow_catcher: Option<(Vector, Vector)>,
+    /// The radiance of the fog and its density
+    fog: Option<(Vector, f64)>,
+    media: Vec<Medium>,
+    accelerator: Accelerator,
+    /// Built on the first intersection query, and reset whenever an object is added
+    bvh: OnceLock<Bvh>,
+    /// Same as bvh, with Accelerator::Grid
+    grid: OnceLock<UniformGrid>,
+    /// Whether each object is also a light object (see same_object), built on the first intersection query like bvh
+    light_object_flags: OnceLock<Vec<bool>>,
+}
+
+/// The content of a scene file: a Scene whose objects are described as Primitives
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    objects: Vec<Primitive>,
+    lights: Vec<Light>,
+    light_objects: Vec<Primitive>,
+    show_emissive_surfaces: bool,
+    #[serde(default)]
+    multiple_importance_sampling: bool,
+    background: Background,
+    environment_map: Option<ImageTexture>,
+    environment_map_intensity: f64,
+    portals: Vec<Portal>,
+    unlinked_lights: Vec<(usize, usize)>,
+    unlinked_light_objects: Vec<(usize, usize)>,
+    textures: Vec<ImageTexture>,
+    shadow_catcher: Option<(Vector, Vector)>,
+    #[serde(default)]
+    fog: Option<(Vector, f64)>,
+    #[serde(default)]
+    media: Vec<Medium>,
+    #[serde(default)]
+    accelerator: Accelerator,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scene {
@@ -28,11 +128,29 @@ impl Scene {
             lights,
             light_objects,
             show_emissive_surfaces: false,
+            multiple_importance_sampling: false,
+            background: Background::Solid(Vector::new_eq(0.)),
+            environment_map: None,
+            environment_map_intensity: 1.,
+            portals: Vec::new(),
+            unlinked_lights: Vec::new(),
+            unlinked_light_objects: Vec::new(),
+            textures: Vec::new(),
+            shadow_catcher: None,
+            fog: None,
+            media: Vec::new(),
+            accelerator: Accelerator::Bvh,
+            bvh: OnceLock::new(),
+            grid: OnceLock::new(),
+            light_object_flags: OnceLock::new(),
         }
     }
 
     pub fn add_object(&mut self, obj: Box<dyn Object + Sync>) {
         self.objects.push(obj);
+        self.bvh = OnceLock::new();
+        self.grid = OnceLock::new();
+        self.light_object_flags = OnceLock::new();
     }
 
     pub fn add_light(&mut self, light: Light) {
@@ -41,388 +159,2905 @@ impl Scene {
 
     pub fn add_light_object(&mut self, obj: Box<dyn Object + Sync>) {
         self.light_objects.push(obj);
+        self.light_object_flags = OnceLock::new();
     }
 
-    pub fn set_show_emissive_surfaces(&mut self, show_emissive_surfaces: bool) {
-        self.show_emissive_surfaces = show_emissive_surfaces;
+    /// Removes the object of the given index (in the order of add_object) and returns it, or returns None if there is no such object
+    /// The following objects move down by one index: their object IDs (see gbuffer::object_id_pass) and light links follow them.
+    pub fn remove_object(&mut self, index: usize) -> Option<Box<dyn Object + Sync>> {
+        if index >= self.objects.len() {
+            return None;
+        }
+        let obj = self.objects.remove(index);
+        Self::remove_from_links(&mut self.unlinked_lights, index, false);
+        Self::remove_from_links(&mut self.unlinked_light_objects, index, false);
+        self.bvh = OnceLock::new();
+        self.grid = OnceLock::new();
+        self.light_object_flags = OnceLock::new();
+        Some(obj)
     }
 
-    /// Computes the closest intersection between your Ray and the objects of your scene
-    pub fn compute_intersection(&self, ray: Ray, time: f64) -> Option<Intersection> {
-        let mut current_min_norm_sq = f64::MAX;
-        let mut current_inter: Option<Intersection> = None;
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
 
-        for obj in self.objects.iter() {
-            let animations = obj.get_animations();
-            let ray = ray.reverse_animations(animations.clone(), time);
-            let col = obj.intersection(ray);
-            let ray = ray.apply_animations(animations, time);
-
-            if let Some(inter) = col {
-                if (inter.point - ray.origin).norm_sq() <= current_min_norm_sq {
-                    current_min_norm_sq = (inter.point - ray.origin).norm_sq();
-                    current_inter = Some(inter);
-                }
-            }
+    pub fn get_object(&self, index: usize) -> Option<&(dyn Object + Sync)> {
+        self.objects.get(index).map(|obj| obj.as_ref())
+    }
+
+    /// Removes the light of the given index (in the order of add_light) and returns it, see remove_object
+    pub fn remove_light(&mut self, index: usize) -> Option<Light> {
+        if index >= self.lights.len() {
+            return None;
         }
-        current_inter
+        let light = self.lights.remove(index);
+        Self::remove_from_links(&mut self.unlinked_lights, index, true);
+        Some(light)
     }
 
-    /// Detects if there is an object in the path between your point and a given light
-    /// Returns true if the light is visible, false if it is shadowed
-    pub fn compute_shadows(&self, point: Vector, light: &Light, time: f64) -> bool {
-        let light_animations = light.get_animations();
-        let fake_ray = Ray::new(light.center, point);
-        let fake_ray = fake_ray.apply_animations(light_animations, time);
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
 
-        let ray = Ray::new(point, fake_ray.origin - point).normalize();
+    pub fn get_light(&self, index: usize) -> Option<&Light> {
+        self.lights.get(index)
+    }
+
+    /// Removes the light object of the given index (in the order of add_light_object) and returns it, see remove_object
+    pub fn remove_light_object(&mut self, index: usize) -> Option<Box<dyn Object + Sync>> {
+        if index >= self.light_objects.len() {
+            return None;
+        }
+        let obj = self.light_objects.remove(index);
+        Self::remove_from_links(&mut self.unlinked_light_objects, index, true);
+        self.light_object_flags = OnceLock::new();
+        Some(obj)
+    }
 
-        let mut light_visible = true;
+    pub fn light_object_count(&self) -> usize {
+        self.light_objects.len()
+    }
 
-        for obj in self.objects.iter() {
-            let animations = obj.get_animations();
-            let ray = ray.reverse_animations(animations.clone(), time);
-            let col = obj.intersection(ray);
-            let ray = ray.apply_animations(animations, time);
-
-            if let Some(inter) = col {
-                if (inter.point - ray.origin).norm_sq() <= (point - light.center).norm_sq() {
-                    light_visible = false;
+    pub fn get_light_object(&self, index: usize) -> Option<&(dyn Object + Sync)> {
+        self.light_objects.get(index).map(|obj| obj.as_ref())
+    }
+
+    /// Computes the axis-aligned box (min, max) enclosing the objects and the light objects of the Scene, in world space
+    /// Objects with infinite bounds (e.g. infinite planes) are left out, and animated objects count where they are at time 0. (see bounding_box_at).
+    /// Returns None if no object has finite bounds.
+    pub fn bounding_box(&self) -> Option<(Vector, Vector)> {
+        self.bounding_box_at(0.)
+    }
+
+    /// Same as bounding_box, with the animated objects moved to where they are at that time
+    pub fn bounding_box_at(&self, time: f64) -> Option<(Vector, Vector)> {
+        self.objects
+            .iter()
+            .chain(self.light_objects.iter())
+            .filter_map(|obj| {
+                let (min, max) = obj.bounding_box();
+                match (
+                    min.is_finite() && max.is_finite(),
+                    Self::object_transform(obj.as_ref(), time),
+                ) {
+                    (false, _) => None,
+                    (true, Some(transform)) => Some(transform.to_world_box(min, max)),
+                    (true, None) => Some((min, max)),
+                }
+            })
+            .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+    }
+
+    /// Updates the (light index, object index) pairs of unlinked lights after the removal of a light (light_side) or of an object:
+    /// the pairs of the removed one are dropped, and the following indices move down by one
+    fn remove_from_links(unlinked: &mut Vec<(usize, usize)>, removed: usize, light_side: bool) {
+        unlinked.retain_mut(|(light_index, object_index)| {
+            let index = match light_side {
+                true => light_index,
+                false => object_index,
+            };
+            match (*index).cmp(&removed) {
+                Ordering::Less => true,
+                Ordering::Equal => false,
+                Ordering::Greater => {
+                    *index -= 1;
+                    true
                 }
             }
+        });
+    }
+
+    /// Adds a horizontal floor at height y, facing up, made of square_size wide squares of color_a and color_b
+    /// The floor is a Quad large enough to reach the horizon of most Scenes.
+    pub fn add_checker_floor(&mut self, y: f64, square_size: f64, color_a: Color, color_b: Color) {
+        let extent = 10_000.;
+        self.add_object(Box::new(Quad::new(
+            Vector::new(-extent, y, -extent),
+            Vector::new(0., 0., 2. * extent),
+            Vector::new(2. * extent, 0., 0.),
+            Material::create_checker(color_a, color_b, 2. * extent / square_size),
+        )));
+    }
+
+    /// Makes the emissive objects visible to the Rays of the camera and after specular bounces
+    /// After a diffuse bounce, the surfaces of the light objects are not seen again, as compute_direct samples them instead.
+    pub fn set_show_emissive_surfaces(&mut self, show_emissive_surfaces: bool) {
+        self.show_emissive_surfaces = show_emissive_surfaces;
+    }
+
+    /// Stores an image in the Scene, and returns the Texture to use in the Materials of its objects
+    pub fn add_texture(&mut self, texture: ImageTexture) -> Texture {
+        self.textures.push(texture);
+        Texture::Image(self.textures.len() - 1)
+    }
+
+    /// Computes the albedo of a Material at the given point (in world space) and uv coordinates, looking it up in its Texture if it has one
+    pub fn albedo(&self, material: Material, point: Vector, uv: (f64, f64)) -> Color {
+        match material.texture {
+            None => material.color,
+            Some(texture) => self.texture_color(texture, point, uv),
         }
+    }
+
+    /// Computes the normal used for shading an intersection: the geometric normal, perturbed by the normal map of its Material if it has one
+    /// The map is read in the frame of the tangent, the bitangent (normal × tangent) and the normal of the Intersection.
+    pub fn shading_normal(&self, intersection: Intersection) -> Vector {
+        let normal = intersection.normal;
+        let map = match intersection.material.normal_map {
+            None => return normal,
+            Some(map) => self.texture_color(map, intersection.point, intersection.uv),
+        };
 
-        light_visible
+        let tangent =
+            (intersection.tangent - normal * intersection.tangent.dot(normal)).normalize();
+        let bitangent = normal.cross(tangent);
+        let perturbed = tangent * (2. * map.r - 1.)
+            + bitangent * (2. * map.g - 1.)
+            + normal * (2. * map.b - 1.);
+
+        match perturbed.norm_sq() > 1e-12 && perturbed.dot(normal) > 0. {
+            true => perturbed.normalize(),
+            false => normal,
+        }
     }
 
-    /// Computes the light intensity, color by color, of an intersection
-    pub fn compute_intensity(
-        &self,
-        ray: Ray,
-        intersection: Intersection,
-        nb_iter_max: usize,
-        time: f64,
-    ) -> Vector {
-        match nb_iter_max {
-            0 => Vector::new_eq(0.),
-            _ => {
-                let mut cur_intensity = Vector::new(0., 0., 0.);
-
-                cur_intensity += self
-                    .compute_point_light(intersection, nb_iter_max, time)
-                    .max(Vector::new_eq(0.));
-                cur_intensity += self
-                    .compute_mirror(ray, intersection, nb_iter_max, time)
-                    .max(Vector::new_eq(0.));
-                cur_intensity += self
-                    .compute_transparent(ray, intersection, nb_iter_max, time)
-                    .max(Vector::new_eq(0.));
-                cur_intensity += self
-                    .compute_emissive(intersection, self.show_emissive_surfaces, time)
-                    .max(Vector::new_eq(0.));
-                cur_intensity += self
-                    .compute_indirect(ray, intersection, nb_iter_max, time)
-                    .max(Vector::new_eq(0.));
-                cur_intensity += self
-                    .compute_direct(ray, intersection, nb_iter_max, time)
-                    .max(Vector::new_eq(0.));
-
-                cur_intensity
+    /// Looks up the color of a Texture at the given point (in world space) and uv coordinates
+    fn texture_color(&self, texture: Texture, point: Vector, uv: (f64, f64)) -> Color {
+        match texture {
+            Texture::Solid(color) => color,
+            Texture::Image(index) => self.textures[index].sample(uv),
+            Texture::Checker {
+                color_a,
+                color_b,
+                scale,
+            } => Texture::checker(color_a, color_b, scale, uv),
+            Texture::Marble {
+                color_a,
+                color_b,
+                frequency,
+                octaves,
+                turbulence,
+                seed,
+            } => {
+                let t = Texture::marble_ramp(point, frequency, octaves, turbulence, seed);
+                color_a * (1. - t) + color_b * t
             }
         }
     }
 
-    /// Computes the mirror component of the light intensity, color by color, of an intersection
-    pub fn compute_mirror(
-        &self,
-        ray: Ray,
-        intersection: Intersection,
-        nb_iter_max: usize,
-        time: f64,
-    ) -> Vector {
-        match intersection.material.mirror {
-            false => Vector::new_eq(0.),
-            true => {
-                let reflected_ray = ray.reflect(intersection);
+    /// Computes the albedo of the surface at an intersection, as seen by a denoiser: specular surfaces count with their reflected color
+    pub fn surface_albedo(&self, intersection: Intersection) -> Color {
+        let material = intersection.material;
+        match material.mirror || material.transparent || material.is_microfacet() {
+            true => material.specular_color,
+            false => self.albedo(material, intersection.point, intersection.uv),
+        }
+    }
 
-                let reflected_inter = self.compute_intersection(reflected_ray, time);
+    /// Sets the shadow catcher: an infinite plane, going through point and facing normal, which is invisible to the camera
+    /// but records the shadows cast on it by the objects (see RenderMode::ShadowCatcher).
+    pub fn set_shadow_catcher(&mut self, point: Vector, normal: Vector) {
+        self.shadow_catcher = Some((point, normal.normalize()));
+    }
 
-                if let Some(inter) = reflected_inter {
-                    self.compute_intensity(
-                        reflected_ray,
-                        inter.get_inter_nudged(),
-                        nb_iter_max - 1,
-                        time,
-                    ) * intersection.material.specular_color
-                } else {
-                    Vector::new_eq(0.)
-                }
-            }
+    /// Chooses how the light objects are sampled
+    /// By default, their light only reaches a surface through compute_direct, which samples a point on one of them.
+    /// With multiple importance sampling, the Rays sampled from the BRDF for indirect lighting also collect the light of the light objects they hit,
+    /// and both estimates are weighted with the power heuristic: glossy surfaces then reflect small lights with much less noise.
+    pub fn set_multiple_importance_sampling(&mut self, multiple_importance_sampling: bool) {
+        self.multiple_importance_sampling = multiple_importance_sampling;
+    }
+
+    /// Chooses the structure used to find the objects crossed by the Rays (the bounding volume hierarchy by default)
+    pub fn set_accelerator(&mut self, accelerator: Accelerator) {
+        self.accelerator = accelerator;
+        self.grid = OnceLock::new();
+    }
+
+    /// Sets the Background seen by the Rays escaping the Scene, when there is no environment map
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /// Sets the radiance, color by color, of the uniform environment seen by the Rays escaping the Scene
+    #[deprecated(note = "use set_background(Background::Solid(environment)) instead")]
+    pub fn set_environment(&mut self, environment: Vector) {
+        self.set_background(Background::Solid(environment));
+    }
+
+    /// Fills the Scene with an exponential fog of the given radiance (like a Background)
+    /// The radiance seen by the camera fades into the fog color with the distance d of the surface it comes from, by 1 - exp(-density * d):
+    /// the Rays escaping the Scene only see the fog. A density of 0. removes the fog.
+    pub fn set_fog(&mut self, color: Vector, density: f64) {
+        self.fog = match density > 0. {
+            true => Some((color, density)),
+            false => None,
+        };
+    }
+
+    /// Blends the radiance coming from a distance (infinite for the Rays escaping the Scene) with the fog, see set_fog
+    pub fn apply_fog(&self, radiance: Vector, distance: f64) -> Vector {
+        match self.fog {
+            None => radiance,
+            Some((color, density)) => radiance.lerp(color, 1. - (-density * distance).exp()),
         }
     }
 
-    /// Computes the transparency component of the light intensity, color by color, of an intersection
-    pub fn compute_transparent(
-        &self,
-        ray: Ray,
+    /// Fills a box of the Scene with a participating Medium, in which the Rays scatter (see Medium)
+    pub fn add_medium(&mut self, medium: Medium) {
+        self.media.push(medium);
+    }
+
+    /// Computes the fraction of the light going from one point to another which goes through the Media of the Scene
+    pub fn transmittance(&self, from: Vector, to: Vector) -> f64 {
+        self.media
+            .iter()
+            .map(|medium| medium.transmittance(from, to))
+            .product()
+    }
+
+    /// Sets an equirectangular map (e.g. an HDR panorama) as the environment seen by the Rays escaping the Scene, instead of the Background
+    /// The top row of the map is straight up (+y), and a texel of value 1. has a radiance of intensity.
+    pub fn set_environment_map(&mut self, map: ImageTexture, intensity: f64) {
+        self.environment_map = Some(map);
+        self.environment_map_intensity = intensity;
+    }
+
+    /// Adds a Portal through which the environment is sampled
+    /// Once a Portal is added, the environment is assumed to be only visible through the Portals (e.g. the windows of a closed room).
+    pub fn add_portal(&mut self, portal: Portal) {
+        self.portals.push(portal);
+    }
+
+    /// Prevents the light of index light_index (in the order of add_light) from lighting the object of index object_index (in the order of add_object)
+    pub fn unlink_light(&mut self, light_index: usize, object_index: usize) {
+        self.unlinked_lights.push((light_index, object_index));
+    }
+
+    /// Prevents the light object of index light_index (in the order of add_light_object) from lighting the object of index object_index (in the order of add_object)
+    pub fn unlink_light_object(&mut self, light_index: usize, object_index: usize) {
+        self.unlinked_light_objects
+            .push((light_index, object_index));
+    }
+
+    /// Tells if a light is linked to the object of an intersection
+    fn is_linked(
+        unlinked: &[(usize, usize)],
+        light_index: usize,
         intersection: Intersection,
-        nb_iter_max: usize,
-        time: f64,
-    ) -> Vector {
-        match intersection.material.transparent {
-            false => Vector::new_eq(0.),
-            true => {
-                let n_object = intersection.material.n_object;
-                let refracted_ray = ray.refract(intersection, 1., n_object, false);
+    ) -> bool {
+        match intersection.object_index {
+            None => true,
+            Some(object_index) => !unlinked.contains(&(light_index, object_index)),
+        }
+    }
 
-                match refracted_ray {
-                    None => {
-                        let mut intersection_as_mirror = intersection;
-                        intersection_as_mirror.material.mirror = true;
+    /// Computes the radiance, color by color, seen by a Ray escaping the Scene
+    pub fn background(&self, ray: Ray) -> Vector {
+        match &self.environment_map {
+            None => self.background.radiance(ray.direction),
+            Some(map) => {
+                let direction = ray.direction.normalize();
+                let u = 0.5 + direction.z.atan2(direction.x) / (2. * PI);
+                let v = direction.y.clamp(-1., 1.).acos() / PI;
 
-                        if ray.direction.dot(intersection.normal) >= 0. {
-                            intersection_as_mirror.normal = intersection_as_mirror.normal * (-1.)
-                        }
-                        self.compute_mirror(ray, intersection_as_mirror, nb_iter_max, time)
-                    }
-                    Some(refracted_ray_a) => {
-                        let refracted_intersection =
-                            self.compute_intersection(refracted_ray_a, time);
-
-                        if let Some(inter) = refracted_intersection {
-                            self.compute_intensity(refracted_ray_a, inter, nb_iter_max - 1, time)
-                        } else {
-                            Vector::new_eq(0.)
-                        }
-                    }
-                }
+                Vector::new_eq(self.environment_map_intensity) * map.sample((u, v))
             }
         }
     }
 
-    /// Computes the indirect lightning component of the light intensity, color by color, of an intersection
-    pub fn compute_indirect(
+    /// Saves the Scene as a JSON scene file, which can be edited and loaded back with from_json
+    /// Fails if the Scene holds an object defined outside of the raytracer (see Object::to_primitive).
+    pub fn to_json<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let describe = |objects: &[Box<dyn Object + Sync>]| {
+            objects
+                .iter()
+                .map(|obj| obj.to_primitive().ok_or(SceneError::UnsupportedObject))
+                .collect::<Result<Vec<Primitive>, SceneError>>()
+        };
+
+        let file = SceneFile {
+            objects: describe(&self.objects)?,
+            lights: self.lights.clone(),
+            light_objects: describe(&self.light_objects)?,
+            show_emissive_surfaces: self.show_emissive_surfaces,
+            multiple_importance_sampling: self.multiple_importance_sampling,
+            background: self.background,
+            environment_map: self.environment_map.clone(),
+            environment_map_intensity: self.environment_map_intensity,
+            portals: self.portals.clone(),
+            unlinked_lights: self.unlinked_lights.clone(),
+            unlinked_light_objects: self.unlinked_light_objects.clone(),
+            textures: self.textures.clone(),
+            shadow_catcher: self.shadow_catcher,
+            fog: self.fog,
+            media: self.media.clone(),
+            accelerator: self.accelerator,
+        };
+
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &file)?;
+        Ok(())
+    }
+
+    /// Loads a Scene from a JSON scene file, as written by to_json
+    pub fn from_json<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+        let file: SceneFile = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        let into_objects = |primitives: Vec<Primitive>| {
+            primitives
+                .into_iter()
+                .map(Primitive::into_object)
+                .collect::<Vec<Box<dyn Object + Sync>>>()
+        };
+
+        Ok(Scene {
+            objects: into_objects(file.objects),
+            lights: file.lights,
+            light_objects: into_objects(file.light_objects),
+            show_emissive_surfaces: file.show_emissive_surfaces,
+            multiple_importance_sampling: file.multiple_importance_sampling,
+            background: file.background,
+            environment_map: file.environment_map,
+            environment_map_intensity: file.environment_map_intensity,
+            portals: file.portals,
+            unlinked_lights: file.unlinked_lights,
+            unlinked_light_objects: file.unlinked_light_objects,
+            textures: file.textures,
+            shadow_catcher: file.shadow_catcher,
+            fog: file.fog,
+            media: file.media,
+            accelerator: file.accelerator,
+            bvh: OnceLock::new(),
+            grid: OnceLock::new(),
+            light_object_flags: OnceLock::new(),
+        })
+    }
+
+    /// Computes the closest intersection between your Ray and the objects of your scene
+    /// The Intersection records the index of the object hit, in the order of add_object (see Intersection::object_index).
+    /// Partially opaque surfaces are cut out with a threshold: see compute_intersection_with_rng for the stochastic cutouts.
+    pub fn compute_intersection(&self, ray: Ray, time: f64) -> Option<Intersection> {
+        self.compute_intersection_with_rng(ray, time, false, &mut rand::thread_rng())
+    }
+
+    /// Same as compute_intersection, drawing the random hits (e.g. in a ConstantMedium) from rng
+    /// With stochastic_transparency, partially opaque surfaces are cut out at random as well (see Config::stochastic_transparency).
+    pub fn compute_intersection_with_rng(
         &self,
         ray: Ray,
-        intersection: Intersection,
-        nb_iter_max: usize,
         time: f64,
-    ) -> Vector {
-        let mut cur_intensity = Vector::new(0., 0., 0.);
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection> {
+        stats::count_secondary_ray();
+        self.closest(ray, |index| {
+            self.intersect_object(index, ray, time, stochastic_transparency, rng)
+                .map(|inter| ((inter.point - ray.origin).norm_sq(), inter))
+        })
+    }
 
-        let mut rng = rand::thread_rng();
+    /// Computes the closest intersection between a Ray of the camera and the objects of your scene, ignoring the objects hidden from the camera (see Object::visible_primary)
+    /// The random hits draw from rng, see compute_intersection_with_rng.
+    pub fn compute_primary_intersection(
+        &self,
+        ray: Ray,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection> {
+        stats::count_primary_rays(1);
+        self.closest(ray, |index| {
+            match self.objects[index].visible_primary() {
+                true => self.intersect_object(index, ray, time, stochastic_transparency, rng),
+                false => None,
+            }
+            .map(|inter| ((inter.point - ray.origin).norm_sq(), inter))
+        })
+    }
 
-        let rand: f64 = rng.gen_range(0.0..1.0);
+    /// Computes the closest intersection of each Ray of a packet, going through the bounding volume hierarchy once for the whole packet
+    /// This pays off for coherent Rays, such as the primary Rays of a block of neighbouring pixels: like compute_primary_intersection,
+    /// the objects hidden from the camera are ignored. Each Ray draws its random hits from the generator of the same index in rngs.
+    pub fn compute_intersection_packet(
+        &self,
+        rays: &[Ray],
+        time: f64,
+        stochastic_transparency: bool,
+        rngs: &mut [impl RngCore],
+    ) -> Vec<Option<Intersection>> {
+        if rays.is_empty() {
+            return Vec::new();
+        }
+        stats::count_primary_rays(rays.len());
+        self.bvh().closest_packet(rays, |ray_index, index| {
+            stats::count_intersection_test();
+            let ray = rays[ray_index];
+            match self.objects[index].visible_primary() {
+                true => self.intersect_object(
+                    index,
+                    ray,
+                    time,
+                    stochastic_transparency,
+                    &mut rngs[ray_index],
+                ),
+                false => None,
+            }
+            .map(|inter| ((inter.point - ray.origin).norm_sq(), inter))
+        })
+    }
 
-        let p = match intersection.material.phong {
-            true => 0.5,
-            false => 1.,
-        };
+    /// Returns the bounding volume hierarchy of the objects, building it if needed
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| Bvh::build(&self.objects))
+    }
 
-        let new_ray: Ray;
+    /// Returns whether each object is also a light object, finding them if needed
+    fn light_object_flags(&self) -> &[bool] {
+        self.light_object_flags.get_or_init(|| {
+            self.objects
+                .iter()
+                .map(|obj| {
+                    self.light_objects
+                        .iter()
+                        .any(|light_object| Self::same_object(obj.as_ref(), light_object.as_ref()))
+                })
+                .collect()
+        })
+    }
 
-        match intersection.material.phong && rand >= p {
-            false => {
-                new_ray = Ray::new_rand_ray(intersection.get_point_nudged(), intersection.normal);
-            }
-            true => {
-                let reflected_ray = ray.reflect(intersection);
-                new_ray = Ray::new_rand_ray_phong(
-                    intersection.get_point_nudged(),
-                    intersection.material.phong_exponent,
-                    reflected_ray.direction,
-                );
-                if new_ray.direction.dot(intersection.normal) <= 0. {
-                    return Vector::new_eq(0.);
-                }
-                if new_ray.direction.dot(reflected_ray.direction) <= 0. {
-                    return Vector::new_eq(0.);
-                }
+    /// Tells if two objects are the same, e.g. an emissive object added both with add_object and add_light_object
+    /// The objects written in scene files are compared as such, the others by their bounding box, center and surface area.
+    fn same_object(a: &(dyn Object + Sync), b: &(dyn Object + Sync)) -> bool {
+        match (a.to_primitive(), b.to_primitive()) {
+            (Some(a), Some(b)) => match (serde_json::to_value(a), serde_json::to_value(b)) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => false,
+            },
+            (None, None) => {
+                a.bounding_box() == b.bounding_box()
+                    && a.get_center() == b.get_center()
+                    && a.get_surface_area() == b.get_surface_area()
             }
+            _ => false,
         }
+    }
 
-        let new_intersection = self.compute_intersection(new_ray, time);
+    /// Finds the closest hit along the Ray with the Accelerator of the Scene, see Bvh::closest
+    /// Each object tested is counted by the stats (see Config::stats).
+    fn closest<T>(&self, ray: Ray, mut hit: impl FnMut(usize) -> Option<(f64, T)>) -> Option<T> {
+        let hit = |index| {
+            stats::count_intersection_test();
+            hit(index)
+        };
+        match self.accelerator {
+            Accelerator::Bvh => self.bvh().closest(ray, hit),
+            Accelerator::Grid(resolution) => self
+                .grid
+                .get_or_init(|| UniformGrid::build(&self.objects, resolution))
+                .closest(ray, hit),
+        }
+    }
 
-        let indirect_intensity: Vector;
+    /// Tells if any object is hit along the Ray with the Accelerator of the Scene, see Bvh::any
+    /// The Ray is counted as a secondary Ray by the stats, and each object tested as an intersection test (see Config::stats).
+    fn any_hit(&self, ray: Ray, max_dist_sq: f64, mut hit: impl FnMut(usize) -> bool) -> bool {
+        stats::count_secondary_ray();
+        let hit = |index| {
+            stats::count_intersection_test();
+            hit(index)
+        };
+        match self.accelerator {
+            Accelerator::Bvh => self.bvh().any(ray, max_dist_sq, hit),
+            Accelerator::Grid(resolution) => self
+                .grid
+                .get_or_init(|| UniformGrid::build(&self.objects, resolution))
+                .any(ray, max_dist_sq, hit),
+        }
+    }
 
-        if let Some(inter) = new_intersection {
-            indirect_intensity = self.compute_intensity(new_ray, inter, nb_iter_max - 1, time);
+    /// Computes the intersection between your Ray and one object of your scene, moved to where the object is at that time
+    fn intersect_object(
+        &self,
+        index: usize,
+        ray: Ray,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection> {
+        let obj = self.objects[index].as_ref();
+        let transform = Self::object_transform(obj, time);
+        let ray = Self::to_object_space(transform, ray);
 
-            let reflected_ray = ray.reflect(intersection);
+        Self::opaque_intersection(obj, ray, stochastic_transparency, rng).map(|inter| {
+            Intersection {
+                object_index: Some(index),
+                light_object: self.light_object_flags()[index],
+                ..Self::to_world_space(obj, transform, inter, time)
+            }
+        })
+    }
 
-            let proba_phong = (intersection.material.phong_exponent + 1.)
-                * new_ray
-                    .direction
-                    .dot(reflected_ray.direction)
-                    .powf(intersection.material.phong_exponent);
-            let proba_diffuse = intersection.normal.dot(new_ray.direction);
+    /// Computes the object-to-world Transform of an object at that time: its own Transform, then its animations (see animate_transform)
+    /// Returns None for the objects which are neither transformed nor animated, whose space is the world space.
+    fn object_transform(obj: &(dyn Object + Sync), time: f64) -> Option<Transform> {
+        let animations = obj.get_animations();
+        match (animations.is_empty(), obj.get_transform()) {
+            (true, transform) => transform,
+            (false, None) => Some(animate_transform(&animations, time)),
+            (false, Some(transform)) => Some(transform.then(&animate_transform(&animations, time))),
+        }
+    }
 
-            let proba = p * proba_diffuse + (1. - p) * proba_phong;
+    /// Moves a Ray to the space of an object placed by transform (see object_transform)
+    fn to_object_space(transform: Option<Transform>, ray: Ray) -> Ray {
+        match transform {
+            Some(transform) => ray.to_local(&transform),
+            None => ray,
+        }
+    }
 
-            match intersection.material.phong && rand >= p {
-                false => {
-                    cur_intensity +=
-                        indirect_intensity * proba_diffuse * intersection.material.color
-                            / (2. * PI)
-                            / proba;
-                }
-                true => {
-                    cur_intensity += indirect_intensity
-                        * proba_diffuse
-                        * (intersection.material.phong_exponent + 2.)
-                        * new_ray
-                            .direction
-                            .dot(reflected_ray.direction)
-                            .powf(intersection.material.phong_exponent)
-                        * intersection.material.specular_color
-                        / (2. * PI)
-                        / proba;
-                }
-            }
-        } else {
-            return Vector::new_eq(0.);
+    /// Moves a point of the world space to the space of an object placed by transform, where the light objects sample their points
+    fn point_to_object(transform: Option<Transform>, point: Vector) -> Vector {
+        match transform {
+            Some(transform) => transform.to_local_point(point),
+            None => point,
         }
+    }
 
-        cur_intensity
+    /// Computes how much the surface of an object grows from its space to the world space, around a point whose normal is normal
+    /// The densities of the points sampled on the surface of the light objects, given over the area of the object space, are divided by it.
+    fn area_scale(transform: Option<Transform>, normal: Vector) -> f64 {
+        // The Transform is affine: two unit tangents at the point span a patch scaled like any other patch there
+        let (tangent, bitangent) = normal.normalize().orthonormal_basis();
+        match transform {
+            Some(transform) => transform
+                .to_world_vector(tangent)
+                .cross(transform.to_world_vector(bitangent))
+                .norm(),
+            None => 1.,
+        }
     }
 
-    /// Computes the emissive surface component of the light intensity, color by color, of an intersection
-    pub fn compute_emissive(
-        &self,
-        intersection: Intersection,
-        show_emissive_surfaces: bool,
-        _time: f64,
-    ) -> Vector {
-        match intersection.material.emissive && show_emissive_surfaces {
-            false => Vector::new_eq(0.),
-            true => {
-                Vector::new_eq(1.) * intersection.material.color * intersection.material.emissivity
+    /// Computes the area of the surface of an object in the world space, from the one of its space (see Object::get_surface_area)
+    /// The area is exact when the object is scaled uniformly: other scalings grow it by their average, the cube root of the volume scale squared.
+    fn surface_area(obj: &(dyn Object + Sync), time: f64) -> f64 {
+        let volume_scale = match Self::object_transform(obj, time) {
+            Some(transform) => {
+                let axis = |x, y, z| transform.to_world_vector(Vector::new(x, y, z));
+                axis(1., 0., 0.)
+                    .dot(axis(0., 1., 0.).cross(axis(0., 0., 1.)))
+                    .abs()
             }
+            None => 1.,
+        };
+        obj.get_surface_area() * volume_scale.powf(2. / 3.)
+    }
+
+    /// Moves a point sampled on the surface of an object, with its normal, from the space of the object to the world space
+    fn sample_to_world(
+        transform: Option<Transform>,
+        point: Vector,
+        normal: Vector,
+    ) -> (Vector, Vector) {
+        match transform {
+            Some(transform) => (
+                transform.to_world_point(point),
+                transform.to_world_normal(normal),
+            ),
+            None => (point, normal.normalize()),
         }
     }
 
-    /// Computes the direct lightning component of the light intensity, color by color, of an intersection
-    pub fn compute_direct(
-        &self,
-        ray: Ray,
-        intersection: Intersection,
-        _nb_iter_max: usize,
+    /// Moves an Intersection computed in the space of an object placed by transform back to the world space (see to_object_space)
+    /// The emission of an emissive object is animated along with it.
+    fn to_world_space(
+        obj: &(dyn Object + Sync),
+        transform: Option<Transform>,
+        inter: Intersection,
         time: f64,
-    ) -> Vector {
-        let mut cur_intensity = Vector::new(0., 0., 0.);
-
-        let mut rng = rand::thread_rng();
+    ) -> Intersection {
+        let mut inter = match transform {
+            Some(transform) => inter.to_world(&transform),
+            None => inter,
+        };
+        if inter.material.emissive {
+            (inter.material.emission_color, inter.material.emissivity) = animate_emission(
+                inter.material.emission_color,
+                inter.material.emissivity,
+                &obj.get_animations(),
+                time,
+            );
+        }
+        inter
+    }
 
-        let rand: f64 = rng.gen_range(0.0..1.0);
+    /// Computes the closest intersection between your Ray and an object, skipping the hits cut out by the alpha of its Material
+    fn opaque_intersection(
+        obj: &(dyn Object + Sync),
+        ray: Ray,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Option<Intersection> {
+        let mut cur_ray = ray;
+        loop {
+            let inter = obj.sample_intersection(cur_ray, rng)?;
+            if Self::is_opaque(inter.material, stochastic_transparency, rng) {
+                return Some(inter);
+            }
+            cur_ray = Ray::new(
+                inter.point + ray.direction.normalize() * 0.0001,
+                ray.direction,
+            );
+        }
+    }
 
-        let new_ray: Ray;
+    /// Tells if a hit on a Material stops the Ray
+    /// With stochastic transparency, the hit is kept with a probability equal to the alpha of the Material: averaged over the samples, cutouts blend smoothly.
+    /// Otherwise, the alpha is thresholded at 0.5.
+    fn is_opaque(material: Material, stochastic_transparency: bool, rng: &mut dyn RngCore) -> bool {
+        match (material.alpha >= 1., stochastic_transparency) {
+            (true, _) => true,
+            (false, true) => rng.gen_range(0.0..1.0) < material.alpha,
+            (false, false) => material.alpha >= 0.5,
+        }
+    }
 
-        // We aim one of the emissive object (with chances proportional to total light intensity of the object)
-        let mut probas: Vec<f64> = Vec::new();
+    /// Computes every intersection between your Ray and the objects of your scene, sorted by increasing distance
+    /// Each object contributes all of its hits along the Ray (e.g. entry and exit points)
+    pub fn intersect_all(&self, ray: Ray, time: f64) -> Vec<Intersection> {
+        let mut intersections: Vec<Intersection> = Vec::new();
 
-        for i in 0..self.light_objects.len() {
-            probas.push(
-                self.light_objects[i].get_material().emissivity
-                    / self.light_objects[i].get_surface_area(),
+        for obj in self.objects.iter() {
+            let obj = obj.as_ref();
+            let transform = Self::object_transform(obj, time);
+            intersections.extend(
+                obj.intersections(Self::to_object_space(transform, ray))
+                    .into_iter()
+                    .map(|inter| Self::to_world_space(obj, transform, inter, time)),
             );
         }
 
-        let sum: f64 = probas.iter().sum();
+        intersections.sort_by(|a, b| {
+            (a.point - ray.origin)
+                .norm_sq()
+                .total_cmp(&(b.point - ray.origin).norm_sq())
+        });
+        intersections
+    }
 
-        for i in 0..self.light_objects.len() {
-            {
-                if rand * sum <= probas[i] {
-                    // We get a random direction (giving us a point on the Sphere)
-                    let light_object_i = &self.light_objects[i];
-
-                    let light_center = light_object_i.get_center();
-
-                    let dir_center_light = (intersection.point - light_center).normalize();
-
-                    new_ray = Ray::new_rand_ray_angle_uniform(
-                        light_center,
-                        light_object_i.get_surface_area(),
-                        dir_center_light,
-                    );
-
-                    let rand_result_point = new_ray.origin;
-                    let rand_result_dir = new_ray.direction.normalize();
-                    let rand_result_dir_to_intersection =
-                        (intersection.point - rand_result_point).normalize();
-                    let d = (intersection.point - rand_result_point).norm_sq();
-                    let nudged = intersection.get_point_nudged();
-
-                    let mut new_light = Light::new(
-                        rand_result_point,
-                        Vector::new_eq(1.) * light_object_i.get_material().emissivity
-                            / light_object_i.get_surface_area()
-                            * light_object_i.get_material().color,
-                    );
-
-                    for anim in light_object_i.get_animations() {
-                        new_light.add_animation(anim);
-                    }
+    /// Computes the X-ray intensity of a Ray, color by color
+    /// The thickness of each object crossed by the Ray (tinted by its color) is accumulated, then absorbed with the given density:
+    /// where objects overlap, the thicknesses add up and the result is brighter.
+    pub fn compute_xray(&self, ray: Ray, density: f64, time: f64) -> Vector {
+        let mut thickness = Vector::new_eq(0.);
+        let mut inside_color = Vector::new_eq(0.);
+        let mut previous_point = ray.origin;
 
-                    if self.compute_shadows(nudged, &new_light, time) {
-                        if intersection.material.phong {
-                            let reflected_ray = ray.reflect(intersection);
-
-                            let phong_term = reflected_ray
-                                .direction
-                                .dot(rand_result_dir_to_intersection)
-                                .powf(intersection.material.phong_exponent)
-                                * (intersection.material.phong_exponent + 2.)
-                                / (2. * PI);
-
-                            let color_phonged = intersection.material.color
-                                + intersection.material.specular_color * (phong_term - 1.);
-
-                            cur_intensity += Vector::new_eq(1.)
-                                * light_object_i.get_material().emissivity
-                                * light_object_i.get_material().color
-                                * intersection
-                                    .normal
-                                    .dot(rand_result_dir_to_intersection * (-1.))
-                                    .max(0.)
-                                * light_object_i.get_surface_area()
-                                * rand_result_dir.dot(rand_result_dir_to_intersection).max(0.)
-                                * color_phonged
-                                / (dir_center_light.dot(rand_result_dir).max(0.) * d * 4. * PI);
-                        } else {
-                            cur_intensity += Vector::new_eq(1.)
-                                * light_object_i.get_material().emissivity
-                                * light_object_i.get_material().color
-                                * intersection
-                                    .normal
-                                    .dot(rand_result_dir_to_intersection * (-1.))
-                                    .max(0.)
-                                * light_object_i.get_surface_area()
-                                * rand_result_dir.dot(rand_result_dir_to_intersection).max(0.)
-                                * intersection.material.color
-                                / (dir_center_light.dot(rand_result_dir).max(0.) * d * 4. * PI);
-                        }
-                    }
-                    break;
-                }
+        for inter in self.intersect_all(ray, time) {
+            thickness += inside_color * (inter.point - previous_point).norm();
+            previous_point = inter.point;
+
+            // Normals point outwards: we enter the object when going against them
+            let color = Vector::from(inter.material.color);
+            if ray.direction.dot(inter.normal) < 0. {
+                inside_color += color;
+            } else {
+                inside_color = (inside_color - color).max(Vector::new_eq(0.));
             }
         }
 
-        cur_intensity
+        Vector::new(
+            255. * (1. - (-density * thickness.x).exp()),
+            255. * (1. - (-density * thickness.y).exp()),
+            255. * (1. - (-density * thickness.z).exp()),
+        )
     }
 
-    /// Computes the point light component of the light intensity, color by color, of an intersection
-    pub fn compute_point_light(
-        &self,
-        intersection: Intersection,
-        _nb_iter_max: usize,
-        time: f64,
-    ) -> Vector {
-        let mut cur_intensity = Vector::new(0., 0., 0.);
-        for light in self.lights.iter() {
-            if self.compute_shadows(intersection.get_point_nudged(), light, time) {
-                cur_intensity += intersection.get_intensity(light, time);
-            }
-        }
-        cur_intensity
+    /// Runs a furnace test: a single diffuse sphere of albedo FURNACE_ALBEDO is placed inside a uniform environment of radiance 1.
+    /// Since the environment is uniform, an energy-conserving renderer sees the sphere with a radiance equal to its albedo:
+    /// the measured radiance, averaged over config.nb_gi_samples() paths and the three color channels, is returned.
+    pub fn furnace_test(config: Config) -> f64 {
+        Self::furnace_test_with_material(
+            Material::create_diffuse(Color::new_eq(FURNACE_ALBEDO)),
+            config,
+        )
+    }
+
+    /// Same as furnace_test, with a sphere of the given material
+    /// A Material which neither absorbs nor emits light returns 1., and the others their albedo.
+    pub fn furnace_test_with_material(material: Material, config: Config) -> f64 {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(Vector::new_eq(0.), 1., material)));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            100.,
+            Material::create_emissive(Color::white(), 1.),
+        )));
+        scene.set_show_emissive_surfaces(true);
+
+        let ray = Ray::new(Vector::new(0., 0., 10.), Vector::new(0., 0., -1.));
+        let mut rng = config.sample_rng(0, 0, 0, 0);
+        let intersection = scene
+            .compute_intersection_with_rng(
+                ray,
+                config.start_time,
+                config.stochastic_transparency,
+                &mut rng,
+            )
+            .expect("the furnace sphere is in front of the camera");
+
+        // Only one bounce is measured: the environment emits light but should not reflect it
+        let radiance: Vector = (0..config.nb_gi_samples())
+            .map(|_| {
+                scene.compute_intensity(
+                    ray,
+                    intersection,
+                    2,
+                    config.start_time,
+                    config.stochastic_transparency,
+                    &mut rng,
+                )
+            })
+            .sum();
+        let radiance = radiance / config.nb_gi_samples() as f64;
+
+        (radiance.x + radiance.y + radiance.z) / 3.
+    }
+
+    /// Runs a white furnace test: a perfectly diffuse white sphere is rendered, filling the view, inside a uniform environment of the given radiance
+    /// The sphere neither absorbs nor emits light, and being convex it never sees itself: an energy-conserving renderer sees it with exactly
+    /// the radiance of the environment, whatever the number of bounces. The radiance of every pixel is returned, rendered with the given Config.
+    pub fn white_furnace_test(radiance: f64, config: Config) -> Vec<Vector> {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.set_background(Background::Solid(Vector::new_eq(radiance)));
+
+        // Seen from 15. away, the sphere spans more than the 60 degrees of the field of view, even in the corners
+        let camera = Camera::new(
+            Vector::new(0., 0., 15.),
+            Vector::new(0., 0., -1.),
+            Vector::new(0., 1., 0.),
+            60.,
+            15.,
+            config.height,
+            config.width,
+        );
+
+        crate::render_radiance(&camera, &scene, config, 0)
+    }
+
+    /// Detects if there is an object in the path between your point and a given light
+    /// Returns true if the light is visible, false if it is shadowed. Partially opaque surfaces are cut out with a threshold, see compute_intersection.
+    pub fn compute_shadows(&self, point: Vector, light: &Light, time: f64) -> bool {
+        self.compute_shadows_with_rng(point, light, time, false, &mut rand::thread_rng())
+    }
+
+    /// Same as compute_shadows, drawing the random hits from rng, see compute_intersection_with_rng
+    pub fn compute_shadows_with_rng(
+        &self,
+        point: Vector,
+        light: &Light,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let light_animations = light.get_animations();
+        let fake_ray = Ray::new(light.center, point);
+        let fake_ray = fake_ray.apply_animations(light_animations, time);
+
+        let ray = Ray::new(point, fake_ray.origin - point).normalize();
+
+        // We stop as soon as an object is found between the point and the light
+        let max_dist_sq = (fake_ray.origin - point).norm_sq();
+        !self.any_hit(ray, max_dist_sq, |index| {
+            self.occludes(
+                index,
+                ray,
+                fake_ray.origin,
+                time,
+                stochastic_transparency,
+                rng,
+            )
+        })
+    }
+
+    /// Tells if the object of the given index, moved to where it is at that time, stands along the Ray before the point end
+    /// Only the objects casting shadows occlude (see Object::casts_shadows), and cut out surfaces let some Rays through.
+    fn occludes(
+        &self,
+        index: usize,
+        ray: Ray,
+        end: Vector,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let obj = self.objects[index].as_ref();
+        if !obj.casts_shadows() {
+            return false;
+        }
+        let transform = Self::object_transform(obj, time);
+        let local_ray = Self::to_object_space(transform, ray);
+        let local_end = Self::point_to_object(transform, end);
+
+        let local_max_dist_sq = (local_end - local_ray.origin).norm_sq();
+
+        match obj.get_material().alpha >= 1. {
+            true => obj.sample_intersects_before(local_ray, local_max_dist_sq, rng),
+            false => Self::opaque_intersection(obj, local_ray, stochastic_transparency, rng)
+                .is_some_and(|inter| {
+                    (inter.point - local_ray.origin).norm_sq() <= local_max_dist_sq
+                }),
+        }
+    }
+
+    /// Computes how much of the light received by the shadow catcher plane where the Ray hits it is blocked by the objects, from 0. to 1.
+    /// Returns 0. when there is no shadow catcher, when the Ray misses it, or when an object stands in front of it.
+    /// Point lights are all accounted for, and one point is sampled on each light object.
+    pub fn compute_shadow_catcher(
+        &self,
+        ray: Ray,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> f64 {
+        let (plane_point, plane_normal) = match self.shadow_catcher {
+            Some(catcher) => catcher,
+            None => return 0.,
+        };
+
+        let t = (plane_point - ray.origin).dot(plane_normal) / ray.direction.dot(plane_normal);
+        if !t.is_finite() || t <= 0. {
+            return 0.;
+        }
+        let point = ray.get_point(t);
+        if let Some(inter) =
+            self.compute_primary_intersection(ray, time, stochastic_transparency, rng)
+        {
+            if (inter.point - ray.origin).norm_sq() < (point - ray.origin).norm_sq() {
+                return 0.;
+            }
+        }
+
+        // The side of the plane seen by the Ray receives the light
+        let normal = match ray.direction.dot(plane_normal) < 0. {
+            true => plane_normal,
+            false => plane_normal * (-1.),
+        };
+        let nudged = point + normal * 0.0001;
+        let mut unshadowed = 0.;
+        let mut shadowed = 0.;
+
+        for light in self.lights.iter() {
+            let intensity = light.get_intensity_local(point, normal, Color::white(), time);
+            let intensity = (intensity.x + intensity.y + intensity.z) / 3.;
+            unshadowed += intensity;
+            if self.compute_shadows_with_rng(nudged, light, time, stochastic_transparency, rng) {
+                shadowed += intensity;
+            }
+        }
+
+        for light_object in self.light_objects.iter() {
+            let (color, emissivity) = Self::light_emission(light_object.as_ref(), time);
+            let obj = light_object.as_ref();
+            let transform = Self::object_transform(obj, time);
+            let (local_point, local_normal, _) = obj.sample_point(rng);
+            let (light_point, light_normal) =
+                Self::sample_to_world(transform, local_point, local_normal);
+
+            let to_light = light_point - point;
+            let intensity = emissivity * (color.r + color.g + color.b) / 3.
+                * normal.dot(to_light.normalize()).max(0.)
+                * light_normal.dot(to_light.normalize() * (-1.)).max(0.)
+                * obj.get_surface_area()
+                * Self::area_scale(transform, local_normal)
+                / to_light.norm_sq();
+            unshadowed += intensity;
+            if self.compute_shadows_with_rng(
+                nudged,
+                &Light::new(light_point, Vector::new_eq(0.)),
+                time,
+                stochastic_transparency,
+                rng,
+            ) {
+                shadowed += intensity;
+            }
+        }
+
+        match unshadowed > 0. {
+            true => 1. - shadowed / unshadowed,
+            false => 0.,
+        }
+    }
+
+    /// Computes the ambient occlusion where the Ray hits the Scene: the fraction of nb_samples cosine-weighted Rays leaving the surface
+    /// that travel the given distance without hitting anything, from 0. (fully occluded) to 1. (unoccluded)
+    /// Returns 1. when the Ray misses the Scene.
+    pub fn compute_ambient_occlusion(
+        &self,
+        ray: Ray,
+        nb_samples: usize,
+        distance: f64,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> f64 {
+        let inter = match self.compute_primary_intersection(ray, time, stochastic_transparency, rng)
+        {
+            Some(inter) => inter,
+            None => return 1.,
+        };
+        if nb_samples == 0 {
+            return 1.;
+        }
+
+        // Like shadows, the occlusion only comes from the objects casting shadows (see Object::casts_shadows)
+        let origin = inter.get_point_nudged();
+        let unoccluded = (0..nb_samples)
+            .filter(|_| {
+                let occlusion_ray = Ray::new_rand_ray(origin, inter.normal, rng);
+                let end = occlusion_ray.get_point(distance);
+                !self.any_hit(occlusion_ray, (end - origin).norm_sq(), |index| {
+                    self.occludes(
+                        index,
+                        occlusion_ray,
+                        end,
+                        time,
+                        stochastic_transparency,
+                        rng,
+                    )
+                })
+            })
+            .count();
+
+        unoccluded as f64 / nb_samples as f64
+    }
+
+    /// Computes the mirror component of the light intensity, color by color, of an intersection
+    /// The reflection counts against depth.reflection, besides depth.total.
+    pub fn compute_mirror(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        depth: impl Into<Depth>,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        match self.mirror_bounce(ray, intersection) {
+            Some(bounce) => self.follow_bounce(
+                intersection,
+                bounce,
+                depth.into(),
+                true,
+                time,
+                stochastic_transparency,
+                rng,
+            ),
+            None => Vector::new_eq(0.),
+        }
+    }
+
+    /// Computes the transparency component of the light intensity, color by color, of an intersection
+    /// The refraction counts against depth.refraction, besides depth.total.
+    pub fn compute_transparent(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        depth: impl Into<Depth>,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        match self.transparent_bounce(ray, intersection, rng) {
+            Some(bounce) => self.follow_bounce(
+                intersection,
+                bounce,
+                depth.into(),
+                true,
+                time,
+                stochastic_transparency,
+                rng,
+            ),
+            None => Vector::new_eq(0.),
+        }
+    }
+
+    /// Computes the indirect lightning component of the light intensity, color by color, of an intersection
+    /// The bounce counts against depth.diffuse, besides depth.total.
+    pub fn compute_indirect(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        depth: impl Into<Depth>,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        match self.indirect_bounce(ray, intersection, rng) {
+            Some(bounce) => self.follow_bounce(
+                intersection,
+                bounce,
+                depth.into(),
+                true,
+                time,
+                stochastic_transparency,
+                rng,
+            ),
+            None => Vector::new_eq(0.),
+        }
+    }
+
+    /// The reflected Ray of a mirror Material, weighted by its specular color
+    fn mirror_bounce(&self, ray: Ray, intersection: Intersection) -> Option<Bounce> {
+        match intersection.material.mirror {
+            false => None,
+            true => {
+                // Thin films interfere with themselves, so their reflected color depends on the viewing angle
+                let specular_color = match intersection.material.iridescent {
+                    false => intersection.material.specular_color,
+                    true => intersection
+                        .material
+                        .thin_film_reflectance(ray.direction.dot(intersection.normal)),
+                };
+
+                Some(Bounce {
+                    ray: ray.reflect(intersection),
+                    weight: specular_color,
+                    nudge: true,
+                    sees_background: true,
+                    sees_emissive_surfaces: true,
+                    sees_light_objects: false,
+                    bsdf_pdf: None,
+                    kind: BounceKind::Reflection,
+                })
+            }
+        }
+    }
+
+    /// The refracted Ray of a transparent Material, or its reflected Ray in case of total internal reflection
+    /// The thin film of an iridescent Material reflects the light it does not let through: either Ray is picked at random, weighted accordingly.
+    fn transparent_bounce(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        rng: &mut dyn RngCore,
+    ) -> Option<Bounce> {
+        match intersection.material.transparent {
+            false => None,
+            true => {
+                let n_object = intersection.material.n_object;
+                let refracted_ray = ray.refract(intersection, 1., n_object, false, rng);
+
+                let mut intersection_as_mirror = intersection;
+                intersection_as_mirror.material.mirror = true;
+                if ray.direction.dot(intersection.normal) >= 0. {
+                    intersection_as_mirror.normal = intersection_as_mirror.normal * (-1.)
+                }
+                let reflected = |intersection_as_mirror: Intersection, scale: f64| {
+                    self.mirror_bounce(ray, intersection_as_mirror)
+                        .map(|bounce| Bounce {
+                            weight: bounce.weight * scale,
+                            kind: BounceKind::Refraction,
+                            ..bounce
+                        })
+                };
+
+                match refracted_ray {
+                    None => {
+                        // The whole light is reflected, whether or not it goes through a thin film
+                        intersection_as_mirror.material.iridescent = false;
+                        reflected(intersection_as_mirror, 1.)
+                    }
+                    Some(refracted_ray_a) => {
+                        let (transmittance, scale) = match intersection.material.iridescent {
+                            false => (Color::white(), 1.),
+                            true => {
+                                let reflectance = intersection
+                                    .material
+                                    .thin_film_reflectance(ray.direction.dot(intersection.normal));
+                                let p_reflect = ((reflectance.r + reflectance.g + reflectance.b)
+                                    / 3.)
+                                    .clamp(0.01, 0.99);
+
+                                let rand: f64 = rng.gen_range(0.0..1.0);
+                                if rand < p_reflect {
+                                    return reflected(intersection_as_mirror, 1. / p_reflect);
+                                }
+                                (Color::white() - reflectance, 1. / (1. - p_reflect))
+                            }
+                        };
+
+                        Some(Bounce {
+                            ray: refracted_ray_a,
+                            weight: transmittance * scale,
+                            nudge: false,
+                            sees_background: true,
+                            sees_emissive_surfaces: true,
+                            sees_light_objects: false,
+                            bsdf_pdf: None,
+                            kind: BounceKind::Refraction,
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    /// A Ray sampled from the BRDF of the Material, weighted by the BRDF and the cosine over the density of the sampled direction
+    fn indirect_bounce(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        rng: &mut dyn RngCore,
+    ) -> Option<Bounce> {
+        let rand: f64 = rng.gen_range(0.0..1.0);
+
+        let p = match intersection.material.phong {
+            true => 0.5,
+            false => 1.,
+        };
+
+        let new_ray: Ray;
+        let view = ray.direction * (-1.);
+
+        match intersection.material.phong && rand >= p {
+            _ if intersection.material.is_microfacet() => {
+                new_ray = Ray::new(
+                    intersection.get_point_nudged(),
+                    intersection.material.sample_ggx(
+                        view,
+                        intersection.normal,
+                        intersection.tangent,
+                        rng,
+                    ),
+                );
+                if new_ray.direction.dot(intersection.normal) <= 0. {
+                    return None;
+                }
+            }
+            false => {
+                new_ray = match intersection.material.uniform_sampling {
+                    true => Ray::new_rand_ray_uniform(
+                        intersection.get_point_nudged(),
+                        intersection.normal,
+                        rng,
+                    ),
+                    false => {
+                        Ray::new_rand_ray(intersection.get_point_nudged(), intersection.normal, rng)
+                    }
+                };
+            }
+            true => {
+                let reflected_ray = ray.reflect(intersection);
+                new_ray = Ray::new_rand_ray_phong(
+                    intersection.get_point_nudged(),
+                    intersection.material.phong_exponent,
+                    reflected_ray.direction,
+                    rng,
+                );
+                if new_ray.direction.dot(intersection.normal) <= 0. {
+                    return None;
+                }
+                if new_ray.direction.dot(reflected_ray.direction) <= 0. {
+                    return None;
+                }
+            }
+        }
+
+        let cos_theta = intersection.normal.dot(new_ray.direction).max(0.);
+        let proba = Self::bounce_pdf(ray, intersection, new_ray.direction);
+
+        if proba <= 0. {
+            return None;
+        }
+
+        let brdf = Self::surface_brdf(ray, intersection, new_ray.direction);
+
+        // With Portals, the environment is accounted for by compute_portals, and the emission of the light objects by compute_direct
+        Some(Bounce {
+            ray: new_ray,
+            weight: brdf * (cos_theta / proba),
+            nudge: false,
+            sees_background: self.portals.is_empty(),
+            sees_emissive_surfaces: false,
+            sees_light_objects: self.multiple_importance_sampling,
+            bsdf_pdf: match self.multiple_importance_sampling {
+                true => Some(proba),
+                false => None,
+            },
+            kind: BounceKind::Diffuse,
+        })
+    }
+
+    /// Computes the density (per solid angle) with which indirect_bounce samples a direction leaving an intersection seen by the Ray
+    fn bounce_pdf(ray: Ray, intersection: Intersection, direction: Vector) -> f64 {
+        let material = intersection.material;
+        let cos_theta = intersection.normal.dot(direction).max(0.);
+
+        // The direction was sampled from a mix of the cosine-weighted and the Phong lobe densities, or from the GGX distribution
+        match material.is_microfacet() {
+            true => material.ggx_pdf(
+                ray.direction * (-1.),
+                direction,
+                intersection.normal,
+                intersection.tangent,
+            ),
+            false => {
+                let p = match material.phong {
+                    true => 0.5,
+                    false => 1.,
+                };
+                let phong_lobe = direction
+                    .dot(ray.reflect(intersection).direction)
+                    .max(0.)
+                    .powf(material.phong_exponent);
+
+                let proba_diffuse = match material.uniform_sampling {
+                    true => 1. / (2. * PI),
+                    false => cos_theta / PI,
+                };
+                let proba_phong = (material.phong_exponent + 1.) / (2. * PI) * phong_lobe;
+                p * proba_diffuse + (1. - p) * proba_phong
+            }
+        }
+    }
+
+    /// Computes the light of the closest light object hit by a Bounce sampled from the BRDF, closer than max_dist_sq,
+    /// weighted against the sampling of the same point by compute_direct when the Bounce has a bsdf_pdf (see set_multiple_importance_sampling)
+    pub(crate) fn compute_bounce_emission(
+        &self,
+        intersection: Intersection,
+        bounce: Bounce,
+        max_dist_sq: f64,
+        time: f64,
+    ) -> Vector {
+        if !bounce.sees_light_objects {
+            return Vector::new_eq(0.);
+        }
+
+        let ray = bounce.ray;
+        let hit = self
+            .light_objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, light_object)| {
+                let obj = light_object.as_ref();
+                let transform = Self::object_transform(obj, time);
+                obj.intersection(Self::to_object_space(transform, ray))
+                    .map(|inter| {
+                        (
+                            index,
+                            inter,
+                            Self::to_world_space(obj, transform, inter, time),
+                        )
+                    })
+            })
+            .map(|(index, local, inter)| {
+                (index, (inter.point - ray.origin).norm_sq(), local, inter)
+            })
+            .filter(|(_, dist_sq, _, _)| *dist_sq < max_dist_sq)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        let (index, d, local_inter, light_inter) = match hit {
+            Some(hit) => hit,
+            None => return Vector::new_eq(0.),
+        };
+        if !Self::is_linked(&self.unlinked_light_objects, index, intersection) {
+            return Vector::new_eq(0.);
+        }
+
+        // Light objects only emit on the outer side of their surface
+        let cos_light = light_inter
+            .outward_normal()
+            .dot(ray.direction.normalize() * (-1.));
+        if cos_light <= 0. {
+            return Vector::new_eq(0.);
+        }
+
+        let powers = self.light_powers(time);
+        let light_object = self.light_objects[index].as_ref();
+        let transform = Self::object_transform(light_object, time);
+        let proba_point = light_object.sample_pdf(
+            Self::point_to_object(transform, ray.origin),
+            local_inter.point,
+        ) / Self::area_scale(transform, local_inter.normal);
+        let light_pdf = powers[index] / powers.iter().sum::<f64>() * proba_point * d / cos_light;
+        let (light_color, light_emissivity) = Self::light_emission(light_object, time);
+
+        let weight = match bounce.bsdf_pdf {
+            Some(bsdf_pdf) => power_heuristic(bsdf_pdf, light_pdf),
+            None => 1.,
+        };
+
+        Vector::from(light_color)
+            * light_emissivity
+            * self.emission_pattern(light_inter.material, light_inter.point, light_inter.uv)
+            * weight
+    }
+
+    /// Computes the total power of each light object, with which compute_direct chooses the light to sample
+    /// The emission Textures are left out: a light object counts as if its whole surface emitted its emission_color.
+    fn light_powers(&self, time: f64) -> Vec<f64> {
+        self.light_objects
+            .iter()
+            .map(|light_object| {
+                let (color, emissivity) = Self::light_emission(light_object.as_ref(), time);
+                emissivity
+                    * Self::surface_area(light_object.as_ref(), time)
+                    * (color.r + color.g + color.b)
+                    / 3.
+            })
+            .collect()
+    }
+
+    /// Computes the emissive surface component of the light intensity, color by color, of an intersection
+    pub fn compute_emissive(
+        &self,
+        intersection: Intersection,
+        show_emissive_surfaces: bool,
+        _time: f64,
+    ) -> Vector {
+        match intersection.material.emissive && show_emissive_surfaces {
+            false => Vector::new_eq(0.),
+            true => {
+                Vector::from(intersection.material.emission_color)
+                    * intersection.material.emissivity
+                    * self.emission_pattern(
+                        intersection.material,
+                        intersection.point,
+                        intersection.uv,
+                    )
+            }
+        }
+    }
+
+    /// Computes the tint of the light emitted at a point of uv coordinates of a surface, by the emission Texture of its Material (white without one)
+    fn emission_pattern(&self, material: Material, point: Vector, uv: (f64, f64)) -> Color {
+        match material.emission_texture {
+            None => Color::white(),
+            Some(texture) => self.texture_color(texture, point, uv),
+        }
+    }
+
+    /// Computes the direct lightning component of the light intensity, color by color, of an intersection
+    pub fn compute_direct(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        _nb_iter_max: usize,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let (dir_to_light, light, proba_light) = match self.sample_light_object(
+            intersection.get_point_nudged(),
+            intersection,
+            time,
+            stochastic_transparency,
+            rng,
+        ) {
+            Some(sample) => sample,
+            None => return Vector::new_eq(0.),
+        };
+        let brdf = Self::surface_brdf(ray, intersection, dir_to_light);
+
+        // With multiple importance sampling, the Rays sampled from the BRDF may also have found this point
+        let weight = match self.multiple_importance_sampling {
+            true => power_heuristic(
+                proba_light,
+                Self::bounce_pdf(ray, intersection, dir_to_light),
+            ),
+            false => 1.,
+        };
+
+        light * brdf * intersection.normal.dot(dir_to_light).max(0.) * weight
+    }
+
+    /// Samples a point on one of the light objects, chosen with chances proportional to their power, to light a receiving intersection
+    /// Returns the direction towards the point, the light received from it divided by the density of the sample (in solid angle),
+    /// and this density. Returns None when the point is hidden from origin, where the shadow Ray starts, or unlinked from the receiver.
+    fn sample_light_object(
+        &self,
+        origin: Vector,
+        receiver: Intersection,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Vector, Vector, f64)> {
+        let rand: f64 = rng.gen_range(0.0..1.0);
+
+        // We aim one of the emissive objects, with chances proportional to its total power
+        let powers = self.light_powers(time);
+
+        let sum: f64 = powers.iter().sum();
+        if sum <= 0. {
+            return None;
+        }
+
+        let mut remaining = rand * sum;
+        let mut chosen = self.light_objects.len() - 1;
+        for (i, power) in powers.iter().enumerate() {
+            if remaining < *power {
+                chosen = i;
+                break;
+            }
+            remaining -= power;
+        }
+        // An unlinked light does not light this object at all
+        if !Self::is_linked(&self.unlinked_light_objects, chosen, receiver) {
+            return None;
+        }
+        let light_object = &self.light_objects[chosen];
+        let proba_light = powers[chosen] / sum;
+        let (light_color, light_emissivity) = Self::light_emission(light_object.as_ref(), time);
+        let light_surface = Self::surface_area(light_object.as_ref(), time);
+
+        // We get a random point on the surface of the light, aimed at the receiver in the space of the light,
+        // and moved to where the light is in the world space at that time
+        let transform = Self::object_transform(light_object.as_ref(), time);
+        let local_receiver = Self::point_to_object(transform, receiver.point);
+        let (light_point, light_normal, light_uv, proba_point) =
+            light_object.sample_point_towards(local_receiver, rng);
+        if proba_point <= 0. {
+            return None;
+        }
+        let proba_point = proba_point / Self::area_scale(transform, light_normal);
+        let (light_point, light_normal) =
+            Self::sample_to_world(transform, light_point, light_normal);
+        let pattern = self.emission_pattern(light_object.get_material(), light_point, light_uv);
+
+        let dir_to_receiver = (receiver.point - light_point).normalize();
+        let d = (receiver.point - light_point).norm_sq();
+
+        // The light is moved off its surface, so that a light object which is also an object of the Scene does not shadow itself
+        let new_light = Light::new(
+            light_point + light_normal * 0.0001,
+            Vector::from(light_color) * light_emissivity / light_surface,
+        );
+        if !self.compute_shadows_with_rng(origin, &new_light, time, stochastic_transparency, rng) {
+            return None;
+        }
+
+        let cos_light = light_normal.dot(dir_to_receiver).max(0.);
+
+        let light = Vector::from(light_color) * light_emissivity * pattern * cos_light
+            / (d * proba_point * proba_light)
+            * self.transmittance(origin, new_light.center);
+        Some((
+            dir_to_receiver * (-1.),
+            light,
+            proba_point * proba_light * d / cos_light,
+        ))
+    }
+
+    /// Computes the environment lighting component of the light intensity, color by color, of an intersection, through the Portals of the Scene
+    /// A point is sampled uniformly on one of the Portals: the environment is seen there if nothing stands between the intersection and the Portal,
+    /// and nothing stands behind the Portal either.
+    pub fn compute_portals(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        _nb_iter_max: usize,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        if self.portals.is_empty() {
+            return Vector::new_eq(0.);
+        }
+
+        let portal = &self.portals[rng.gen_range(0..self.portals.len())];
+        let proba_portal = 1. / self.portals.len() as f64;
+        let portal_point = portal.sample_point(rng);
+
+        let dir_to_portal = (portal_point - intersection.point).normalize();
+        let d = (portal_point - intersection.point).norm_sq();
+        let cos_theta = intersection.normal.dot(dir_to_portal);
+        let cos_portal = portal.normal().dot(dir_to_portal).abs();
+
+        if cos_theta <= 0. {
+            return Vector::new_eq(0.);
+        }
+
+        let nudged = intersection.get_point_nudged();
+        let portal_light = Light::new(portal_point, Vector::new_eq(0.));
+        let escaping_ray = Ray::new(portal_point + dir_to_portal * 0.0001, dir_to_portal);
+
+        if !self.compute_shadows_with_rng(nudged, &portal_light, time, stochastic_transparency, rng)
+            || self
+                .compute_intersection_with_rng(escaping_ray, time, stochastic_transparency, rng)
+                .is_some()
+        {
+            return Vector::new_eq(0.);
+        }
+
+        let brdf = Self::surface_brdf(ray, intersection, dir_to_portal);
+
+        // The point was sampled uniformly on the surface of the Portal
+        let proba_point = 1. / portal.get_surface_area();
+
+        self.background(escaping_ray) * brdf * cos_theta * cos_portal
+            / (d * proba_point * proba_portal)
+    }
+
+    /// Evaluates the BRDF of the Material at an intersection seen by the Ray, for the light coming from direction
+    fn surface_brdf(ray: Ray, intersection: Intersection, direction: Vector) -> Color {
+        let material = intersection.material;
+        match material.is_microfacet() {
+            true => material.ggx_brdf(
+                ray.direction * (-1.),
+                direction,
+                intersection.normal,
+                intersection.tangent,
+            ),
+            false => material.brdf(ray.reflect(intersection).direction, direction),
+        }
+    }
+
+    /// Computes the emitted color and emissivity of a light object at a given time
+    fn light_emission(light_object: &(dyn Object + Sync), time: f64) -> (Color, f64) {
+        let material = light_object.get_material();
+        animate_emission(
+            material.emission_color,
+            material.emissivity,
+            &light_object.get_animations(),
+            time,
+        )
+    }
+
+    /// Computes the point light component of the light intensity, color by color, of an intersection
+    pub fn compute_point_light(
+        &self,
+        intersection: Intersection,
+        _nb_iter_max: usize,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let mut cur_intensity = Vector::new(0., 0., 0.);
+        for (index, light) in self.lights.iter().enumerate() {
+            if Self::is_linked(&self.unlinked_lights, index, intersection)
+                && self.compute_shadows_with_rng(
+                    intersection.get_point_nudged(),
+                    light,
+                    time,
+                    stochastic_transparency,
+                    rng,
+                )
+            {
+                cur_intensity += intersection.get_intensity(light, time)
+                    * self.light_transmittance(intersection.point, light, time);
+            }
+        }
+        cur_intensity
+    }
+
+    /// Computes the fraction of the light of a point light reaching a point through the Media of the Scene
+    fn light_transmittance(&self, point: Vector, light: &Light, time: f64) -> f64 {
+        if self.media.is_empty() {
+            return 1.;
+        }
+
+        let center = Ray::new(light.center, point)
+            .apply_animations(light.get_animations(), time)
+            .origin;
+        self.transmittance(point, center)
+    }
+}
+
+/// Weight of a sample drawn with the density pdf, when another strategy could have drawn it with the density other_pdf
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    match pdf.is_finite() && pdf > 0. {
+        true => pdf * pdf / (pdf * pdf + other_pdf * other_pdf),
+        false => 0.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animate::Animation;
+    use crate::object::disk::Disk;
+    use crate::testing::{example_camera, mean_and_variance};
+    use crate::utils::{Matrix4, Transform};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// A black horizontal ceiling at y = 10., with a square opening of half side 1. above the origin
+    struct Ceiling;
+
+    impl Animatable for Ceiling {
+        fn add_animation(&mut self, _animation: Animation) {}
+
+        fn get_animations(&self) -> Vec<Animation> {
+            Vec::new()
+        }
+    }
+
+    impl Object for Ceiling {
+        fn intersection(&self, ray: Ray) -> Option<Intersection> {
+            let t = (10. - ray.origin.y) / ray.direction.y;
+            let point = ray.origin + ray.direction * t;
+
+            match t > 0. && (point.x.abs() > 1. || point.z.abs() > 1.) {
+                false => None,
+                true => Some(Intersection::new(
+                    point,
+                    Vector::new(0., -1., 0.),
+                    self.get_material(),
+                    (0., 0.),
+                )),
+            }
+        }
+
+        fn get_material(&self) -> Material {
+            Material::create_diffuse(Color::black())
+        }
+    }
+
+    /// Returns the mean and the variance of the light received at the origin (on an upward facing diffuse surface) below the Ceiling
+    fn interior_lighting(scene: &Scene) -> (f64, f64) {
+        let intersection = Intersection::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 1., 0.),
+            Material::create_diffuse(Color::white()),
+            (0., 0.),
+        );
+        let ray = Ray::new(Vector::new(0., 5., 5.), Vector::new(0., -1., -1.)).normalize();
+
+        mean_and_variance(20000, || {
+            scene
+                .compute_intensity(ray, intersection, 1, 0., false, &mut rand::thread_rng())
+                .x
+        })
+    }
+
+    const CONFIG: Config = Config::new(1, 1, 1., false, 2, 20000, false, false, 0., 0., 1);
+
+    /// Averages the direct lighting received at the origin (on an upward facing diffuse surface)
+    fn average_direct(scene: &Scene) -> f64 {
+        let intersection = Intersection::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 1., 0.),
+            Material::create_diffuse(Color::white()),
+            (0., 0.),
+        );
+        let ray = Ray::new(Vector::new(0., 10., 10.), Vector::new(0., -1., -1.)).normalize();
+
+        let intensity: Vector = (0..20000)
+            .map(|_| scene.compute_direct(ray, intersection, 1, 0., false, &mut rand::thread_rng()))
+            .sum();
+        intensity.x / 20000.
+    }
+
+    #[test]
+    fn bounding_box_leaves_out_infinite_objects() {
+        let mut scene = Scene::new();
+        assert!(scene.bounding_box().is_none());
+        scene.add_object(Box::new(Ceiling));
+        assert!(scene.bounding_box().is_none());
+
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            1.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let mut disk = Disk::new(
+            Vector::new_eq(0.),
+            Vector::new(0., -1., 0.),
+            2.,
+            Material::create_emissive(Color::white(), 10.),
+        );
+        disk.set_transform(
+            Transform::new(Matrix4::translation(Vector::new(0., 10., 0.)))
+                .expect("a translation is invertible"),
+        );
+        disk.add_animation(Animation::translation(0., 1., Vector::new(5., 0., 0.)));
+        scene.add_light_object(Box::new(disk));
+
+        let (min, max) = scene.bounding_box().expect("the Scene has finite objects");
+        assert_approx_eq::assert_approx_eq!((min - Vector::new(-2., -1., -2.)).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!((max - Vector::new(2., 10., 2.)).norm(), 0.);
+
+        // The animation moves the disk on after its Transform
+        let (min, max) = scene
+            .bounding_box_at(1.)
+            .expect("the Scene has finite objects");
+        assert_approx_eq::assert_approx_eq!((min - Vector::new(-1., -1., -2.)).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!((max - Vector::new(7., 10., 2.)).norm(), 0.);
+    }
+
+    #[test]
+    fn direct_lighting_sums_all_lights() {
+        let light = |x: f64| {
+            Box::new(Sphere::new(
+                Vector::new(x, 10., 0.),
+                1.,
+                Material::create_emissive(Color::white(), 1000.),
+            ))
+        };
+        let mut scene_single = Scene::new();
+        scene_single.add_light_object(light(-10.));
+        let mut scene_double = Scene::new();
+        scene_double.add_light_object(light(-10.));
+        scene_double.add_light_object(light(10.));
+
+        let single = average_direct(&scene_single);
+        let double = average_direct(&scene_double);
+
+        assert_approx_eq::assert_approx_eq!(double / single, 2., 0.1);
+    }
+
+    #[test]
+    fn multiple_importance_sampling_reduces_glossy_noise() {
+        use crate::object::disk::Disk;
+
+        // A glossy metal floor reflecting a small disk light
+        let scene = |multiple_importance_sampling: bool| {
+            let mut scene = Scene::new();
+            scene.add_object(Box::new(Disk::new(
+                Vector::new_eq(0.),
+                Vector::new(0., 1., 0.),
+                100.,
+                Material::create_brushed_metal(Color::new_eq(0.8), 0.05, 0.05),
+            )));
+            scene.add_light_object(Box::new(Disk::new(
+                Vector::new(10., 10., 0.),
+                Vector::new(0., -1., 0.),
+                1.,
+                Material::create_emissive(Color::white(), 1000.),
+            )));
+            scene.set_multiple_importance_sampling(multiple_importance_sampling);
+            scene
+        };
+
+        let ray = Ray::new(
+            Vector::new(-10., 10., 0.),
+            Vector::new(1., -1., 0.).normalize(),
+        );
+        let intensity = |scene: &Scene| {
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the floor");
+            mean_and_variance(20000, || {
+                scene
+                    .compute_intensity(ray, inter, 1, 0., false, &mut rand::thread_rng())
+                    .x
+            })
+        };
+
+        let (light_sampling, light_sampling_variance) = intensity(&scene(false));
+        let (mis, mis_variance) = intensity(&scene(true));
+
+        // Both converge to the same highlight, with much less noise when combined
+        let standard_error = ((light_sampling_variance + mis_variance) / 20000.).sqrt();
+        assert!((mis - light_sampling).abs() < 5. * standard_error);
+        assert!(mis_variance * 100. < light_sampling_variance);
+    }
+
+    #[test]
+    fn next_event_estimation_converges_to_brute_force() {
+        use crate::object::disk::Disk;
+
+        // A diffuse floor lit by a light object, which the paths without next event estimation have to hit
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Disk::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 1., 0.),
+            100.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_light_object(Box::new(Sphere::new(
+            Vector::new(0., 10., 0.),
+            1.,
+            Material::create_emissive(Color::white(), 1000.),
+        )));
+
+        let ray = Ray::new(Vector::new(3., 1., 0.), Vector::new(0., -1., 0.));
+        let inter = scene
+            .compute_intersection(ray, 0.)
+            .expect("the ray hits the floor");
+        let nb_samples = 100_000;
+        let mut rng = rand::thread_rng();
+        let (nee, nee_variance) = mean_and_variance(nb_samples, || {
+            scene
+                .compute_intensity(ray, inter, 1, 0., false, &mut rng)
+                .x
+        });
+        let (brute_force, brute_force_variance) = mean_and_variance(nb_samples, || {
+            scene
+                .compute_intensity_without_nee(ray, inter, 1, 0., false, &mut rng)
+                .x
+        });
+
+        // Both converge to the same lighting, the brute force with much more noise
+        assert!(nee > 0.);
+        let standard_error = ((nee_variance + brute_force_variance) / nb_samples as f64).sqrt();
+        assert!((nee - brute_force).abs() < 5. * standard_error);
+        assert!(nee_variance * 10. < brute_force_variance);
+    }
+
+    #[test]
+    fn emissive_surfaces_are_not_counted_twice() {
+        use crate::object::disk::Disk;
+
+        // A diffuse floor lit by an emissive sphere, and a light object at light_object_center: on the sphere, sampled directly, or elsewhere
+        let scene = |light_object_center: Option<Vector>| {
+            let light = |center| {
+                Box::new(Sphere::new(
+                    center,
+                    2.,
+                    Material::create_emissive(Color::white(), 1000.),
+                ))
+            };
+            let mut scene = Scene::new();
+            scene.add_object(Box::new(Disk::new(
+                Vector::new_eq(0.),
+                Vector::new(0., 1., 0.),
+                100.,
+                Material::create_diffuse(Color::white()),
+            )));
+            scene.add_object(light(Vector::new(0., 10., 0.)));
+            if let Some(center) = light_object_center {
+                scene.add_light_object(light(center));
+            }
+            scene.set_show_emissive_surfaces(true);
+            scene
+        };
+
+        let ray = Ray::new(Vector::new(3., 1., 0.), Vector::new(0., -1., 0.));
+        let nb_samples = 40000;
+        let intensity = |scene: &Scene| {
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the floor");
+            mean_and_variance(nb_samples, || {
+                scene
+                    .compute_intensity(ray, inter, 2, 0., false, &mut rand::thread_rng())
+                    .x
+            })
+        };
+
+        let sampled = Some(Vector::new(0., 10., 0.));
+        let (direct, direct_variance) = intensity(&scene(sampled));
+        let (indirect, indirect_variance) = intensity(&scene(None));
+
+        // Sampling the light directly replaces the light found by the diffuse bounces, instead of adding to it
+        let standard_error = ((direct_variance + indirect_variance) / nb_samples as f64).sqrt();
+        assert!(direct > 0.);
+        assert!(
+            (direct - indirect).abs() < 5. * standard_error,
+            "{direct} vs {indirect} (standard error {standard_error})"
+        );
+
+        // An emissive object which is not a light object is still seen after a diffuse bounce, whatever the light objects
+        // (here one hidden under the floor)
+        let (unsampled, unsampled_variance) = intensity(&scene(Some(Vector::new(0., -10., 0.))));
+        let standard_error = ((unsampled_variance + indirect_variance) / nb_samples as f64).sqrt();
+        assert!(
+            (unsampled - indirect).abs() < 5. * standard_error,
+            "{unsampled} vs {indirect} (standard error {standard_error})"
+        );
+
+        // The camera still sees the emissive surface
+        let at_light = Ray::new(Vector::new(0., 0.5, 0.), Vector::new(0., 1., 0.));
+        let inter = scene(sampled)
+            .compute_intersection(at_light, 0.)
+            .expect("the ray hits the light");
+        assert!(inter.light_object);
+        assert!(
+            !scene(Some(Vector::new(0., -10., 0.)))
+                .compute_intersection(at_light, 0.)
+                .expect("the ray hits the light")
+                .light_object
+        );
+        assert!(
+            scene(sampled)
+                .compute_intensity(at_light, inter, 2, 0., false, &mut rand::thread_rng())
+                .x
+                >= 1000.
+        );
+    }
+
+    #[test]
+    fn textured_panel_casts_its_pattern() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., -1000., 0.),
+            1000.,
+            Material::create_diffuse(Color::white()),
+        )));
+        // A panel facing down, red on the quarters around u = v = 0.25 and u = v = 0.75, blue on the others
+        let panel = Material::create_emissive_textured(
+            Texture::Checker {
+                color_a: Color::red(),
+                color_b: Color::blue(),
+                scale: 2.,
+            },
+            100.,
+        );
+        scene.add_light_object(Box::new(Quad::new(
+            Vector::new(-2., 1., -2.),
+            Vector::new(4., 0., 0.),
+            Vector::new(0., 0., 4.),
+            panel,
+        )));
+
+        // The floor below each quarter mostly receives its color
+        let received = |x: f64, z: f64| {
+            let ray = Ray::new(Vector::new(x, 0.5, z), Vector::new(0., -1., 0.));
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the floor");
+            (0..2000)
+                .map(|_| scene.compute_direct(ray, inter, 1, 0., false, &mut rand::thread_rng()))
+                .sum::<Vector>()
+        };
+        let below_red = received(-1., -1.);
+        let below_blue = received(1., -1.);
+        assert_eq!(below_red.y, 0.);
+        assert!(below_red.x > 2. * below_red.z);
+        assert!(below_blue.z > 2. * below_blue.x);
+
+        // Seen directly, the panel shows its pattern
+        let emitted = |uv: (f64, f64)| {
+            let inter = Intersection::new(Vector::new_eq(0.), Vector::new(0., -1., 0.), panel, uv);
+            scene.compute_emissive(inter, true, 0.)
+        };
+        assert_eq!(emitted((0.25, 0.25)).x, 100.);
+        assert_eq!(emitted((0.75, 0.25)).z, 100.);
+    }
+
+    #[test]
+    fn iridescent_glass_reflects_what_it_does_not_transmit() {
+        let scene = Scene::new();
+        let material = Material {
+            mirror: false,
+            transparent: true,
+            n_object: 1.5,
+            ..Material::create_iridescent(400., 1.33)
+        };
+        let normal = Vector::new(0., 0., 1.);
+        let ray = Ray::new(Vector::new(0., 0., 1.), Vector::new(0., 0.6, -0.8));
+        let intersection = Intersection::new(Vector::new_eq(0.), normal, material, (0., 0.));
+        let reflectance = material.thin_film_reflectance(ray.direction.dot(normal));
+
+        let nb_samples = 20000;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut reflected = Color::black();
+        let mut transmitted = Color::black();
+        for _ in 0..nb_samples {
+            let bounce = scene
+                .transparent_bounce(ray, intersection, &mut rng)
+                .unwrap();
+            match bounce.ray.direction.dot(normal) > 0. {
+                true => reflected += bounce.weight / nb_samples as f64,
+                false => transmitted += bounce.weight / nb_samples as f64,
+            }
+        }
+
+        // On average, the reflection carries the reflectance of the film and the refraction the rest of the light
+        for (mean, expected) in [
+            (reflected.r, reflectance.r),
+            (reflected.b, reflectance.b),
+            (transmitted.r, 1. - reflectance.r),
+            (transmitted.b, 1. - reflectance.b),
+        ] {
+            assert!(
+                (mean - expected).abs() < 0.1 * expected,
+                "{mean} vs {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn white_light_can_emit_blue() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., -1000., 0.),
+            1000.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let lamp = Material::create_emissive_colored(Color::white(), Color::blue(), 1000.);
+        scene.add_light_object(Box::new(Sphere::new(Vector::new(0., 10., 0.), 1., lamp)));
+
+        // The floor only receives blue light
+        let ray = Ray::new(Vector::new(0., 1., 0.), Vector::new(0., -1., 0.));
+        let inter = scene
+            .compute_intersection(ray, 0.)
+            .expect("the ray hits the floor");
+        let direct: Vector = (0..100)
+            .map(|_| scene.compute_direct(ray, inter, 1, 0., false, &mut rand::thread_rng()))
+            .sum();
+        assert_eq!((direct.x, direct.y), (0., 0.));
+        assert!(direct.z > 0.);
+
+        // The lamp is seen emitting blue, while its white surface still reflects other lights
+        let lamp_inter = Intersection::new(
+            Vector::new(0., 9., 0.),
+            Vector::new(0., -1., 0.),
+            lamp,
+            (0., 0.),
+        );
+        let emitted = scene.compute_emissive(lamp_inter, true, 0.);
+        assert_eq!((emitted.x, emitted.y, emitted.z), (0., 0., 1000.));
+        assert_eq!(lamp.color.r, 1.);
+
+        // Materials created with create_emissive emit their own color
+        let red = Material::create_emissive(Color::red(), 1.);
+        assert_eq!((red.emission_color.r, red.emission_color.g), (1., 0.));
+    }
+
+    #[test]
+    fn plane_hit_from_behind_is_lit_when_two_sided() {
+        let scene_with = |material: Material| {
+            let mut scene = Scene::new();
+            scene.add_object(Box::new(Quad::new(
+                Vector::new(-5., 0., -5.),
+                Vector::new(0., 0., 10.),
+                Vector::new(10., 0., 0.),
+                material,
+            )));
+            scene.add_light(Light::new(Vector::new(0., -5., 0.), Vector::new_eq(1e4)));
+            scene
+        };
+
+        // The Quad faces up, towards y: the Ray and the light are behind it
+        let ray = Ray::new(Vector::new(1., -2., 1.), Vector::new(0., 1., 0.));
+        let intensity = |scene: &Scene| {
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the quad");
+            assert!(!inter.front_face);
+            scene.compute_intensity(ray, inter, 1, 0., false, &mut rand::thread_rng())
+        };
+
+        let two_sided = Material::create_diffuse(Color::white());
+        assert!(intensity(&scene_with(two_sided)).x > 0.);
+        let one_sided = Material {
+            two_sided: false,
+            ..two_sided
+        };
+        assert_eq!(intensity(&scene_with(one_sided)).x, 0.);
+
+        // An Intersection whose normal faces away from the Ray is flipped towards it
+        let inter = Intersection::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 2., 0.),
+            two_sided,
+            (0., 0.),
+        )
+        .face_ray(ray);
+        assert_eq!(inter.normal.y, -1.);
+        assert!(!inter.front_face);
+    }
+
+    #[test]
+    fn mirrored_objects_keep_their_front_face() {
+        // The Sphere is mirrored across the plane x = 0, to be centered on (-3, 0, 0)
+        let mut sphere = Sphere::new(
+            Vector::new(3., 0., 0.),
+            1.,
+            Material::create_diffuse(Color::white()),
+        );
+        sphere.set_transform(
+            Transform::new(Matrix4::scaling(Vector::new(-1., 1., 1.)))
+                .expect("the mirror is invertible"),
+        );
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(sphere));
+
+        let faced = |ray: Ray| {
+            scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the sphere")
+                .face_ray(ray)
+        };
+        let outside = faced(Ray::new(
+            Vector::new(-3., 0., 10.),
+            Vector::new(0., 0., -1.),
+        ));
+        assert!(outside.front_face);
+        assert_approx_eq::assert_approx_eq!(outside.normal.z, 1.);
+        let inside = faced(Ray::new(Vector::new(-3., 0., 0.), Vector::new(0., 0., -1.)));
+        assert!(!inside.front_face);
+        assert_approx_eq::assert_approx_eq!(inside.normal.z, 1.);
+    }
+
+    #[test]
+    fn checker_floor_alternates_squares() {
+        let mut scene = Scene::new();
+        scene.add_checker_floor(-10., 5., Color::white(), Color::black());
+
+        let albedo_below = |x: f64, z: f64| {
+            let ray = Ray::new(Vector::new(x, 0., z), Vector::new(0., -1., 0.));
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the floor is below");
+            assert_approx_eq::assert_approx_eq!(inter.point.y, -10.);
+            assert!(inter.front_face);
+            scene.albedo(inter.material, inter.point, inter.uv).r
+        };
+
+        // Neighboring squares differ, and the pattern repeats every two squares
+        let first = albedo_below(2.5, 2.5);
+        assert_ne!(first, albedo_below(7.5, 2.5));
+        assert_ne!(first, albedo_below(2.5, 7.5));
+        assert_eq!(first, albedo_below(7.5, 7.5));
+        assert_eq!(first, albedo_below(-7.5, 12.5));
+        assert_eq!(first, albedo_below(1002.5, -497.5));
+    }
+
+    #[test]
+    fn normal_map_makes_a_smooth_sphere_bumpy() {
+        // Normals tilted towards the tangent, which goes around the vertical axis
+        let map = image::RgbImage::from_pixel(4, 1, image::Rgb([230, 128, 200]));
+        let shade = |normal_map: &dyn Fn(&mut Scene) -> Option<Texture>| {
+            let mut scene = Scene::new();
+            let mut material = Material::create_diffuse(Color::white());
+            material.normal_map = normal_map(&mut scene);
+            scene.add_object(Box::new(Sphere::new(Vector::new_eq(0.), 1., material)));
+            scene.add_light(Light::new(Vector::new(0., 0., 10.), Vector::new_eq(1e4)));
+
+            // Two points on each side of the front of the sphere, in neighboring columns of the map
+            [-0.2_f64, 0.2].map(|angle| {
+                let target = Vector::new(angle.sin(), 0., angle.cos());
+                let ray = Ray::new(target * 5., target * (-1.));
+                let inter = scene
+                    .compute_intersection(ray, 0.)
+                    .expect("the ray hits the sphere");
+                (
+                    inter.normal,
+                    scene.shading_normal(inter),
+                    scene
+                        .compute_intensity(ray, inter, 1, 0., false, &mut rand::thread_rng())
+                        .x,
+                )
+            })
+        };
+        // Without a map, or with a flat one, the shading normal is the geometric one and the sphere looks smooth
+        let flat = Texture::Solid(Color::new(0.5, 0.5, 1.));
+        for points in [shade(&|_| None), shade(&|_| Some(flat))] {
+            for (normal, shading_normal, _) in points {
+                assert_approx_eq::assert_approx_eq!((shading_normal - normal).norm(), 0.);
+            }
+            assert_approx_eq::assert_approx_eq!(points[0].2, points[1].2, 1e-6 * points[0].2);
+        }
+
+        // With the map, the normals tilt towards the light on one side and away from it on the other
+        let points = shade(&|scene| {
+            let texture = ImageTexture::new_data(&image::DynamicImage::ImageRgb8(map.clone()));
+            Some(scene.add_texture(texture))
+        });
+        for (normal, shading_normal, _) in points {
+            assert!(normal.dot(shading_normal) < 0.95);
+            assert_approx_eq::assert_approx_eq!(shading_normal.norm(), 1.);
+        }
+        assert!((points[0].2 - points[1].2).abs() > 0.2 * points[0].2.max(points[1].2));
+    }
+
+    #[test]
+    fn lit_medium_shows_light_shafts() {
+        // A point light shines through the opening of the Ceiling into a box of smoke: the shaft widens to a half side of 2. at y = 0.
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Ceiling));
+        scene.add_light(Light::new(Vector::new(0., 20., 0.), Vector::new_eq(1e5)));
+
+        // Horizontal Rays at y = 0., crossing the shaft or only the shadow of the Ceiling, which see nothing behind
+        let mean_radiance = |scene: &Scene, x: f64, nb_iter_max: usize| {
+            let config = Config::new(1, 1, 1., false, nb_iter_max, 1, false, false, 0., 0., 1);
+            let ray = Ray::new(Vector::new(x, 0., 20.), Vector::new(0., 0., -1.));
+            let nb_samples = 4000;
+            let sum: f64 = (0..nb_samples)
+                .map(|_| {
+                    crate::shade_primary(scene, config, ray, None, 0, 0., &mut rand::thread_rng()).x
+                })
+                .sum();
+            sum / nb_samples as f64
+        };
+        assert_eq!(mean_radiance(&scene, 0., 1), 0.);
+
+        scene.add_medium(Medium::new(
+            Vector::new_eq(-10.),
+            Vector::new(10., 9.9, 10.),
+            0.02,
+            0.05,
+        ));
+        // With single scattering, the light only comes from the shaft
+        let shaft = mean_radiance(&scene, 0., 1);
+        assert!(shaft > 0.);
+        assert_eq!(mean_radiance(&scene, 6., 1), 0.);
+
+        // Scattered again, it spreads dimly around the shaft
+        let shadow = mean_radiance(&scene, 6., 3);
+        assert!(shadow > 0. && shaft > 5. * shadow, "{shaft} vs {shadow}");
+
+        // Seen through the smoke, a lit surface is dimmed on the way to the light and on the way to the camera
+        let floor = Sphere::new(
+            Vector::new(0., -1000., 0.),
+            990.,
+            Material::create_diffuse(Color::white()),
+        );
+        let ray = Ray::new(Vector::new(0., 0., 0.), Vector::new(0., -1., 0.));
+        let inter = floor.intersection(ray).expect("the ray hits the floor");
+        let (mut clear, mut smoky) = (Scene::new(), Scene::new());
+        for scene in [&mut clear, &mut smoky] {
+            scene.add_object(Box::new(floor.clone()));
+            scene.add_light(Light::new(Vector::new(0., 0., 0.), Vector::new_eq(1e5)));
+        }
+        smoky.add_medium(Medium::new(
+            Vector::new_eq(-20.),
+            Vector::new_eq(20.),
+            0.1,
+            0.,
+        ));
+        let clear_radiance = clear
+            .compute_intensity(ray, inter, 1, 0., false, &mut rand::thread_rng())
+            .x;
+        let smoky_radiance = (0..10_000)
+            .map(|_| {
+                smoky
+                    .compute_intensity(ray, inter, 1, 0., false, &mut rand::thread_rng())
+                    .x
+            })
+            .sum::<f64>()
+            / 10_000.;
+        let expected = clear_radiance * (-0.1_f64 * 20.).exp();
+        assert!(
+            (smoky_radiance - expected).abs() < 0.1 * expected,
+            "{smoky_radiance} vs {expected}"
+        );
+    }
+
+    #[test]
+    fn visibility_flags_split_camera_and_shadows() {
+        let scene = |casts_shadows: bool, visible_primary: bool| {
+            let mut scene = Scene::new();
+            scene.add_object(Box::new(Quad::new(
+                Vector::new(-50., 0., -50.),
+                Vector::new(0., 0., 100.),
+                Vector::new(100., 0., 0.),
+                Material::create_diffuse(Color::white()),
+            )));
+            let mut sphere = Sphere::new(
+                Vector::new(0., 5., 0.),
+                1.,
+                Material::create_diffuse(Color::red()),
+            );
+            sphere.set_casts_shadows(casts_shadows);
+            sphere.set_visible_primary(visible_primary);
+            scene.add_object(Box::new(sphere));
+            scene.add_light(Light::new(Vector::new(0., 20., 0.), Vector::new_eq(1e5)));
+            scene
+        };
+
+        // Seen from the camera, above the sphere
+        let camera_ray = Ray::new(Vector::new(0., 10., 0.), Vector::new(0., -1., 0.));
+        let seen_object = |scene: &Scene| {
+            scene
+                .compute_primary_intersection(camera_ray, 0., false, &mut rand::thread_rng())
+                .and_then(|inter| inter.object_index)
+        };
+        // The light received by the floor right under the sphere
+        let shadowed = |scene: &Scene| {
+            let ray = Ray::new(Vector::new(0., 1., 0.), Vector::new(0., -1., 0.));
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the floor");
+            scene
+                .compute_point_light(inter, 1, 0., false, &mut rand::thread_rng())
+                .x
+                == 0.
+        };
+
+        let regular = scene(true, true);
+        assert_eq!(seen_object(&regular), Some(1));
+        assert!(shadowed(&regular));
+
+        // The sphere appears, but leaves no shadow on the floor
+        let shadowless = scene(false, true);
+        assert_eq!(seen_object(&shadowless), Some(1));
+        assert!(!shadowed(&shadowless));
+
+        // The sphere only shows through its shadow, and is still found by the other Rays
+        let holdout = scene(true, false);
+        assert_eq!(seen_object(&holdout), Some(0));
+        assert!(shadowed(&holdout));
+        assert_eq!(
+            holdout
+                .compute_intersection(camera_ray, 0.)
+                .and_then(|inter| inter.object_index),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn furnace_diffuse() {
+        assert_approx_eq::assert_approx_eq!(Scene::furnace_test(CONFIG), FURNACE_ALBEDO, 0.01);
+    }
+
+    #[test]
+    fn white_furnace_conserves_energy() {
+        let config = Config::builder()
+            .height(4)
+            .width(4)
+            .nb_iter_max(5)
+            .nb_rays(64)
+            .build();
+
+        for pixel in Scene::white_furnace_test(100., config) {
+            assert_approx_eq::assert_approx_eq!(pixel.x, 100., 1.);
+            assert_approx_eq::assert_approx_eq!(pixel.y, 100., 1.);
+            assert_approx_eq::assert_approx_eq!(pixel.z, 100., 1.);
+        }
+    }
+
+    #[test]
+    fn furnace_diffuse_uniform_sampling() {
+        let material = Material {
+            uniform_sampling: true,
+            ..Material::create_diffuse(Color::new_eq(0.6))
+        };
+
+        assert_approx_eq::assert_approx_eq!(
+            Scene::furnace_test_with_material(material, CONFIG),
+            0.6,
+            0.02
+        );
+    }
+
+    #[test]
+    fn furnace_brushed_metal() {
+        // Single scattering microfacets lose a bit of energy, but never create any
+        let material = Material::create_brushed_metal(Color::white(), 0.3, 0.6);
+        let albedo = Scene::furnace_test_with_material(material, CONFIG);
+
+        assert!(albedo > 0.85 && albedo < 1.01);
+    }
+
+    #[test]
+    fn furnace_phong() {
+        let material = Material::create_phong(Color::new_eq(0.5), Color::new_eq(0.3), 20.);
+
+        assert_approx_eq::assert_approx_eq!(
+            Scene::furnace_test_with_material(material, CONFIG),
+            0.8,
+            0.02
+        );
+    }
+
+    #[test]
+    fn portal_reduces_noise() {
+        let mut naive = Scene::new();
+        naive.add_object(Box::new(Ceiling));
+        naive.set_background(Background::Solid(Vector::new_eq(100.)));
+
+        let mut portal = Scene::new();
+        portal.add_object(Box::new(Ceiling));
+        portal.set_background(Background::Solid(Vector::new_eq(100.)));
+        portal.add_portal(Portal::new(
+            Vector::new(-1., 10., -1.),
+            Vector::new(2., 0., 0.),
+            Vector::new(0., 0., 2.),
+        ));
+
+        let (naive_mean, naive_variance) = interior_lighting(&naive);
+        let (portal_mean, portal_variance) = interior_lighting(&portal);
+
+        assert_approx_eq::assert_approx_eq!(portal_mean, naive_mean, naive_mean * 0.2);
+        assert!(portal_variance * 10. < naive_variance);
+    }
+
+    #[test]
+    fn removed_objects_keep_indices_and_links_consistent() {
+        let mut scene = Scene::new();
+        for x in [-10., 0., 10.] {
+            scene.add_object(Box::new(Sphere::new(
+                Vector::new(x, 0., 0.),
+                1.,
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        scene.add_light(Light::new(
+            Vector::new(-10., 10., 0.),
+            Vector::new_eq(1000.),
+        ));
+        scene.add_light(Light::new(Vector::new(10., 10., 0.), Vector::new_eq(1000.)));
+        scene.unlink_light(1, 2);
+        scene.unlink_light(0, 1);
+
+        let hit_from_above = |scene: &Scene, x: f64| {
+            let ray = Ray::new(Vector::new(x, 5., 0.), Vector::new(0., -1., 0.));
+            scene.compute_intersection(ray, 0.)
+        };
+        assert_eq!(hit_from_above(&scene, 0.).unwrap().object_index, Some(1));
+
+        let removed = scene.remove_object(1).expect("the Scene has 3 objects");
+        assert_eq!(removed.get_center().x, 0.);
+        assert_eq!(scene.object_count(), 2);
+        assert!(scene.remove_object(2).is_none());
+        assert_eq!(scene.object_count(), 2);
+        assert_eq!(scene.get_object(1).unwrap().get_center().x, 10.);
+        assert!(scene.get_object(2).is_none());
+
+        // The removed object is no longer hit, and the last one moved down to index 1 with its light links
+        assert!(hit_from_above(&scene, 0.).is_none());
+        let last = hit_from_above(&scene, 10.).unwrap();
+        assert_eq!(last.object_index, Some(1));
+        assert!(
+            scene
+                .compute_point_light(last, 1, 0., false, &mut rand::thread_rng())
+                .x
+                > 0.
+        );
+        assert!(scene.remove_light(0).is_some());
+        assert!(scene.remove_light(1).is_none());
+        assert_eq!(scene.light_count(), 1);
+        assert_eq!(scene.get_light(0).unwrap().center.x, 10.);
+        assert_eq!(
+            scene
+                .compute_point_light(last, 1, 0., false, &mut rand::thread_rng())
+                .x,
+            0.
+        );
+
+        scene.add_light_object(Box::new(Sphere::new(
+            Vector::new(0., 20., 0.),
+            1.,
+            Material::create_emissive(Color::white(), 1.),
+        )));
+        assert_eq!(scene.light_object_count(), 1);
+        assert!(scene.get_light_object(0).is_some());
+        assert!(scene.remove_light_object(0).is_some());
+        assert!(scene.remove_light_object(0).is_none());
+        assert_eq!(scene.light_object_count(), 0);
+    }
+
+    #[test]
+    fn unlinked_light_does_not_light_object() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            1.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_light(Light::new(Vector::new(0., 10., 0.), Vector::new_eq(1000.)));
+
+        let ray = Ray::new(Vector::new(0., 10., 0.), Vector::new(0., -1., 0.));
+        let intersection = scene.compute_intersection(ray, 0.).unwrap();
+        assert!(
+            scene
+                .compute_point_light(intersection, 1, 0., false, &mut rand::thread_rng())
+                .x
+                > 0.
+        );
+
+        scene.unlink_light(0, 0);
+        assert_eq!(
+            scene
+                .compute_point_light(intersection, 1, 0., false, &mut rand::thread_rng())
+                .x,
+            0.
+        );
+    }
+
+    /// Fraction of the Rays aimed at a sphere of the given alpha which hit it
+    fn coverage(alpha: f64, stochastic_transparency: bool) -> f64 {
+        let mut material = Material::create_diffuse(Color::white());
+        material.alpha = alpha;
+
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(Vector::new_eq(0.), 1., material)));
+        let config = Config::builder()
+            .stochastic_transparency(stochastic_transparency)
+            .build();
+
+        let ray = Ray::new(Vector::new(0., 0., 10.), Vector::new(0., 0., -1.));
+        let mut rng = rand::thread_rng();
+        let hits = (0..10000)
+            .filter(|_| {
+                scene
+                    .compute_intersection_with_rng(
+                        ray,
+                        0.,
+                        config.stochastic_transparency,
+                        &mut rng,
+                    )
+                    .is_some()
+            })
+            .count();
+        hits as f64 / 10000.
+    }
+
+    #[test]
+    fn stochastic_transparency_is_smooth() {
+        // The Ray crosses two surfaces of the sphere, each one stopping it with a probability alpha
+        assert_approx_eq::assert_approx_eq!(coverage(0.5, true), 0.75, 0.02);
+        assert_approx_eq::assert_approx_eq!(coverage(0.4, true), 0.64, 0.02);
+
+        assert_eq!(coverage(0.5, false), 1.);
+        assert_eq!(coverage(0.4, false), 0.);
+
+        // The hits are drawn from the generator of the sample: the same seed cuts out the same hits
+        let mut material = Material::create_diffuse(Color::white());
+        material.alpha = 0.5;
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(Vector::new_eq(0.), 1., material)));
+        let ray = Ray::new(Vector::new(0., 0., 10.), Vector::new(0., 0., -1.));
+        let hits = |seed: u64| {
+            let mut rng = CONFIG.sample_rng(0, 0, 0, seed as usize);
+            (0..100)
+                .map(|_| {
+                    scene
+                        .compute_intersection_with_rng(ray, 0., true, &mut rng)
+                        .map(|inter| inter.point.z)
+                })
+                .collect::<Vec<Option<f64>>>()
+        };
+        assert_eq!(hits(1), hits(1));
+        assert_ne!(hits(1), hits(2));
+    }
+
+    #[test]
+    fn shadow_catcher_records_shadow_only() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            5.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_light(Light::new(Vector::new(0., 50., 0.), Vector::new_eq(1000.)));
+        scene.set_shadow_catcher(Vector::new(0., -10., 0.), Vector::new(0., 1., 0.));
+
+        // Rays grazing below the sphere towards the catcher plane at (x, -10., 0.)
+        let shadow_at = |x: f64| {
+            let ray = Ray::new(Vector::new(x, -5., 40.), Vector::new(0., -5., -40.)).normalize();
+            scene.compute_shadow_catcher(ray, 0., false, &mut rand::thread_rng())
+        };
+
+        assert_eq!(shadow_at(0.), 1.);
+        assert_eq!(shadow_at(15.), 0.);
+        // The catcher is invisible behind the object, and where it is not hit
+        let on_sphere = Ray::new(Vector::new(0., 0., 40.), Vector::new(0., 0., -1.));
+        assert_eq!(
+            scene.compute_shadow_catcher(on_sphere, 0., false, &mut rand::thread_rng()),
+            0.
+        );
+        let above = Ray::new(Vector::new(0., 0., 40.), Vector::new(0., 1., -1.));
+        assert_eq!(
+            scene.compute_shadow_catcher(above, 0., false, &mut rand::thread_rng()),
+            0.
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn set_environment_sets_a_solid_background() {
+        let mut scene = Scene::new();
+        scene.set_environment(Vector::new(1., 2., 3.));
+        let radiance = scene.background(Ray::new(Vector::new_eq(0.), Vector::new(0., 1., 0.)));
+        assert_eq!((radiance.x, radiance.y, radiance.z), (1., 2., 3.));
+    }
+
+    #[test]
+    fn environment_map_follows_directions() {
+        // A 4x2 map: one column per quarter of turn around the vertical axis, the top row is up
+        let map = image::Rgb32FImage::from_fn(4, 2, |x, y| image::Rgb([x as f32, y as f32, 1.]));
+        let mut scene = Scene::new();
+        assert_eq!(
+            scene
+                .background(Ray::new(Vector::new_eq(0.), Vector::new(1., 0., 0.)))
+                .z,
+            0.
+        );
+
+        scene.set_environment_map(
+            ImageTexture::new(&image::DynamicImage::ImageRgb32F(map)),
+            2.,
+        );
+        let texel = |direction: Vector| {
+            let radiance = scene.background(Ray::new(Vector::new_eq(10.), direction));
+            (radiance.x / 2., radiance.y / 2., radiance.z / 2.)
+        };
+
+        assert_eq!(texel(Vector::new(-1., 0.5, -0.1)), (0., 0., 1.));
+        assert_eq!(texel(Vector::new(0., 0.5, -1.)), (1., 0., 1.));
+        assert_eq!(texel(Vector::new(1., -0.5, 0.1)), (2., 1., 1.));
+        assert_eq!(texel(Vector::new(0., -0.5, 1.)), (3., 1., 1.));
+    }
+
+    #[test]
+    fn json_round_trip_renders_identically() {
+        use crate::object::volume::ConstantMedium;
+        use crate::utils::RenderMode;
+
+        let mut scene = Scene::new();
+        let mut sphere = Sphere::new(
+            Vector::new(-8., 0., 0.),
+            6.,
+            Material::create_checker(Color::red(), Color::blue(), 8.),
+        );
+        sphere.add_animation(Animation::translation(0., 10., Vector::new(0., 5., 0.)));
+        scene.add_object(Box::new(sphere));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(8., 0., 0.),
+            6.,
+            Material::create_brushed_metal(Color::new(0.9, 0.6, 0.2), 0.1, 0.4),
+        )));
+        scene.add_light(Light::new(Vector::new(0., 20., 20.), Vector::new_eq(1e6)));
+        scene.set_background(Background::Gradient(
+            Vector::new_eq(0.),
+            Vector::new(10., 20., 30.),
+        ));
+        scene.unlink_light(0, 1);
+
+        let path = std::env::temp_dir().join("raytracer_scene_round_trip.json");
+        scene.to_json(&path).expect("the scene can be saved");
+        let loaded = Scene::from_json(&path).expect("the scene can be loaded");
+
+        let mut config = Config::new(12, 18, 1., false, 0, 1, false, false, 0., 10., 2);
+        let camera = example_camera(&config);
+        // Path tracing draws its samples from the seed of the config: both Scenes see the same paths
+        for render_mode in [
+            RenderMode::Albedo,
+            RenderMode::Normals,
+            RenderMode::PathTracing,
+        ] {
+            config.render_mode = render_mode;
+            for k in 0..config.nb_frames {
+                assert_eq!(
+                    crate::render_radiance(&camera, &scene, config, k),
+                    crate::render_radiance(&camera, &loaded, config, k)
+                );
+            }
+        }
+
+        // Volumes are saved with their boundary, and the file of a loaded Scene is the same
+        scene.add_object(Box::new(ConstantMedium::new(
+            Box::new(Sphere::new(
+                Vector::new_eq(0.),
+                2.,
+                Material::create_diffuse(Color::white()),
+            )),
+            0.5,
+            Color::green(),
+        )));
+        scene.to_json(&path).expect("the scene can be saved");
+        let saved = std::fs::read_to_string(&path).expect("the file exists");
+        Scene::from_json(&path)
+            .expect("the scene can be loaded")
+            .to_json(&path)
+            .expect("the scene can be saved");
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("the file exists"),
+            saved
+        );
+        assert!(saved.contains("\"type\": \"ConstantMedium\""));
+
+        scene.add_object(Box::new(Ceiling));
+        assert!(matches!(
+            scene.to_json(&path),
+            Err(SceneError::UnsupportedObject)
+        ));
+    }
+
+    #[test]
+    fn transformed_light_objects_light_like_objects_built_in_place() {
+        use crate::object::quad::Quad;
+
+        let emissive = Material::create_emissive(Color::white(), 1000.);
+        // A 1 x 1 quad stretched to 4 x 2, and a unit sphere scaled twice: both moved above the origin
+        let mut quad = Quad::new(
+            Vector::new(-0.5, 0., -0.5),
+            Vector::new(1., 0., 0.),
+            Vector::new(0., 0., 1.),
+            emissive,
+        );
+        quad.set_transform(
+            Transform::new(
+                Matrix4::translation(Vector::new(1., 8., 0.))
+                    * Matrix4::scaling(Vector::new(4., 1., 2.)),
+            )
+            .expect("the matrix is invertible"),
+        );
+        let mut sphere = Sphere::new(Vector::new_eq(0.), 1., emissive);
+        sphere.set_transform(
+            Transform::new(
+                Matrix4::translation(Vector::new(0., 10., 3.))
+                    * Matrix4::scaling(Vector::new_eq(2.)),
+            )
+            .expect("the matrix is invertible"),
+        );
+        let pairs: [(Box<dyn Object + Sync>, Box<dyn Object + Sync>); 2] = [
+            (
+                Box::new(quad),
+                Box::new(Quad::new(
+                    Vector::new(-1., 8., -1.),
+                    Vector::new(4., 0., 0.),
+                    Vector::new(0., 0., 2.),
+                    emissive,
+                )),
+            ),
+            (
+                Box::new(sphere),
+                Box::new(Sphere::new(Vector::new(0., 10., 3.), 2., emissive)),
+            ),
+        ];
+
+        // The light received at the origin, sampled directly and found by the diffuse bounces
+        let intersection = Intersection::new(
+            Vector::new_eq(0.),
+            Vector::new(0., 1., 0.),
+            Material::create_diffuse(Color::white()),
+            (0., 0.),
+        );
+        let ray = Ray::new(Vector::new(0., 5., 5.), Vector::new(0., -1., -1.)).normalize();
+        let nb_samples = 20000;
+        let lighting = |light: Box<dyn Object + Sync>| {
+            let mut scene = Scene::new();
+            scene.add_light_object(light);
+            scene.set_multiple_importance_sampling(true);
+            let mut rng = rand::thread_rng();
+            mean_and_variance(nb_samples, || {
+                scene
+                    .compute_intensity(ray, intersection, 1, 0., false, &mut rng)
+                    .x
+            })
+        };
+
+        for (transformed, in_place) in pairs {
+            let (transformed, transformed_variance) = lighting(transformed);
+            let (in_place, in_place_variance) = lighting(in_place);
+            let standard_error =
+                ((transformed_variance + in_place_variance) / nb_samples as f64).sqrt();
+            assert!(in_place > 0.);
+            assert!(
+                (transformed - in_place).abs() < 5. * standard_error,
+                "{transformed} vs {in_place} (standard error {standard_error})"
+            );
+        }
+    }
+
+    #[test]
+    fn transformed_sphere_is_an_ellipsoid() {
+        use crate::utils::{Matrix4, Transform};
+
+        // A unit sphere stretched twice along x, then moved to x = 10.
+        let mut sphere = Sphere::new(
+            Vector::new_eq(0.),
+            1.,
+            Material::create_diffuse(Color::white()),
+        );
+        sphere.set_transform(
+            Transform::new(
+                Matrix4::translation(Vector::new(10., 0., 0.))
+                    * Matrix4::scaling(Vector::new(2., 1., 1.)),
+            )
+            .expect("the matrix is invertible"),
+        );
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(sphere));
+
+        let inter = scene
+            .compute_intersection(
+                Ray::new(Vector::new(11., 5., 0.), Vector::new(0., -1., 0.)),
+                0.,
+            )
+            .expect("the ray hits the ellipsoid");
+
+        // The ellipsoid is ((x - 10) / 2)^2 + y^2 + z^2 = 1: its normal follows the gradient (x - 10) / 4, y, z
+        let expected_point = Vector::new(11., 0.75_f64.sqrt(), 0.);
+        let expected_normal = Vector::new(0.25, expected_point.y, 0.).normalize();
+        assert_approx_eq::assert_approx_eq!((inter.point - expected_point).norm(), 0.);
+        assert_approx_eq::assert_approx_eq!((inter.normal - expected_normal).norm(), 0.);
+
+        assert!(scene
+            .compute_intersection(
+                Ray::new(Vector::new(0., 5., 0.), Vector::new(0., -1., 0.)),
+                0.
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_creases() {
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., -1000., 0.),
+            1000.,
+            Material::create_diffuse(Color::white()),
+        )));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(0., 2., 0.),
+            2.,
+            Material::create_diffuse(Color::white()),
+        )));
+        let towards_floor = |x: f64| Ray::new(Vector::new(x, 10., 0.), Vector::new(0., -1., 0.));
+
+        // Far from the sphere, the floor sees the whole sky
+        assert_eq!(
+            scene.compute_ambient_occlusion(
+                towards_floor(50.),
+                200,
+                10.,
+                0.,
+                false,
+                &mut rand::thread_rng()
+            ),
+            1.
+        );
+        // Next to the foot of the sphere, about half of the Rays hit it
+        let crease = scene.compute_ambient_occlusion(
+            towards_floor(2.5),
+            2000,
+            10.,
+            0.,
+            false,
+            &mut rand::thread_rng(),
+        );
+        assert!(crease > 0.2 && crease < 0.9, "{crease}");
+        // Unless the occlusion distance is shorter than the gap
+        assert_eq!(
+            scene.compute_ambient_occlusion(
+                towards_floor(2.5),
+                200,
+                0.1,
+                0.,
+                false,
+                &mut rand::thread_rng()
+            ),
+            1.
+        );
+        // Misses are unoccluded
+        let sky = Ray::new(Vector::new(0., 10., 0.), Vector::new(0., 1., 0.));
+        assert_eq!(
+            scene.compute_ambient_occlusion(sky, 200, 10., 0., false, &mut rand::thread_rng()),
+            1.
+        );
+
+        // Objects which cast no shadows do not occlude either
+        let mut shadowless = Sphere::new(
+            Vector::new(50., 2., 0.),
+            2.,
+            Material::create_diffuse(Color::white()),
+        );
+        shadowless.set_casts_shadows(false);
+        scene.add_object(Box::new(shadowless));
+        assert_eq!(
+            scene.compute_ambient_occlusion(
+                towards_floor(52.5),
+                200,
+                10.,
+                0.,
+                false,
+                &mut rand::thread_rng()
+            ),
+            1.
+        );
     }
 }
@@ -0,0 +1,39 @@
+use crate::utils::Vector;
+use serde::{Deserialize, Serialize};
+
+/// # Background
+///
+/// The radiance, color by color, seen by the Rays escaping the Scene when there is no environment map.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum Background {
+    Solid(Vector),
+    /// A vertical gradient, from the first radiance (looking straight down) to the second one (looking straight up)
+    Gradient(Vector, Vector),
+}
+
+impl Background {
+    /// Computes the radiance of the Background in a given direction
+    pub fn radiance(&self, direction: Vector) -> Vector {
+        match *self {
+            Background::Solid(radiance) => radiance,
+            Background::Gradient(bottom, top) => {
+                let t = 0.5 * (direction.normalize().y + 1.);
+                bottom.lerp(top, t)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_blends_vertically() {
+        let sky = Background::Gradient(Vector::new_eq(0.), Vector::new(100., 200., 255.));
+
+        assert_eq!(sky.radiance(Vector::new(0., -1., 0.)).z, 0.);
+        assert_eq!(sky.radiance(Vector::new(0., 3., 0.)).z, 255.);
+        assert_approx_eq::assert_approx_eq!(sky.radiance(Vector::new(1., 0., 0.)).y, 100.);
+    }
+}
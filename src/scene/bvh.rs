@@ -0,0 +1,475 @@
+use crate::object::Object;
+use crate::ray::Ray;
+use crate::utils::Vector;
+
+/// Maximum number of objects in a leaf of the hierarchy
+const LEAF_SIZE: usize = 2;
+
+/// # Bvh
+///
+/// A bounding volume hierarchy over the objects of a Scene: a binary tree of axis-aligned boxes,
+/// each one enclosing the bounding boxes of the objects below it, so that a Ray only tests the objects whose boxes it crosses.
+/// Objects with infinite bounds and animated objects (whose bounds move with time) are not stored in the tree and are always tested.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Indices (in the Scene) of the objects stored in the tree, the leaves refer to ranges of this list
+    indices: Vec<usize>,
+    /// Indices (in the Scene) of the objects which are always tested
+    unbounded: Vec<usize>,
+}
+
+struct Node {
+    min: Vector,
+    max: Vector,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Leaf { start: usize, count: usize },
+    Inner { left: usize, right: usize },
+}
+
+impl Bvh {
+    /// Builds the hierarchy, splitting each node in two halves along the longest axis of the centers of its boxes
+    pub fn build(objects: &[Box<dyn Object + Sync>]) -> Self {
+        let mut bounded: Vec<(usize, Vector, Vector)> = Vec::new();
+        let mut unbounded = Vec::new();
+
+        for (index, obj) in objects.iter().enumerate() {
+            let (min, max) = obj.bounding_box();
+            let finite = [min.x, min.y, min.z, max.x, max.y, max.z]
+                .iter()
+                .all(|v| v.is_finite());
+            let (min, max) = match (finite, obj.get_transform()) {
+                (true, Some(transform)) => transform.to_world_box(min, max),
+                _ => (min, max),
+            };
+
+            match finite && obj.get_animations().is_empty() {
+                true => bounded.push((index, min, max)),
+                false => unbounded.push(index),
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: Vec::new(),
+            unbounded,
+        };
+        if !bounded.is_empty() {
+            bvh.build_node(&mut bounded);
+        }
+        bvh
+    }
+
+    /// Builds the node enclosing the given boxes (and its children), and returns its index
+    fn build_node(&mut self, boxes: &mut [(usize, Vector, Vector)]) -> usize {
+        let mut min = Vector::new_eq(f64::INFINITY);
+        let mut max = Vector::new_eq(f64::NEG_INFINITY);
+        for (_, box_min, box_max) in boxes.iter() {
+            min = min.min(*box_min);
+            max = max.max(*box_max);
+        }
+
+        let index = self.nodes.len();
+
+        if boxes.len() <= LEAF_SIZE {
+            let start = self.indices.len();
+            self.indices.extend(boxes.iter().map(|(i, _, _)| *i));
+            self.nodes.push(Node {
+                min,
+                max,
+                kind: NodeKind::Leaf {
+                    start,
+                    count: boxes.len(),
+                },
+            });
+            return index;
+        }
+
+        let centers_extent = boxes.iter().fold(
+            (
+                Vector::new_eq(f64::INFINITY),
+                Vector::new_eq(f64::NEG_INFINITY),
+            ),
+            |(lo, hi), (_, box_min, box_max)| {
+                let center = (*box_min + *box_max) / 2.;
+                (lo.min(center), hi.max(center))
+            },
+        );
+        let extent = centers_extent.1 - centers_extent.0;
+        let axis = match (
+            extent.x >= extent.y,
+            extent.x >= extent.z,
+            extent.y >= extent.z,
+        ) {
+            (true, true, _) => 0,
+            (false, _, true) => 1,
+            _ => 2,
+        };
+        boxes.sort_by(|a, b| (a.1 + a.2)[axis].total_cmp(&(b.1 + b.2)[axis]));
+
+        // The children are pushed after their parent, whose links are filled once they are built
+        self.nodes.push(Node {
+            min,
+            max,
+            kind: NodeKind::Inner { left: 0, right: 0 },
+        });
+        let (left_boxes, right_boxes) = boxes.split_at_mut(boxes.len() / 2);
+        let left = self.build_node(left_boxes);
+        let right = self.build_node(right_boxes);
+        self.nodes[index].kind = NodeKind::Inner { left, right };
+
+        index
+    }
+
+    /// Returns the traversal stack holding the root node, if there is one
+    fn root(&self) -> Vec<usize> {
+        match self.nodes.is_empty() {
+            true => Vec::new(),
+            false => vec![0],
+        }
+    }
+
+    /// Computes the distance along the Ray (in units of its direction) at which it enters the box of a node, if it crosses it
+    fn entry_distance(node: &Node, ray: Ray) -> Option<f64> {
+        let mut t_min: f64 = 0.;
+        let mut t_max = f64::INFINITY;
+
+        for axis in 0..3 {
+            let inv = 1. / ray.direction[axis];
+            let t0 = (node.min[axis] - ray.origin[axis]) * inv;
+            let t1 = (node.max[axis] - ray.origin[axis]) * inv;
+            // min / max ignore the NaN obtained when the Ray is parallel to the slab and starts on its border
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+
+        match t_min <= t_max {
+            true => Some(t_min),
+            false => None,
+        }
+    }
+
+    /// Finds the closest hit along the Ray
+    /// The hit function tests one object (by index in the Scene) and returns the squared distance of the hit from the origin of the Ray along with the hit itself:
+    /// nodes further than the closest hit found so far are skipped.
+    pub fn closest<T>(&self, ray: Ray, hit: impl FnMut(usize) -> Option<(f64, T)>) -> Option<T> {
+        self.closest_counted(ray, hit).0
+    }
+
+    /// Same as closest, also returning the number of nodes visited
+    fn closest_counted<T>(
+        &self,
+        ray: Ray,
+        mut hit: impl FnMut(usize) -> Option<(f64, T)>,
+    ) -> (Option<T>, usize) {
+        let mut best: Option<(f64, T)> = None;
+        let mut consider = |index: usize, best: &mut Option<(f64, T)>| {
+            if let Some((dist_sq, value)) = hit(index) {
+                if best
+                    .as_ref()
+                    .map_or(true, |(best_dist_sq, _)| dist_sq <= *best_dist_sq)
+                {
+                    *best = Some((dist_sq, value));
+                }
+            }
+        };
+
+        for index in self.unbounded.iter() {
+            consider(*index, &mut best);
+        }
+
+        let direction_norm_sq = ray.direction.norm_sq();
+        let mut visits = 0;
+        let mut stack = self.root();
+
+        while let Some(node_index) = stack.pop() {
+            visits += 1;
+            let node = &self.nodes[node_index];
+            let entry = match Self::entry_distance(node, ray) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if let Some((best_dist_sq, _)) = best {
+                if entry * entry * direction_norm_sq > best_dist_sq {
+                    continue;
+                }
+            }
+
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    for index in self.indices[start..start + count].iter() {
+                        consider(*index, &mut best);
+                    }
+                }
+                NodeKind::Inner { left, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        (best.map(|(_, value)| value), visits)
+    }
+
+    /// Finds the closest hit along each Ray of a packet
+    /// Coherent Rays (e.g. the primary Rays of neighbouring pixels) mostly cross the same nodes: the packet goes through the hierarchy at once,
+    /// visiting a node as long as one of its Rays may hit something in it, and each object of a leaf is only tested against the Rays which reached the leaf.
+    /// The hit function tests one object (by index in the Scene) against one Ray (by index in the packet), like in closest.
+    pub fn closest_packet<T>(
+        &self,
+        rays: &[Ray],
+        hit: impl FnMut(usize, usize) -> Option<(f64, T)>,
+    ) -> Vec<Option<T>> {
+        self.closest_packet_counted(rays, hit).0
+    }
+
+    /// Same as closest_packet, also returning the number of nodes visited
+    fn closest_packet_counted<T>(
+        &self,
+        rays: &[Ray],
+        mut hit: impl FnMut(usize, usize) -> Option<(f64, T)>,
+    ) -> (Vec<Option<T>>, usize) {
+        let mut best: Vec<Option<(f64, T)>> = rays.iter().map(|_| None).collect();
+        let mut consider = |ray_index: usize, index: usize, best: &mut Vec<Option<(f64, T)>>| {
+            if let Some((dist_sq, value)) = hit(ray_index, index) {
+                if best[ray_index]
+                    .as_ref()
+                    .map_or(true, |(best_dist_sq, _)| dist_sq <= *best_dist_sq)
+                {
+                    best[ray_index] = Some((dist_sq, value));
+                }
+            }
+        };
+
+        for ray_index in 0..rays.len() {
+            for index in self.unbounded.iter() {
+                consider(ray_index, *index, &mut best);
+            }
+        }
+
+        let directions_norm_sq: Vec<f64> = rays.iter().map(|ray| ray.direction.norm_sq()).collect();
+        let mut visits = 0;
+        let mut stack = self.root();
+        let mut active = Vec::with_capacity(rays.len());
+
+        while let Some(node_index) = stack.pop() {
+            visits += 1;
+            let node = &self.nodes[node_index];
+
+            active.clear();
+            for (ray_index, ray) in rays.iter().enumerate() {
+                if let Some(entry) = Self::entry_distance(node, *ray) {
+                    let closer = match &best[ray_index] {
+                        Some((best_dist_sq, _)) => {
+                            entry * entry * directions_norm_sq[ray_index] <= *best_dist_sq
+                        }
+                        None => true,
+                    };
+                    if closer {
+                        active.push(ray_index);
+                    }
+                }
+            }
+            if active.is_empty() {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    for ray_index in active.iter() {
+                        for index in self.indices[start..start + count].iter() {
+                            consider(*ray_index, *index, &mut best);
+                        }
+                    }
+                }
+                NodeKind::Inner { left, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        let closest = best
+            .into_iter()
+            .map(|best| best.map(|(_, value)| value))
+            .collect();
+        (closest, visits)
+    }
+
+    /// Tells if any object is hit along the Ray closer than a given (squared) distance from its origin
+    /// The hit function tests one object (by index in the Scene), the traversal stops at the first object hit.
+    pub fn any(&self, ray: Ray, max_dist_sq: f64, mut hit: impl FnMut(usize) -> bool) -> bool {
+        if self.unbounded.iter().any(|index| hit(*index)) {
+            return true;
+        }
+
+        let direction_norm_sq = ray.direction.norm_sq();
+        let mut stack = self.root();
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            match Self::entry_distance(node, ray) {
+                Some(entry) if entry * entry * direction_norm_sq <= max_dist_sq => {}
+                _ => continue,
+            }
+
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    if self.indices[start..start + count]
+                        .iter()
+                        .any(|index| hit(*index))
+                    {
+                        return true;
+                    }
+                }
+                NodeKind::Inner { left, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::animate::{Animatable, Animation};
+    use crate::camera::Camera;
+    use crate::intersection::Intersection;
+    use crate::object::sphere::Sphere;
+    use crate::ray::Ray;
+    use crate::scene::Scene;
+    use crate::utils::{Color, Material, Vector};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Tests every object of the Scene, without the hierarchy
+    fn brute_force(scene: &Scene, ray: Ray) -> Option<Intersection> {
+        let mut closest: Option<Intersection> = None;
+        for index in 0..scene.objects.len() {
+            if let Some(inter) =
+                scene.intersect_object(index, ray, 0.5, false, &mut rand::thread_rng())
+            {
+                let dist_sq = (inter.point - ray.origin).norm_sq();
+                if closest.map_or(true, |c| dist_sq <= (c.point - ray.origin).norm_sq()) {
+                    closest = Some(inter);
+                }
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let random_vector = |rng: &mut StdRng, size: f64| {
+            Vector::new(
+                rng.gen_range(-size..size),
+                rng.gen_range(-size..size),
+                rng.gen_range(-size..size),
+            )
+        };
+
+        let mut scene = Scene::new();
+        for _ in 0..1000 {
+            scene.add_object(Box::new(Sphere::new(
+                random_vector(&mut rng, 50.),
+                rng.gen_range(0.1..3.),
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        // Animated objects are kept out of the hierarchy
+        let mut moving = Sphere::new(
+            Vector::new_eq(0.),
+            5.,
+            Material::create_diffuse(Color::white()),
+        );
+        moving.add_animation(Animation::translation(0., 1., Vector::new(20., 0., 0.)));
+        scene.add_object(Box::new(moving));
+
+        for _ in 0..2000 {
+            let ray = Ray::new(random_vector(&mut rng, 60.), random_vector(&mut rng, 1.));
+            let expected = brute_force(&scene, ray);
+            let found = scene.compute_intersection(ray, 0.5);
+
+            match (expected, found) {
+                (None, None) => {}
+                (Some(expected), Some(found)) => {
+                    assert_eq!(expected.object_index, found.object_index);
+                    assert_eq!((expected.point - found.point).norm_sq(), 0.);
+                }
+                _ => panic!("the hierarchy and the brute force disagree on a hit"),
+            }
+        }
+    }
+
+    #[test]
+    fn packets_match_single_rays_with_fewer_visits() {
+        let mut scene = Scene::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                scene.add_object(Box::new(Sphere::new(
+                    Vector::new(i as f64 * 4. - 40., j as f64 * 4. - 40., 0.),
+                    1.5,
+                    Material::create_diffuse(Color::white()),
+                )));
+            }
+        }
+        let camera = Camera::new(
+            Vector::new(0., 0., 100.),
+            Vector::new(0., 0., -1.),
+            Vector::new(0., 1., 0.),
+            60.,
+            35.,
+            64,
+            64,
+        );
+        let bvh = scene.bvh();
+        let hit = |ray: Ray, index: usize| {
+            scene
+                .intersect_object(index, ray, 0., false, &mut rand::thread_rng())
+                .map(|inter| ((inter.point - ray.origin).norm_sq(), inter))
+        };
+
+        let (mut single_visits, mut packet_visits) = (0, 0);
+        for block_i in (0..64).step_by(4) {
+            for block_j in (0..64).step_by(4) {
+                let rays: Vec<Ray> = (block_i..block_i + 4)
+                    .flat_map(|i| (block_j..block_j + 4).map(move |j| (i, j)))
+                    .map(|(i, j)| Ray::new_basic_ray(i, j, &camera))
+                    .collect();
+
+                let (packet, visits) = bvh
+                    .closest_packet_counted(&rays, |ray_index, index| hit(rays[ray_index], index));
+                packet_visits += visits;
+
+                for (ray, packet_inter) in rays.iter().zip(packet) {
+                    let (single_inter, visits) =
+                        bvh.closest_counted(*ray, |index| hit(*ray, index));
+                    single_visits += visits;
+
+                    assert_eq!(
+                        single_inter.map(|inter| (
+                            inter.object_index,
+                            inter.point.x,
+                            inter.point.y,
+                            inter.point.z
+                        )),
+                        packet_inter.map(|inter| (
+                            inter.object_index,
+                            inter.point.x,
+                            inter.point.y,
+                            inter.point.z
+                        ))
+                    );
+                }
+            }
+        }
+
+        assert!(packet_visits * 4 < single_visits);
+    }
+}
@@ -0,0 +1,358 @@
+use crate::object::Object;
+use crate::ray::Ray;
+use crate::utils::Vector;
+
+/// # UniformGrid
+///
+/// A regular grid of axis-aligned cells over the objects of a Scene: each cell lists the objects whose bounding boxes overlap it,
+/// and a Ray walks through the cells it crosses in order (3D-DDA), testing their objects until a hit is found inside the current cell.
+/// It is quicker to build than a Bvh, and as fast to traverse for Scenes of many objects of similar sizes, spread evenly.
+/// Like in the Bvh, objects with infinite bounds and animated objects are not stored in the grid and are always tested.
+pub struct UniformGrid {
+    min: Vector,
+    cell_size: Vector,
+    /// Number of cells along x, y and z
+    counts: [usize; 3],
+    /// Indices (in the Scene) of the objects overlapping each cell, x varying fastest
+    cells: Vec<Vec<usize>>,
+    /// Indices (in the Scene) of the objects which are always tested
+    unbounded: Vec<usize>,
+}
+
+/// The position of a Ray walking through the cells of a UniformGrid
+struct Walk {
+    cell: [usize; 3],
+    /// Direction of the next cell along each axis
+    step: [isize; 3],
+    /// Distance along the Ray (in units of its direction) at which it crosses into the next cell along each axis
+    next: [f64; 3],
+    /// Distance along the Ray between two crossings along each axis
+    delta: [f64; 3],
+    /// Distance along the Ray at which it leaves the grid
+    exit: f64,
+}
+
+impl UniformGrid {
+    /// Builds the grid, with resolution cells along the longest side of the box enclosing the objects, and cells as close to cubes as possible along the others
+    pub fn build(objects: &[Box<dyn Object + Sync>], resolution: usize) -> Self {
+        let mut bounded: Vec<(usize, Vector, Vector)> = Vec::new();
+        let mut unbounded = Vec::new();
+
+        for (index, obj) in objects.iter().enumerate() {
+            let (min, max) = obj.bounding_box();
+            let finite = min.is_finite() && max.is_finite();
+            let (min, max) = match (finite, obj.get_transform()) {
+                (true, Some(transform)) => transform.to_world_box(min, max),
+                _ => (min, max),
+            };
+
+            match finite && obj.get_animations().is_empty() {
+                true => bounded.push((index, min, max)),
+                false => unbounded.push(index),
+            }
+        }
+
+        let (min, max) = bounded.iter().fold(
+            (
+                Vector::new_eq(f64::INFINITY),
+                Vector::new_eq(f64::NEG_INFINITY),
+            ),
+            |(lo, hi), (_, box_min, box_max)| (lo.min(*box_min), hi.max(*box_max)),
+        );
+        if bounded.is_empty() {
+            return UniformGrid {
+                min: Vector::new_eq(0.),
+                cell_size: Vector::new_eq(1.),
+                counts: [0; 3],
+                cells: Vec::new(),
+                unbounded,
+            };
+        }
+
+        // A flat box still gets cells of some thickness
+        let longest = (0..3).map(|axis| (max - min)[axis]).fold(0., f64::max);
+        let extent = (max - min).max(Vector::new_eq(longest.max(1.) * 1e-9));
+        let longest = (0..3).map(|axis| extent[axis]).fold(0., f64::max);
+        let counts = [0, 1, 2].map(|axis| {
+            ((resolution.max(1) as f64 * extent[axis] / longest).round() as usize).max(1)
+        });
+        let mut cell_size = extent;
+        for axis in 0..3 {
+            cell_size[axis] /= counts[axis] as f64;
+        }
+
+        let mut grid = UniformGrid {
+            min,
+            cell_size,
+            counts,
+            cells: vec![Vec::new(); counts[0] * counts[1] * counts[2]],
+            unbounded,
+        };
+        for (index, box_min, box_max) in bounded {
+            let (first, last) = (grid.cell_of(box_min), grid.cell_of(box_max));
+            for z in first[2]..=last[2] {
+                for y in first[1]..=last[1] {
+                    for x in first[0]..=last[0] {
+                        let cell = grid.cell_index([x, y, z]);
+                        grid.cells[cell].push(index);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// Finds the cell holding a point, the points outside the grid going to the closest cell
+    fn cell_of(&self, point: Vector) -> [usize; 3] {
+        let offset = point - self.min;
+        [0, 1, 2].map(|axis| {
+            ((offset[axis] / self.cell_size[axis]).floor().max(0.) as usize)
+                .min(self.counts[axis] - 1)
+        })
+    }
+
+    fn cell_index(&self, cell: [usize; 3]) -> usize {
+        (cell[2] * self.counts[1] + cell[1]) * self.counts[0] + cell[0]
+    }
+
+    /// Computes the distances along the Ray (in units of its direction) at which it enters and leaves the grid, if it crosses it
+    fn overlap(&self, ray: Ray) -> Option<(f64, f64)> {
+        if self.cells.is_empty() {
+            return None;
+        }
+
+        let mut t_min: f64 = 0.;
+        let mut t_max = f64::INFINITY;
+        let (origin, direction) = (ray.origin, ray.direction);
+        let (min, size) = (self.min, self.cell_size);
+
+        for axis in 0..3 {
+            let max = min[axis] + size[axis] * self.counts[axis] as f64;
+            let inv = 1. / direction[axis];
+            let t0 = (min[axis] - origin[axis]) * inv;
+            let t1 = (max - origin[axis]) * inv;
+            // min / max ignore the NaN obtained when the Ray is parallel to the slab and starts on its border
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+
+        match t_min <= t_max {
+            true => Some((t_min, t_max)),
+            false => None,
+        }
+    }
+
+    /// Starts walking along the Ray from the cell where it enters the grid, if it crosses it
+    fn start_walk(&self, ray: Ray) -> Option<Walk> {
+        let (entry, exit) = self.overlap(ray)?;
+        let cell = self.cell_of(ray.get_point(entry));
+        let (origin, direction) = (ray.origin, ray.direction);
+        let (min, size) = (self.min, self.cell_size);
+
+        let mut walk = Walk {
+            cell,
+            step: [0; 3],
+            next: [f64::INFINITY; 3],
+            delta: [f64::INFINITY; 3],
+            exit,
+        };
+        for axis in 0..3 {
+            if direction[axis] == 0. {
+                continue;
+            }
+            let forward = direction[axis] > 0.;
+            let boundary = min[axis]
+                + size[axis]
+                    * match forward {
+                        true => cell[axis] as f64 + 1.,
+                        false => cell[axis] as f64,
+                    };
+            walk.step[axis] = match forward {
+                true => 1,
+                false => -1,
+            };
+            walk.next[axis] = (boundary - origin[axis]) / direction[axis];
+            walk.delta[axis] = size[axis] / direction[axis].abs();
+        }
+        Some(walk)
+    }
+
+    /// Walks along the Ray through the cells it crosses, calling visit with the objects of each cell and the distance at which the Ray leaves it,
+    /// until visit returns true or the Ray leaves the grid
+    fn walk(&self, ray: Ray, mut visit: impl FnMut(&[usize], f64) -> bool) {
+        let mut walk = match self.start_walk(ray) {
+            Some(walk) => walk,
+            None => return,
+        };
+
+        loop {
+            let axis = match (
+                walk.next[0] <= walk.next[1],
+                walk.next[0] <= walk.next[2],
+                walk.next[1] <= walk.next[2],
+            ) {
+                (true, true, _) => 0,
+                (false, _, true) => 1,
+                _ => 2,
+            };
+            let cell_exit = walk.next[axis].min(walk.exit);
+
+            if visit(&self.cells[self.cell_index(walk.cell)], cell_exit) {
+                return;
+            }
+            if walk.next[axis] > walk.exit {
+                return;
+            }
+
+            let cell = walk.cell[axis] as isize + walk.step[axis];
+            if cell < 0 || cell >= self.counts[axis] as isize {
+                return;
+            }
+            walk.cell[axis] = cell as usize;
+            walk.next[axis] += walk.delta[axis];
+        }
+    }
+
+    /// Finds the closest hit along the Ray, see Bvh::closest
+    /// An object overlapping several cells may be tested once per cell.
+    pub fn closest<T>(
+        &self,
+        ray: Ray,
+        mut hit: impl FnMut(usize) -> Option<(f64, T)>,
+    ) -> Option<T> {
+        let mut best: Option<(f64, T)> = None;
+        let mut consider = |index: usize, best: &mut Option<(f64, T)>| {
+            if let Some((dist_sq, value)) = hit(index) {
+                if best
+                    .as_ref()
+                    .map_or(true, |(best_dist_sq, _)| dist_sq <= *best_dist_sq)
+                {
+                    *best = Some((dist_sq, value));
+                }
+            }
+        };
+
+        for index in self.unbounded.iter() {
+            consider(*index, &mut best);
+        }
+
+        // A hit found in a cell may lie further along the Ray, behind objects of the next cells: the walk stops once the closest hit is inside the current cell
+        let direction_norm_sq = ray.direction.norm_sq();
+        self.walk(ray, |indices, cell_exit| {
+            for index in indices {
+                consider(*index, &mut best);
+            }
+            best.as_ref().is_some_and(|(best_dist_sq, _)| {
+                *best_dist_sq <= cell_exit * cell_exit * direction_norm_sq
+            })
+        });
+
+        best.map(|(_, value)| value)
+    }
+
+    /// Tells if any object is hit along the Ray closer than a given (squared) distance from its origin, see Bvh::any
+    pub fn any(&self, ray: Ray, max_dist_sq: f64, mut hit: impl FnMut(usize) -> bool) -> bool {
+        if self.unbounded.iter().any(|index| hit(*index)) {
+            return true;
+        }
+
+        let direction_norm_sq = ray.direction.norm_sq();
+        let mut found = false;
+        let mut entry: f64 = 0.;
+        self.walk(ray, |indices, cell_exit| {
+            if entry * entry * direction_norm_sq > max_dist_sq {
+                return true;
+            }
+            entry = cell_exit;
+            found = indices.iter().any(|index| hit(*index));
+            found
+        });
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intersection::Intersection;
+    use crate::light::Light;
+    use crate::object::sphere::Sphere;
+    use crate::ray::Ray;
+    use crate::scene::{Accelerator, Scene};
+    use crate::utils::{Color, Material, Vector};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Tests every object of the Scene, without any acceleration structure
+    fn brute_force(scene: &Scene, ray: Ray) -> Option<Intersection> {
+        let mut closest: Option<Intersection> = None;
+        for index in 0..scene.objects.len() {
+            if let Some(inter) =
+                scene.intersect_object(index, ray, 0., false, &mut rand::thread_rng())
+            {
+                let dist_sq = (inter.point - ray.origin).norm_sq();
+                if closest.map_or(true, |c| dist_sq <= (c.point - ray.origin).norm_sq()) {
+                    closest = Some(inter);
+                }
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn grid_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let random_vector = |rng: &mut StdRng, size: f64| {
+            Vector::new(
+                rng.gen_range(-size..size),
+                rng.gen_range(-size..size),
+                rng.gen_range(-size..size),
+            )
+        };
+
+        let mut scene = Scene::new();
+        for _ in 0..500 {
+            scene.add_object(Box::new(Sphere::new(
+                random_vector(&mut rng, 40.),
+                rng.gen_range(0.5..2.),
+                Material::create_diffuse(Color::white()),
+            )));
+        }
+        let rays: Vec<Ray> = (0..1000)
+            .map(|_| Ray::new(random_vector(&mut rng, 60.), random_vector(&mut rng, 1.)))
+            .collect();
+        let lights: Vec<Light> = (0..100)
+            .map(|_| Light::new(random_vector(&mut rng, 60.), Vector::new_eq(1.)))
+            .collect();
+
+        let expected: Vec<_> = rays
+            .iter()
+            .map(|ray| brute_force(&scene, *ray).map(|inter| (inter.object_index, inter.point)))
+            .collect();
+        // The shadows are checked against the bounding volume hierarchy
+        let shadowed: Vec<bool> = lights
+            .iter()
+            .zip(rays.iter())
+            .map(|(light, ray)| scene.compute_shadows(ray.origin, light, 0.))
+            .collect();
+        assert!(expected.iter().any(|hit| hit.is_some()));
+        assert!(expected.iter().any(|hit| hit.is_none()));
+        assert!(shadowed.contains(&true) && shadowed.contains(&false));
+
+        for resolution in [1, 4, 17, 64] {
+            scene.set_accelerator(Accelerator::Grid(resolution));
+            for (ray, expected) in rays.iter().zip(expected.iter()) {
+                match (expected, scene.compute_intersection(*ray, 0.)) {
+                    (None, None) => {}
+                    (Some((index, point)), Some(found)) => {
+                        assert_eq!(*index, found.object_index);
+                        assert_eq!((*point - found.point).norm_sq(), 0.);
+                    }
+                    _ => panic!("the grid and the brute force disagree on a hit"),
+                }
+            }
+            for ((light, ray), shadowed) in lights.iter().zip(rays.iter()).zip(shadowed.iter()) {
+                assert_eq!(scene.compute_shadows(ray.origin, light, 0.), *shadowed);
+            }
+        }
+    }
+}
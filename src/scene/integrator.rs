@@ -0,0 +1,745 @@
+use crate::animate::Animatable;
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::utils::{Color, Depth, Material, Vector};
+use rand::{Rng, RngCore};
+use std::f64::consts::PI;
+
+/// # Bounce
+///
+/// A Ray leaving a surface (reflected, refracted or sampled for indirect lighting), whose radiance contributes to the surface
+#[derive(Copy, Clone)]
+pub(crate) struct Bounce {
+    pub ray: Ray,
+    /// Factor applied to the radiance carried back by the Ray
+    pub weight: Color,
+    /// Whether the next intersection is moved off its surface, as mirror reflections do
+    pub nudge: bool,
+    /// Whether the Ray sees the background when it leaves the Scene
+    pub sees_background: bool,
+    /// Whether the Ray sees the emissive surface it hits when it belongs to a light object, when Scene::set_show_emissive_surfaces is on
+    /// The other emissive surfaces are always seen, as compute_direct does not sample them (see Intersection::light_object).
+    pub sees_emissive_surfaces: bool,
+    /// Whether the Ray collects the light of the light objects it hits (see compute_bounce_emission)
+    pub sees_light_objects: bool,
+    /// Density (per solid angle) with which the BSDF sampled the direction of the Ray, when the light of the light objects it hits
+    /// is weighted against compute_direct (see Scene::set_multiple_importance_sampling)
+    pub bsdf_pdf: Option<f64>,
+    /// The budget of bounces the Ray counts against (see Depth)
+    pub kind: BounceKind,
+}
+
+impl Bounce {
+    /// The same Bounce, for a path which does not sample the lights at each intersection (see Config::nee): a diffuse Bounce sees
+    /// the background and the light objects it hits, at full weight, as nothing else accounts for them
+    fn without_nee(self) -> Self {
+        match self.kind {
+            BounceKind::Diffuse => Bounce {
+                sees_background: true,
+                sees_light_objects: true,
+                bsdf_pdf: None,
+                ..self
+            },
+            _ => self,
+        }
+    }
+}
+
+/// # BounceKind
+///
+/// The kinds of Bounce, each limited by its own depth (see Config::max_reflection_depth, max_refraction_depth and max_diffuse_depth)
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) enum BounceKind {
+    /// Mirror reflections
+    Reflection,
+    /// Refractions through transparent Materials, and their total internal reflections
+    Refraction,
+    /// Indirect lighting, and scattering in the Media
+    Diffuse,
+}
+
+/// Computes the budget left to a path after a bounce of the given kind, None when the path may not take it
+fn after(depth: Depth, kind: BounceKind) -> Option<Depth> {
+    let left = match kind {
+        BounceKind::Reflection => depth.reflection,
+        BounceKind::Refraction => depth.refraction,
+        BounceKind::Diffuse => depth.diffuse,
+    };
+    if depth.total == 0 || left == 0 {
+        return None;
+    }
+
+    let mut next = Depth {
+        total: depth.total - 1,
+        ..depth
+    };
+    match kind {
+        BounceKind::Reflection => next.reflection -= 1,
+        BounceKind::Refraction => next.refraction -= 1,
+        BounceKind::Diffuse => next.diffuse -= 1,
+    }
+    Some(next)
+}
+
+/// # PathVertex
+///
+/// An intersection waiting to be shaded, with the throughput of the path leading to it
+struct PathVertex {
+    ray: Ray,
+    intersection: Intersection,
+    depth: Depth,
+    throughput: Vector,
+    /// Whether the emission of the intersection is counted, see Bounce::sees_emissive_surfaces
+    sees_emissive_surfaces: bool,
+}
+
+impl Scene {
+    /// Computes the light intensity, color by color, of an intersection
+    /// The paths are followed iteratively: each intersection adds its local lighting times the throughput of the path leading to it,
+    /// and pushes the intersections of its bounces with a throughput scaled by their weight. A mirror or a glass with a diffuse part
+    /// branches like the recursive definition would, but deep paths no longer grow the call stack.
+    /// Emissive surfaces are seen by the Ray and after specular bounces, but not after a diffuse bounce when compute_direct already sampled them as light objects.
+    /// In the Media of the Scene, each Ray may scatter before reaching its intersection (see scatter_along).
+    /// The paths take at most depth bounces: a number of iterations limits all the kinds of bounces alike (see Depth).
+    pub fn compute_intensity(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        depth: impl Into<Depth>,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        self.estimate_intensity(
+            ray,
+            intersection,
+            depth.into(),
+            true,
+            time,
+            stochastic_transparency,
+            rng,
+        )
+    }
+
+    /// Same as compute_intensity, without next event estimation: the light objects and the Portals are not sampled at each intersection,
+    /// the paths only collect their light when a diffuse bounce happens to hit them (the point lights, which cannot be hit, are still sampled).
+    /// Both estimators converge to the same image, this one with much more noise: it is meant as a reference for compute_direct and compute_portals.
+    pub fn compute_intensity_without_nee(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        depth: impl Into<Depth>,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        self.estimate_intensity(
+            ray,
+            intersection,
+            depth.into(),
+            false,
+            time,
+            stochastic_transparency,
+            rng,
+        )
+    }
+
+    /// Computes the light intensity of an intersection, with or without next event estimation
+    #[allow(clippy::too_many_arguments)]
+    fn estimate_intensity(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        depth: Depth,
+        nee: bool,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let distance = (intersection.point - ray.origin).norm() / ray.direction.norm();
+        match self.scatter_along(
+            ray,
+            distance,
+            depth,
+            nee,
+            time,
+            stochastic_transparency,
+            rng,
+        ) {
+            Some(radiance) => radiance,
+            None => self.compute_path(
+                ray,
+                intersection,
+                depth,
+                true,
+                nee,
+                time,
+                stochastic_transparency,
+                rng,
+            ),
+        }
+    }
+
+    /// Samples whether a Ray interacts with the Media of the Scene before max_distance (along its direction), and when it does,
+    /// computes the radiance reaching its origin from the interaction, following the scattered Ray as a diffuse bounce.
+    /// Returns None when the Ray goes through: it then carries the radiance from max_distance unattenuated, since the probability
+    /// of going through already is the transmittance. An absorbed Ray carries nothing, which the albedo of the Medium accounts for.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn scatter_along(
+        &self,
+        ray: Ray,
+        max_distance: f64,
+        depth: Depth,
+        nee: bool,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Option<Vector> {
+        // The interactions with each Medium are independent: the first one happens
+        let (t, medium) = self
+            .media
+            .iter()
+            .filter_map(|medium| {
+                medium
+                    .sample_interaction(ray, max_distance, rng)
+                    .map(|t| (t, medium))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+        if depth.total == 0 {
+            return Some(Vector::new_eq(0.));
+        }
+
+        // The scattering point, seen as an Intersection with no object so that all the lights reach it
+        let point = ray.get_point(t);
+        let receiver = Intersection::new(
+            point,
+            ray.direction.normalize() * (-1.),
+            Material::create_diffuse(Color::white()),
+            (0., 0.),
+        );
+
+        // The Ray is scattered in a uniformly random direction, as the phase function is isotropic
+        let z: f64 = rng.gen_range(-1.0..1.0);
+        let phi: f64 = rng.gen_range(0.0..(2. * PI));
+        let r = (1. - z * z).sqrt();
+        let bounce = Bounce {
+            ray: Ray::new(point, Vector::new(r * phi.cos(), r * phi.sin(), z)),
+            weight: Color::white(),
+            nudge: false,
+            sees_background: true,
+            sees_emissive_surfaces: false,
+            sees_light_objects: false,
+            bsdf_pdf: None,
+            kind: BounceKind::Diffuse,
+        };
+        let bounce = match nee {
+            true => bounce,
+            false => bounce.without_nee(),
+        };
+
+        let radiance =
+            self.compute_in_scattering(receiver, nee, time, stochastic_transparency, rng)
+                + self.follow_bounce(
+                    receiver,
+                    bounce,
+                    depth,
+                    nee,
+                    time,
+                    stochastic_transparency,
+                    rng,
+                );
+        Some(radiance * medium.albedo())
+    }
+
+    /// Computes the light scattered towards the Ray at a point of a Medium, coming directly from the point lights and the light objects
+    /// The isotropic phase function is 1 / 4π: the point lights light the Medium a quarter as much as a diffuse surface facing them,
+    /// whose BRDF is 1 / π (see compute_point_light). Without next event estimation, only the point lights are sampled.
+    fn compute_in_scattering(
+        &self,
+        receiver: Intersection,
+        nee: bool,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let point = receiver.point;
+        let mut radiance = Vector::new_eq(0.);
+
+        for light in &self.lights {
+            if self.compute_shadows_with_rng(point, light, time, stochastic_transparency, rng) {
+                let center = Ray::new(light.center, point)
+                    .apply_animations(light.get_animations(), time)
+                    .origin;
+                let to_light = (center - point).normalize();
+                radiance += light.get_intensity_local(point, to_light, Color::white(), time)
+                    * (self.light_transmittance(point, light, time) / 4.);
+            }
+        }
+        if !nee {
+            return radiance;
+        }
+        if let Some((_, light, _)) =
+            self.sample_light_object(point, receiver, time, stochastic_transparency, rng)
+        {
+            radiance += light / (4. * PI);
+        }
+
+        radiance
+    }
+
+    /// Same as estimate_intensity, for a Ray which may not see the emission of the intersection
+    #[allow(clippy::too_many_arguments)]
+    fn compute_path(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        depth: Depth,
+        sees_emissive_surfaces: bool,
+        nee: bool,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let mut radiance = Vector::new_eq(0.);
+        let mut vertices = vec![PathVertex {
+            ray,
+            intersection,
+            depth,
+            throughput: Vector::new_eq(1.),
+            sees_emissive_surfaces,
+        }];
+
+        while let Some(vertex) = vertices.pop() {
+            if vertex.depth.total == 0 {
+                continue;
+            }
+
+            // The normal faces the Ray, and textured materials are shaded with their albedo and normal map at the intersection
+            let mut intersection = vertex.intersection.face_ray(vertex.ray);
+            intersection.material.color =
+                self.albedo(intersection.material, intersection.point, intersection.uv);
+            intersection.normal = self.shading_normal(intersection);
+
+            // The back faces of one-sided materials are not lit: they only reflect and refract the light
+            let lit = intersection.front_face || intersection.material.two_sided;
+            if lit {
+                radiance += self.compute_local(
+                    vertex.ray,
+                    intersection,
+                    vertex.depth.total,
+                    vertex.sees_emissive_surfaces,
+                    nee,
+                    time,
+                    stochastic_transparency,
+                    rng,
+                ) * vertex.throughput;
+            }
+
+            let bounces = [
+                self.mirror_bounce(vertex.ray, intersection),
+                self.transparent_bounce(vertex.ray, intersection, rng),
+                match lit {
+                    true => self.indirect_bounce(vertex.ray, intersection, rng),
+                    false => None,
+                },
+            ];
+            for bounce in bounces.into_iter().flatten() {
+                // A black bounce carries nothing back: a perfect mirror does not spawn a diffuse path
+                let weight = bounce.weight;
+                if weight.r <= 0. && weight.g <= 0. && weight.b <= 0. {
+                    continue;
+                }
+                // The path has no bounce of that kind left
+                let depth = match after(vertex.depth, bounce.kind) {
+                    Some(depth) => depth,
+                    None => continue,
+                };
+                let bounce = match nee {
+                    true => bounce,
+                    false => bounce.without_nee(),
+                };
+
+                let throughput = vertex.throughput * weight;
+                let (next, bounce_radiance) = self.trace_bounce(
+                    intersection,
+                    bounce,
+                    depth,
+                    nee,
+                    time,
+                    stochastic_transparency,
+                    rng,
+                );
+                radiance += bounce_radiance * throughput;
+                if let Some(inter) = next {
+                    vertices.push(PathVertex {
+                        ray: bounce.ray,
+                        intersection: inter,
+                        depth,
+                        throughput,
+                        sees_emissive_surfaces: bounce.sees_emissive_surfaces,
+                    });
+                }
+            }
+        }
+
+        radiance
+    }
+
+    /// Computes the radiance carried back by a Bounce leaving an intersection, following it with the depth left after it
+    /// Nothing comes back when the path has no bounce of that kind left.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn follow_bounce(
+        &self,
+        intersection: Intersection,
+        bounce: Bounce,
+        depth: Depth,
+        nee: bool,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let depth = match after(depth, bounce.kind) {
+            Some(depth) => depth,
+            None => return Vector::new_eq(0.),
+        };
+        let (next, mut radiance) = self.trace_bounce(
+            intersection,
+            bounce,
+            depth,
+            nee,
+            time,
+            stochastic_transparency,
+            rng,
+        );
+        if let Some(inter) = next {
+            radiance += self.compute_path(
+                bounce.ray,
+                inter,
+                depth,
+                bounce.sees_emissive_surfaces,
+                nee,
+                time,
+                stochastic_transparency,
+                rng,
+            );
+        }
+
+        radiance * bounce.weight
+    }
+
+    /// Finds where a Bounce leaving an intersection hits the Scene, and the radiance it collects on the way:
+    /// the background when it leaves the Scene, and the light objects it hits with multiple importance sampling or without next event estimation.
+    /// When the Bounce scatters in a Medium on the way, it collects the radiance of the scattering instead, and hits nothing.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_bounce(
+        &self,
+        intersection: Intersection,
+        bounce: Bounce,
+        depth: Depth,
+        nee: bool,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> (Option<Intersection>, Vector) {
+        let next =
+            self.compute_intersection_with_rng(bounce.ray, time, stochastic_transparency, rng);
+        let max_dist_sq = match next {
+            Some(inter) => (inter.point - bounce.ray.origin).norm_sq(),
+            None => f64::INFINITY,
+        };
+        let distance = max_dist_sq.sqrt() / bounce.ray.direction.norm();
+        if let Some(radiance) = self.scatter_along(
+            bounce.ray,
+            distance,
+            depth,
+            nee,
+            time,
+            stochastic_transparency,
+            rng,
+        ) {
+            return (None, radiance);
+        }
+        let emission = self.compute_bounce_emission(intersection, bounce, max_dist_sq, time);
+
+        match next {
+            Some(inter) => (
+                Some(match bounce.nudge {
+                    true => inter.get_inter_nudged(),
+                    false => inter,
+                }),
+                emission,
+            ),
+            None if bounce.sees_background => (None, emission + self.background(bounce.ray)),
+            None => (None, emission),
+        }
+    }
+
+    /// Computes the light reaching an intersection without bouncing on other surfaces: point lights, emission, light objects and Portals
+    /// Without next event estimation, the light objects and the Portals are left to the bounces.
+    #[allow(clippy::too_many_arguments)]
+    fn compute_local(
+        &self,
+        ray: Ray,
+        intersection: Intersection,
+        nb_iter_max: usize,
+        sees_emissive_surfaces: bool,
+        nee: bool,
+        time: f64,
+        stochastic_transparency: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let local = self
+            .compute_point_light(
+                intersection,
+                nb_iter_max,
+                time,
+                stochastic_transparency,
+                rng,
+            )
+            .max(Vector::new_eq(0.))
+            + self
+                .compute_emissive(
+                    intersection,
+                    self.show_emissive_surfaces
+                        && (sees_emissive_surfaces || !intersection.light_object),
+                    time,
+                )
+                .max(Vector::new_eq(0.));
+        if !nee {
+            return local;
+        }
+
+        local
+            + self
+                .compute_direct(
+                    ray,
+                    intersection,
+                    nb_iter_max,
+                    time,
+                    stochastic_transparency,
+                    rng,
+                )
+                .max(Vector::new_eq(0.))
+            + self
+                .compute_portals(
+                    ray,
+                    intersection,
+                    nb_iter_max,
+                    time,
+                    stochastic_transparency,
+                    rng,
+                )
+                .max(Vector::new_eq(0.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::sphere::Sphere;
+    use crate::testing::{example_room, mean_and_variance};
+    use crate::utils::{Config, Material};
+
+    /// The recursive definition that compute_intensity unrolls
+    fn recursive_intensity(
+        scene: &Scene,
+        ray: Ray,
+        intersection: Intersection,
+        nb_iter_max: usize,
+        sees_emissive_surfaces: bool,
+        time: f64,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        if nb_iter_max == 0 {
+            return Vector::new_eq(0.);
+        }
+
+        let mut intersection = intersection.face_ray(ray);
+        intersection.material.color =
+            scene.albedo(intersection.material, intersection.point, intersection.uv);
+        intersection.normal = scene.shading_normal(intersection);
+        let lit = intersection.front_face || intersection.material.two_sided;
+
+        let mut intensity = match lit {
+            true => scene.compute_local(
+                ray,
+                intersection,
+                nb_iter_max,
+                sees_emissive_surfaces,
+                true,
+                time,
+                false,
+                rng,
+            ),
+            false => Vector::new_eq(0.),
+        };
+        for bounce in [
+            scene.mirror_bounce(ray, intersection),
+            scene.transparent_bounce(ray, intersection, rng),
+            match lit {
+                true => scene.indirect_bounce(ray, intersection, rng),
+                false => None,
+            },
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let (next, mut radiance) = scene.trace_bounce(
+                intersection,
+                bounce,
+                Depth::new(nb_iter_max - 1),
+                true,
+                time,
+                false,
+                rng,
+            );
+            if let Some(inter) = next {
+                radiance += recursive_intensity(
+                    scene,
+                    bounce.ray,
+                    inter,
+                    nb_iter_max - 1,
+                    bounce.sees_emissive_surfaces,
+                    time,
+                    rng,
+                );
+            }
+            intensity += radiance * bounce.weight;
+        }
+
+        intensity
+    }
+
+    /// The scene of the crate example, with a mirror and a glass sphere in the middle of the room
+    fn cornell_scene() -> Scene {
+        let mut scene = example_room();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(-12., 0., 0.),
+            8.,
+            Material::create_mirror(Color::new_eq(0.9)),
+        )));
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new(12., 0., 0.),
+            8.,
+            Material::create_transparent(Color::white(), 1.5),
+        )));
+        scene
+    }
+
+    #[test]
+    fn iterative_integrator_matches_recursion_on_cornell_scene() {
+        let scene = cornell_scene();
+        let nb_samples = 3000;
+
+        // Seen from the camera of the example: the mirror, the glass, and the wall between them
+        for target in [
+            Vector::new(-12., 0., 8.),
+            Vector::new(12., 0., 8.),
+            Vector::new(0., 0., -60.),
+        ] {
+            let ray = Ray::new(
+                Vector::new(0., 0., 55.),
+                (target - Vector::new(0., 0., 55.)).normalize(),
+            );
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the room is closed");
+
+            let mean_and_error = |estimate: &dyn Fn() -> Vector| {
+                let (mean, variance) = mean_and_variance(nb_samples, || {
+                    let radiance = estimate();
+                    radiance.x + radiance.y + radiance.z
+                });
+                (mean, (variance / nb_samples as f64).sqrt())
+            };
+
+            let (iterative, iterative_error) = mean_and_error(&|| {
+                scene.compute_intensity(ray, inter, 4, 0., false, &mut rand::thread_rng())
+            });
+            let (recursive, recursive_error) = mean_and_error(&|| {
+                recursive_intensity(&scene, ray, inter, 4, true, 0., &mut rand::thread_rng())
+            });
+
+            assert!(iterative > 0.);
+            let tolerance = 5. * (iterative_error.powi(2) + recursive_error.powi(2)).sqrt();
+            assert!(
+                (iterative - recursive).abs() <= tolerance,
+                "{iterative} vs {recursive} (tolerance {tolerance})"
+            );
+        }
+    }
+
+    #[test]
+    fn each_kind_of_bounce_has_its_own_depth() {
+        // A single sphere under a uniform sky: every bounce leaving it sees the background
+        let radiance = |material: Material, depth: Depth| {
+            let mut scene = Scene::new();
+            scene.add_object(Box::new(Sphere::new(Vector::new_eq(0.), 5., material)));
+            scene.set_background(crate::scene::background::Background::Solid(Vector::new_eq(
+                100.,
+            )));
+            let ray = Ray::new(Vector::new(0., 0., 20.), Vector::new(0., 0., -1.));
+            let inter = scene
+                .compute_intersection(ray, 0.)
+                .expect("the ray hits the sphere");
+            scene
+                .compute_intensity(ray, inter, depth, 0., false, &mut rand::thread_rng())
+                .x
+        };
+        let mirror = Material::create_mirror(Color::white());
+        let glass = Material::create_transparent(Color::white(), 1.5);
+        let diffuse = Material::create_diffuse(Color::white());
+
+        // By default, nb_iter_max limits every kind of bounce alike
+        let depth = Config::new(1, 1, 1., false, 5, 1, false, false, 0., 0., 1).depth();
+        assert_eq!(depth, Depth::new(5));
+        for material in [mirror, glass, diffuse] {
+            assert!(radiance(material, depth) > 0.);
+        }
+
+        // Going through the glass takes two refractions, and the other budgets do not limit it
+        let no_reflection = Depth {
+            reflection: 0,
+            ..depth
+        };
+        let one_refraction = Depth {
+            refraction: 1,
+            ..depth
+        };
+        let no_diffuse = Depth {
+            diffuse: 0,
+            ..depth
+        };
+        assert_eq!(radiance(mirror, no_reflection), 0.);
+        assert!(radiance(mirror, one_refraction) > 0.);
+        assert_eq!(radiance(glass, one_refraction), 0.);
+        assert!(radiance(glass, no_diffuse) > 0.);
+        assert_eq!(radiance(diffuse, no_diffuse), 0.);
+        assert!(radiance(diffuse, no_reflection) > 0.);
+    }
+
+    #[test]
+    fn endless_mirror_reflections_do_not_overflow_the_stack() {
+        // From the inside of a mirror sphere, the Ray bounces until nb_iter_max runs out
+        let mut scene = Scene::new();
+        scene.add_object(Box::new(Sphere::new(
+            Vector::new_eq(0.),
+            10.,
+            Material::create_mirror(Color::white()),
+        )));
+        scene.add_light(crate::light::Light::new(
+            Vector::new_eq(0.),
+            Vector::new_eq(1e6),
+        ));
+
+        let ray = Ray::new(Vector::new_eq(0.), Vector::new(1., 0.2, 0.3).normalize());
+        let inter = scene
+            .compute_intersection(ray, 0.)
+            .expect("the ray hits the sphere");
+
+        let radiance =
+            scene.compute_intensity(ray, inter, 200_000, 0., false, &mut rand::thread_rng());
+        assert!(radiance.is_finite());
+    }
+}
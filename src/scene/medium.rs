@@ -0,0 +1,130 @@
+use crate::ray::Ray;
+use crate::utils::Vector;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// # Medium
+///
+/// A homogeneous participating medium (e.g. smoke or haze) filling an axis-aligned box, from min to max.
+/// Along a unit of length, a Ray travelling through the Medium is absorbed with a probability of absorption,
+/// and scattered in a random direction with a probability of scattering: lit media show the shafts of light crossing them.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Medium {
+    pub min: Vector,
+    pub max: Vector,
+    pub absorption: f64,
+    pub scattering: f64,
+}
+
+impl Medium {
+    pub fn new(min: Vector, max: Vector, absorption: f64, scattering: f64) -> Self {
+        Medium {
+            min: min.min(max),
+            max: min.max(max),
+            absorption,
+            scattering,
+        }
+    }
+
+    /// The probability per unit of length that a Ray interacts with the Medium
+    pub fn extinction(&self) -> f64 {
+        self.absorption + self.scattering
+    }
+
+    /// The fraction of the interactions which scatter the Ray instead of absorbing it
+    pub fn albedo(&self) -> f64 {
+        match self.extinction() > 0. {
+            true => self.scattering / self.extinction(),
+            false => 0.,
+        }
+    }
+
+    /// Computes the distances along the Ray at which it enters and leaves the box of the Medium, within [0., max_distance]
+    fn overlap(&self, ray: Ray, max_distance: f64) -> Option<(f64, f64)> {
+        let mut t_min: f64 = 0.;
+        let mut t_max = max_distance;
+        for (origin, direction, min, max) in [
+            (ray.origin.x, ray.direction.x, self.min.x, self.max.x),
+            (ray.origin.y, ray.direction.y, self.min.y, self.max.y),
+            (ray.origin.z, ray.direction.z, self.min.z, self.max.z),
+        ] {
+            if direction == 0. {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+            let (t_1, t_2) = ((min - origin) / direction, (max - origin) / direction);
+            t_min = t_min.max(t_1.min(t_2));
+            t_max = t_max.min(t_1.max(t_2));
+        }
+
+        match t_min < t_max {
+            true => Some((t_min, t_max)),
+            false => None,
+        }
+    }
+
+    /// Samples the distance along the Ray at which it interacts with the Medium, if it does before max_distance
+    /// The distance travelled in the Medium before an interaction follows an exponential distribution of rate the extinction.
+    pub fn sample_interaction(
+        &self,
+        ray: Ray,
+        max_distance: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<f64> {
+        let (t_min, t_max) = self.overlap(ray, max_distance)?;
+        let length: f64 = -(1. - rng.gen_range(0.0..1.0_f64)).ln() / self.extinction();
+        let t = t_min + length / ray.direction.norm();
+
+        match t < t_max {
+            true => Some(t),
+            false => None,
+        }
+    }
+
+    /// Computes the fraction of the light going from one point to another which is neither absorbed nor scattered by the Medium
+    pub fn transmittance(&self, from: Vector, to: Vector) -> f64 {
+        match self.overlap(Ray::new(from, to - from), 1.) {
+            Some((t_min, t_max)) => {
+                (-self.extinction() * (t_max - t_min) * (to - from).norm()).exp()
+            }
+            None => 1.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interactions_follow_the_transmittance() {
+        let medium = Medium::new(Vector::new_eq(-1.), Vector::new_eq(1.), 0.3, 0.2);
+        let ray = Ray::new(Vector::new(-5., 0., 0.), Vector::new(2., 0., 0.));
+        let mut rng = rand::thread_rng();
+
+        // The Ray crosses 2. of Medium, between t = 2. and t = 3.
+        let nb_samples = 100_000;
+        let interactions: Vec<f64> = (0..nb_samples)
+            .filter_map(|_| medium.sample_interaction(ray, f64::INFINITY, &mut rng))
+            .collect();
+        assert!(interactions.iter().all(|t| (2. ..3.).contains(t)));
+
+        let transmittance = medium.transmittance(ray.origin, ray.get_point(10.));
+        assert_approx_eq::assert_approx_eq!(transmittance, (-0.5_f64 * 2.).exp());
+        let passed = 1. - interactions.len() as f64 / nb_samples as f64;
+        assert!((passed - transmittance).abs() < 0.01);
+
+        // Stopped before the Medium, or missing it, the Ray never interacts
+        assert!(medium.sample_interaction(ray, 1., &mut rng).is_none());
+        let above = Ray::new(Vector::new(-5., 2., 0.), Vector::new(1., 0., 0.));
+        assert!(medium
+            .sample_interaction(above, f64::INFINITY, &mut rng)
+            .is_none());
+        assert_eq!(
+            medium.transmittance(Vector::new_eq(5.), Vector::new_eq(6.)),
+            1.
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use crate::utils::Vector;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// # Portal
+///
+/// A Portal is a parallelogram (e.g. a window) through which the environment of the Scene lights the inside of a room.
+/// It is defined by one corner (origin) and two edges: instead of hoping that random rays find their way through a small opening,
+/// points are sampled on the Portal to aim directly at the visible part of the environment.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Portal {
+    pub origin: Vector,
+    pub edge_u: Vector,
+    pub edge_v: Vector,
+}
+
+impl Portal {
+    pub fn new(origin: Vector, edge_u: Vector, edge_v: Vector) -> Self {
+        Portal {
+            origin,
+            edge_u,
+            edge_v,
+        }
+    }
+
+    pub fn get_surface_area(&self) -> f64 {
+        self.edge_u.cross(self.edge_v).norm()
+    }
+
+    /// The unit normal of the Portal, oriented along edge_u x edge_v
+    pub fn normal(&self) -> Vector {
+        self.edge_u.cross(self.edge_v).normalize()
+    }
+
+    /// Samples a point uniformly on the Portal
+    pub fn sample_point(&self, rng: &mut dyn RngCore) -> Vector {
+        let u: f64 = rng.gen_range(0.0..1.0);
+        let v: f64 = rng.gen_range(0.0..1.0);
+
+        self.origin + self.edge_u * u + self.edge_v * v
+    }
+}
@@ -0,0 +1,129 @@
+//! # Stats
+//!
+//! This module counts the work done by the renders which ask for it (see Config::stats): the primary Rays shot from the camera,
+//! the secondary Rays (bounces and shadow Rays) and the intersection tests between a Ray and an object.
+//! A measured render runs on a thread pool of its own, whose threads count its work: the other renders running at the same time,
+//! even of the same Scene, are not counted in its stats.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// # RenderStats
+///
+/// The work done to render a frame, see render_one_frame_with_stats
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct RenderStats {
+    /// Rays shot from the camera (three per sample with chromatic aberration), including the ones of the guides of an upscaled frame
+    pub primary_rays: u64,
+    /// Rays traced from the intersections: bounces, and shadow Rays towards the lights
+    pub secondary_rays: u64,
+    /// Tests of a Ray against one object, which the acceleration structure could not rule out
+    pub intersection_tests: u64,
+    /// Wall-clock time spent computing the frame, without saving it
+    pub duration: Duration,
+}
+
+impl RenderStats {
+    /// Number of Rays traced per second of the render
+    /// A render too fast to be timed has no rate, and counts as 0 Rays per second
+    pub fn rays_per_second(&self) -> f64 {
+        match self.duration.is_zero() {
+            true => 0.,
+            false => (self.primary_rays + self.secondary_rays) as f64 / self.duration.as_secs_f64(),
+        }
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} primary rays, {} secondary rays, {} intersection tests in {:.3} s ({:.0} rays/s)",
+            self.primary_rays,
+            self.secondary_rays,
+            self.intersection_tests,
+            self.duration.as_secs_f64(),
+            self.rays_per_second()
+        )
+    }
+}
+
+/// The counters of the work done by a measured render, see measure
+#[derive(Default)]
+struct Counters {
+    primary_rays: AtomicU64,
+    secondary_rays: AtomicU64,
+    intersection_tests: AtomicU64,
+}
+
+thread_local! {
+    /// The counters of the measured render the thread works for, if any
+    static COUNTERS: RefCell<Option<Arc<Counters>>> = const { RefCell::new(None) };
+}
+
+/// Counts Rays shot from the camera
+pub(crate) fn count_primary_rays(nb_rays: usize) {
+    count(|counters| &counters.primary_rays, nb_rays);
+}
+
+/// Counts a Ray traced from an intersection
+pub(crate) fn count_secondary_ray() {
+    count(|counters| &counters.secondary_rays, 1);
+}
+
+/// Counts the test of a Ray against an object
+pub(crate) fn count_intersection_test() {
+    count(|counters| &counters.intersection_tests, 1);
+}
+
+/// Adds to a counter of the render the thread works for: outside of a measured render, this only reads a thread local
+fn count(counter: impl Fn(&Counters) -> &AtomicU64, amount: usize) {
+    COUNTERS.with(|counters| {
+        if let Some(counters) = counters.borrow().as_ref() {
+            counter(counters).fetch_add(amount as u64, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Runs a render on a thread pool of threads threads (as many as the global thread pool of rayon with 0), and returns its result
+/// with the work counted and the time elapsed meanwhile. Without a pool of its own, the render still runs, with nothing counted.
+pub(crate) fn measure<T, F>(threads: usize, render: F) -> (T, RenderStats)
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    let counters = Arc::new(Counters::default());
+    let pool_counters = counters.clone();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .start_handler(move |_| {
+            COUNTERS.with(|counters| *counters.borrow_mut() = Some(pool_counters.clone()))
+        })
+        .build();
+
+    let start = Instant::now();
+    let result = match pool {
+        Ok(pool) => pool.install(render),
+        Err(_) => render(),
+    };
+    let duration = start.elapsed();
+
+    let [primary_rays, secondary_rays, intersection_tests] = [
+        &counters.primary_rays,
+        &counters.secondary_rays,
+        &counters.intersection_tests,
+    ]
+    .map(|counter| counter.load(Ordering::Relaxed));
+    (
+        result,
+        RenderStats {
+            primary_rays,
+            secondary_rays,
+            intersection_tests,
+            duration,
+        },
+    )
+}
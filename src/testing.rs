@@ -0,0 +1,76 @@
+//! # Testing
+//!
+//! This module gathers the fixtures and the statistics shared by the tests of several modules: the scene of the crate example,
+//! seen from its camera, and the mean and variance of noisy estimates, with which the tests compare the estimators.
+
+use crate::camera::Camera;
+use crate::object::sphere::Sphere;
+use crate::render_radiance;
+use crate::scene::Scene;
+use crate::utils::{Color, Config, Material, Vector};
+use std::f64::consts::PI;
+
+/// The room of the crate example: four large diffuse spheres as walls, lit by a spherical light object
+pub(crate) fn example_room() -> Scene {
+    let mut scene = Scene::new();
+    for (center, radius, color) in [
+        (Vector::new(0., 1000., 0.), 940., Color::red()),
+        (Vector::new(0., 0., -1000.), 940., Color::green()),
+        (Vector::new(0., 0., 1000.), 940., Color::yellow()),
+        (Vector::new(0., -1000., 0.), 990., Color::blue()),
+    ] {
+        scene.add_object(Box::new(Sphere::new(
+            center,
+            radius,
+            Material::create_diffuse(color),
+        )));
+    }
+    scene.add_light_object(Box::new(Sphere::new(
+        Vector::new(-30., 5., 45.),
+        10.,
+        Material::create_emissive(Color::white(), 2e9 / (4. * PI * 10. * 10.)),
+    )));
+    scene
+}
+
+/// The camera of the crate example, at (0, 0, 55) and looking down the z axis, for the image size of config
+pub(crate) fn example_camera(config: &Config) -> Camera {
+    Camera::new(
+        Vector::new(0., 0., 55.),
+        Vector::new(0., 0., -1.),
+        Vector::new(0., 1., 0.),
+        60.,
+        35.,
+        config.height,
+        config.width,
+    )
+}
+
+/// Returns the mean and the variance of nb_samples values drawn from estimate
+pub(crate) fn mean_and_variance(
+    nb_samples: usize,
+    mut estimate: impl FnMut() -> f64,
+) -> (f64, f64) {
+    let values: Vec<f64> = (0..nb_samples).map(|_| estimate()).collect();
+    let mean = values.iter().sum::<f64>() / nb_samples as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / nb_samples as f64;
+    (mean, variance)
+}
+
+/// Renders the Scene with the seeds 0..nb_renders, and sums over the pixels the variance of their red channel
+pub(crate) fn render_variance(
+    camera: &Camera,
+    scene: &Scene,
+    config: Config,
+    nb_renders: u64,
+) -> f64 {
+    let renders: Vec<Vec<Vector>> = (0..nb_renders)
+        .map(|seed| render_radiance(camera, scene, Config { seed, ..config }, 0))
+        .collect();
+    (0..config.height * config.width)
+        .map(|p| {
+            let mean = renders.iter().map(|r| r[p].x).sum::<f64>() / renders.len() as f64;
+            renders.iter().map(|r| (r[p].x - mean).powi(2)).sum::<f64>() / renders.len() as f64
+        })
+        .sum::<f64>()
+}
@@ -0,0 +1,272 @@
+//! # Texture
+//!
+//! This module maps colors on the surface of objects, using the uv coordinates of their Intersections.
+
+use crate::utils::noise::Perlin;
+use crate::utils::{Color, Vector};
+use serde::{Deserialize, Serialize};
+
+/// # Texture
+///
+/// Where the albedo of a Material comes from (see Material::texture).
+/// Images are stored in the Scene (see Scene::add_texture), so that a Texture is a lightweight handle and Materials can still be copied around.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum Texture {
+    Solid(Color),
+    /// Index of an ImageTexture in the Scene, as returned by Scene::add_texture
+    Image(usize),
+    /// Alternating squares of two colors, scale squares along each of u and v
+    Checker {
+        color_a: Color,
+        color_b: Color,
+        scale: f64,
+    },
+    /// Veins of color_b in color_a, following a Perlin noise turbulence in world space (see Material::create_marble)
+    Marble {
+        color_a: Color,
+        color_b: Color,
+        frequency: f64,
+        octaves: usize,
+        turbulence: f64,
+        seed: u64,
+    },
+}
+
+impl Texture {
+    /// Computes the marble pattern at a point, from 0. (color_a) to 1. (color_b)
+    /// The veins are parallel stripes along x, bent by the turbulence.
+    pub fn marble_ramp(
+        point: Vector,
+        frequency: f64,
+        octaves: usize,
+        turbulence: f64,
+        seed: u64,
+    ) -> f64 {
+        let distortion = Perlin::new(seed).turbulence(point * frequency, octaves);
+        0.5 * (1. + (frequency * point.x + turbulence * distortion).sin())
+    }
+
+    /// Picks the color of the checker square containing the uv coordinates
+    pub fn checker(color_a: Color, color_b: Color, scale: f64, uv: (f64, f64)) -> Color {
+        let parity = (uv.0 * scale).floor() + (uv.1 * scale).floor();
+        match parity.rem_euclid(2.) < 1. {
+            true => color_a,
+            false => color_b,
+        }
+    }
+}
+
+/// # Filter
+///
+/// How an ImageTexture computes the color between the centers of its texels
+#[derive(Copy, Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+    /// The color of the closest texel
+    #[default]
+    Nearest,
+    /// The four closest texels, weighted by the distance to their centers: the colors blend smoothly, and do not shimmer in animations
+    Bilinear,
+}
+
+/// # Wrap
+///
+/// What an ImageTexture shows outside of the [0., 1.] range of the uv coordinates
+#[derive(Copy, Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Wrap {
+    /// The texture repeats itself, and its opposite edges blend together with bilinear filtering
+    #[default]
+    Repeat,
+    /// The texels of the edges stretch outwards
+    Clamp,
+}
+
+/// # ImageTexture
+///
+/// An image mapped on the surface of objects: u goes along the columns and v along the rows, from the top of the image.
+/// The texels are stored as linear colors.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+    #[serde(default)]
+    filter: Filter,
+    #[serde(default)]
+    wrap: Wrap,
+}
+
+impl ImageTexture {
+    /// Converts an image into a texture
+    /// The colors of 8 and 16 bits images are gamma-encoded and get linearized with a gamma of 2.2, the ones of float images (e.g. HDR) are already linear.
+    pub fn new(image: &image::DynamicImage) -> Self {
+        let mut texture = ImageTexture::new_data(image);
+        match image {
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_) => (),
+            _ => {
+                for texel in texture.texels.iter_mut() {
+                    *texel = Color::new(texel.r.powf(2.2), texel.g.powf(2.2), texel.b.powf(2.2));
+                }
+            }
+        }
+
+        texture
+    }
+
+    /// Converts an image storing data rather than colors, e.g. a normal map: the values are kept as they are, from 0. to 1. for 8 and 16 bits images
+    pub fn new_data(image: &image::DynamicImage) -> Self {
+        ImageTexture {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            texels: image
+                .to_rgb32f()
+                .pixels()
+                .map(|pixel| Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64))
+                .collect(),
+            filter: Filter::Nearest,
+            wrap: Wrap::Repeat,
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+    }
+
+    /// Loads a texture from an image file (PNG, JPEG, BMP, HDR, etc.)
+    pub fn open(path: &str) -> image::ImageResult<Self> {
+        Ok(Self::new(&image::open(path)?))
+    }
+
+    /// Looks up the color of the texture at the uv coordinates, following its Filter and its Wrap mode
+    pub fn sample(&self, uv: (f64, f64)) -> Color {
+        // In texel units, the center of the texel (i, j) is at (i + 0.5, j + 0.5)
+        let x = uv.0 * self.width as f64;
+        let y = uv.1 * self.height as f64;
+
+        match self.filter {
+            Filter::Nearest => self.texel(y.floor() as isize, x.floor() as isize),
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (j, i) = (x.floor() as isize, y.floor() as isize);
+                let (fx, fy) = (x - x.floor(), y - y.floor());
+
+                let top = self.texel(i, j) * (1. - fx) + self.texel(i, j + 1) * fx;
+                let bottom = self.texel(i + 1, j) * (1. - fx) + self.texel(i + 1, j + 1) * fx;
+                top * (1. - fy) + bottom * fy
+            }
+        }
+    }
+
+    /// Returns the texel of row i and column j, which may be outside of the image (see Wrap)
+    fn texel(&self, i: isize, j: isize) -> Color {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let (i, j) = match self.wrap {
+            Wrap::Repeat => (i.rem_euclid(height), j.rem_euclid(width)),
+            Wrap::Clamp => (i.clamp(0, height - 1), j.clamp(0, width - 1)),
+        };
+
+        self.texels[(i * width + j) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intersection::Intersection;
+    use crate::object::sphere::Sphere;
+    use crate::object::Object;
+    use crate::ray::Ray;
+    use crate::scene::Scene;
+    use crate::utils::{Material, Vector};
+
+    #[test]
+    fn checkerboard_on_sphere() {
+        // A 2x2 black and white checkerboard
+        let image = image::RgbImage::from_fn(2, 2, |x, y| match (x + y) % 2 {
+            0 => image::Rgb([255, 255, 255]),
+            _ => image::Rgb([0, 0, 0]),
+        });
+        let mut scene = Scene::new();
+        let texture = scene.add_texture(ImageTexture::new(&image::DynamicImage::ImageRgb8(image)));
+        let sphere = Sphere::new(Vector::new_eq(0.), 1., Material::create_textured(texture));
+
+        let albedo_at = |origin: Vector| {
+            let inter: Intersection = sphere
+                .intersection(Ray::new(origin, origin * (-1.)))
+                .unwrap();
+            scene.albedo(inter.material, inter.point, inter.uv).r
+        };
+
+        // Above the equator, the quarter of the sphere around -x (u = 0.) is white and the one around +x (u = 0.5) is black
+        assert_approx_eq::assert_approx_eq!(albedo_at(Vector::new(-10., 3., -1.)), 1.);
+        assert_approx_eq::assert_approx_eq!(albedo_at(Vector::new(10., 3., 1.)), 0.);
+        // Below the equator, the colors are swapped
+        assert_approx_eq::assert_approx_eq!(albedo_at(Vector::new(-10., -3., -1.)), 0.);
+        assert_approx_eq::assert_approx_eq!(albedo_at(Vector::new(10., -3., 1.)), 1.);
+    }
+
+    #[test]
+    fn bilinear_center_averages_four_texels() {
+        let image =
+            image::Rgb32FImage::from_fn(2, 2, |x, y| image::Rgb([(x + 2 * y) as f32, 1., 0.]));
+        let mut texture = ImageTexture::new(&image::DynamicImage::ImageRgb32F(image));
+
+        // Nearest: the texel containing the uv coordinates
+        assert_eq!(texture.sample((0.6, 0.4)).r, 1.);
+
+        texture.set_filter(Filter::Bilinear);
+        assert_eq!(texture.sample((0.5, 0.5)).r, (0. + 1. + 2. + 3.) / 4.);
+        assert_eq!(texture.sample((0.5, 0.5)).g, 1.);
+        // On the center of a texel, its own color
+        assert_eq!(texture.sample((0.75, 0.25)).r, 1.);
+
+        // On the left edge, the texture blends with its right edge when repeated, and not when clamped
+        assert_eq!(texture.sample((0., 0.25)).r, 0.5);
+        texture.set_wrap(Wrap::Clamp);
+        assert_eq!(texture.sample((0., 0.25)).r, 0.);
+        assert_eq!(texture.sample((-3., 2.)).r, 2.);
+    }
+
+    #[test]
+    fn checker_squares_scale() {
+        let scene = Scene::new();
+        let material = Material::create_checker(Color::white(), Color::black(), 4.);
+        let albedo_at = |u: f64, v: f64| scene.albedo(material, Vector::new_eq(0.), (u, v)).r;
+
+        assert_eq!(albedo_at(0.1, 0.1), 1.);
+        assert_eq!(albedo_at(0.2, 0.1), 1.);
+        assert_eq!(albedo_at(0.3, 0.1), 0.);
+        assert_eq!(albedo_at(0.3, 0.3), 1.);
+        assert_eq!(albedo_at(0.1, 0.3), 0.);
+
+        // With twice the scale, the squares are twice as small
+        let material = Material::create_checker(Color::white(), Color::black(), 8.);
+        assert_eq!(scene.albedo(material, Vector::new_eq(0.), (0.2, 0.1)).r, 0.);
+        assert_eq!(
+            scene.albedo(material, Vector::new_eq(0.), (0.01, 0.01)).r,
+            1.
+        );
+    }
+
+    #[test]
+    fn marble_ramp_is_seedable() {
+        let ramp = |seed: u64| -> Vec<f64> {
+            (0..200)
+                .map(|k| {
+                    let point = Vector::new(k as f64 * 0.05, 0.3, -0.7);
+                    Texture::marble_ramp(point, 2., 4, 5., seed)
+                })
+                .collect()
+        };
+        let grayscale = ramp(1);
+
+        assert!(grayscale.iter().all(|v| (0. ..=1.).contains(v)));
+        // The veins go through the whole ramp
+        assert!(grayscale.iter().any(|v| *v < 0.1) && grayscale.iter().any(|v| *v > 0.9));
+        assert_eq!(grayscale, ramp(1));
+        assert_ne!(grayscale, ramp(2));
+    }
+}
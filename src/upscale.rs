@@ -0,0 +1,218 @@
+//! # Upscale
+//!
+//! This module upscales a frame rendered at a lower resolution (see Config::render_scale) back to the full resolution.
+//! The guided upscaling uses cheap full-resolution normal and depth buffers (one Ray per pixel) to weight the low resolution samples,
+//! so that colors do not bleed across the silhouettes of the objects (joint bilateral upsampling).
+
+use crate::animate::Animatable;
+use crate::camera::Camera;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::utils::{Config, Vector};
+
+use rayon::prelude::*;
+
+/// # Guide
+///
+/// Geometric information of the primary hit of a pixel, used to guide the upscaling
+#[derive(Copy, Clone)]
+pub struct Guide {
+    pub normal: Vector,
+    /// Distance from the camera to the hit, infinite if the Ray misses every object
+    pub depth: f64,
+}
+
+impl Guide {
+    pub fn new(normal: Vector, depth: f64) -> Self {
+        Guide { normal, depth }
+    }
+
+    /// The Guide of a Ray that does not hit anything
+    pub fn miss() -> Self {
+        Guide {
+            normal: Vector::new_eq(0.),
+            depth: f64::INFINITY,
+        }
+    }
+
+    /// Computes how likely two pixels are to belong to the same surface, from 0. to 1.
+    fn similarity(self, other: Guide) -> f64 {
+        match (self.depth.is_finite(), other.depth.is_finite()) {
+            (false, false) => 1.,
+            (true, true) => {
+                let normal_diff = (self.normal - other.normal).norm_sq();
+                let depth_diff = (self.depth - other.depth) / self.depth.max(other.depth);
+
+                (-normal_diff / 0.2).exp() * (-depth_diff * depth_diff / 0.005).exp()
+            }
+            _ => 0.,
+        }
+    }
+}
+
+/// Computes the Guide of every pixel at the full resolution of the Config, with one Ray per pixel
+pub fn render_guides(camera: &Camera, scene: &Scene, config: Config, time: f64) -> Vec<Guide> {
+    let camera = &camera.at_time(time);
+    (0..config.height as isize)
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            (0..config.width as isize).map(move |j| {
+                let ray = Ray::new_basic_ray(i, j, camera)
+                    .apply_animations(camera.get_animations(), time);
+
+                let mut rng = config.sample_rng(0, i as usize, j as usize, 0);
+
+                match scene
+                    .compute_primary_intersection(
+                        ray,
+                        time,
+                        config.stochastic_transparency,
+                        &mut rng,
+                    )
+                    .filter(|_| camera.in_image_circle(i as f64 + 0.5, j as f64 + 0.5))
+                {
+                    Some(inter) => Guide::new(inter.normal, (inter.point - ray.origin).norm()),
+                    None => Guide::miss(),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Maps the center of the full resolution pixel i to a continuous coordinate in the low resolution image
+fn low_coordinate(i: usize, size: usize, low_size: usize) -> f64 {
+    (i as f64 + 0.5) * low_size as f64 / size as f64 - 0.5
+}
+
+/// Upscales a low resolution buffer with a bilinear filter
+pub fn upscale_bilinear(
+    low: &[Vector],
+    low_width: usize,
+    low_height: usize,
+    width: usize,
+    height: usize,
+) -> Vec<Vector> {
+    let get = |i: isize, j: isize| {
+        let i = i.clamp(0, low_height as isize - 1) as usize;
+        let j = j.clamp(0, low_width as isize - 1) as usize;
+        low[i * low_width + j]
+    };
+
+    let mut result = Vec::with_capacity(width * height);
+    for i in 0..height {
+        let y = low_coordinate(i, height, low_height);
+        let (i0, fy) = (y.floor() as isize, y - y.floor());
+        for j in 0..width {
+            let x = low_coordinate(j, width, low_width);
+            let (j0, fx) = (x.floor() as isize, x - x.floor());
+
+            let top = get(i0, j0) * (1. - fx) + get(i0, j0 + 1) * fx;
+            let bottom = get(i0 + 1, j0) * (1. - fx) + get(i0 + 1, j0 + 1) * fx;
+            result.push(top * (1. - fy) + bottom * fy);
+        }
+    }
+    result
+}
+
+/// Upscales a low resolution buffer, weighting the 4x4 closest low resolution pixels by their distance
+/// and by the similarity of their full resolution Guides with the Guide of the upscaled pixel.
+pub fn upscale_guided(
+    low: &[Vector],
+    low_width: usize,
+    low_height: usize,
+    guides: &[Guide],
+    width: usize,
+    height: usize,
+) -> Vec<Vector> {
+    // Guide of the full resolution pixel at the center of a low resolution pixel
+    let low_guide = |i: usize, j: usize| {
+        let hi_i =
+            (((i as f64 + 0.5) * height as f64 / low_height as f64) as usize).min(height - 1);
+        let hi_j = (((j as f64 + 0.5) * width as f64 / low_width as f64) as usize).min(width - 1);
+        guides[hi_i * width + hi_j]
+    };
+
+    let mut result = Vec::with_capacity(width * height);
+    for i in 0..height {
+        let y = low_coordinate(i, height, low_height);
+        for j in 0..width {
+            let x = low_coordinate(j, width, low_width);
+            let guide = guides[i * width + j];
+
+            let mut sum = Vector::new_eq(0.);
+            let mut sum_weights = 0.;
+            let mut closest = (f64::MAX, Vector::new_eq(0.));
+
+            for qi in (y.floor() as isize - 1)..=(y.floor() as isize + 2) {
+                for qj in (x.floor() as isize - 1)..=(x.floor() as isize + 2) {
+                    if qi < 0 || qj < 0 || qi >= low_height as isize || qj >= low_width as isize {
+                        continue;
+                    }
+                    let (qi, qj) = (qi as usize, qj as usize);
+                    let dist_sq = (qi as f64 - y).powi(2) + (qj as f64 - x).powi(2);
+                    let color = low[qi * low_width + qj];
+
+                    let weight = (-dist_sq / 2.).exp() * guide.similarity(low_guide(qi, qj));
+                    sum += color * weight;
+                    sum_weights += weight;
+
+                    if dist_sq < closest.0 {
+                        closest = (dist_sq, color);
+                    }
+                }
+            }
+
+            // No similar neighbour: fall back to the nearest one
+            match sum_weights > 1e-6 {
+                true => result.push(sum / sum_weights),
+                false => result.push(closest.1),
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts the pixels which are neither close to black nor close to white
+    fn count_blurred(buffer: &[Vector]) -> usize {
+        buffer.iter().filter(|v| v.x > 0.1 && v.x < 0.9).count()
+    }
+
+    #[test]
+    fn guided_upscale_keeps_silhouette_crisp() {
+        let (width, height) = (64, 64);
+        let (low_width, low_height) = (16, 16);
+        let inside = |i: f64, j: f64, size: f64| {
+            (i - size / 2.).powi(2) + (j - size / 2.).powi(2) < (size / 3.).powi(2)
+        };
+
+        // A white sphere on a black background
+        let mut low = Vec::new();
+        for i in 0..low_height {
+            for j in 0..low_width {
+                match inside(i as f64 + 0.5, j as f64 + 0.5, low_height as f64) {
+                    true => low.push(Vector::new_eq(1.)),
+                    false => low.push(Vector::new_eq(0.)),
+                }
+            }
+        }
+        let mut guides = Vec::new();
+        for i in 0..height {
+            for j in 0..width {
+                match inside(i as f64 + 0.5, j as f64 + 0.5, height as f64) {
+                    true => guides.push(Guide::new(Vector::new(0., 0., 1.), 10.)),
+                    false => guides.push(Guide::miss()),
+                }
+            }
+        }
+
+        let bilinear = upscale_bilinear(&low, low_width, low_height, width, height);
+        let guided = upscale_guided(&low, low_width, low_height, &guides, width, height);
+
+        assert!(count_blurred(&bilinear) > 0);
+        assert_eq!(count_blurred(&guided), 0);
+    }
+}
@@ -1,15 +1,23 @@
 //! # Utils
 //!
-//! This module contains the following useful data structures : 3D Vectors, RGB Colors, Materials and render Configurations
+//! This module contains the following useful data structures : 3D Vectors, RGB Colors, 4x4 Matrices, Materials and render Configurations
 
+use crate::error::ConfigError;
+use crate::texture::Texture;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
+use std::fmt;
 use std::iter::Sum;
-use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, Index, IndexMut, Mul, MulAssign, Neg, Sub};
+
+pub mod noise;
 
 /// # Vector
 ///
 /// A 3D Vector structure.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Vector {
     pub x: f64,
     pub y: f64,
@@ -35,6 +43,15 @@ impl Vector {
         }
     }
 
+    /// Builds a new Vector by taking the minimum of the two given Vectors component by component
+    pub fn min(self, other: Vector) -> Self {
+        Vector {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
     /// Computes the squared norm of the Vector
     pub fn norm_sq(self) -> f64 {
         self.x * self.x + self.y * self.y + self.z * self.z
@@ -53,6 +70,19 @@ impl Vector {
         Vector::new(u2 * v3 - u3 * v2, u3 * v1 - u1 * v3, u1 * v2 - u2 * v1)
     }
 
+    /// Completes a unit Vector into a right-handed orthonormal basis: returns u and v such that (u, v, self) are orthonormal and u x v = self
+    /// Uses the branchless construction of Duff et al. (2017), which holds for every direction (there is no helper Vector to be parallel to).
+    pub fn orthonormal_basis(self) -> (Self, Self) {
+        let sign = 1_f64.copysign(self.z);
+        let a = -1. / (sign + self.z);
+        let b = self.x * self.y * a;
+
+        (
+            Vector::new(1. + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vector::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
     /// Computes the dot product of two vectors
     pub fn dot(self, other: Self) -> f64 {
         let (u1, u2, u3) = (self.x, self.y, self.z);
@@ -62,8 +92,26 @@ impl Vector {
     }
 
     /// Divides the Vector by its norm
+    /// A (nearly) null Vector has no direction: it is returned as a null Vector instead of NaNs.
     pub fn normalize(self) -> Self {
-        self / self.norm()
+        match self.norm_sq() > 1e-24 {
+            true => self / self.norm(),
+            false => Vector::new_eq(0.),
+        }
+    }
+
+    /// Scales the Vector down so that none of its components is above max, keeping their ratios (and so the hue of a color)
+    pub fn clamp_max_component(self, max: f64) -> Self {
+        let highest = self.x.max(self.y).max(self.z);
+        match highest > max {
+            true => self * (max / highest),
+            false => self,
+        }
+    }
+
+    /// Tells if no component of the Vector is infinite or NaN
+    pub fn is_finite(self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
     }
 
     pub fn rotate_x(self, theta_deg: f64) -> Self {
@@ -94,6 +142,59 @@ impl Vector {
 
         Vector { x, y, z }
     }
+
+    /// Rotates the Vector around an axis going through the origin (Rodrigues' rotation formula)
+    /// The rotation is counterclockwise when the axis points towards the viewer, like rotate_x, rotate_y and rotate_z.
+    pub fn rotate_axis(self, axis: Vector, theta_deg: f64) -> Self {
+        let theta_rad = theta_deg * PI / 180.;
+        let k = axis.normalize();
+
+        self * theta_rad.cos()
+            + k.cross(self) * theta_rad.sin()
+            + k * k.dot(self) * (1. - theta_rad.cos())
+    }
+
+    /// Linearly interpolates between the Vector (t = 0.) and other (t = 1.)
+    pub fn lerp(self, other: Vector, t: f64) -> Self {
+        self * (1. - t) + other * t
+    }
+
+    /// Clamps each component of the Vector between min and max
+    pub fn clamp(self, min: f64, max: f64) -> Self {
+        Vector {
+            x: self.x.clamp(min, max),
+            y: self.y.clamp(min, max),
+            z: self.z.clamp(min, max),
+        }
+    }
+
+    /// Computes the distance between two points
+    pub fn distance(self, other: Vector) -> f64 {
+        (self - other).norm()
+    }
+
+    /// Computes the angle between two Vectors, in radians (from 0. to PI)
+    pub fn angle_between(self, other: Vector) -> f64 {
+        self.cross(other).norm().atan2(self.dot(other))
+    }
+
+    /// Computes the mirror reflection of the Vector on a surface of the given (unit) normal
+    pub fn reflect(self, normal: Vector) -> Self {
+        self - normal * 2. * self.dot(normal)
+    }
+
+    /// Computes the refraction of the (unit) Vector through a surface of the given (unit) normal, facing against the Vector
+    /// eta is the ratio n_1 / n_2 of the refractive indices of the medium the Vector comes from and of the one it enters.
+    /// Returns None in case of total internal reflection.
+    pub fn refract(self, normal: Vector, eta: f64) -> Option<Self> {
+        let scalar = self.dot(normal);
+        let radical = 1. - eta * eta * (1. - scalar * scalar);
+
+        match radical >= 0. {
+            true => Some(self * eta - normal * (eta * scalar + radical.sqrt())),
+            false => None,
+        }
+    }
 }
 
 impl Add for Vector {
@@ -165,6 +266,19 @@ impl Mul<Color> for Vector {
         Self::new(self.x * rhs.r, self.y * rhs.g, self.z * rhs.b)
     }
 }
+/// Componentwise product, e.g. to scale a radiance by the throughput of a path, color by color
+impl Mul for Vector {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.x * rhs.x, self.y * rhs.y, self.z * rhs.z)
+    }
+}
+impl MulAssign for Vector {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
 impl Div<f64> for Vector {
     type Output = Self;
 
@@ -172,17 +286,66 @@ impl Div<f64> for Vector {
         Self::new(self.x / rhs, self.y / rhs, self.z / rhs)
     }
 }
+/// The components along the axes 0, 1 and 2 are x, y and z: any other axis panics
+impl Index<usize> for Vector {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("a Vector has no axis {}", axis),
+        }
+    }
+}
+impl IndexMut<usize> for Vector {
+    fn index_mut(&mut self, axis: usize) -> &mut f64 {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("a Vector has no axis {}", axis),
+        }
+    }
+}
 
 /// # Color
 ///
 /// A simple Color data structure with red, green and blue values as 0. .. 1. f64 floeats
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Color {
     pub r: f64,
     pub g: f64,
     pub b: f64,
 }
 
+/// # ParseColorError
+///
+/// The error returned when an hexadecimal color can not be parsed (see Color::from_hex)
+#[derive(Debug, PartialEq)]
+pub enum ParseColorError {
+    /// The color does not have 3 or 6 hexadecimal digits
+    InvalidLength(String),
+    /// The color contains a character which is not an hexadecimal digit
+    InvalidDigit(String),
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseColorError::InvalidLength(hex) => {
+                write!(f, "{} should have 3 or 6 hexadecimal digits", hex)
+            }
+            ParseColorError::InvalidDigit(hex) => {
+                write!(f, "{} contains a non hexadecimal digit", hex)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
 impl Color {
     pub fn red() -> Self {
         Color {
@@ -246,6 +409,74 @@ impl Color {
     pub fn new_eq(a: f64) -> Self {
         Color { r: a, g: a, b: a }
     }
+
+    /// Builds a Color from 8 bits channels, as found in most color pickers
+    pub fn from_rgb8(r: u8, g: u8, b: u8) -> Self {
+        Color::new(r as f64 / 255., g as f64 / 255., b as f64 / 255.)
+    }
+
+    /// Parses an hexadecimal color, such as "#ff8800" or its short form "#f80" (the leading # is optional)
+    pub fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ParseColorError::InvalidDigit(hex.to_string()));
+        }
+        // Only ASCII digits are left, so that the digits can be sliced one byte at a time
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).unwrap_or(0);
+
+        match digits.len() {
+            // Each digit is repeated: "f80" is "ff8800"
+            3 => Ok(Color::from_rgb8(
+                channel(&digits[0..1]) * 17,
+                channel(&digits[1..2]) * 17,
+                channel(&digits[2..3]) * 17,
+            )),
+            6 => Ok(Color::from_rgb8(
+                channel(&digits[0..2]),
+                channel(&digits[2..4]),
+                channel(&digits[4..6]),
+            )),
+            _ => Err(ParseColorError::InvalidLength(hex.to_string())),
+        }
+    }
+
+    /// Clamps each channel of the Color between 0. and 1.
+    pub fn clamp(self) -> Self {
+        Color {
+            r: self.r.clamp(0., 1.),
+            g: self.g.clamp(0., 1.),
+            b: self.b.clamp(0., 1.),
+        }
+    }
+
+    /// Approximates the color of a black body at a given temperature in Kelvin (from 1000K to 40000K)
+    /// 6500K is close to white, lower temperatures are warmer (red) and higher ones are colder (blue).
+    pub fn from_temperature(kelvin: f64) -> Self {
+        let t = kelvin.clamp(1000., 40000.) / 100.;
+
+        let r = match t <= 66. {
+            true => 255.,
+            false => 329.698727446 * (t - 60.).powf(-0.1332047592),
+        };
+        let g = match t <= 66. {
+            true => 99.4708025861 * t.ln() - 161.1195681661,
+            false => 288.1221695283 * (t - 60.).powf(-0.0755148492),
+        };
+        let b = if t >= 66. {
+            255.
+        } else if t <= 19. {
+            0.
+        } else {
+            138.5177312231 * (t - 10.).ln() - 305.0447927307
+        };
+
+        Color::new(
+            r.clamp(0., 255.) / 255.,
+            g.clamp(0., 255.) / 255.,
+            b.clamp(0., 255.) / 255.,
+        )
+    }
 }
 
 impl Add for Color {
@@ -308,11 +539,379 @@ impl Div<f64> for Color {
         Self::new(self.r / rhs, self.g / rhs, self.b / rhs)
     }
 }
+/// A radiance with the channels of the Color: r, g and b map to x, y and z
+impl From<Color> for Vector {
+    fn from(color: Color) -> Self {
+        Vector::new(color.r, color.g, color.b)
+    }
+}
+/// A Color with the components of the radiance: x, y and z map to r, g and b
+impl From<Vector> for Color {
+    fn from(vector: Vector) -> Self {
+        Color::new(vector.x, vector.y, vector.z)
+    }
+}
+
+/// # Matrix4
+///
+/// A 4x4 matrix of homogeneous coordinates, stored row by row, used to import transforms and projections from external engines.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Matrix4 { m }
+    }
+
+    pub fn identity() -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.;
+        }
+        Matrix4 { m }
+    }
+
+    /// Builds a view matrix (world to camera) for a camera at eye looking at target, looking down its -z axis
+    pub fn look_at(eye: Vector, target: Vector, up: Vector) -> Self {
+        let f = (target - eye).normalize();
+        let s = f.cross(up).normalize();
+        let u = s.cross(f);
+
+        Matrix4::new([
+            [s.x, s.y, s.z, -s.dot(eye)],
+            [u.x, u.y, u.z, -u.dot(eye)],
+            [-f.x, -f.y, -f.z, f.dot(eye)],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// Builds a perspective projection matrix (OpenGL convention, with normalized device coordinates in [-1, 1])
+    /// fov_degrees is the vertical field of view and aspect is width / height.
+    pub fn perspective(fov_degrees: f64, aspect: f64, near: f64, far: f64) -> Self {
+        let f = 1. / (fov_degrees * PI / 180. / 2.).tan();
+
+        Matrix4::new([
+            [f / aspect, 0., 0., 0.],
+            [0., f, 0., 0.],
+            [
+                0.,
+                0.,
+                (far + near) / (near - far),
+                2. * far * near / (near - far),
+            ],
+            [0., 0., -1., 0.],
+        ])
+    }
+
+    pub fn translation(translation: Vector) -> Self {
+        Matrix4::new([
+            [1., 0., 0., translation.x],
+            [0., 1., 0., translation.y],
+            [0., 0., 1., translation.z],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// Scales each axis by the matching component of factors
+    pub fn scaling(factors: Vector) -> Self {
+        Matrix4::new([
+            [factors.x, 0., 0., 0.],
+            [0., factors.y, 0., 0.],
+            [0., 0., factors.z, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn rotation(rotation: Quaternion) -> Self {
+        let x = rotation.rotate(Vector::new(1., 0., 0.));
+        let y = rotation.rotate(Vector::new(0., 1., 0.));
+        let z = rotation.rotate(Vector::new(0., 0., 1.));
+
+        Matrix4::new([
+            [x.x, y.x, z.x, 0.],
+            [x.y, y.y, z.y, 0.],
+            [x.z, y.z, z.z, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn transpose(self) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    /// Computes the inverse of the Matrix with a Gauss-Jordan elimination
+    /// Returns None if the Matrix is singular.
+    pub fn inverse(self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Matrix4::identity().m;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for k in 0..4 {
+                a[col][k] /= p;
+                inv[col][k] /= p;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for k in 0..4 {
+                        a[row][k] -= factor * a[col][k];
+                        inv[row][k] -= factor * inv[col][k];
+                    }
+                }
+            }
+        }
+
+        Some(Matrix4 { m: inv })
+    }
+
+    /// Applies the Matrix to a point (w = 1), with the perspective division
+    pub fn transform_point(self, point: Vector) -> Vector {
+        let [x, y, z, w] = self.transform([point.x, point.y, point.z, 1.]);
+        Vector::new(x / w, y / w, z / w)
+    }
+
+    /// Applies the Matrix to a direction (w = 0), ignoring the translation
+    pub fn transform_vector(self, vector: Vector) -> Vector {
+        let [x, y, z, _] = self.transform([vector.x, vector.y, vector.z, 0.]);
+        Vector::new(x, y, z)
+    }
+
+    fn transform(self, v: [f64; 4]) -> [f64; 4] {
+        self.m
+            .map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2] + row[3] * v[3])
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+/// # Transform
+///
+/// An object-to-world Matrix4 and its inverse, placing an Object in the Scene (see Object::get_transform)
+/// Points and directions go through the matrix, while normals go through its inverse-transpose, so that they stay orthogonal to the surface under non-uniform scalings.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub matrix: Matrix4,
+    pub inverse: Matrix4,
+}
+
+impl Transform {
+    /// Returns None if the matrix is singular
+    pub fn new(matrix: Matrix4) -> Option<Self> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn to_world_point(&self, point: Vector) -> Vector {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn to_world_vector(&self, vector: Vector) -> Vector {
+        self.matrix.transform_vector(vector)
+    }
+
+    /// Transforms a normal with the inverse-transpose of the matrix, and normalizes it
+    pub fn to_world_normal(&self, normal: Vector) -> Vector {
+        self.inverse
+            .transpose()
+            .transform_vector(normal)
+            .normalize()
+    }
+
+    pub fn to_local_point(&self, point: Vector) -> Vector {
+        self.inverse.transform_point(point)
+    }
+
+    pub fn to_local_vector(&self, vector: Vector) -> Vector {
+        self.inverse.transform_vector(vector)
+    }
+
+    /// Chains the Transforms: the object is placed by this one, then moved by other
+    pub fn then(&self, other: &Transform) -> Transform {
+        Transform {
+            matrix: other.matrix * self.matrix,
+            inverse: self.inverse * other.inverse,
+        }
+    }
+
+    /// Computes the world space axis-aligned box enclosing a box of the object space, from its 8 transformed corners
+    pub fn to_world_box(&self, min: Vector, max: Vector) -> (Vector, Vector) {
+        let mut world_min = Vector::new_eq(f64::INFINITY);
+        let mut world_max = Vector::new_eq(f64::NEG_INFINITY);
+
+        for corner in 0..8 {
+            let point = self.to_world_point(Vector::new(
+                match corner & 1 == 0 {
+                    true => min.x,
+                    false => max.x,
+                },
+                match corner & 2 == 0 {
+                    true => min.y,
+                    false => max.y,
+                },
+                match corner & 4 == 0 {
+                    true => min.z,
+                    false => max.z,
+                },
+            ));
+            world_min = world_min.min(point);
+            world_max = world_max.max(point);
+        }
+        (world_min, world_max)
+    }
+}
+
+/// # Quaternion
+///
+/// A unit quaternion w + xi + yj + zk, representing a rotation around an arbitrary axis.
+/// Unlike a sequence of rotations around the x, y and z axes, it is applied in a single step, and can be interpolated without gimbal lock (see slerp).
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Quaternion {
+            w: 1.,
+            x: 0.,
+            y: 0.,
+            z: 0.,
+        }
+    }
+
+    /// Builds the rotation of theta_deg degrees around axis (see Vector::rotate_axis)
+    pub fn from_axis_angle(axis: Vector, theta_deg: f64) -> Self {
+        let half = theta_deg * PI / 180. / 2.;
+        let axis = axis.normalize() * half.sin();
+
+        Quaternion {
+            w: half.cos(),
+            x: axis.x,
+            y: axis.y,
+            z: axis.z,
+        }
+    }
+
+    /// The inverse rotation
+    pub fn conjugate(self) -> Self {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub fn dot(self, other: Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Spherical linear interpolation between the rotation (t = 0.) and other (t = 1.), at constant angular speed along the shortest path
+    pub fn slerp(self, other: Quaternion, t: f64) -> Self {
+        // q and -q are the same rotation: go towards the closest one
+        let (other, cos) = match self.dot(other) < 0. {
+            true => (other.scale(-1.), -self.dot(other)),
+            false => (other, self.dot(other)),
+        };
+
+        // Nearly identical rotations: a linear interpolation is accurate and avoids dividing by sin(0.)
+        if cos > 0.9995 {
+            return (self.scale(1. - t) + other.scale(t)).normalize();
+        }
+
+        let theta = cos.acos();
+        let a = ((1. - t) * theta).sin() / theta.sin();
+        let b = (t * theta).sin() / theta.sin();
+        self.scale(a) + other.scale(b)
+    }
+
+    /// Rotates a Vector
+    pub fn rotate(self, v: Vector) -> Vector {
+        let u = Vector::new(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.;
+        v + t * self.w + u.cross(t)
+    }
+
+    fn normalize(self) -> Self {
+        self.scale(1. / self.dot(self).sqrt())
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Quaternion {
+            w: self.w * factor,
+            x: self.x * factor,
+            y: self.y * factor,
+            z: self.z * factor,
+        }
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Quaternion {
+            w: self.w + rhs.w,
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
+/// Composes two rotations: (a * b).rotate(v) == a.rotate(b.rotate(v))
+impl Mul for Quaternion {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Quaternion {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
 
 /// # Material
 ///
 /// A struct to store information about a material and its behaviour (color, emissibity, transparency, etc.)
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(from = "MaterialFile")]
 pub struct Material {
     pub color: Color,
     pub mirror: bool,
@@ -321,8 +920,105 @@ pub struct Material {
     pub n_object: f64,
     pub emissive: bool,
     pub emissivity: f64,
+    /// Color of the light emitted by emissive materials, which may differ from the color of their surface
+    /// It is the color itself unless set otherwise (see create_emissive_colored), including for scene files written without it.
+    pub emission_color: Color,
     pub phong: bool,
     pub phong_exponent: f64,
+    pub iridescent: bool,
+    pub film_thickness: f64,
+    pub film_ior: f64,
+    /// Opacity of the surface, from 0. (fully cut out) to 1. (opaque), see Config::stochastic_transparency
+    pub alpha: f64,
+    /// When set, the albedo is looked up in the Texture at the uv coordinates of the Intersection instead of using color
+    pub texture: Option<Texture>,
+    /// When set, the shading normal is read in the Texture at the uv coordinates of the Intersection (see Scene::shading_normal)
+    /// Its colors encode a normal in the (tangent, bitangent, normal) frame of the surface: a flat map is (0.5, 0.5, 1.).
+    pub normal_map: Option<Texture>,
+    /// When set, the light emitted by emissive materials is tinted by the Texture at the uv coordinates of the surface, e.g. a window showing a skyline
+    /// The emission_color (with its animations) and the emissivity still apply on top of it.
+    pub emission_texture: Option<Texture>,
+    /// Anisotropic GGX metal (see create_brushed_metal), reflecting specular_color
+    pub brushed: bool,
+    pub roughness_u: f64,
+    pub roughness_v: f64,
+    /// Roughness of the isotropic GGX reflection of glossy metals (see create_glossy), reflecting specular_color
+    /// Materials with a roughness of 0. have no glossy lobe: a glossy metal that smooth is a mirror.
+    pub roughness: f64,
+    /// Samples the indirect lighting of the diffuse part uniformly over the hemisphere instead of with a cosine-weighted density
+    /// Both converge to the same result, the uniform sampling with more noise: it is meant for debugging and comparisons.
+    pub uniform_sampling: bool,
+    /// Whether the back face of the surface, hit from inside the object, is lit like its front face
+    /// The back faces of one-sided materials are black, apart from their reflections and refractions. Scene files written without it are two-sided.
+    pub two_sided: bool,
+}
+
+/// The content of a Material in a scene file, whose fields added over time may be missing
+#[derive(Deserialize)]
+struct MaterialFile {
+    color: Color,
+    mirror: bool,
+    specular_color: Color,
+    transparent: bool,
+    n_object: f64,
+    emissive: bool,
+    emissivity: f64,
+    #[serde(default)]
+    emission_color: Option<Color>,
+    phong: bool,
+    phong_exponent: f64,
+    iridescent: bool,
+    film_thickness: f64,
+    film_ior: f64,
+    alpha: f64,
+    texture: Option<Texture>,
+    #[serde(default)]
+    normal_map: Option<Texture>,
+    #[serde(default)]
+    emission_texture: Option<Texture>,
+    brushed: bool,
+    roughness_u: f64,
+    roughness_v: f64,
+    #[serde(default)]
+    roughness: f64,
+    #[serde(default)]
+    uniform_sampling: bool,
+    #[serde(default = "two_sided_default")]
+    two_sided: bool,
+}
+
+fn two_sided_default() -> bool {
+    true
+}
+
+impl From<MaterialFile> for Material {
+    fn from(file: MaterialFile) -> Self {
+        Material {
+            color: file.color,
+            mirror: file.mirror,
+            specular_color: file.specular_color,
+            transparent: file.transparent,
+            n_object: file.n_object,
+            emissive: file.emissive,
+            emissivity: file.emissivity,
+            emission_color: file.emission_color.unwrap_or(file.color),
+            phong: file.phong,
+            phong_exponent: file.phong_exponent,
+            iridescent: file.iridescent,
+            film_thickness: file.film_thickness,
+            film_ior: file.film_ior,
+            alpha: file.alpha,
+            texture: file.texture,
+            normal_map: file.normal_map,
+            emission_texture: file.emission_texture,
+            brushed: file.brushed,
+            roughness_u: file.roughness_u,
+            roughness_v: file.roughness_v,
+            roughness: file.roughness,
+            uniform_sampling: file.uniform_sampling,
+            two_sided: file.two_sided,
+        }
+    }
 }
 
 impl Material {
@@ -335,8 +1031,22 @@ impl Material {
             n_object: 1.0,
             emissive: false,
             emissivity: 0.0,
+            emission_color: Color::black(),
             phong: false,
             phong_exponent: 1.0,
+            iridescent: false,
+            film_thickness: 0.0,
+            film_ior: 1.0,
+            alpha: 1.0,
+            texture: None,
+            normal_map: None,
+            emission_texture: None,
+            brushed: false,
+            roughness_u: 0.0,
+            roughness_v: 0.0,
+            roughness: 0.0,
+            uniform_sampling: false,
+            two_sided: true,
         }
     }
 
@@ -349,12 +1059,31 @@ impl Material {
             n_object,
             emissive: false,
             emissivity: 0.0,
+            emission_color: Color::black(),
             phong: false,
             phong_exponent: 1.0,
+            iridescent: false,
+            film_thickness: 0.0,
+            film_ior: 1.0,
+            alpha: 1.0,
+            texture: None,
+            normal_map: None,
+            emission_texture: None,
+            brushed: false,
+            roughness_u: 0.0,
+            roughness_v: 0.0,
+            roughness: 0.0,
+            uniform_sampling: false,
+            two_sided: true,
         }
     }
 
     pub fn create_emissive(color: Color, emissivity: f64) -> Self {
+        Material::create_emissive_colored(color, color, emissivity)
+    }
+
+    /// An emissive material whose surface has the albedo color and emits light of emission_color, e.g. a white lamp giving a blue light
+    pub fn create_emissive_colored(color: Color, emission_color: Color, emissivity: f64) -> Self {
         Material {
             color,
             mirror: false,
@@ -363,8 +1092,30 @@ impl Material {
             n_object: 1.0,
             emissive: true,
             emissivity,
+            emission_color,
             phong: false,
             phong_exponent: 1.0,
+            iridescent: false,
+            film_thickness: 0.0,
+            film_ior: 1.0,
+            alpha: 1.0,
+            texture: None,
+            normal_map: None,
+            emission_texture: None,
+            brushed: false,
+            roughness_u: 0.0,
+            roughness_v: 0.0,
+            roughness: 0.0,
+            uniform_sampling: false,
+            two_sided: true,
+        }
+    }
+
+    /// An emissive material whose light follows a Texture (see emission_texture), e.g. an image shown by a light panel
+    pub fn create_emissive_textured(texture: Texture, emissivity: f64) -> Self {
+        Material {
+            emission_texture: Some(texture),
+            ..Material::create_emissive(Color::white(), emissivity)
         }
     }
 
@@ -377,11 +1128,62 @@ impl Material {
             n_object: 1.0,
             emissive: false,
             emissivity: 0.0,
+            emission_color: color,
             phong: false,
             phong_exponent: 1.0,
+            iridescent: false,
+            film_thickness: 0.0,
+            film_ior: 1.0,
+            alpha: 1.0,
+            texture: None,
+            normal_map: None,
+            emission_texture: None,
+            brushed: false,
+            roughness_u: 0.0,
+            roughness_v: 0.0,
+            roughness: 0.0,
+            uniform_sampling: false,
+            two_sided: true,
+        }
+    }
+
+    /// A diffuse material whose albedo comes from a Texture
+    pub fn create_textured(texture: Texture) -> Self {
+        Material {
+            texture: Some(texture),
+            ..Material::create_diffuse(Color::white())
         }
     }
 
+    /// A diffuse material made of alternating squares of color_a and color_b, scale squares along each of the uv coordinates
+    pub fn create_checker(color_a: Color, color_b: Color, scale: f64) -> Self {
+        Material::create_textured(Texture::Checker {
+            color_a,
+            color_b,
+            scale,
+        })
+    }
+
+    /// A diffuse material veined like marble, blending color_a and color_b with Perlin noise evaluated at the intersection point
+    /// frequency sets the number of veins per unit of length, octaves and turbulence how finely and how much they are distorted.
+    pub fn create_marble(
+        color_a: Color,
+        color_b: Color,
+        frequency: f64,
+        octaves: usize,
+        turbulence: f64,
+        seed: u64,
+    ) -> Self {
+        Material::create_textured(Texture::Marble {
+            color_a,
+            color_b,
+            frequency,
+            octaves,
+            turbulence,
+            seed,
+        })
+    }
+
     pub fn create_phong(color: Color, specular_color: Color, phong_exponent: f64) -> Self {
         Material {
             color,
@@ -391,49 +1193,548 @@ impl Material {
             n_object: 1.0,
             emissive: false,
             emissivity: 0.0,
+            emission_color: color,
             phong: true,
             phong_exponent,
+            iridescent: false,
+            film_thickness: 0.0,
+            film_ior: 1.0,
+            alpha: 1.0,
+            texture: None,
+            normal_map: None,
+            emission_texture: None,
+            brushed: false,
+            roughness_u: 0.0,
+            roughness_v: 0.0,
+            roughness: 0.0,
+            uniform_sampling: false,
+            two_sided: true,
         }
     }
-}
 
-/// # Config
-///
-/// A configuration struct containing output and rendering configurations
-#[derive(Copy, Clone)]
-pub struct Config {
-    pub height: usize,
-    pub width: usize,
-    pub gamma: f64,
-    pub debug_info: bool,
-    pub nb_iter_max: usize,
-    pub nb_rays: usize,
-    pub dof: bool,
-    pub aa: bool,
-    pub start_time: f64,
-    pub end_time: f64,
-    pub nb_frames: usize,
-}
+    /// Evaluates the BRDF of the material, channel by channel, for the light coming from direction
+    /// reflected is the mirror reflection of the viewing Ray, around which the Phong specular lobe is centered.
+    pub fn brdf(self, reflected: Vector, direction: Vector) -> Color {
+        match self.phong {
+            false => self.color / PI,
+            true => {
+                let phong_lobe = reflected.dot(direction).max(0.).powf(self.phong_exponent);
 
-impl Config {
-    pub const fn new(
-        height: usize,
-        width: usize,
-        gamma: f64,
-        debug_info: bool,
-        nb_iter_max: usize,
-        nb_rays: usize,
-        dof: bool,
-        aa: bool,
-        start_time: f64,
-        end_time: f64,
-        nb_frames: usize,
-    ) -> Self {
-        Config {
-            height,
-            width,
-            gamma,
-            debug_info,
+                self.color / PI
+                    + self.specular_color * ((self.phong_exponent + 2.) / (2. * PI) * phong_lobe)
+            }
+        }
+    }
+
+    /// Creates a brushed metal, reflecting the albedo with an anisotropic GGX microfacet distribution
+    /// roughness_u is the roughness along the tangent of the surface (see Intersection::tangent) and roughness_v the one along the bitangent:
+    /// the highlight stretches along the roughest direction, i.e. perpendicular to the grooves.
+    pub fn create_brushed_metal(albedo: Color, roughness_u: f64, roughness_v: f64) -> Self {
+        Material {
+            specular_color: albedo,
+            brushed: true,
+            roughness_u,
+            roughness_v,
+            ..Material::create_diffuse(Color::black())
+        }
+    }
+
+    /// Creates a glossy metal, reflecting the albedo with an isotropic GGX microfacet distribution
+    /// The highlight widens as the roughness grows towards 1., and a roughness of 0. gives a perfect mirror (see create_mirror).
+    pub fn create_glossy(albedo: Color, roughness: f64) -> Self {
+        match roughness > 0. {
+            false => Material::create_mirror(albedo),
+            true => Material {
+                specular_color: albedo,
+                roughness,
+                ..Material::create_diffuse(Color::black())
+            },
+        }
+    }
+
+    /// Whether the Material reflects with a GGX microfacet distribution, as brushed and glossy metals do
+    pub fn is_microfacet(self) -> bool {
+        self.brushed || self.roughness > 0.
+    }
+
+    /// The GGX widths along the tangent and the bitangent, squared from the roughnesses
+    fn ggx_alphas(self) -> (f64, f64) {
+        let (roughness_u, roughness_v) = match self.brushed {
+            true => (self.roughness_u, self.roughness_v),
+            false => (self.roughness, self.roughness),
+        };
+        (
+            (roughness_u * roughness_u).max(1e-4),
+            (roughness_v * roughness_v).max(1e-4),
+        )
+    }
+
+    /// Expresses a direction in the (tangent, bitangent, normal) frame of the surface
+    fn to_local(direction: Vector, normal: Vector, tangent: Vector) -> Vector {
+        let tangent = (tangent - normal * tangent.dot(normal)).normalize();
+        let bitangent = normal.cross(tangent);
+        Vector::new(
+            direction.dot(tangent),
+            direction.dot(bitangent),
+            direction.dot(normal),
+        )
+    }
+
+    /// GGX distribution of the microfacet normals (given in the local frame)
+    fn ggx_distribution(self, half: Vector) -> f64 {
+        let (alpha_u, alpha_v) = self.ggx_alphas();
+        let e = (half.x / alpha_u).powi(2) + (half.y / alpha_v).powi(2) + half.z * half.z;
+        1. / (PI * alpha_u * alpha_v * e * e)
+    }
+
+    /// Smith shadowing term of the GGX distribution for a direction (given in the local frame)
+    fn ggx_lambda(self, direction: Vector) -> f64 {
+        let (alpha_u, alpha_v) = self.ggx_alphas();
+        let tan_sq = ((alpha_u * direction.x).powi(2) + (alpha_v * direction.y).powi(2))
+            / (direction.z * direction.z);
+        ((1. + tan_sq).sqrt() - 1.) / 2.
+    }
+
+    /// Evaluates the anisotropic GGX BRDF, channel by channel, for the light coming from direction and leaving towards view
+    /// The Fresnel reflectance follows Schlick's approximation, starting from specular_color at normal incidence.
+    pub fn ggx_brdf(
+        self,
+        view: Vector,
+        direction: Vector,
+        normal: Vector,
+        tangent: Vector,
+    ) -> Color {
+        let view = Self::to_local(view.normalize(), normal, tangent);
+        let light = Self::to_local(direction.normalize(), normal, tangent);
+        if view.z <= 0. || light.z <= 0. {
+            return Color::black();
+        }
+
+        let half = (view + light).normalize();
+        let fresnel = self.specular_color
+            + (Color::white() - self.specular_color) * (1. - view.dot(half).max(0.)).powi(5);
+        let shadowing = 1. / (1. + self.ggx_lambda(view) + self.ggx_lambda(light));
+
+        fresnel * (self.ggx_distribution(half) * shadowing / (4. * view.z * light.z))
+    }
+
+    /// Probability density (over the solid angle) of sampling direction with sample_ggx
+    pub fn ggx_pdf(self, view: Vector, direction: Vector, normal: Vector, tangent: Vector) -> f64 {
+        let view = Self::to_local(view.normalize(), normal, tangent);
+        let light = Self::to_local(direction.normalize(), normal, tangent);
+        let half = (view + light).normalize();
+        if half.z <= 0. || view.dot(half) <= 0. {
+            return 0.;
+        }
+
+        self.ggx_distribution(half) * half.z / (4. * view.dot(half))
+    }
+
+    /// Samples a direction of incoming light, by reflecting view on a microfacet normal sampled with a density D(h) * cos(theta_h)
+    /// The returned direction may go below the surface, in which case it should be discarded.
+    pub fn sample_ggx(
+        self,
+        view: Vector,
+        normal: Vector,
+        tangent: Vector,
+        rng: &mut dyn RngCore,
+    ) -> Vector {
+        let (alpha_u, alpha_v) = self.ggx_alphas();
+        let r1: f64 = rng.gen_range(0.0..1.0);
+        let r2: f64 = rng.gen_range(0.0..1.0);
+
+        // The slopes of the anisotropic distribution are the ones of the unit isotropic distribution, stretched
+        let tan_theta = (r1 / (1. - r1)).sqrt();
+        let phi = 2. * PI * r2;
+        let half = Vector::new(
+            alpha_u * tan_theta * phi.cos(),
+            alpha_v * tan_theta * phi.sin(),
+            1.,
+        )
+        .normalize();
+
+        let tangent = (tangent - normal * tangent.dot(normal)).normalize();
+        let bitangent = normal.cross(tangent);
+        let half = tangent * half.x + bitangent * half.y + normal * half.z;
+
+        let view = view.normalize();
+        half * (2. * view.dot(half)) - view
+    }
+
+    /// Creates a thin-film material (soap bubble, oil slick) that reflects rainbow colors depending on the viewing angle
+    /// film_thickness is given in nanometers, film_ior is the refractive index of the film.
+    pub fn create_iridescent(film_thickness: f64, film_ior: f64) -> Self {
+        Material {
+            color: Color::black(),
+            mirror: true,
+            specular_color: Color::white(),
+            transparent: false,
+            n_object: 1.0,
+            emissive: false,
+            emissivity: 0.0,
+            emission_color: Color::black(),
+            phong: false,
+            phong_exponent: 1.0,
+            iridescent: true,
+            film_thickness,
+            film_ior,
+            alpha: 1.0,
+            texture: None,
+            normal_map: None,
+            emission_texture: None,
+            brushed: false,
+            roughness_u: 0.0,
+            roughness_v: 0.0,
+            roughness: 0.0,
+            uniform_sampling: false,
+            two_sided: true,
+        }
+    }
+
+    /// Computes the reflectance of the thin film, channel by channel, for a given cosine of the incidence angle
+    /// The film lies between the air and the material (of index n_object), and the reflected waves interfere depending on the wavelength.
+    pub fn thin_film_reflectance(self, cos_theta: f64) -> Color {
+        // Representative wavelengths (in nanometers) of the red, green and blue channels
+        const WAVELENGTHS: [f64; 3] = [650., 510., 475.];
+
+        let n_1 = 1.;
+        let n_2 = self.film_ior;
+        let n_3 = self.n_object;
+
+        let cos_1 = cos_theta.abs().min(1.);
+        let sin_1_sq = 1. - cos_1 * cos_1;
+        let cos_2 = (1. - sin_1_sq * (n_1 / n_2).powi(2)).max(0.).sqrt();
+        let cos_3 = (1. - sin_1_sq * (n_1 / n_3).powi(2)).max(0.).sqrt();
+
+        // Fresnel amplitude coefficients at both interfaces, for s and p polarizations
+        let r_s = |n_i: f64, cos_i: f64, n_t: f64, cos_t: f64| {
+            (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t)
+        };
+        let r_p = |n_i: f64, cos_i: f64, n_t: f64, cos_t: f64| {
+            (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t)
+        };
+        let airy = |r_12: f64, r_23: f64, delta: f64| {
+            let cross = 2. * r_12 * r_23 * delta.cos();
+            (r_12 * r_12 + r_23 * r_23 + cross) / (1. + r_12 * r_12 * r_23 * r_23 + cross)
+        };
+
+        let reflectances = WAVELENGTHS.map(|wavelength| {
+            let delta = 4. * PI * n_2 * self.film_thickness * cos_2 / wavelength;
+
+            let reflectance_s = airy(
+                r_s(n_1, cos_1, n_2, cos_2),
+                r_s(n_2, cos_2, n_3, cos_3),
+                delta,
+            );
+            let reflectance_p = airy(
+                r_p(n_1, cos_1, n_2, cos_2),
+                r_p(n_2, cos_2, n_3, cos_3),
+                delta,
+            );
+
+            (reflectance_s + reflectance_p) / 2.
+        });
+
+        Color::new(reflectances[0], reflectances[1], reflectances[2])
+    }
+}
+
+/// # RenderMode
+///
+/// Selects how the color of each pixel is computed
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RenderMode {
+    /// Full path tracing, with direct and indirect lighting
+    PathTracing,
+    /// Additive transparency: the pixel gets brighter with the thickness of matter crossed by the Ray (see Config::xray_density)
+    XRay,
+    /// Normal of the surface at the primary hit, each component mapped from [-1., 1.] to [0., 255.] (black on misses)
+    Normals,
+    /// Albedo of the surface at the primary hit, without any lighting (black on misses)
+    Albedo,
+    /// Shadow catcher pass (see Scene::set_shadow_catcher): how much darker the invisible catcher plane is due to the shadows of the objects,
+    /// from 0. (unshadowed, or not the catcher plane) to 255. (fully shadowed), to be used as the alpha of a black layer when compositing
+    ShadowCatcher,
+    /// Ambient occlusion at the primary hit: the fraction of cosine-weighted Rays leaving the surface that travel Config::ao_distance
+    /// without hitting anything, from 0. (fully occluded) to 255. (unoccluded, and on misses), see Config::ao_samples
+    AmbientOcclusion,
+}
+
+/// # Parallelism
+///
+/// Selects what the threads of a render work on
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Parallelism {
+    /// The frames are rendered one after the other, the pixels of each frame in parallel
+    Pixels,
+    /// The frames of an animation are rendered in parallel, which keeps every core busy on short animations of small frames
+    /// The pixels of each frame are still shared with the idle threads.
+    Frames,
+}
+
+/// # GammaMode
+///
+/// The transfer function turning the linear radiance into the values of the image (see Config::gamma)
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GammaMode {
+    /// A pure power curve: a radiance r gives the value r^(1/gamma)
+    PowerGamma,
+    /// The sRGB transfer function, with a linear segment near black
+    /// The radiance given the value 255 is still 255^gamma, so that both modes are equally bright.
+    Srgb,
+}
+
+impl GammaMode {
+    /// Computes the value (from 0. to 255., unclamped) of a radiance, color by color
+    pub fn encode(self, radiance: f64, gamma: f64) -> f64 {
+        match self {
+            GammaMode::PowerGamma => radiance.powf(1. / gamma),
+            GammaMode::Srgb => {
+                let linear = radiance / 255_f64.powf(gamma);
+                match linear <= 0.0031308 {
+                    true => 255. * 12.92 * linear,
+                    false => 255. * (1.055 * linear.powf(1. / 2.4) - 0.055),
+                }
+            }
+        }
+    }
+}
+
+/// # BitDepth
+///
+/// The number of bits per color of the saved images (see Config::bit_depth)
+#[derive(Copy, Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BitDepth {
+    /// 8 bits per color, saved as image_{k}.bmp
+    #[default]
+    Eight,
+    /// 16 bits per color, saved as image_{k}.png (see tone_map_16)
+    Sixteen,
+}
+
+/// # Depth
+///
+/// The number of bounces a path may still take: in total, and of each kind (mirror reflections, refractions, and diffuse bounces)
+/// Each bounce uses one of the total and one of its kind: a path stops when either runs out (see Config::depth).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Depth {
+    pub total: usize,
+    pub reflection: usize,
+    pub refraction: usize,
+    pub diffuse: usize,
+}
+
+impl Depth {
+    /// The same budget for every kind of bounce, which only the total limits
+    pub const fn new(nb_iter_max: usize) -> Self {
+        Depth {
+            total: nb_iter_max,
+            reflection: nb_iter_max,
+            refraction: nb_iter_max,
+            diffuse: nb_iter_max,
+        }
+    }
+}
+
+impl From<usize> for Depth {
+    fn from(nb_iter_max: usize) -> Self {
+        Depth::new(nb_iter_max)
+    }
+}
+
+/// # Config
+///
+/// A configuration struct containing output and rendering configurations
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub height: usize,
+    pub width: usize,
+    pub gamma: f64,
+    pub debug_info: bool,
+    pub nb_iter_max: usize,
+    pub nb_rays: usize,
+    pub dof: bool,
+    pub aa: bool,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub nb_frames: usize,
+    pub render_mode: RenderMode,
+    pub xray_density: f64,
+    /// Fraction of the resolution at which the frame is actually rendered, before being upscaled (1. renders at full resolution)
+    pub render_scale: f64,
+    /// When upscaling, use full-resolution normal and depth buffers to keep the edges crisp instead of a plain bilinear filter
+    pub upscale_guided: bool,
+    /// Side of the square blocks of pixels whose primary Rays are traced together as a packet (1 traces every Ray alone)
+    pub packet_size: usize,
+    /// Spreads the anti-aliasing samples of each pixel on a jittered grid instead of drawing them independently
+    pub stratified_aa: bool,
+    /// Side of the square tiles of pixels rendered together (see render_radiance)
+    pub tile_size: usize,
+    /// Number of primary Rays shot through each pixel, for anti-aliasing and depth of field (None uses nb_rays, see nb_aa_samples)
+    pub aa_samples: Option<usize>,
+    /// Number of light paths sampled for each pixel, for global illumination (None uses nb_rays, see nb_gi_samples)
+    pub gi_samples: Option<usize>,
+    /// Also save the depth and normal passes of each frame, as depth_{k}.png and normal_{k}.png
+    pub save_aovs: bool,
+    /// Distances from the camera mapped to black and to white in the depth pass (see gbuffer::depth_pass)
+    /// The range is the same for every frame, so that the depth of an animation does not flicker.
+    pub depth_range: (f64, f64),
+    /// Also save the object ID pass of each frame, as object_id_{k}.png
+    pub save_object_ids: bool,
+    /// Highest radiance of a single path: brighter paths are scaled down before being averaged, which removes fireflies at the cost of a little bias
+    /// f64::INFINITY disables the clamping.
+    pub max_sample_radiance: f64,
+    /// Number of Rays shot from each primary hit in RenderMode::AmbientOcclusion
+    pub ao_samples: usize,
+    /// Distance under which an object occludes the surface in RenderMode::AmbientOcclusion
+    pub ao_distance: f64,
+    /// Adaptive sampling: each pixel keeps drawing batches of nb_aa_samples primary Rays until the standard error of its mean radiance
+    /// falls below this fraction of the mean, or it reaches adaptive_max_samples (None always draws nb_aa_samples, see nb_aa_samples)
+    pub adaptive_error: Option<f64>,
+    /// Highest number of primary Rays drawn for a pixel with adaptive sampling
+    pub adaptive_max_samples: usize,
+    /// Also save the number of primary Rays drawn for each pixel of each frame, as a grayscale heatmap samples_{k}.png
+    pub save_sample_counts: bool,
+    /// Number of threads rendering the frames (0 uses every core)
+    /// The threads belong to each render: this does not limit the other renders, nor the global thread pool of rayon.
+    pub threads: usize,
+    /// Only renders the pixels of the region (x0, y0, x1, y1): columns x0..x1 and rows y0..y1, the other pixels are left black
+    /// None renders the whole image.
+    pub crop: Option<(usize, usize, usize, usize)>,
+    /// Whether render_all_frames renders the frames one at a time or in parallel
+    pub parallelism: Parallelism,
+    /// Transfer function applied with the gamma when the image is saved
+    pub gamma_mode: GammaMode,
+    /// Bits per color of the saved images (8 by default, see BitDepth)
+    pub bit_depth: BitDepth,
+    /// Also save the linear radiance of each frame, before the gamma correction, as image_{k}.hdr (see save_hdr)
+    pub save_hdr: bool,
+    /// When set, render_all_frames also assembles the frames into animation.gif, each one shown for this many milliseconds
+    pub gif_delay_ms: Option<u32>,
+    /// Seed of the random numbers drawn by the render: the same seed renders the same images (see sample_rng)
+    pub seed: u64,
+    /// Fraction of the time between two frames during which the shutter stays open: each primary Ray sees the Scene at a random time
+    /// of that interval, which blurs the moving objects (0. renders every Ray at the time of the frame, see sample_time)
+    pub shutter: f64,
+    /// Highest number of mirror reflections along a path (None uses nb_iter_max, see depth)
+    pub max_reflection_depth: Option<usize>,
+    /// Highest number of refractions through transparent Materials along a path (None uses nb_iter_max, see depth)
+    pub max_refraction_depth: Option<usize>,
+    /// Highest number of diffuse bounces along a path, for indirect lighting (None uses nb_iter_max, see depth)
+    pub max_diffuse_depth: Option<usize>,
+    /// Next event estimation: the light objects and the Portals are sampled at each intersection (see Scene::compute_direct)
+    /// Turned off, the paths only find them by hitting them, which converges to the same image with much more noise (see Scene::compute_intensity_without_nee).
+    pub nee: bool,
+    /// Chooses how partially opaque surfaces (of alpha lower than 1., see Material::alpha) are cut out
+    /// When enabled, each hit is kept with a probability equal to the alpha, drawn from the rng of the sample, which gives smooth cutouts once the samples are averaged.
+    /// When disabled (the default), surfaces of alpha lower than 0.5 are fully cut out, and the others are opaque.
+    pub stochastic_transparency: bool,
+    /// Strength of the glow around the highlights: 1. spreads all the radiance above the bloom_threshold around its pixel (0. disables it, see bloom::add_bloom)
+    pub bloom_intensity: f64,
+    /// Luminance above which a pixel glows, as a fraction of the radiance saved as white (255^gamma): 1. only blooms the overexposed pixels
+    pub bloom_threshold: f64,
+    /// Distance (in pixels of the saved image) at which the glow around a highlight fades out
+    pub bloom_radius: f64,
+    /// Counts the Rays traced and the intersection tests, and times the frames (see render_one_frame_with_stats)
+    /// Printed for each frame with debug_info. Turned off, the counters are left alone to keep the render fast.
+    pub stats: bool,
+}
+
+impl Config {
+    /// Starts building a Config from the defaults of ConfigBuilder
+    pub const fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// Builds a Config from its main values, the others taking the defaults of ConfigBuilder
+    /// Being const, it accepts any values: a zero height or width renders an empty image, a zero nb_rays a black one,
+    /// a gamma which is not positive gives NaNs, and an end_time before the start_time plays the animation backwards.
+    /// Use try_new to have them rejected instead.
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new(
+        height: usize,
+        width: usize,
+        gamma: f64,
+        debug_info: bool,
+        nb_iter_max: usize,
+        nb_rays: usize,
+        dof: bool,
+        aa: bool,
+        start_time: f64,
+        end_time: f64,
+        nb_frames: usize,
+    ) -> Self {
+        Config {
+            height,
+            width,
+            gamma,
+            debug_info,
+            nb_iter_max,
+            nb_rays,
+            dof,
+            aa,
+            start_time,
+            end_time,
+            nb_frames,
+            render_mode: RenderMode::PathTracing,
+            xray_density: 0.05,
+            render_scale: 1.,
+            upscale_guided: true,
+            packet_size: 1,
+            stratified_aa: false,
+            tile_size: 32,
+            aa_samples: None,
+            gi_samples: None,
+            save_aovs: false,
+            depth_range: (0., 100.),
+            save_object_ids: false,
+            max_sample_radiance: f64::INFINITY,
+            ao_samples: 16,
+            ao_distance: 10.,
+            adaptive_error: None,
+            adaptive_max_samples: 1024,
+            save_sample_counts: false,
+            threads: 0,
+            crop: None,
+            parallelism: Parallelism::Pixels,
+            gamma_mode: GammaMode::PowerGamma,
+            bit_depth: BitDepth::Eight,
+            save_hdr: false,
+            gif_delay_ms: None,
+            seed: 0,
+            shutter: 0.,
+            max_reflection_depth: None,
+            max_refraction_depth: None,
+            max_diffuse_depth: None,
+            nee: true,
+            stochastic_transparency: false,
+            bloom_intensity: 0.,
+            bloom_threshold: 1.,
+            bloom_radius: 10.,
+            stats: false,
+        }
+    }
+
+    /// Same as new, returning an error when a value can not render an image (see validate)
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_new(
+        height: usize,
+        width: usize,
+        gamma: f64,
+        debug_info: bool,
+        nb_iter_max: usize,
+        nb_rays: usize,
+        dof: bool,
+        aa: bool,
+        start_time: f64,
+        end_time: f64,
+        nb_frames: usize,
+    ) -> Result<Self, ConfigError> {
+        let config = Config::new(
+            height,
+            width,
+            gamma,
+            debug_info,
             nb_iter_max,
             nb_rays,
             dof,
@@ -441,14 +1742,415 @@ impl Config {
             start_time,
             end_time,
             nb_frames,
+        );
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that the image has pixels, that at least one Ray is shot through them, that the gamma is positive,
+    /// and that the animation does not end before it starts
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.height == 0 || self.width == 0 {
+            return Err(ConfigError::EmptyImage {
+                height: self.height,
+                width: self.width,
+            });
+        }
+        if self.nb_rays == 0 {
+            return Err(ConfigError::NoRays);
+        }
+        // NaN is not positive either
+        if self.gamma.is_nan() || self.gamma <= 0. {
+            return Err(ConfigError::InvalidGamma(self.gamma));
+        }
+        if self.start_time > self.end_time {
+            return Err(ConfigError::BackwardsTime {
+                start_time: self.start_time,
+                end_time: self.end_time,
+            });
+        }
+        Ok(())
+    }
+
+    /// Number of primary Rays shot through each pixel: aa_samples, or nb_rays when it is not set
+    /// Every primary Ray costs an intersection with the Scene and at least one path: a high count only pays off on the edges of the objects.
+    pub fn nb_aa_samples(&self) -> usize {
+        self.aa_samples.unwrap_or(self.nb_rays).max(1)
+    }
+
+    /// Number of light paths sampled for each pixel: gi_samples, or nb_rays when it is not set
+    /// Each primary Ray starts at least one path, so this is never below nb_aa_samples.
+    /// Paths are the expensive part of a render: a noisy Scene needs many of them, a low-noise one can use as few as nb_aa_samples.
+    pub fn nb_gi_samples(&self) -> usize {
+        self.gi_samples
+            .unwrap_or(self.nb_rays)
+            .max(self.nb_aa_samples())
+    }
+
+    /// Number of bounces of the paths: nb_iter_max in total, and the max depth of each kind of bounce when it is set
+    /// Deep glass needs many refractions and little diffuse lighting: separate budgets spend the Rays where the Scene needs them.
+    pub fn depth(&self) -> Depth {
+        Depth {
+            total: self.nb_iter_max,
+            reflection: self.max_reflection_depth.unwrap_or(self.nb_iter_max),
+            refraction: self.max_refraction_depth.unwrap_or(self.nb_iter_max),
+            diffuse: self.max_diffuse_depth.unwrap_or(self.nb_iter_max),
         }
     }
+
+    /// Tells if the pixel of row i and column j is rendered (see crop)
+    pub fn in_crop(&self, i: usize, j: usize) -> bool {
+        match self.crop {
+            Some((x0, y0, x1, y1)) => (y0..y1).contains(&i) && (x0..x1).contains(&j),
+            None => true,
+        }
+    }
+
+    /// Creates the random number generator of the sample-th primary Ray of the pixel (i, j) of the k-th frame, and of the paths it starts
+    /// Each sample draws from its own stream, derived from the seed: a render gives the same image whichever threads draw the samples.
+    pub fn sample_rng(&self, k: usize, i: usize, j: usize, sample: usize) -> StdRng {
+        // Each index is mixed in with a step of SplitMix64, so that neighbouring samples get unrelated streams
+        let seed = [k, i, j, sample].iter().fold(self.seed, |state, index| {
+            let mut z = (state ^ *index as u64)
+                .wrapping_add(0x9E37_79B9_7F4A_7C15)
+                .wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        });
+        StdRng::seed_from_u64(seed)
+    }
+
+    /// Number of paths started at the primary hit of the sample-th primary Ray, spreading nb_gi_samples evenly over the primary Rays
+    pub fn nb_paths(&self, sample: usize) -> usize {
+        let (aa_samples, gi_samples) = (self.nb_aa_samples(), self.nb_gi_samples());
+        gi_samples / aa_samples + usize::from(sample < gi_samples % aa_samples)
+    }
+}
+
+/// # ConfigBuilder
+///
+/// Builds a Config with chained setters, starting from defaults:
+/// a 200x200 image with a gamma of 2.2, 100 anti-aliased samples per pixel and paths of 5 bounces, one frame at time 0.
+///
+/// ```
+/// use raytracer::utils::{Config, ConfigBuilder};
+///
+/// const CONFIG: Config = ConfigBuilder::new().height(300).width(400).nb_rays(50).build();
+/// assert_eq!(CONFIG.nb_iter_max, 5);
+/// ```
+#[derive(Copy, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigBuilder {
+    pub const fn new() -> Self {
+        ConfigBuilder {
+            config: Config::new(200, 200, 2.2, false, 5, 100, false, true, 0., 0., 1),
+        }
+    }
+
+    /// Height of the image, in pixels
+    pub const fn height(mut self, height: usize) -> Self {
+        self.config.height = height;
+        self
+    }
+
+    /// Width of the image, in pixels
+    pub const fn width(mut self, width: usize) -> Self {
+        self.config.width = width;
+        self
+    }
+
+    pub const fn gamma(mut self, gamma: f64) -> Self {
+        self.config.gamma = gamma;
+        self
+    }
+
+    pub const fn debug_info(mut self, debug_info: bool) -> Self {
+        self.config.debug_info = debug_info;
+        self
+    }
+
+    /// Maximum number of bounces of a path
+    pub const fn nb_iter_max(mut self, nb_iter_max: usize) -> Self {
+        self.config.nb_iter_max = nb_iter_max;
+        self
+    }
+
+    /// Number of samples per pixel
+    pub const fn nb_rays(mut self, nb_rays: usize) -> Self {
+        self.config.nb_rays = nb_rays;
+        self
+    }
+
+    /// Enables the depth of field (see Camera::aperture)
+    pub const fn dof(mut self, dof: bool) -> Self {
+        self.config.dof = dof;
+        self
+    }
+
+    /// Enables the anti-aliasing
+    pub const fn aa(mut self, aa: bool) -> Self {
+        self.config.aa = aa;
+        self
+    }
+
+    pub const fn start_time(mut self, start_time: f64) -> Self {
+        self.config.start_time = start_time;
+        self
+    }
+
+    pub const fn end_time(mut self, end_time: f64) -> Self {
+        self.config.end_time = end_time;
+        self
+    }
+
+    pub const fn nb_frames(mut self, nb_frames: usize) -> Self {
+        self.config.nb_frames = nb_frames;
+        self
+    }
+
+    pub const fn render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.config.render_mode = render_mode;
+        self
+    }
+
+    pub const fn xray_density(mut self, xray_density: f64) -> Self {
+        self.config.xray_density = xray_density;
+        self
+    }
+
+    pub const fn render_scale(mut self, render_scale: f64) -> Self {
+        self.config.render_scale = render_scale;
+        self
+    }
+
+    pub const fn upscale_guided(mut self, upscale_guided: bool) -> Self {
+        self.config.upscale_guided = upscale_guided;
+        self
+    }
+
+    pub const fn packet_size(mut self, packet_size: usize) -> Self {
+        self.config.packet_size = packet_size;
+        self
+    }
+
+    pub const fn stratified_aa(mut self, stratified_aa: bool) -> Self {
+        self.config.stratified_aa = stratified_aa;
+        self
+    }
+
+    pub const fn tile_size(mut self, tile_size: usize) -> Self {
+        self.config.tile_size = tile_size;
+        self
+    }
+
+    pub const fn aa_samples(mut self, aa_samples: usize) -> Self {
+        self.config.aa_samples = Some(aa_samples);
+        self
+    }
+
+    pub const fn gi_samples(mut self, gi_samples: usize) -> Self {
+        self.config.gi_samples = Some(gi_samples);
+        self
+    }
+
+    pub const fn save_aovs(mut self, save_aovs: bool) -> Self {
+        self.config.save_aovs = save_aovs;
+        self
+    }
+
+    pub const fn save_object_ids(mut self, save_object_ids: bool) -> Self {
+        self.config.save_object_ids = save_object_ids;
+        self
+    }
+
+    pub const fn max_sample_radiance(mut self, max_sample_radiance: f64) -> Self {
+        self.config.max_sample_radiance = max_sample_radiance;
+        self
+    }
+
+    pub const fn depth_range(mut self, near: f64, far: f64) -> Self {
+        self.config.depth_range = (near, far);
+        self
+    }
+
+    pub const fn ao_samples(mut self, ao_samples: usize) -> Self {
+        self.config.ao_samples = ao_samples;
+        self
+    }
+
+    pub const fn ao_distance(mut self, ao_distance: f64) -> Self {
+        self.config.ao_distance = ao_distance;
+        self
+    }
+
+    pub const fn adaptive_error(mut self, adaptive_error: Option<f64>) -> Self {
+        self.config.adaptive_error = adaptive_error;
+        self
+    }
+
+    pub const fn adaptive_max_samples(mut self, adaptive_max_samples: usize) -> Self {
+        self.config.adaptive_max_samples = adaptive_max_samples;
+        self
+    }
+
+    pub const fn save_sample_counts(mut self, save_sample_counts: bool) -> Self {
+        self.config.save_sample_counts = save_sample_counts;
+        self
+    }
+
+    pub const fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    pub const fn crop(mut self, crop: Option<(usize, usize, usize, usize)>) -> Self {
+        self.config.crop = crop;
+        self
+    }
+
+    pub const fn parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.config.parallelism = parallelism;
+        self
+    }
+
+    pub const fn gamma_mode(mut self, gamma_mode: GammaMode) -> Self {
+        self.config.gamma_mode = gamma_mode;
+        self
+    }
+
+    pub const fn bit_depth(mut self, bit_depth: BitDepth) -> Self {
+        self.config.bit_depth = bit_depth;
+        self
+    }
+
+    pub const fn save_hdr(mut self, save_hdr: bool) -> Self {
+        self.config.save_hdr = save_hdr;
+        self
+    }
+
+    pub const fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = seed;
+        self
+    }
+
+    pub const fn shutter(mut self, shutter: f64) -> Self {
+        self.config.shutter = shutter;
+        self
+    }
+
+    pub const fn max_reflection_depth(mut self, max_reflection_depth: usize) -> Self {
+        self.config.max_reflection_depth = Some(max_reflection_depth);
+        self
+    }
+
+    pub const fn max_refraction_depth(mut self, max_refraction_depth: usize) -> Self {
+        self.config.max_refraction_depth = Some(max_refraction_depth);
+        self
+    }
+
+    pub const fn max_diffuse_depth(mut self, max_diffuse_depth: usize) -> Self {
+        self.config.max_diffuse_depth = Some(max_diffuse_depth);
+        self
+    }
+
+    pub const fn nee(mut self, nee: bool) -> Self {
+        self.config.nee = nee;
+        self
+    }
+
+    pub const fn stochastic_transparency(mut self, stochastic_transparency: bool) -> Self {
+        self.config.stochastic_transparency = stochastic_transparency;
+        self
+    }
+
+    pub const fn bloom_intensity(mut self, bloom_intensity: f64) -> Self {
+        self.config.bloom_intensity = bloom_intensity;
+        self
+    }
+
+    pub const fn bloom_threshold(mut self, bloom_threshold: f64) -> Self {
+        self.config.bloom_threshold = bloom_threshold;
+        self
+    }
+
+    pub const fn bloom_radius(mut self, bloom_radius: f64) -> Self {
+        self.config.bloom_radius = bloom_radius;
+        self
+    }
+
+    pub const fn stats(mut self, stats: bool) -> Self {
+        self.config.stats = stats;
+        self
+    }
+
+    pub const fn gif_delay_ms(mut self, gif_delay_ms: Option<u32>) -> Self {
+        self.config.gif_delay_ms = gif_delay_ms;
+        self
+    }
+
+    pub const fn build(self) -> Config {
+        self.config
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn try_new_rejects_configs_rendering_nothing() {
+        let try_new = |height, width, gamma, nb_rays, start_time, end_time| {
+            Config::try_new(
+                height, width, gamma, false, 5, nb_rays, false, false, start_time, end_time, 1,
+            )
+        };
+
+        assert!(try_new(10, 20, 2.2, 4, 0., 1.).is_ok());
+        assert_eq!(
+            try_new(10, 0, 2.2, 4, 0., 1.).err(),
+            Some(ConfigError::EmptyImage {
+                height: 10,
+                width: 0
+            })
+        );
+        assert_eq!(
+            try_new(10, 20, 2.2, 0, 0., 1.).err(),
+            Some(ConfigError::NoRays)
+        );
+        assert_eq!(
+            try_new(10, 20, 0., 4, 0., 1.).err(),
+            Some(ConfigError::InvalidGamma(0.))
+        );
+        assert!(matches!(
+            try_new(10, 20, f64::NAN, 4, 0., 1.),
+            Err(ConfigError::InvalidGamma(_))
+        ));
+        let error = try_new(10, 20, 2.2, 4, 2., 1.)
+            .err()
+            .expect("the time runs backwards");
+        assert_eq!(
+            error,
+            ConfigError::BackwardsTime {
+                start_time: 2.,
+                end_time: 1.
+            }
+        );
+        assert!(error.to_string().contains("start_time 2"));
+
+        // The builder can be checked as well
+        assert!(Config::builder().build().validate().is_ok());
+        assert!(Config::builder().nb_rays(0).build().validate().is_err());
+    }
+
     #[test]
     fn vector_norms() {
         let vec = Vector::new(3., 4., 0.);
@@ -467,6 +2169,80 @@ mod tests {
         assert_approx_eq::assert_approx_eq!(vec1.cross(vec2).dot(vec2), 0.);
     }
 
+    #[test]
+    fn colors_convert_to_vectors_channel_by_channel() {
+        let color = Color::new(0.1, 0.2, 0.3);
+        let vector = Vector::from(color);
+        assert_eq!((vector.x, vector.y, vector.z), (0.1, 0.2, 0.3));
+        assert_eq!(vector, Vector::new_eq(1.) * color);
+
+        let back: Color = vector.into();
+        assert_eq!((back.r, back.g, back.b), (0.1, 0.2, 0.3));
+    }
+
+    #[test]
+    fn vectors_are_indexed_by_axis() {
+        let mut vec = Vector::new(1., 2., 3.);
+        assert_eq!([vec[0], vec[1], vec[2]], [1., 2., 3.]);
+
+        vec[0] = -1.;
+        vec[2] *= 2.;
+        assert_eq!(vec, Vector::new(-1., 2., 6.));
+    }
+
+    #[test]
+    #[should_panic(expected = "a Vector has no axis 3")]
+    fn vectors_have_three_axes() {
+        let _ = Vector::new_eq(0.)[3];
+    }
+
+    #[test]
+    fn vectors_multiply_componentwise() {
+        let radiance = Vector::new(2., 3., 4.);
+        let throughput = Vector::new(0.5, 0., -1.);
+
+        let product = radiance * throughput;
+        assert_eq!((product.x, product.y, product.z), (1., 0., -4.));
+        assert_eq!(product, throughput * radiance);
+
+        let mut attenuated = radiance;
+        attenuated *= throughput;
+        assert_eq!(attenuated, product);
+        assert_eq!(radiance * Vector::new_eq(1.), radiance);
+    }
+
+    #[test]
+    fn orthonormal_basis_holds_for_every_direction() {
+        let mut normals = vec![
+            Vector::new(0., 0., 1.),
+            Vector::new(0., 0., -1.),
+            Vector::new(1., 0., -0.),
+            Vector::new(1., 0., 0.),
+            Vector::new(0., -1., 0.),
+            Vector::new(1e-9, 0., -1.).normalize(),
+        ];
+        for a in 0..24 {
+            for b in 0..12 {
+                let (phi, theta) = (a as f64 * 0.27, b as f64 * std::f64::consts::PI / 11.);
+                normals.push(Vector::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ));
+            }
+        }
+
+        for n in normals {
+            let (u, v) = n.normalize().orthonormal_basis();
+            assert_approx_eq::assert_approx_eq!(u.norm(), 1.);
+            assert_approx_eq::assert_approx_eq!(v.norm(), 1.);
+            assert_approx_eq::assert_approx_eq!(u.dot(v), 0.);
+            assert_approx_eq::assert_approx_eq!(u.dot(n), 0.);
+            assert_approx_eq::assert_approx_eq!(v.dot(n), 0.);
+            assert_approx_eq::assert_approx_eq!((u.cross(v) - n.normalize()).norm(), 0.);
+        }
+    }
+
     #[test]
     fn rotates() {
         let vec1 = Vector::new(1., 0., 0.);
@@ -477,6 +2253,34 @@ mod tests {
         assert_approx_eq::assert_approx_eq!(vec1.rotate_y(180.).z, vec2.z);
     }
 
+    #[test]
+    fn iridescence_shifts_with_viewing_angle() {
+        let material = Material::create_iridescent(400., 1.33);
+
+        let normal = material.thin_film_reflectance(1.);
+        let grazing = material.thin_film_reflectance(0.3);
+
+        // Magenta-ish at normal incidence, cyan-ish at grazing incidence
+        assert!(normal.r > normal.g);
+        assert!(grazing.g > grazing.r);
+    }
+
+    #[test]
+    fn matrix_inverse() {
+        let view = Matrix4::look_at(
+            Vector::new(1., 2., 3.),
+            Vector::new(0., 0., 0.),
+            Vector::new(0., 1., 0.),
+        );
+        let identity = view * view.inverse().expect("view matrices are invertible");
+
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_approx_eq::assert_approx_eq!(identity.m[i][j], Matrix4::identity().m[i][j]);
+            }
+        }
+    }
+
     #[test]
     fn vector_times_white() {
         let vec1 = Vector::new(4., 3., -2.);
@@ -485,4 +2289,226 @@ mod tests {
         assert_approx_eq::assert_approx_eq!((vec1 * Color::white()).y, vec1.y);
         assert_approx_eq::assert_approx_eq!((vec1 * Color::white()).z, vec1.z);
     }
+
+    #[test]
+    fn glossy_directions_follow_ggx_pdf() {
+        let material = Material::create_glossy(Color::white(), 0.4);
+        let normal = Vector::new(0., 0., 1.);
+        let tangent = Vector::new(1., 0., 0.);
+        let view = Vector::new(0.5, 0., 0.75_f64.sqrt());
+
+        // Histogram of the angle between the sampled directions and the normal, in bins of 10 degrees
+        let (nb_bins, nb_samples) = (9, 200_000);
+        let bin_width = PI / 2. / nb_bins as f64;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut histogram = vec![0.; nb_bins];
+        for _ in 0..nb_samples {
+            let direction = material.sample_ggx(view, normal, tangent, &mut rng);
+            let theta = direction.dot(normal).clamp(-1., 1.).acos();
+            if theta < PI / 2. {
+                histogram[(theta / bin_width) as usize] += 1. / nb_samples as f64;
+            }
+        }
+
+        // The probability of each bin, integrating ggx_pdf over its solid angle
+        let steps = 100;
+        let (d_theta, d_phi) = (bin_width / steps as f64, 2. * PI / steps as f64);
+        for (bin, frequency) in histogram.iter().enumerate() {
+            let expected: f64 = (0..steps * steps)
+                .map(|step| {
+                    let theta = bin as f64 * bin_width + ((step / steps) as f64 + 0.5) * d_theta;
+                    let phi = ((step % steps) as f64 + 0.5) * d_phi;
+                    let direction = Vector::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    );
+                    material.ggx_pdf(view, direction, normal, tangent)
+                        * theta.sin()
+                        * d_theta
+                        * d_phi
+                })
+                .sum();
+            assert!(
+                (frequency - expected).abs() < 0.005,
+                "bin {bin}: {frequency} vs {expected}"
+            );
+        }
+        assert!(histogram.iter().any(|frequency| *frequency > 0.2));
+
+        // Without roughness, the glossy metal is a perfect mirror
+        let smooth = Material::create_glossy(Color::white(), 0.);
+        assert!(smooth.mirror && !smooth.is_microfacet());
+    }
+
+    #[test]
+    fn glossy_highlight_widens_with_roughness() {
+        let normal = Vector::new(0., 0., 1.);
+        let tangent = Vector::new(1., 0., 0.);
+        let view = normal;
+
+        // Angle (in degrees) at which the highlight falls below half of its peak, tilting the light along an axis
+        let extent = |material: Material, axis: Vector| {
+            let peak = material.ggx_brdf(view, normal, normal, tangent).r;
+            (1..90)
+                .find(|degrees| {
+                    let angle = *degrees as f64 * PI / 180.;
+                    let direction = normal * angle.cos() + axis * angle.sin();
+                    material.ggx_brdf(view, direction, normal, tangent).r < peak / 2.
+                })
+                .unwrap()
+        };
+
+        let smooth = Material::create_glossy(Color::white(), 0.2);
+        let rough = Material::create_glossy(Color::white(), 0.5);
+        assert_eq!(
+            extent(smooth, tangent),
+            extent(smooth, normal.cross(tangent))
+        );
+        assert!(extent(smooth, tangent) * 2 < extent(rough, tangent));
+    }
+
+    #[test]
+    fn brushed_highlight_is_anisotropic() {
+        let material = Material::create_brushed_metal(Color::white(), 0.2, 0.5);
+        let normal = Vector::new(0., 0., 1.);
+        let tangent = Vector::new(1., 0., 0.);
+        let view = normal;
+
+        // Angle (in degrees) at which the highlight falls below half of its peak, tilting the light along an axis
+        let extent = |axis: Vector| {
+            let peak = material.ggx_brdf(view, normal, normal, tangent).r;
+            (1..90)
+                .find(|degrees| {
+                    let angle = *degrees as f64 * PI / 180.;
+                    let direction = normal * angle.cos() + axis * angle.sin();
+                    material.ggx_brdf(view, direction, normal, tangent).r < peak / 2.
+                })
+                .unwrap()
+        };
+
+        let along_tangent = extent(tangent);
+        let along_bitangent = extent(normal.cross(tangent));
+        assert!(along_tangent * 2 < along_bitangent);
+    }
+
+    #[test]
+    fn reflect_and_refract() {
+        let normal = Vector::new(0., 1., 0.);
+        let incoming = Vector::new(1., -1., 0.).normalize();
+
+        let reflected = incoming.reflect(normal);
+        assert_approx_eq::assert_approx_eq!(reflected.x, incoming.x);
+        assert_approx_eq::assert_approx_eq!(reflected.y, -incoming.y);
+
+        // Same index: the Vector goes straight through
+        let straight = incoming.refract(normal, 1.).unwrap();
+        assert_approx_eq::assert_approx_eq!((straight - incoming).norm(), 0.);
+
+        // Snell's law: n_1 sin(theta_1) = n_2 sin(theta_2)
+        let refracted = incoming.refract(normal, 1. / 1.5).unwrap();
+        assert_approx_eq::assert_approx_eq!(refracted.norm(), 1.);
+        assert_approx_eq::assert_approx_eq!(refracted.x * 1.5, incoming.x);
+        assert!(refracted.y < 0.);
+
+        // From the glass at 45 degrees, beyond the critical angle (about 42 degrees)
+        assert!(incoming.refract(normal, 1.5).is_none());
+    }
+
+    #[test]
+    fn lerp_clamp_distance_angle() {
+        let a = Vector::new(1., -2., 0.3);
+        let b = Vector::new(-4., 5., 7.1);
+
+        let start = a.lerp(b, 0.);
+        let end = a.lerp(b, 1.);
+        assert!(start.x == a.x && start.y == a.y && start.z == a.z);
+        assert!(end.x == b.x && end.y == b.y && end.z == b.z);
+        assert_approx_eq::assert_approx_eq!(a.lerp(b, 0.5).x, -1.5);
+
+        let clamped = b.clamp(0., 6.);
+        assert_eq!((clamped.x, clamped.y, clamped.z), (0., 5., 6.));
+
+        assert_approx_eq::assert_approx_eq!(
+            Vector::new(1., 2., 3.).distance(Vector::new(4., 6., 3.)),
+            5.
+        );
+
+        let x = Vector::new(2., 0., 0.);
+        assert_approx_eq::assert_approx_eq!(x.angle_between(Vector::new(0., 3., 0.)), PI / 2.);
+        assert_approx_eq::assert_approx_eq!(x.angle_between(Vector::new(-1., 0., 0.)), PI);
+        assert_approx_eq::assert_approx_eq!(x.angle_between(Vector::new(1., 1., 0.)), PI / 4.);
+    }
+
+    #[test]
+    fn color_from_hex_and_clamp() {
+        let orange = Color::from_hex("#ff8800").unwrap();
+        assert_eq!((orange.r, orange.g, orange.b), (1., 136. / 255., 0.));
+
+        let short = Color::from_hex("f80").unwrap();
+        assert_eq!((short.r, short.g, short.b), (orange.r, orange.g, orange.b));
+
+        let rgb8 = Color::from_rgb8(255, 136, 0);
+        assert_eq!((rgb8.r, rgb8.g, rgb8.b), (orange.r, orange.g, orange.b));
+
+        assert_eq!(
+            Color::from_hex("#ff88").err(),
+            Some(ParseColorError::InvalidLength("#ff88".to_string()))
+        );
+        assert_eq!(
+            Color::from_hex("+f+f+f").err(),
+            Some(ParseColorError::InvalidDigit("+f+f+f".to_string()))
+        );
+        assert_eq!(
+            Color::from_hex("#ff88zz").err(),
+            Some(ParseColorError::InvalidDigit("#ff88zz".to_string()))
+        );
+
+        let clamped = Color::new(1.5, -0.2, 0.4).clamp();
+        assert_eq!((clamped.r, clamped.g, clamped.b), (1., 0., 0.4));
+    }
+
+    #[test]
+    fn sample_counts_default_to_nb_rays() {
+        let config = Config::builder().nb_rays(10).build();
+        assert_eq!((config.nb_aa_samples(), config.nb_gi_samples()), (10, 10));
+        assert!((0..10).all(|sample| config.nb_paths(sample) == 1));
+
+        let config = Config::builder()
+            .nb_rays(10)
+            .aa_samples(4)
+            .gi_samples(10)
+            .build();
+        let paths: Vec<usize> = (0..4).map(|sample| config.nb_paths(sample)).collect();
+        assert_eq!(paths, vec![3, 3, 2, 2]);
+
+        // Every primary Ray needs a path
+        let config = Config::builder().aa_samples(16).gi_samples(1).build();
+        assert_eq!(config.nb_gi_samples(), 16);
+    }
+
+    #[test]
+    fn normalizing_null_vector_gives_no_nan() {
+        let normalized = Vector::new_eq(0.).normalize();
+        assert!(normalized.is_finite());
+        assert_eq!(normalized, Vector::new_eq(0.));
+
+        assert_approx_eq::assert_approx_eq!(Vector::new(3., 0., 4.).normalize().norm(), 1.);
+        assert!(!Vector::new(f64::NAN, 0., 0.).is_finite());
+    }
+
+    #[test]
+    fn materials_without_emission_color_emit_their_color() {
+        let lamp = Material::create_emissive_colored(Color::white(), Color::blue(), 1.);
+        let mut json = serde_json::to_value(lamp).unwrap();
+        let blue: Material = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!((blue.emission_color.r, blue.emission_color.b), (0., 1.));
+
+        // Scene files written before emission_color existed emitted the color of the surface
+        json.as_object_mut().unwrap().remove("emission_color");
+        json["color"] = serde_json::to_value(Color::red()).unwrap();
+        let red: Material = serde_json::from_value(json).unwrap();
+        assert_eq!((red.emission_color.r, red.emission_color.b), (1., 0.));
+        assert!(red.two_sided);
+    }
 }
@@ -0,0 +1,128 @@
+//! # Noise
+//!
+//! This module generates Perlin gradient noise, used for procedural textures (see Texture::Marble).
+
+use crate::utils::Vector;
+
+/// # Perlin
+///
+/// A 3D Perlin noise generator: smooth pseudo-random values, from about -1. to 1., varying over distances of 1.
+/// The gradients at the lattice points are drawn from a hash of their coordinates and of the seed,
+/// so that the generator is lightweight to copy and the same seed always gives the same noise.
+#[derive(Copy, Clone)]
+pub struct Perlin {
+    seed: u64,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        Perlin { seed }
+    }
+
+    /// Hashes the coordinates of a lattice point (SplitMix64 finalizer)
+    fn hash(&self, x: i64, y: i64, z: i64) -> u64 {
+        let mut h = self.seed
+            ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^ (h >> 31)
+    }
+
+    /// Dot product between the gradient of a lattice point (one of the 12 edges of a cube) and the offset from that point
+    fn gradient(&self, x: i64, y: i64, z: i64, offset: Vector) -> f64 {
+        match self.hash(x, y, z) % 12 {
+            0 => offset.x + offset.y,
+            1 => -offset.x + offset.y,
+            2 => offset.x - offset.y,
+            3 => -offset.x - offset.y,
+            4 => offset.x + offset.z,
+            5 => -offset.x + offset.z,
+            6 => offset.x - offset.z,
+            7 => -offset.x - offset.z,
+            8 => offset.y + offset.z,
+            9 => -offset.y + offset.z,
+            10 => offset.y - offset.z,
+            _ => -offset.y - offset.z,
+        }
+    }
+
+    /// Computes the noise at a point, by blending the gradients of the 8 corners of its lattice cell
+    pub fn noise(&self, point: Vector) -> f64 {
+        let (x0, y0, z0) = (point.x.floor(), point.y.floor(), point.z.floor());
+        let local = point - Vector::new(x0, y0, z0);
+        let (x0, y0, z0) = (x0 as i64, y0 as i64, z0 as i64);
+
+        // Quintic fade curve, for continuous second derivatives
+        let fade = |t: f64| t * t * t * (t * (t * 6. - 15.) + 10.);
+        let (u, v, w) = (fade(local.x), fade(local.y), fade(local.z));
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+        let corner = |dx: i64, dy: i64, dz: i64| {
+            let offset = local - Vector::new(dx as f64, dy as f64, dz as f64);
+            self.gradient(x0 + dx, y0 + dy, z0 + dz, offset)
+        };
+
+        lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+
+    /// Sums the absolute value of octaves of noise, each one twice as fine and half as strong as the previous one
+    pub fn turbulence(&self, point: Vector, octaves: usize) -> f64 {
+        let mut sum = 0.;
+        let mut frequency = 1.;
+        let mut amplitude = 1.;
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(point * frequency).abs();
+            frequency *= 2.;
+            amplitude /= 2.;
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_smooth_and_seedable() {
+        let perlin = Perlin::new(7);
+        let points: Vec<Vector> = (0..1000)
+            .map(|k| {
+                Vector::new(
+                    k as f64 * 0.037,
+                    k as f64 * 0.011 - 3.,
+                    2.5 - k as f64 * 0.023,
+                )
+            })
+            .collect();
+
+        for point in points.iter() {
+            let value = perlin.noise(*point);
+            assert!(value.abs() <= 1.5);
+            // Close points have close values
+            assert!((perlin.noise(*point + Vector::new_eq(1e-4)) - value).abs() < 1e-2);
+            assert_eq!(Perlin::new(7).noise(*point), value);
+        }
+
+        // The noise vanishes on the lattice, but not in between
+        assert_eq!(perlin.noise(Vector::new(3., -2., 5.)), 0.);
+        assert!(points.iter().any(|p| perlin.noise(*p).abs() > 0.2));
+        assert!(points
+            .iter()
+            .any(|p| perlin.noise(*p) != Perlin::new(8).noise(*p)));
+    }
+}